@@ -0,0 +1,28 @@
+// Measures how fast a client ticks, generating packets at a few rates relative to the resolution:
+// at fine resolutions most ticks generate nothing, at coarse ones most generate a packet or more.
+//
+//   cargo bench --bench client
+
+extern crate qlib;
+
+mod common;
+
+use qlib::generators::{Deterministic, Markov};
+use qlib::{Client, Generator};
+
+const TICKS: u32 = 50_000_000;
+
+// run ticks a client with the given generator through TICKS ticks.
+fn run<G: Generator>(generator: G) -> (f64, u64) {
+    let mut client = Client::new(generator, 1e6);
+    let (elapsed, generated) = common::time(|| (0..TICKS).map(|_| client.tick()).sum::<u32>());
+    assert_eq!(generated, client.packets_generated());
+    (elapsed, u64::from(TICKS))
+}
+
+fn main() {
+    for &rate in &[1e3, 1e5, 1e6] {
+        common::report(&format!("markov, rate {}", rate), "ticks", || run(Markov::new(rate)));
+    }
+    common::report("deterministic, rate 1e5", "ticks", || run(Deterministic::new(1e5)));
+}
@@ -0,0 +1,47 @@
+// Helpers shared between the benches. Each is a plain binary (harness = false), run with:
+//
+//   cargo bench --bench <name>
+//
+// Timings are the best of a few runs, so as to be less at the mercy of whatever else the machine
+// is up to.
+
+// Not every bench uses every helper.
+#![allow(dead_code)]
+
+use std::time::Instant;
+
+pub const RUNS: u32 = 5;
+
+// time returns how long the given closure took to run, in seconds, along with what it returned.
+pub fn time<T, F: FnOnce() -> T>(f: F) -> (f64, T) {
+    let start = Instant::now();
+    let t = f();
+    (start.elapsed().as_secs_f64(), t)
+}
+
+// best runs the given closure RUNS times, each returning the time it took in seconds along with
+// what it got through (the same every run), and returns the quickest.
+pub fn best<T, F: FnMut() -> (f64, T)>(mut run: F) -> (f64, T) {
+    let mut best = run();
+    for _ in 1..RUNS {
+        let r = run();
+        if r.0 < best.0 {
+            best = r;
+        }
+    }
+    best
+}
+
+// report prints the best rate at which the given closure gets through its work, counted in units.
+pub fn report<F: FnMut() -> (f64, u64)>(name: &str, unit: &str, run: F) {
+    let (elapsed, work) = best(run);
+    println!(
+        "{}: {} {} in {:.3}s ({:.1}M {}/s)",
+        name,
+        work,
+        unit,
+        elapsed,
+        work as f64 / elapsed / 1e6,
+        unit
+    );
+}
@@ -0,0 +1,74 @@
+// Measures how fast a server ticks through packets that each spend a long while in service, the
+// common case at fine resolutions: most ticks only move the packet in service along (the bulk of
+// what Server.tick goes through), and how fast it churns through a full queue with each of its
+// backends.
+//
+//   cargo bench --bench server
+
+extern crate qlib;
+
+mod common;
+
+use qlib::{Packet, QueueBackend, Server, ServerBuilder};
+
+const TICKS: u32 = 50_000_000;
+
+// The buffer limit of the queues churned through.
+const LIMIT: usize = 1024;
+
+fn packet(id: u32) -> Packet {
+    Packet {
+        id,
+        length: 1,
+        ..Packet::default()
+    }
+}
+
+// service ticks a server through TICKS ticks, a thousand a packet with the next one always queued.
+fn service() -> (f64, u64) {
+    let mut server = Server::new(1e6, 1e3, None);
+    let (elapsed, departed) = common::time(|| {
+        let mut departed = vec![];
+        for t in 0..TICKS {
+            if server.qlen() == 0 {
+                server.enqueue(packet(t));
+            }
+            server.tick_into(&mut departed);
+        }
+        departed.len() as u32
+    });
+    assert_eq!(departed, TICKS / 1000);
+    (elapsed, u64::from(TICKS))
+}
+
+// churn ticks a server with the given queue backend through TICKS ticks, a packet arriving and a
+// packet departing every tick with the queue kept full.
+fn churn(backend: QueueBackend) -> (f64, u64) {
+    let mut server = ServerBuilder::new()
+        .resolution(1.0)
+        .pspeed(1.0)
+        .buffer_limit(Some(LIMIT))
+        .queue_backend(backend)
+        .build();
+    for id in 0..LIMIT as u32 {
+        server.enqueue(packet(id));
+    }
+    let (elapsed, departed) = common::time(|| {
+        let (mut departed, mut count) = (vec![], 0);
+        for t in 0..TICKS {
+            server.tick_into(&mut departed);
+            count += departed.len() as u32;
+            departed.clear();
+            server.enqueue(packet(t));
+        }
+        count
+    });
+    assert_eq!(departed, TICKS);
+    (elapsed, u64::from(TICKS))
+}
+
+fn main() {
+    common::report("server", "ticks", service);
+    common::report("churn (growable)", "ticks", || churn(QueueBackend::Growable));
+    common::report("churn (fixed)", "ticks", || churn(QueueBackend::Fixed));
+}
@@ -0,0 +1,44 @@
+// Measures how fast the full simulation loop runs at a few utilizations, in ticks and events
+// (packets arriving and departing) a second. Idle stretches are skipped over rather than ticked
+// through (see Simulation.fast_forward), so lightly loaded runs get through the ticks faster.
+//
+//   cargo bench --bench simulation
+
+extern crate qlib;
+
+mod common;
+
+use qlib::{Config, Simulation};
+
+// The simulated duration of each run, in seconds, at the default resolution of a microsecond.
+const DURATION: u32 = 10;
+
+fn run(utilization: f64) -> (f64, (u64, u64)) {
+    let config = Config {
+        rate: (utilization * 10_000.0) as u32,
+        pspeed: 10_000,
+        duration: DURATION,
+        seed: Some(1),
+        ..Config::default()
+    };
+    let ticks = config.ticks();
+    let sim = Simulation::new(config);
+    let (elapsed, results) = common::time(|| sim.run());
+    let events = u64::from(results.packets_generated) + u64::from(results.packets_processed);
+    (elapsed, (u64::from(ticks.0), events))
+}
+
+fn main() {
+    for &utilization in &[0.1, 0.5, 0.9, 0.99] {
+        let (elapsed, (ticks, events)) = common::best(|| run(utilization));
+        println!(
+            "utilization {}: {} ticks, {} events in {:.3}s ({:.1}M ticks/s, {:.2}M events/s)",
+            utilization,
+            ticks,
+            events,
+            elapsed,
+            ticks as f64 / elapsed / 1e6,
+            events as f64 / elapsed / 1e6
+        );
+    }
+}
@@ -0,0 +1,60 @@
+/*
+ * The C interface to qsim, for embedding its queue models in larger simulators (see src/ffi.rs).
+ * Link against the qlib library built by cargo (libqlib.a or libqlib.so).
+ *
+ * Simulations are configured with a JSON document of the form written out by
+ * `qsim --output-format json`, any fields left out taking on their defaults, and report on their
+ * configuration and results the same way. Strings returned are the caller's, to be freed with
+ * qsim_free_string.
+ */
+
+#ifndef QSIM_H
+#define QSIM_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct qsim_simulation qsim_simulation;
+
+/*
+ * Returns a simulation of the configuration, run off of the given seed unless the configuration
+ * has its own, or NULL if the configuration is invalid (or the simulator panics), in which case the
+ * reason is written to *error unless error is NULL.
+ */
+qsim_simulation *qsim_create(const char *config, uint64_t seed, char **error);
+
+/*
+ * Moves the simulation forward by up to the given number of ticks, resuming it if it was paused,
+ * and returns the number it moved forward by: fewer once it completes. Should the simulator panic,
+ * it returns UINT32_MAX and writes why to *error unless error is NULL; the simulation is then only
+ * good for freeing.
+ */
+uint32_t qsim_step(qsim_simulation *sim, uint32_t ticks, char **error);
+
+/* Returns the current simulated time, in ticks. */
+uint32_t qsim_now(const qsim_simulation *sim);
+
+/* Returns whether the simulation has run to completion. */
+bool qsim_done(const qsim_simulation *sim);
+
+/*
+ * Returns the JSON document describing the configuration and the results thus far, or NULL should
+ * the simulator panic, in which case the reason is written to *error unless error is NULL.
+ */
+char *qsim_results(const qsim_simulation *sim, char **error);
+
+/* Frees the simulation; NULL is ignored. */
+void qsim_destroy(qsim_simulation *sim);
+
+/* Frees a string returned by any of the above; NULL is ignored. */
+void qsim_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,722 @@
+extern crate stats;
+
+use self::stats::{Commute, OnlineStats};
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+
+// repeated returns the statistics of n copies of the given sample, using O(log n) merges rather
+// than n additions.
+pub fn repeated(sample: f64, n: u64) -> OnlineStats {
+    let mut result = OnlineStats::new();
+    let mut power = OnlineStats::new();
+    power.add(sample);
+    let mut n = n;
+    while n > 0 {
+        if n & 1 == 1 {
+            result.merge(power);
+        }
+        power.merge(power);
+        n >>= 1;
+    }
+    result
+}
+
+// normal_quantile returns the p-th quantile of the standard normal distribution, using Acklam's
+// rational approximation (relative error below 1.15e-9).
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    const LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    if p < LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) /
+            ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q /
+            (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -normal_quantile(1.0 - p)
+    }
+}
+
+// t_quantile returns the p-th quantile of Student's t-distribution with the given degrees of
+// freedom. The cases for one and two degrees of freedom are exact, beyond that we use the
+// Cornish-Fisher expansion around the normal quantile (Abramowitz & Stegun 26.7.5), which is
+// accurate to a few parts in ten thousand from three degrees of freedom onwards.
+pub fn t_quantile(p: f64, df: u32) -> f64 {
+    match df {
+        0 => f64::NAN,
+        1 => (PI * (p - 0.5)).tan(),
+        2 => (2.0 * p - 1.0) / (2.0 * p * (1.0 - p)).sqrt(),
+        _ => {
+            let z = normal_quantile(p);
+            let n = f64::from(df);
+            let (z3, z5, z7, z9) = (z.powi(3), z.powi(5), z.powi(7), z.powi(9));
+            z + (z3 + z) / (4.0 * n) + (5.0 * z5 + 16.0 * z3 + 3.0 * z) / (96.0 * n.powi(2)) +
+                (3.0 * z7 + 19.0 * z5 + 17.0 * z3 - 15.0 * z) / (384.0 * n.powi(3)) +
+                (79.0 * z9 + 776.0 * z7 + 1482.0 * z5 - 1920.0 * z3 - 945.0 * z) /
+                    (92_160.0 * n.powi(4))
+        }
+    }
+}
+
+// t_cdf returns the probability that a variable following Student's t-distribution with the given
+// degrees of freedom, which needn't be whole (as with Welch's approximation), is at most t.
+pub fn t_cdf(t: f64, df: f64) -> f64 {
+    if t.is_nan() || df.is_nan() || df <= 0.0 {
+        return f64::NAN;
+    }
+    if t.is_infinite() {
+        return if t > 0.0 { 1.0 } else { 0.0 };
+    }
+    let tail = 0.5 * incomplete_beta(df / (df + t * t), df / 2.0, 0.5);
+    if t > 0.0 {
+        1.0 - tail
+    } else {
+        tail
+    }
+}
+
+// incomplete_beta returns the regularized incomplete beta function I_x(a, b), evaluated by its
+// continued fraction (Numerical Recipes 6.4), using the symmetry I_x(a, b) = 1 - I_1-x(b, a) where
+// that converges faster.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let ln_beta = ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b);
+    let front = (a * x.ln() + b * (1.0 - x).ln() - ln_beta).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_fraction(1.0 - x, b, a) / b
+    }
+}
+
+// beta_fraction evaluates the continued fraction of the incomplete beta function by the modified
+// Lentz method.
+fn beta_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    let nonzero = |v: f64| if v.abs() < TINY { TINY } else { v };
+    let mut c = 1.0;
+    let mut d = 1.0 / nonzero(1.0 - (a + b) * x / (a + 1.0));
+    let mut f = d;
+    for m in 1..1000 {
+        let m = f64::from(m);
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / nonzero(1.0 + even * d);
+        c = nonzero(1.0 + even / c);
+        f *= c * d;
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / nonzero(1.0 + odd * d);
+        c = nonzero(1.0 + odd / c);
+        f *= c * d;
+        if (c * d - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    f
+}
+
+// ln_gamma returns the natural logarithm of the gamma function, using the Lanczos approximation
+// (g = 7, nine coefficients), accurate to about fifteen significant digits.
+fn ln_gamma(x: f64) -> f64 {
+    const G: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // The reflection formula.
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = G[1..].iter().enumerate().fold(G[0], |sum, (i, g)| sum + g / (x + i as f64 + 1.0));
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// autocorrelation returns the sample autocorrelation of the series at the given lag, or NaN if
+// it is undefined (too few samples, or a constant series).
+pub fn autocorrelation(samples: &[f64], lag: usize) -> f64 {
+    let n = samples.len();
+    if n <= lag || n < 2 {
+        return f64::NAN;
+    }
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let denominator: f64 = samples.iter().map(|x| (x - mean).powi(2)).sum();
+    let numerator: f64 = (0..n - lag)
+        .map(|i| (samples[i] - mean) * (samples[i + lag] - mean))
+        .sum();
+    numerator / denominator
+}
+
+// mser returns the truncation point (as an index into the series) that minimizes the marginal
+// standard error of the remaining observations, as per White's MSER heuristic. The series is first
+// averaged over non-overlapping batches of the given size (5 for MSER-5), and the truncation point
+// is restricted to the first half of the series, beyond which the statistic is unreliable.
+pub fn mser(samples: &[f64], batch: usize) -> usize {
+    let batch = batch.max(1);
+    let means: Vec<f64> = samples
+        .chunks(batch)
+        .filter(|c| c.len() == batch)
+        .map(|c| c.iter().sum::<f64>() / batch as f64)
+        .collect();
+    let n = means.len();
+    if n < 2 {
+        return 0;
+    }
+
+    // Compute the MSER statistic for every truncation point d in a single backwards pass, keeping
+    // running sums of the retained batch means.
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    let mut best = (f64::INFINITY, 0);
+    for d in (0..n).rev() {
+        sum += means[d];
+        sum_sq += means[d] * means[d];
+        let k = (n - d) as f64;
+        if d <= n / 2 {
+            let mser = (sum_sq - sum * sum / k) / (k * k);
+            if mser <= best.0 {
+                best = (mser, d);
+            }
+        }
+    }
+    best.1 * batch
+}
+
+// moving_average smooths the series as per Welch's procedure: the i-th value is the average over
+// the window [i - w, i + w], where the window shrinks near the start of the series so as to stay
+// centered. Values within w of the end of the series are not computed, and missing (NaN) values
+// are left out of the average.
+pub fn moving_average(samples: &[f64], window: usize) -> Vec<f64> {
+    let n = samples.len();
+    let mut averaged = vec![];
+    for i in 0..n.saturating_sub(window) {
+        let w = ::std::cmp::min(i, window);
+        averaged.push(mean_present(&samples[i - w..=i + w]));
+    }
+    averaged
+}
+
+// welch_average averages the series of several replications value by value, as per Welch's
+// procedure, leaving out missing (NaN) values. The averaged series is as long as the shortest.
+pub fn welch_average(series: &[&[f64]]) -> Vec<f64> {
+    let n = series.iter().map(|s| s.len()).min().unwrap_or(0);
+    (0..n)
+        .map(|i| mean_present(&series.iter().map(|s| s[i]).collect::<Vec<f64>>()))
+        .collect()
+}
+
+// mean_present returns the mean of the values that aren't missing (NaN), or NaN if all are.
+fn mean_present(samples: &[f64]) -> f64 {
+    let present = samples.iter().filter(|x| !x.is_nan());
+    let (sum, n) = present.fold((0.0, 0), |(sum, n), x| (sum + x, n + 1));
+    if n == 0 {
+        f64::NAN
+    } else {
+        sum / f64::from(n)
+    }
+}
+
+// welch_truncation returns the first index after which the (smoothed) series stays within the
+// given relative tolerance of the mean of its second half, automating the visual inspection of a
+// Welch plot.
+pub fn welch_truncation(averaged: &[f64], tolerance: f64) -> usize {
+    let n = averaged.len();
+    if n < 2 {
+        return 0;
+    }
+    let tail = &averaged[n / 2..];
+    let steady = tail.iter().sum::<f64>() / tail.len() as f64;
+    let band = (steady * tolerance).abs();
+    averaged
+        .iter()
+        .rposition(|x| (x - steady).abs() > band)
+        .map_or(0, |i| i + 1)
+}
+
+// percentile returns the p-th percentile (0 <= p <= 100) of the sorted samples by the nearest-rank
+// method, or NaN if there are none.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+// half_width returns the half-width of the confidence interval for the mean of the given
+// independent observations (e.g. one per replication), or NaN if there are fewer than two.
+pub fn half_width(stats: &OnlineStats, confidence: f64) -> f64 {
+    let n = stats.len();
+    if n < 2 {
+        return f64::NAN;
+    }
+    // OnlineStats.variance() is the population variance, we want the sample variance.
+    let stderr = (stats.variance() / (n - 1) as f64).sqrt();
+    t_quantile(1.0 - (1.0 - confidence) / 2.0, n as u32 - 1) * stderr
+}
+
+// significant reports whether the mean of the given paired differences is significantly different
+// from zero (a paired t-test), i.e. whether its confidence interval excludes zero.
+pub fn significant(differences: &OnlineStats, confidence: f64) -> bool {
+    differences.mean().abs() > half_width(differences, confidence)
+}
+
+// coefficient_of_variation returns the standard deviation relative to the mean of the given
+// observations, e.g. 1 for exponentially distributed ones and 0 for constant ones.
+pub fn coefficient_of_variation(stats: &OnlineStats) -> f64 {
+    stats.stddev() / stats.mean()
+}
+
+// chi_square_distance returns the chi-square distance of the observed distribution from the
+// expected one, Σ (o - e)² / e, over every value and the mass beyond those either covers. Values
+// expected never to occur but observed make it infinite.
+pub fn chi_square_distance(observed: &[f64], expected: &[f64]) -> f64 {
+    let at = |p: &[f64], i: usize| p.get(i).cloned().unwrap_or(0.0);
+    let n = observed.len().max(expected.len());
+    let tail = |p: &[f64]| (1.0 - p.iter().sum::<f64>()).max(0.0);
+    (0..n)
+        .map(|i| (at(observed, i), at(expected, i)))
+        .chain(Some((tail(observed), tail(expected))))
+        .map(|(o, e)| match (o, e) {
+            (o, e) if e > 0.0 => (o - e) * (o - e) / e,
+            (o, _) if o > 1e-12 => f64::INFINITY,
+            _ => 0.0,
+        })
+        .sum()
+}
+
+// jain_fairness returns Jain's fairness index of the given allocations (e.g. throughputs),
+// (Σx)² / (n·Σx²). It ranges from 1/n, when a single party gets everything, to 1, when all get
+// the same; it's NaN if there are none, or if none get anything.
+pub fn jain_fairness(xs: &[f64]) -> f64 {
+    let sum: f64 = xs.iter().sum();
+    let squares: f64 = xs.iter().map(|x| x * x).sum();
+    sum * sum / (xs.len() as f64 * squares)
+}
+
+// KS_CRITICAL is the 5% critical value of the Kolmogorov-Smirnov statistic, as modified for
+// testing against an exponential distribution of estimated mean (Stephens, 1974).
+const KS_CRITICAL: f64 = 1.094;
+
+// PoissonTest tests a series of intervals (e.g. interdeparture times), in ticks, for being those
+// of a Poisson process: exponentially distributed, by a Kolmogorov-Smirnov test against the
+// exponential distribution of the same mean, and independent, by their lag-1 autocorrelation.
+// Both at the 5% significance level. As events happen on whole ticks, the intervals are compared
+// against the exponential distribution as seen through ticks: the difference between the ticks two
+// events uniformly spread within them fall on. Ticks much coarser than the intervals make the test
+// reject even a Poisson process.
+#[derive(Clone, Debug, Default)]
+pub struct PoissonTest {
+    // The number of intervals of every length seen.
+    counts: BTreeMap<u32, u64>,
+    stats: OnlineStats,
+    // The products of consecutive intervals, and the last interval.
+    products: OnlineStats,
+    last: Option<u32>,
+}
+
+impl PoissonTest {
+    pub fn new() -> PoissonTest {
+        PoissonTest::default()
+    }
+
+    pub fn add(&mut self, ticks: u32) {
+        *self.counts.entry(ticks).or_insert(0) += 1;
+        self.stats.add(f64::from(ticks));
+        if let Some(last) = self.last {
+            self.products.add(f64::from(last) * f64::from(ticks));
+        }
+        self.last = Some(ticks);
+    }
+
+    pub fn len(&self) -> usize {
+        self.stats.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stats.len() == 0
+    }
+
+    // PoissonTest.statistic returns the Kolmogorov-Smirnov statistic, the largest distance between
+    // the empirical distribution of the intervals and the exponential one of the same mean, as
+    // modified to be compared against KS_CRITICAL regardless of the number of intervals.
+    pub fn statistic(&self) -> f64 {
+        let (m, n) = (self.stats.mean(), self.stats.len() as f64);
+        // With the first event uniformly spread within its tick, an interval is at most k ticks
+        // long with probability ∫₀¹ F(k + u) du, F being the exponential distribution of mean m.
+        let expected = |k: u32| 1.0 - m * (-f64::from(k) / m).exp() * (1.0 - (-1.0 / m).exp());
+        let (mut seen, mut distance) = (0, 0.0f64);
+        for (&k, &count) in &self.counts {
+            // Below k, the empirical distribution holds still while the expected one grows.
+            if k > 0 {
+                distance = distance.max((expected(k - 1) - seen as f64 / n).abs());
+            }
+            seen += count;
+            distance = distance.max((expected(k) - seen as f64 / n).abs());
+        }
+        (distance - 0.2 / n) * (n.sqrt() + 0.26 + 0.5 / n.sqrt())
+    }
+
+    // PoissonTest.lag1 returns the lag-1 autocorrelation of the intervals.
+    pub fn lag1(&self) -> f64 {
+        let mean = self.stats.mean();
+        (self.products.mean() - mean * mean) / self.stats.variance()
+    }
+
+    // PoissonTest.exponential reports whether the intervals are consistent with being
+    // exponentially distributed.
+    pub fn exponential(&self) -> bool {
+        self.statistic() <= KS_CRITICAL
+    }
+
+    // PoissonTest.independent reports whether the intervals are consistent with being independent,
+    // their lag-1 autocorrelation being within what's expected of independent ones.
+    pub fn independent(&self) -> bool {
+        self.lag1().abs() <= normal_quantile(0.975) / (self.len() as f64).sqrt()
+    }
+
+    // PoissonTest.holds reports whether the intervals are consistent with a Poisson process.
+    pub fn holds(&self) -> bool {
+        self.exponential() && self.independent()
+    }
+}
+
+// BatchMeans splits a single, long series of (autocorrelated) observations into contiguous
+// batches and keeps the mean of each. If the batches are long enough, the batch means are
+// approximately independent and normally distributed, which lets us compute a confidence interval
+// for the steady-state mean from a single run.
+//
+// Batches are either closed explicitly (BatchMeans.close(), e.g. at fixed simulated time
+// boundaries) or automatically every `size` samples.
+#[derive(Clone, Debug)]
+pub struct BatchMeans {
+    size: Option<usize>,
+    current: OnlineStats,
+    means: Vec<f64>,
+}
+
+impl Default for BatchMeans {
+    fn default() -> BatchMeans {
+        BatchMeans::new()
+    }
+}
+
+impl BatchMeans {
+    // BatchMeans::new returns an accumulator where batches are closed explicitly.
+    pub fn new() -> BatchMeans {
+        BatchMeans {
+            size: None,
+            current: OnlineStats::new(),
+            means: vec![],
+        }
+    }
+
+    // BatchMeans::with_batch_size returns an accumulator closing a batch every `size` samples.
+    pub fn with_batch_size(size: usize) -> BatchMeans {
+        BatchMeans {
+            size: Some(size),
+            ..BatchMeans::new()
+        }
+    }
+
+    pub fn add(&mut self, sample: f64) {
+        self.current.add(sample);
+        if Some(self.current.len()) == self.size {
+            self.close();
+        }
+    }
+
+    // BatchMeans.add_repeated adds n copies of the given sample.
+    pub fn add_repeated(&mut self, sample: f64, n: u64) {
+        let mut n = n;
+        while n > 0 {
+            let m = match self.size {
+                Some(size) => ::std::cmp::min(n, (size - self.current.len()) as u64),
+                None => n,
+            };
+            self.current.merge(repeated(sample, m));
+            n -= m;
+            if Some(self.current.len()) == self.size {
+                self.close();
+            }
+        }
+    }
+
+    // BatchMeans.close closes the current batch, if it holds any samples.
+    pub fn close(&mut self) {
+        if self.current.len() > 0 {
+            self.means.push(self.current.mean());
+            self.current = OnlineStats::new();
+        }
+    }
+
+    // BatchMeans.means returns the means of all closed batches.
+    pub fn means(&self) -> &[f64] {
+        &self.means
+    }
+
+    // BatchMeans.summary summarizes the closed batches at the given confidence level (0.95 for
+    // e.g.). At least two batches are needed.
+    pub fn summary(&self, confidence: f64) -> Option<BatchSummary> {
+        let k = self.means.len();
+        if k < 2 {
+            return None;
+        }
+        let stats: OnlineStats = self.means.iter().cloned().collect();
+        // OnlineStats.variance() is the population variance, we want the sample variance.
+        let variance = stats.variance() * k as f64 / (k - 1) as f64;
+        let stderr = (variance / k as f64).sqrt();
+        let t = t_quantile(1.0 - (1.0 - confidence) / 2.0, k as u32 - 1);
+        Some(BatchSummary {
+            batches: k,
+            mean: stats.mean(),
+            stderr,
+            half_width: t * stderr,
+            lag1: autocorrelation(&self.means, 1),
+        })
+    }
+}
+
+// BatchSummary is the confidence interval derived from a set of batch means, along with the lag-1
+// autocorrelation between consecutive batch means.
+#[derive(Clone, Copy, Debug)]
+pub struct BatchSummary {
+    pub batches: usize,
+    pub mean: f64,
+    pub stderr: f64,
+    pub half_width: f64,
+    pub lag1: f64,
+}
+
+impl BatchSummary {
+    // BatchSummary.correlated reports whether the lag-1 autocorrelation between batch means is
+    // significant at the 5% level, in which case the batches are too short for the confidence
+    // interval to be trusted.
+    pub fn correlated(&self) -> bool {
+        self.lag1.abs() > 1.96 / (self.batches as f64).sqrt()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rng;
+
+    extern crate rand;
+    use self::rand::Rng;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn nearest_rank_percentiles() {
+        let sorted: Vec<f64> = (1..11).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 5.0);
+        assert_eq!(percentile(&sorted, 55.0), 6.0);
+        assert_eq!(percentile(&sorted, 100.0), 10.0);
+        assert!(percentile(&[], 50.0).is_nan());
+    }
+
+    #[test]
+    fn confidence_intervals() {
+        let stats: OnlineStats = [1.0, 2.0, 3.0, 4.0].iter().cloned().collect();
+        // The sample standard deviation is √(5/3), and t(0.975, 3) = 3.182.
+        assert_close(half_width(&stats, 0.95), 3.182 * (5.0f64 / 3.0 / 4.0).sqrt(), 0.01);
+        assert!(half_width(&[1.0].iter().cloned().collect(), 0.95).is_nan());
+        assert!(significant(&[1.0, 1.1, 0.9].iter().cloned().collect(), 0.95));
+        assert!(!significant(&[1.0, -1.1, 0.2].iter().cloned().collect(), 0.95));
+    }
+
+    #[test]
+    fn fairness() {
+        assert_eq!(jain_fairness(&[3.0, 3.0, 3.0]), 1.0);
+        assert_eq!(jain_fairness(&[5.0, 0.0, 0.0, 0.0]), 0.25);
+        assert_close(jain_fairness(&[1.0, 2.0]), 0.9, 1e-12);
+        assert!(jain_fairness(&[]).is_nan());
+        assert!(jain_fairness(&[0.0, 0.0]).is_nan());
+    }
+
+    #[test]
+    fn variation() {
+        let stats: OnlineStats = [1.0, 3.0].iter().cloned().collect();
+        assert_eq!(coefficient_of_variation(&stats), 0.5);
+        assert_eq!(coefficient_of_variation(&repeated(2.0, 10)), 0.0);
+    }
+
+    #[test]
+    fn quantiles() {
+        assert_close(normal_quantile(0.975), 1.959_964, 1e-6);
+        assert_close(normal_quantile(0.5), 0.0, 1e-9);
+        assert_close(normal_quantile(0.01), -2.326_348, 1e-6);
+        assert_close(t_quantile(0.975, 1), 12.706, 1e-3);
+        assert_close(t_quantile(0.975, 2), 4.303, 1e-3);
+        assert_close(t_quantile(0.975, 9), 2.262, 1e-3);
+        assert_close(t_quantile(0.975, 29), 2.045, 1e-3);
+    }
+
+    #[test]
+    fn t_distribution() {
+        assert_close(ln_gamma(5.0), 24f64.ln(), 1e-12);
+        assert_close(ln_gamma(0.5), PI.sqrt().ln(), 1e-12);
+        assert_close(t_cdf(0.0, 5.0), 0.5, 1e-12);
+        // The Cauchy distribution.
+        assert_close(t_cdf(1.0, 1.0), 0.75, 1e-9);
+        assert_close(t_cdf(-1.0, 1.0), 0.25, 1e-9);
+        assert_close(t_cdf(12.706, 1.0), 0.975, 1e-5);
+        assert_close(t_cdf(2.262, 9.0), 0.975, 1e-4);
+        assert_close(t_cdf(-2.045, 29.0), 0.025, 1e-4);
+        assert_close(t_cdf(1.959_964, 1e9), 0.975, 1e-6);
+        assert_close(t_cdf(2.5, 4.5), 1.0 - 0.029_953, 1e-5);
+        assert_eq!(t_cdf(f64::INFINITY, 3.0), 1.0);
+        assert!(t_cdf(1.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn repeated_samples() {
+        let r = repeated(3.0, 13);
+        assert_eq!(r.len(), 13);
+        assert_close(r.mean(), 3.0, 1e-12);
+        assert_close(r.variance(), 0.0, 1e-12);
+        assert_eq!(repeated(1.0, 0).len(), 0);
+
+        let mut a = BatchMeans::with_batch_size(4);
+        let mut b = BatchMeans::with_batch_size(4);
+        for x in &[1.0, 2.0, 2.0, 2.0, 2.0, 2.0, 5.0] {
+            a.add(*x);
+        }
+        b.add(1.0);
+        b.add_repeated(2.0, 5);
+        b.add(5.0);
+        assert_eq!(a.means(), b.means());
+    }
+
+    #[test]
+    fn batch_means() {
+        let mut bm = BatchMeans::with_batch_size(2);
+        for x in &[1.0, 3.0, 2.0, 4.0, 3.0, 5.0, 7.0] {
+            bm.add(*x);
+        }
+        assert_eq!(bm.means(), &[2.0, 3.0, 4.0]);
+
+        let s = bm.summary(0.95).unwrap();
+        assert_eq!(s.batches, 3);
+        assert_close(s.mean, 3.0, 1e-9);
+        assert_close(s.stderr, (1.0f64 / 3.0).sqrt(), 1e-9);
+        assert_close(s.half_width, 4.303 * s.stderr, 1e-3);
+    }
+
+    #[test]
+    fn mser_truncation() {
+        // A linearly decaying transient over the first 100 observations, followed by a noisy but
+        // stationary tail.
+        let samples: Vec<f64> = (0..1000)
+            .map(|i| if i < 100 { f64::from(100 - i) } else { f64::from(i % 3) })
+            .collect();
+        let d = mser(&samples, 5);
+        assert!((90..=110).contains(&d), "truncation point: {}", d);
+        assert_eq!(mser(&[1.0, 1.0, 1.0], 5), 0);
+    }
+
+    #[test]
+    fn welch() {
+        let samples = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_eq!(moving_average(&samples, 1), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(moving_average(&samples, 2), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(moving_average(&[1.0, f64::NAN, 3.0, 4.0], 1), vec![1.0, 2.0, 3.5]);
+
+        let (a, b) = ([1.0, 2.0, f64::NAN], [3.0, 6.0, f64::NAN, 8.0]);
+        let averaged = welch_average(&[&a, &b]);
+        assert_eq!(averaged[..2], [2.0, 4.0]);
+        assert!(averaged.len() == 3 && averaged[2].is_nan());
+
+        let transient: Vec<f64> = (0..100).map(|i| if i < 10 { 0.0 } else { 10.0 }).collect();
+        assert_eq!(welch_truncation(&moving_average(&transient, 2), 0.05), 12);
+    }
+
+    #[test]
+    fn chi_square() {
+        let p = [0.5, 0.25, 0.125];
+        assert_eq!(chi_square_distance(&p, &p), 0.0);
+        // The mass beyond counts too.
+        let q = [0.5, 0.25, 0.25];
+        assert_close(chi_square_distance(&q, &p), 0.25, 1e-12);
+        assert_eq!(chi_square_distance(&[0.5, 0.5], &[1.0]), f64::INFINITY);
+    }
+
+    #[test]
+    fn poisson_test() {
+        let mut r = rng::substream(1, rng::ARRIVALS);
+        // The ticks of the events of a Poisson process, and of a regular one, 5 ticks apart on
+        // average.
+        let (mut poisson, mut regular) = (PoissonTest::new(), PoissonTest::new());
+        let (mut t, mut last) = (0.0, 0);
+        for i in 0..10_000 {
+            t += -5.0 * (1.0 - r.next_f64()).ln();
+            poisson.add(t as u32 - last);
+            last = t as u32;
+            regular.add(4 + i % 3);
+        }
+        assert!(poisson.holds(), "{} {}", poisson.statistic(), poisson.lag1());
+        assert!(!regular.exponential() && !regular.independent());
+        assert_eq!(poisson.len(), 10_000);
+    }
+
+    #[test]
+    fn lag1_correlation() {
+        let trending: Vec<f64> = (0..20).map(f64::from).collect();
+        let alternating: Vec<f64> = (0..20).map(|i| f64::from(i % 2)).collect();
+        assert!(autocorrelation(&trending, 1) > 0.8);
+        assert!(autocorrelation(&alternating, 1) < -0.8);
+    }
+}
@@ -0,0 +1,547 @@
+use generators::Distribution;
+use simulation::Config;
+
+// Service is the distribution of service times, for the purposes of closed-form analysis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Service {
+    Exponential,
+    Deterministic,
+}
+
+impl Service {
+    // Service.second_moment returns E[S^2] for a mean service time of 1/mu.
+    fn second_moment(&self, mu: f64) -> f64 {
+        match *self {
+            Service::Exponential => 2.0 / (mu * mu),
+            Service::Deterministic => 1.0 / (mu * mu),
+        }
+    }
+
+    // Service.arrivals returns the probability of j Poisson arrivals (at rate lambda) during a
+    // single service time, for j = 0..n.
+    fn arrivals(&self, lambda: f64, mu: f64, n: usize) -> Vec<f64> {
+        let rho = lambda / mu;
+        let mut a = Vec::with_capacity(n);
+        match *self {
+            Service::Exponential => {
+                let p = rho / (1.0 + rho);
+                a.push(1.0 / (1.0 + rho));
+                for j in 1..n {
+                    let prev = a[j - 1];
+                    a.push(prev * p);
+                }
+            }
+            Service::Deterministic => {
+                a.push((-rho).exp());
+                for j in 1..n {
+                    let prev = a[j - 1];
+                    a.push(prev * rho / j as f64);
+                }
+            }
+        }
+        a
+    }
+}
+
+// Expected is the set of steady-state metrics of a queueing model, in the units Results reports
+// them in: the sojourn time in seconds, the number of packets queued (not including the one in
+// service) and the loss probability as a percentage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expected {
+    pub model: String,
+    pub sojourn: f64,
+    pub qlen: f64,
+    pub loss_probability: f64,
+}
+
+// mg1 returns the expected metrics of an M/G/1 queue with arrival rate lambda and service rate mu
+// (in packets/s) by the Pollaczek-Khinchine formula, or None if the queue is unstable.
+pub fn mg1(lambda: f64, mu: f64, service: Service) -> Option<Expected> {
+    let rho = lambda / mu;
+    if rho >= 1.0 {
+        return None;
+    }
+    let qlen = lambda * lambda * service.second_moment(mu) / (2.0 * (1.0 - rho));
+    Some(Expected {
+        model: format!("M/{}/1", kendall(service)),
+        sojourn: qlen / lambda + 1.0 / mu,
+        qlen,
+        loss_probability: 0.0,
+    })
+}
+
+// mg1k returns the expected metrics of an M/G/1/K queue, K being the capacity of the system
+// including the packet in service. We solve for the queue length distribution at departure
+// epochs, from which the time-average distribution follows (see Gross & Harris, Fundamentals of
+// Queueing Theory, §5.1.8).
+pub fn mg1k(lambda: f64, mu: f64, service: Service, k: usize) -> Expected {
+    assert!(k >= 1, "system capacity must be at least 1");
+    let rho = lambda / mu;
+    let a = service.arrivals(lambda, mu, k);
+
+    // The departure epoch chain over 0..K-1 packets left behind, normalized at the end.
+    let mut pi = vec![1.0];
+    for j in 0..k - 1 {
+        let mut next = pi[j] - pi[0] * a[j];
+        for i in 1..j + 1 {
+            next -= pi[i] * a[j - i + 1];
+        }
+        pi.push(next / a[0]);
+    }
+    let sum: f64 = pi.iter().sum();
+    for p in &mut pi {
+        *p /= sum;
+    }
+
+    // The time-average distribution over 0..K packets in the system.
+    let denom = pi[0] + rho;
+    let mut p: Vec<f64> = pi.iter().map(|&pi| pi / denom).collect();
+    p.push(1.0 - 1.0 / denom);
+
+    let l: f64 = p.iter().enumerate().map(|(n, &p)| n as f64 * p).sum();
+    let throughput = lambda * (1.0 - p[k]);
+    Expected {
+        model: format!("M/{}/1/{}", kendall(service), k),
+        sojourn: l / throughput,
+        qlen: l - (1.0 - p[0]),
+        loss_probability: p[k] * 100.0,
+    }
+}
+
+// mm1_occupancy returns P(N = k), the probability of there being k packets in an M/M/1 system at
+// load rho < 1, which is geometrically distributed.
+pub fn mm1_occupancy(rho: f64, k: usize) -> f64 {
+    (1.0 - rho) * rho.powi(k as i32)
+}
+
+// mm1k_occupancy returns P(N = k) for an M/M/1/K queue at load rho, K being the capacity of the
+// system including the packet in service: the geometric distribution truncated at K,
+// (1 - ρ)ρ^k / (1 - ρ^(K+1)), or the uniform one if ρ = 1.
+pub fn mm1k_occupancy(rho: f64, k: usize, capacity: usize) -> f64 {
+    if k > capacity {
+        return 0.0;
+    }
+    if (rho - 1.0).abs() < 1e-12 {
+        return 1.0 / (capacity + 1) as f64;
+    }
+    (1.0 - rho) * rho.powi(k as i32) / (1.0 - rho.powi(capacity as i32 + 1))
+}
+
+// mm1_distribution returns P(N = k) for k = 0 up to n - 1 in an M/M/1 queue at load rho, or an
+// M/M/1/K one if given its capacity.
+pub fn mm1_distribution(rho: f64, capacity: Option<usize>, n: usize) -> Vec<f64> {
+    (0..n)
+        .map(|k| match capacity {
+            Some(capacity) => mm1k_occupancy(rho, k, capacity),
+            None => mm1_occupancy(rho, k),
+        })
+        .collect()
+}
+
+// mmc_distribution returns P(N = k) for k = 0 up to n - 1 in an M/M/c queue with an offered load of
+// a Erlangs (λ/μ), stable if a < c, or an M/M/c/K one if given its capacity. The terms go as
+// a^k/k! up to c packets and grow by a/c with each packet queued beyond.
+pub fn mmc_distribution(a: f64, c: usize, capacity: Option<usize>, n: usize) -> Vec<f64> {
+    let len = n.max(capacity.unwrap_or(c) + 1);
+    let mut terms = vec![1.0];
+    for k in 1..len {
+        let prev = terms[k - 1];
+        terms.push(prev * a / k.min(c) as f64);
+    }
+    let sum = match capacity {
+        Some(capacity) => terms[..capacity + 1].iter().sum(),
+        None => terms[..c].iter().sum::<f64>() + terms[c] / (1.0 - a / c as f64),
+    };
+    (0..n)
+        .map(|k| if capacity.is_some_and(|capacity| k > capacity) { 0.0 } else { terms[k] / sum })
+        .collect()
+}
+
+// mmck returns the expected metrics of an M/M/c/K queue, K ≥ c being the capacity of the system
+// including the packets in service.
+pub fn mmck(lambda: f64, mu: f64, c: usize, k: usize) -> Expected {
+    assert!(k >= c, "system capacity must be at least the number of servers");
+    let p = mmc_distribution(lambda / mu, c, Some(k), k + 1);
+    let l: f64 = p.iter().enumerate().map(|(n, &p)| n as f64 * p).sum();
+    let qlen: f64 = p.iter().enumerate().skip(c).map(|(n, &p)| (n - c) as f64 * p).sum();
+    let throughput = lambda * (1.0 - p[k]);
+    Expected {
+        model: format!("M/M/{}/{}", c, k),
+        sojourn: l / throughput,
+        qlen,
+        loss_probability: p[k] * 100.0,
+    }
+}
+
+// occupancy returns P(N = k) for k = 0 up to n - 1 in the M/M/c or M/M/c/K queue the configuration
+// maps onto, if it maps onto either (see expected): Poisson arrivals and exponentially distributed
+// packets, stable if the buffer is unlimited.
+pub fn occupancy(c: &Config, n: usize) -> Option<Vec<f64>> {
+    if !modeled(c) {
+        return None;
+    }
+    if c.service != Distribution::Exponential || (c.qlimit.is_none() && c.offered_load() >= 1.0) {
+        return None;
+    }
+    let capacity = c.qlimit.map(|l| l + c.servers);
+    if c.servers > 1 {
+        let a = c.offered_load() * c.servers as f64;
+        return Some(mmc_distribution(a, c.servers, capacity, n));
+    }
+    Some(mm1_distribution(c.offered_load(), capacity, n))
+}
+
+// mm1_sojourn_cdf returns P(T <= t) for the sojourn time of an M/M/1 queue, which is exponentially
+// distributed with rate mu - lambda.
+pub fn mm1_sojourn_cdf(lambda: f64, mu: f64, t: f64) -> f64 {
+    1.0 - (-(mu - lambda) * t).exp()
+}
+
+// erlang_b returns the probability of an arrival being blocked in an M/M/c/c loss system with an
+// offered load of a Erlangs (λ/μ), computed by the usual recurrence to avoid overflowing
+// factorials.
+pub fn erlang_b(c: u32, a: f64) -> f64 {
+    let mut b = 1.0;
+    for k in 1..c + 1 {
+        b = a * b / (f64::from(k) + a * b);
+    }
+    b
+}
+
+// erlang_c returns the probability of an arrival having to wait in an M/M/c queue with an offered
+// load of a Erlangs, or None if the queue is unstable (a >= c).
+pub fn erlang_c(c: u32, a: f64) -> Option<f64> {
+    if a >= f64::from(c) {
+        return None;
+    }
+    let b = erlang_b(c, a);
+    Some(f64::from(c) * b / (f64::from(c) - a * (1.0 - b)))
+}
+
+// mmc returns the expected metrics of an M/M/c queue, or None if it is unstable.
+pub fn mmc(lambda: f64, mu: f64, c: u32) -> Option<Expected> {
+    let wait = erlang_c(c, lambda / mu)? / (f64::from(c) * mu - lambda);
+    Some(Expected {
+        model: format!("M/M/{}", c),
+        sojourn: wait + 1.0 / mu,
+        qlen: lambda * wait,
+        loss_probability: 0.0,
+    })
+}
+
+// Mva is the solution of a closed network for a given number of circulating packets (customers),
+// with the throughput in packets/s, the response time (excluding think time) in seconds and the
+// average number of packets at each station.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mva {
+    pub population: u32,
+    pub throughput: f64,
+    pub response: f64,
+    pub qlen: Vec<f64>,
+}
+
+// mva solves a closed product-form network of single-server FCFS stations with the given service
+// demands (the total service time a packet requires at each station per visit to the think
+// station, in seconds) by exact Mean Value Analysis, returning the solution for every population
+// from 1 to N.
+pub fn mva(demands: &[f64], think: f64, population: u32) -> Vec<Mva> {
+    let mut qlen = vec![0.0; demands.len()];
+    let mut solutions = Vec::with_capacity(population as usize);
+    for n in 1..population + 1 {
+        // By the arrival theorem, an arriving packet sees the network as it is with one packet
+        // fewer in it.
+        let residence: Vec<f64> = demands.iter().zip(&qlen).map(|(&d, &q)| d * (1.0 + q)).collect();
+        let response: f64 = residence.iter().sum();
+        let throughput = f64::from(n) / (think + response);
+        qlen = residence.iter().map(|&r| throughput * r).collect();
+        solutions.push(Mva {
+            population: n,
+            throughput,
+            response,
+            qlen: qlen.clone(),
+        });
+    }
+    solutions
+}
+
+fn kendall(service: Service) -> &'static str {
+    match service {
+        Service::Exponential => "M",
+        Service::Deterministic => "D",
+    }
+}
+
+// modeled returns whether the given configuration runs as the models have it: Poisson arrivals at
+// the configured rate, with fixed parameters throughout the run, offered straight to the server and
+// served through. Packets retransmitted add to the load offered, a client blocked under
+// backpressure takes away from it, tenants' quotas, the rate limiter and limits by color drop
+// packets short of the server, and preempting packets holds up or drops those preempted.
+fn modeled(c: &Config) -> bool {
+    c.schedule.is_empty() && c.trace.is_none() && c.arrival == Distribution::Exponential &&
+        c.retransmission.is_none() && !c.backpressure && c.tenants.is_empty() &&
+        c.rate_limiting.is_none() && c.marking.is_none() && c.preemption.is_none()
+}
+
+// expected returns the expected metrics for the given configuration, provided it maps onto a
+// model with a closed-form solution: Poisson arrivals and fixed size or exponentially distributed
+// packets (i.e. deterministic or exponential service) with fixed parameters throughout the run
+// (see modeled). Given a buffer limit, the capacity of the system includes the packets in service.
+// With several servers, only exponential service is solved for.
+pub fn expected(c: &Config) -> Option<Expected> {
+    if !modeled(c) {
+        return None;
+    }
+    let service = match c.service {
+        Distribution::Exponential => Service::Exponential,
+        Distribution::Deterministic => Service::Deterministic,
+        _ => return None,
+    };
+    let lambda = f64::from(c.rate);
+    let mu = f64::from(c.pspeed) / f64::from(c.psize);
+    if c.servers > 1 {
+        if service != Service::Exponential {
+            return None;
+        }
+        return match c.qlimit {
+            Some(limit) => Some(mmck(lambda, mu, c.servers, limit + c.servers)),
+            None => mmc(lambda, mu, c.servers as u32),
+        };
+    }
+    match c.qlimit {
+        Some(limit) => Some(mg1k(lambda, mu, service, limit + 1)),
+        None => mg1(lambda, mu, service),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use analysis;
+    use simulation::{Marking, RateLimiting, Retransmission, Simulation, Tenant};
+    use simulators::{Discipline, Limiting, Preemption};
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        ((a - b) / b).abs() < tolerance
+    }
+
+    #[test]
+    fn mm1() {
+        let e = mg1(8.0, 10.0, Service::Exponential).unwrap();
+        assert!(close(e.sojourn, 0.5, 1e-9));
+        assert!(close(e.qlen, 3.2, 1e-9));
+        assert_eq!(mg1(10.0, 10.0, Service::Exponential), None);
+    }
+
+    #[test]
+    fn mm1k() {
+        // P(N = n) = (1 - ρ) ρ^n / (1 - ρ^(K+1)).
+        let (rho, k) = (0.8f64, 5);
+        let p = |n: i32| (1.0 - rho) * rho.powi(n) / (1.0 - rho.powi(k + 1));
+        let l: f64 = (0..k + 1).map(|n| f64::from(n) * p(n)).sum();
+        let e = mg1k(8.0, 10.0, Service::Exponential, k as usize);
+        assert!(close(e.loss_probability, p(k) * 100.0, 1e-9));
+        assert!(close(e.qlen, l - (1.0 - p(0)), 1e-9));
+        assert!(close(e.sojourn, l / (8.0 * (1.0 - p(k))), 1e-9));
+    }
+
+    #[test]
+    fn mm1_distributions() {
+        let p: Vec<f64> = (0..1000).map(|k| mm1_occupancy(0.8, k)).collect();
+        assert!(close(p.iter().sum(), 1.0, 1e-9));
+        let l: f64 = p.iter().enumerate().map(|(k, p)| k as f64 * p).sum();
+        assert!(close(l, 4.0, 1e-9));
+        // The median sojourn time is ln(2) / (mu - lambda).
+        assert!(close(mm1_sojourn_cdf(8.0, 10.0, 2f64.ln() / 2.0), 0.5, 1e-12));
+
+        // Truncated, the distribution is renormalized over 0..=K, uniform at ρ = 1.
+        let p = mm1_distribution(0.8, Some(5), 10);
+        assert!(close(p.iter().sum(), 1.0, 1e-9));
+        assert!(close(p[5] / p[4], 0.8, 1e-9));
+        assert_eq!(p[6], 0.0);
+        assert!(close(mm1k_occupancy(1.0, 3, 4), 0.2, 1e-12));
+        let c = Config {
+            rate: 800,
+            psize: 1,
+            pspeed: 1000,
+            service: Distribution::Exponential,
+            ..Config::default()
+        };
+        assert!(close(occupancy(&c, 3).unwrap()[2], mm1_occupancy(0.8, 2), 1e-9));
+        assert_eq!(occupancy(&Config { service: Distribution::Deterministic, ..c }, 3), None);
+    }
+
+    #[test]
+    fn erlang() {
+        assert!(close(erlang_b(1, 1.0), 0.5, 1e-12));
+        assert!(close(erlang_b(10, 5.0), 0.018_384, 1e-4));
+        assert!(close(erlang_c(10, 5.0).unwrap(), 0.036_105, 1e-4));
+        assert_eq!(erlang_c(2, 2.0), None);
+
+        // M/M/1 is M/M/c with a single server.
+        let (e, f) = (mmc(8.0, 10.0, 1).unwrap(), mg1(8.0, 10.0, Service::Exponential).unwrap());
+        assert!(close(e.sojourn, f.sojourn, 1e-9));
+        assert!(close(e.qlen, f.qlen, 1e-9));
+    }
+
+    #[test]
+    fn mmck_queues() {
+        // With a single server, it's the M/M/1/K queue; with a large enough buffer, the M/M/c one.
+        let (e, f) = (mmck(8.0, 10.0, 1, 5), mg1k(8.0, 10.0, Service::Exponential, 5));
+        assert!(close(e.sojourn, f.sojourn, 1e-9));
+        assert!(close(e.qlen, f.qlen, 1e-9));
+        assert!(close(e.loss_probability, f.loss_probability, 1e-9));
+        let (e, f) = (mmck(15.0, 10.0, 2, 500), mmc(15.0, 10.0, 2).unwrap());
+        assert!(close(e.sojourn, f.sojourn, 1e-9));
+        assert!(close(e.qlen, f.qlen, 1e-9));
+        assert!(e.loss_probability < 1e-9);
+        // A loss system blocks by Erlang's B formula.
+        assert!(close(mmck(50.0, 10.0, 10, 10).loss_probability, erlang_b(10, 5.0) * 100.0, 1e-9));
+
+        // The distribution is that of the M/M/1 queue with a single server, and otherwise puts
+        // as many packets in the system on average as are queued and in service.
+        let p = mmc_distribution(0.8, 1, None, 10);
+        let q = mm1_distribution(0.8, None, 10);
+        assert!(p.iter().zip(&q).all(|(p, q)| close(*p, *q, 1e-9)));
+        let p = mmc_distribution(1.5, 2, None, 1000);
+        assert!(close(p.iter().sum(), 1.0, 1e-9));
+        let l: f64 = p.iter().enumerate().map(|(k, p)| k as f64 * p).sum();
+        assert!(close(l, mmc(15.0, 10.0, 2).unwrap().qlen + 1.5, 1e-9));
+        assert_eq!(mmc_distribution(1.5, 2, Some(4), 6)[5], 0.0);
+    }
+
+    #[test]
+    fn mean_value_analysis() {
+        let s = mva(&[1.0, 2.0], 0.0, 2);
+        assert!(close(s[0].response, 3.0, 1e-12));
+        assert!(close(s[0].qlen[1], 2.0 / 3.0, 1e-12));
+        assert!(close(s[1].response, 14.0 / 3.0, 1e-12));
+        assert!(close(s[1].throughput, 3.0 / 7.0, 1e-12));
+
+        // The throughput approaches the bottleneck's service rate.
+        let s = mva(&[0.01, 0.02, 0.005], 1.0, 500);
+        assert!(close(s[499].throughput, 50.0, 1e-6));
+    }
+
+    #[test]
+    fn md1k_converges_to_md1() {
+        let e = mg1(8.0, 10.0, Service::Deterministic).unwrap();
+        let f = mg1k(8.0, 10.0, Service::Deterministic, 200);
+        assert!(close(f.sojourn, e.sojourn, 1e-6));
+        assert!(close(f.qlen, e.qlen, 1e-6));
+        assert!(f.loss_probability < 1e-6);
+    }
+
+    #[test]
+    fn simulated() {
+        // A service time of exactly eight ticks; per-tick progress of 0.1 bits would accumulate
+        // rounding errors and take eleven ticks instead of ten.
+        for &qlimit in &[None, Some(3)] {
+            let c = Config {
+                rate: 1000,
+                pspeed: 1250,
+                duration: 50,
+                resolution: 1e4,
+                qlimit,
+                seed: Some(1),
+                ..Config::default()
+            };
+            let e = expected(&c).unwrap();
+            let r = Simulation::new(c).run();
+            assert!(close(r.sojourn.mean(), e.sojourn, 0.1), "{:?}", e);
+            assert!(close(r.qlen.mean(), e.qlen, 0.1), "{:?}", e);
+            if qlimit.is_some() {
+                assert!(close(r.loss_probability(), e.loss_probability, 0.1), "{:?}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn simulated_mm1() {
+        // Exponentially distributed lengths of 1000 bits on average, processed at 12.5 bits a
+        // tick.
+        let c = Config {
+            rate: 1000,
+            psize: 1000,
+            pspeed: 1_250_000,
+            service: Distribution::Exponential,
+            duration: 20,
+            resolution: 1e5,
+            seed: Some(1),
+            ..Config::default()
+        };
+        let e = expected(&c).unwrap();
+        assert_eq!(e.model, "M/M/1");
+        let r = Simulation::new(c.clone()).run();
+        assert!(close(r.sojourn.mean(), e.sojourn, 0.1), "{:?} {}", e, r.sojourn.mean());
+        assert!(close(r.qlen.mean(), e.qlen, 0.15), "{:?} {}", e, r.qlen.mean());
+        assert!(r.service.stddev() > 0.5 * r.service.mean());
+        assert_eq!(expected(&Config { arrival: Distribution::Deterministic, ..c.clone() }), None);
+        // Nor is a run whose arrivals aren't offered to the server as they are.
+        let retransmission = Some(Retransmission { timeout: 0.001, retries: 3 });
+        assert_eq!(expected(&Config { retransmission, ..c.clone() }), None);
+        assert_eq!(expected(&Config { backpressure: true, ..c.clone() }), None);
+        let tenants = vec![Tenant { weight: 1.0, share: None, rate: Some(500.0) }];
+        assert_eq!(expected(&Config { tenants, ..c.clone() }), None);
+        let limiting = RateLimiting { algorithm: Limiting::TokenBucket, limit: 800.0, burst: 8 };
+        assert_eq!(expected(&Config { rate_limiting: Some(limiting), ..c.clone() }), None);
+        let marking = Marking {
+            committed: 600.0,
+            committed_burst: 4,
+            peak: 900.0,
+            peak_burst: 8,
+            yellow_limit: Some(4),
+            red_limit: Some(2),
+        };
+        assert_eq!(expected(&Config { marking: Some(marking), ..c.clone() }), None);
+        // Nor one whose packets are preempted.
+        let lifo = Config { discipline: Discipline::Lifo, ..c.clone() };
+        assert!(expected(&lifo).is_some());
+        assert_eq!(expected(&Config { preemption: Some(Preemption::Discard), ..lifo }), None);
+
+        // As is the number of packets in the system, geometrically distributed, truncated given a
+        // buffer limit.
+        for &qlimit in &[None, Some(4)] {
+            let c = Config { qlimit, ..c.clone() };
+            let r = Simulation::new(c.clone()).run();
+            let simulated = r.occupancy_distribution();
+            let expected = occupancy(&c, simulated.len()).unwrap();
+            assert!((simulated[0] - expected[0]).abs() < 0.03, "{:?}", simulated);
+            let distance = analysis::chi_square_distance(&simulated, &expected);
+            assert!(distance < 0.05, "{}", distance);
+        }
+    }
+
+    #[test]
+    fn simulated_mmc() {
+        // Two servers, each taking 1000 packets/s on average, at three quarters of their capacity.
+        let c = Config {
+            rate: 1500,
+            psize: 1000,
+            pspeed: 1_000_000,
+            servers: 2,
+            service: Distribution::Exponential,
+            duration: 20,
+            resolution: 1e5,
+            seed: Some(1),
+            ..Config::default()
+        };
+        assert!((c.offered_load() - 0.75).abs() < 1e-9);
+        assert_eq!(expected(&Config { service: Distribution::Deterministic, ..c.clone() }), None);
+        for &qlimit in &[None, Some(4)] {
+            let c = Config { qlimit, ..c.clone() };
+            let e = expected(&c).unwrap();
+            let r = Simulation::new(c.clone()).run();
+            assert!(close(r.sojourn.mean(), e.sojourn, 0.1), "{:?} {}", e, r.sojourn.mean());
+            assert!(close(r.qlen.mean(), e.qlen, 0.15), "{:?} {}", e, r.qlen.mean());
+            if qlimit.is_some() {
+                assert_eq!(e.model, "M/M/2/6");
+                let loss = r.loss_probability();
+                assert!(close(loss, e.loss_probability, 0.15), "{:?} {}", e, loss);
+            }
+            let simulated = r.occupancy_distribution();
+            let expected = occupancy(&c, simulated.len()).unwrap();
+            let distance = analysis::chi_square_distance(&simulated, &expected);
+            assert!(distance < 0.05, "{}", distance);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use simulation::Config;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use sweep;
+use toml::{self, Value};
+
+// Manifest lists the scenarios run as a batch, read from a TOML file. The top-level keys are
+// options shared by every scenario, and each table is a scenario of its own, named after the
+// table, the keys of which override the shared ones:
+//
+//   duration = 60
+//
+//   [baseline]
+//   rate = 9000
+//
+//   [overload]
+//   rate = 12000
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    // The scenarios in the order they're listed in, each with its options (shared ones included).
+    pub scenarios: Vec<(String, Vec<(String, Value)>)>,
+}
+
+impl Manifest {
+    pub fn parse(s: &str) -> Result<Manifest, String> {
+        let mut shared: Vec<(String, Value)> = vec![];
+        let mut scenarios: Vec<(String, Vec<(String, Value)>)> = vec![];
+        for (key, value) in toml::parse(s)? {
+            // Option names have no dots in them, so the scenario's name is everything before the
+            // last one.
+            let (name, option) = match key.rfind('.') {
+                Some(i) => (&key[..i], key[i + 1..].to_string()),
+                None => {
+                    shared.push((key, value));
+                    continue;
+                }
+            };
+            match scenarios.iter_mut().find(|s| s.0 == name) {
+                Some(s) => s.1.push((option, value)),
+                None => scenarios.push((name.to_string(), vec![(option, value)])),
+            }
+        }
+        if scenarios.is_empty() {
+            return Err("no scenarios, expected a [NAME] table for each".to_string());
+        }
+        for (_, options) in &mut scenarios {
+            for (key, value) in shared.iter().rev() {
+                if !options.iter().any(|o| &o.0 == key) {
+                    options.insert(0, (key.clone(), value.clone()));
+                }
+            }
+        }
+        Ok(Manifest { scenarios })
+    }
+}
+
+// run runs every configuration for the given number of replications (see sweep::replicate), as
+// many at a time as there are jobs, and returns the metrics (see sweep::metrics) observed of each
+// in the order given, replication by replication (see sweep::samples). Configurations that aren't
+// seeded are all run off of the given seed, under common random numbers.
+pub fn run(configs: &[Config], replications: u32, seed: u64, jobs: usize) -> Vec<Vec<Vec<f64>>> {
+    parallel(configs.len(), jobs, |i| {
+        let seed = configs[i].seed.unwrap_or(seed);
+        sweep::samples(&configs[i], replications, seed, sweep::observe)
+    })
+}
+
+// parallel calls f with every index up to n, on as many threads at a time as there are jobs, each
+// taking on the next index as soon as it's done with the last, and returns what it did in order.
+pub fn parallel<T: Send, F: Fn(usize) -> T + Sync>(n: usize, jobs: usize, f: F) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let outcomes: Mutex<Vec<Option<T>>> = Mutex::new((0..n).map(|_| None).collect());
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, n.max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= n {
+                    return;
+                }
+                let outcome = f(i);
+                outcomes.lock().unwrap()[i] = Some(outcome);
+            });
+        }
+    });
+    outcomes.into_inner().unwrap().into_iter().map(Option::unwrap).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest() {
+        let m = Manifest::parse(
+            "duration = 2\n\
+             seed = 1\n\
+             [baseline]\n\
+             rate = 900\n\
+             [\"fast.server\"]\n\
+             pspeed = 2000\n\
+             duration = 3\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = m.scenarios.iter().map(|s| s.0.as_str()).collect();
+        assert_eq!(names, vec!["baseline", "fast.server"]);
+        let int = |k: &str, v: i64| (k.to_string(), Value::Integer(v));
+        assert_eq!(m.scenarios[0].1, vec![int("duration", 2), int("seed", 1), int("rate", 900)]);
+        assert_eq!(m.scenarios[1].1, vec![int("seed", 1), int("pspeed", 2000), int("duration", 3)]);
+        assert!(Manifest::parse("rate = 1\n").unwrap_err().starts_with("no scenarios"));
+    }
+
+    #[test]
+    fn parallel() {
+        let config = |rate: u32| Config {
+            rate,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            ..Config::default()
+        };
+        let configs = vec![config(300), config(600), config(900)];
+        let parallel = run(&configs, 2, 1, 4);
+        let serial = run(&configs, 2, 1, 1);
+        assert_eq!(parallel.len(), 3);
+        assert_eq!(parallel, serial);
+        assert!(parallel.iter().all(|c| c.iter().all(|m| m.len() == 2)));
+        // In the order given: the higher the load, the longer the sojourn time.
+        let sojourn = |i: usize| parallel[i][0].iter().sum::<f64>();
+        assert!(sojourn(0) < sojourn(1));
+        assert!(sojourn(1) < sojourn(2));
+    }
+}
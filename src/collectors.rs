@@ -0,0 +1,148 @@
+use simulation::{Event, Sample};
+use simulators::Packet;
+use std::cell::RefCell;
+use std::rc::Rc;
+use time::Ticks;
+
+// StatsCollector is a user-defined set of statistics, collected alongside the built-in ones by
+// hooking into the events of a simulation (see Simulation.add_collector). All times are in ticks.
+// Every hook defaults to doing nothing, so collectors need only implement the ones they care
+// about. Samples are only taken if the configuration asks for them (see Config.sample_interval).
+pub trait StatsCollector {
+    fn on_arrival(&mut self, _time: Ticks, _packet: &Packet) {}
+    fn on_enqueue(&mut self, _time: Ticks, _packet: &Packet) {}
+    fn on_drop(&mut self, _time: Ticks, _packet: &Packet) {}
+    fn on_service_start(&mut self, _time: Ticks, _packet: &Packet) {}
+    fn on_depart(&mut self, _time: Ticks, _packet: &Packet) {}
+    fn on_sample(&mut self, _sample: &Sample) {}
+
+    // StatsCollector.metrics returns the named values to report alongside the built-in statistics
+    // (see Results.collected).
+    fn metrics(&self) -> Vec<(String, f64)> {
+        vec![]
+    }
+
+    // StatsCollector.on_event dispatches the event to the appropriate hook.
+    fn on_event(&mut self, event: &Event) {
+        match *event {
+            Event::Arrival { time, ref packet } => self.on_arrival(time, packet),
+            Event::Enqueue { time, ref packet } => self.on_enqueue(time, packet),
+            Event::Drop { time, ref packet, .. } => self.on_drop(time, packet),
+            Event::ServiceStart { time, ref packet } => self.on_service_start(time, packet),
+            Event::Departure { time, ref packet } => self.on_depart(time, packet),
+        }
+    }
+}
+
+// A shared collector can be registered with a simulation while a handle to it is kept around, so
+// as to inspect it once the simulation is done with.
+impl<C: StatsCollector> StatsCollector for Rc<RefCell<C>> {
+    fn on_event(&mut self, event: &Event) {
+        self.borrow_mut().on_event(event)
+    }
+
+    fn on_sample(&mut self, sample: &Sample) {
+        self.borrow_mut().on_sample(sample)
+    }
+
+    fn metrics(&self) -> Vec<(String, f64)> {
+        self.borrow().metrics()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::{Config, Retransmission, Simulation};
+
+    // Counts packets by length, and the samples at which the server was busy.
+    #[derive(Default)]
+    struct Counter {
+        arrivals: u32,
+        enqueues: u32,
+        drops: u32,
+        starts: u32,
+        departures: u32,
+        busy_samples: u32,
+    }
+
+    impl StatsCollector for Counter {
+        fn on_arrival(&mut self, _: Ticks, _: &Packet) {
+            self.arrivals += 1;
+        }
+
+        fn on_enqueue(&mut self, _: Ticks, _: &Packet) {
+            self.enqueues += 1;
+        }
+
+        fn on_drop(&mut self, _: Ticks, _: &Packet) {
+            self.drops += 1;
+        }
+
+        fn on_service_start(&mut self, _: Ticks, _: &Packet) {
+            self.starts += 1;
+        }
+
+        fn on_depart(&mut self, _: Ticks, _: &Packet) {
+            self.departures += 1;
+        }
+
+        fn on_sample(&mut self, sample: &Sample) {
+            self.busy_samples += u32::from(sample.busy);
+        }
+
+        fn metrics(&self) -> Vec<(String, f64)> {
+            vec![("departures".to_string(), f64::from(self.departures))]
+        }
+    }
+
+    #[test]
+    fn collectors() {
+        let mut sim = Simulation::new(Config {
+            rate: 1100,
+            pspeed: 1000,
+            duration: 1,
+            qlimit: Some(5),
+            resolution: 1e4,
+            seed: Some(1),
+            sample_interval: Some(100),
+            ..Config::default()
+        });
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        sim.add_collector(Box::new(counter.clone()));
+        sim.add_collector(Box::new(Counter::default()));
+        let r = sim.run();
+
+        let c = counter.borrow();
+        assert_eq!(c.arrivals, r.packets_generated);
+        assert_eq!(c.enqueues + c.drops, c.arrivals);
+        assert_eq!(c.drops, r.packets_dropped);
+        assert_eq!(c.departures, r.packets_processed);
+        assert!(c.starts >= c.departures && c.starts <= c.departures + 1);
+        let busy = r.samples.iter().filter(|s| s.busy).count();
+        assert_eq!(c.busy_samples as usize, busy);
+        let departures = ("departures".to_string(), f64::from(r.packets_processed));
+        assert_eq!(r.collected, vec![departures.clone(), departures]);
+
+        // Packets retransmitted are only dropped once given up on.
+        let mut sim = Simulation::new(Config {
+            rate: 1200,
+            pspeed: 1000,
+            duration: 1,
+            qlimit: Some(5),
+            resolution: 1e4,
+            retransmission: Some(Retransmission { timeout: 0.001, retries: 3 }),
+            seed: Some(1),
+            ..Config::default()
+        });
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        sim.add_collector(Box::new(counter.clone()));
+        let r = sim.run();
+        let c = counter.borrow();
+        assert!(r.retransmissions.unwrap().retransmitted > r.packets_dropped);
+        assert_eq!(c.arrivals, r.packets_generated);
+        assert_eq!(c.drops, r.packets_dropped);
+        assert_eq!(c.departures, r.packets_processed);
+    }
+}
@@ -0,0 +1,81 @@
+use simulation::Results;
+
+// CostModel prices the outcome of a run, so that capacity trade-offs (e.g. a faster, pricier
+// server against more drops) can be expressed as a single objective. Amounts are in arbitrary,
+// but consistent, units of currency.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CostModel {
+    // The value of each packet delivered.
+    pub packet_value: f64,
+    // The penalty incurred for each packet dropped.
+    pub drop_penalty: f64,
+    // The cost of each second the server spends busy, and of each second it's provisioned for
+    // (busy or not).
+    pub busy_cost: f64,
+    pub server_cost: f64,
+}
+
+// Costs is the outcome of a run, priced as per a cost model.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Costs {
+    pub revenue: f64,
+    pub penalties: f64,
+    pub busy_cost: f64,
+    pub server_cost: f64,
+}
+
+impl Costs {
+    // Costs.net returns the revenue less the penalties and costs.
+    pub fn net(&self) -> f64 {
+        self.revenue - self.penalties - self.busy_cost - self.server_cost
+    }
+}
+
+impl CostModel {
+    // CostModel.evaluate prices the given run, which lasted the given number of seconds.
+    pub fn evaluate(&self, results: &Results, seconds: f64) -> Costs {
+        Costs {
+            revenue: self.packet_value * f64::from(results.packets_processed),
+            penalties: self.drop_penalty * f64::from(results.packets_dropped),
+            busy_cost: self.busy_cost * results.utilization * seconds,
+            server_cost: self.server_cost * seconds,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::{Config, Simulation};
+
+    #[test]
+    fn costs() {
+        let r = Simulation::new(Config {
+            rate: 1100,
+            psize: 1,
+            pspeed: 1000,
+            duration: 2,
+            qlimit: Some(5),
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        })
+        .run();
+        let model = CostModel {
+            packet_value: 1.0,
+            drop_penalty: 5.0,
+            busy_cost: 100.0,
+            server_cost: 10.0,
+        };
+        let c = model.evaluate(&r, 2.0);
+        assert_eq!(c.revenue, f64::from(r.packets_processed));
+        assert_eq!(c.penalties, 5.0 * f64::from(r.packets_dropped));
+        assert!(r.packets_dropped > 0);
+        assert!((c.busy_cost - 200.0 * r.utilization).abs() < 1e-9);
+        assert_eq!(c.server_cost, 20.0);
+        let net = c.revenue - c.penalties - c.busy_cost - c.server_cost;
+        assert!((c.net() - net).abs() < 1e-9);
+        assert_eq!(CostModel::default().evaluate(&r, 2.0).net(), 0.0);
+    }
+}
@@ -0,0 +1,141 @@
+use simulation::Simulation;
+use std::fmt::Write;
+use time::Ticks;
+
+// The width of the bars, in characters.
+const BAR_WIDTH: usize = 40;
+
+// Clears the terminal and moves the cursor to the top left.
+const CLEAR: &str = "\x1b[2J\x1b[H";
+
+// Dashboard renders a live view of a running simulation for the terminal: its progress, queue
+// occupancy, utilization, arrival and departure rates and latency percentiles. Rates are measured
+// over the simulated time since the previous frame.
+pub struct Dashboard {
+    // The tick of, and the packets generated and processed as of, the previous frame.
+    last: Option<(Ticks, u32, u32)>,
+    max_qlen: usize,
+}
+
+impl Dashboard {
+    pub fn new() -> Dashboard {
+        Dashboard {
+            last: None,
+            max_qlen: 0,
+        }
+    }
+
+    // Dashboard.render returns the next frame, prefixed with the escape codes to clear the screen.
+    pub fn render(&mut self, sim: &Simulation) -> String {
+        let r = sim.results();
+        let c = sim.config();
+        let (now, generated, processed) = (sim.now(), r.packets_generated, r.packets_processed);
+        let (arrival_rate, departure_rate) = match self.last {
+            Some((then, g, p)) if now > then => {
+                let secs = now.since(then).seconds(c.resolution);
+                (f64::from(generated - g) / secs, f64::from(processed - p) / secs)
+            }
+            _ => (0.0, 0.0),
+        };
+        self.last = Some((now, generated, processed));
+        let qlen = sim.server().qlen();
+        self.max_qlen = self.max_qlen.max(qlen);
+        // Without a buffer limit, occupancy is shown relative to the longest queue seen.
+        let capacity = c.qlimit.unwrap_or(self.max_qlen).max(1);
+
+        let mut f = String::from(CLEAR);
+        let elapsed = now.seconds(c.resolution);
+        writeln!(f, "qsim -- rate {} packets/s, pspeed {} bits/s", c.rate, c.pspeed).unwrap();
+        writeln!(f).unwrap();
+        let progress = match c.packets {
+            Some(n) => format!("{:.2} seconds, {}/{} packets", elapsed, processed, n),
+            None => format!("{:.2}/{} seconds", elapsed, c.duration),
+        };
+        writeln!(f, " {:<18}{} {}", "Progress", bar(sim.progress(), BAR_WIDTH), progress).unwrap();
+        writeln!(
+            f,
+            " {:<18}{} {} packets",
+            "Queue occupancy",
+            bar(qlen as f64 / capacity as f64, BAR_WIDTH),
+            qlen
+        )
+        .unwrap();
+        writeln!(
+            f,
+            " {:<18}{} {:.1}%",
+            "Utilization",
+            bar(r.utilization, BAR_WIDTH),
+            r.utilization * 100.0
+        )
+        .unwrap();
+        writeln!(f).unwrap();
+        writeln!(f, " {:<18}{:.0} packets/s", "Arrival rate", arrival_rate).unwrap();
+        writeln!(f, " {:<18}{:.0} packets/s", "Departure rate", departure_rate).unwrap();
+        writeln!(
+            f,
+            " {:<18}{} ({:.2}%)",
+            "Drops",
+            r.packets_dropped,
+            if generated > 0 { r.loss_probability() } else { 0.0 }
+        )
+        .unwrap();
+        writeln!(f).unwrap();
+        for &(p, value) in &r.sojourn_percentiles {
+            writeln!(f, " {:<18}{:.6} seconds", format!("p{} sojourn", p), value).unwrap();
+        }
+        f
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Dashboard {
+        Dashboard::new()
+    }
+}
+
+// bar draws a horizontal bar of the given width, filled to the given fraction.
+pub fn bar(fraction: f64, width: usize) -> String {
+    let filled = (fraction.clamp(0.0, 1.0) * width as f64).round() as usize;
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Config;
+
+    #[test]
+    fn frames() {
+        let mut sim = Simulation::new(Config {
+            rate: 900,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            qlimit: Some(10),
+            seed: Some(1),
+            ..Config::default()
+        });
+        let mut dashboard = Dashboard::new();
+        let first = dashboard.render(&sim);
+        assert!(first.starts_with(CLEAR));
+        assert!(first.contains(" Arrival rate      0 packets/s\n"));
+
+        sim.run_until(|s| s.now() >= Ticks(5000));
+        let frame = dashboard.render(&sim);
+        assert!(frame.contains(&format!("{} 0.50/1 seconds", bar(0.5, BAR_WIDTH))));
+        let rate = |label: &str| -> f64 {
+            let line = frame.lines().find(|l| l.trim_start().starts_with(label)).unwrap();
+            line[19..].split_whitespace().next().unwrap().parse().unwrap()
+        };
+        assert!((rate("Arrival rate") - 900.0).abs() < 100.0);
+        assert!((rate("Departure rate") - 900.0).abs() < 100.0);
+    }
+
+    #[test]
+    fn bars() {
+        assert_eq!(bar(0.0, 10).len(), 12);
+        assert_eq!(bar(0.5, 10).matches('#').count(), 5);
+        assert_eq!(bar(2.0, 10).matches('#').count(), 10);
+    }
+}
@@ -0,0 +1,351 @@
+use simulation::Simulation;
+use std::fmt::Write;
+
+// Metric is a quantity of the simulation state that breakpoints can be set on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    QueueLength,
+    Time,
+    Dropped,
+    Processed,
+}
+
+impl Metric {
+    fn parse(s: &str) -> Option<Metric> {
+        match s {
+            "qlen" => Some(Metric::QueueLength),
+            "time" => Some(Metric::Time),
+            "dropped" => Some(Metric::Dropped),
+            "processed" => Some(Metric::Processed),
+            _ => None,
+        }
+    }
+
+    // Metric.value returns the current value of the metric; time is in seconds.
+    fn value(&self, sim: &Simulation) -> f64 {
+        match *self {
+            Metric::QueueLength => sim.server().qlen() as f64,
+            Metric::Time => sim.now().seconds(sim.config().resolution),
+            Metric::Dropped => f64::from(sim.server().packets_dropped()),
+            Metric::Processed => f64::from(sim.server().packets_processed()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+}
+
+impl Op {
+    fn parse(s: &str) -> Option<Op> {
+        match s {
+            "<" => Some(Op::Lt),
+            "<=" => Some(Op::Le),
+            ">" => Some(Op::Gt),
+            ">=" => Some(Op::Ge),
+            "==" => Some(Op::Eq),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, a: f64, b: f64) -> bool {
+        match *self {
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+            Op::Eq => (a - b).abs() < f64::EPSILON,
+        }
+    }
+}
+
+// Breakpoint is a condition on the simulation state, such as "qlen > 100", that halts the
+// simulation when it holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Breakpoint {
+    pub metric: Metric,
+    pub op: Op,
+    pub value: f64,
+}
+
+impl Breakpoint {
+    // Breakpoint::parse parses conditions of the form "<metric> <op> <value>", where the metric is
+    // one of qlen, time (seconds), dropped or processed.
+    pub fn parse(s: &str) -> Option<Breakpoint> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        if tokens.len() != 3 {
+            return None;
+        }
+        Some(Breakpoint {
+            metric: Metric::parse(tokens[0])?,
+            op: Op::parse(tokens[1])?,
+            value: tokens[2].parse().ok()?,
+        })
+    }
+
+    pub fn hit(&self, sim: &Simulation) -> bool {
+        self.op.apply(self.metric.value(sim), self.value)
+    }
+}
+
+pub const HELP: &str = "\
+step [N]             advance the simulation by N ticks (def: 1)
+continue             run until a breakpoint is hit or the simulation completes
+break METRIC OP NUM  add a breakpoint, e.g. 'break qlen > 100' (metrics: qlen, time, dropped,
+                     processed; ops: <, <=, >, >=, ==)
+breakpoints          list breakpoints
+delete [N]           delete breakpoint N, or all of them
+queue [N]            show the first N queued packets (def: 10)
+stats                show the statistics collected thus far
+set PARAM VALUE      change rate, pspeed or qlimit ('none' for an infinite buffer)
+help                 show this message
+quit                 exit";
+
+// Debugger drives a simulation step by step, interpreting one command at a time and returning
+// its output. It holds no I/O of its own so that it can be embedded in any front-end.
+pub struct Debugger {
+    sim: Simulation,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Debugger {
+    pub fn new(sim: Simulation) -> Debugger {
+        Debugger {
+            sim,
+            breakpoints: vec![],
+        }
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.sim
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    // Debugger.step advances the simulation by up to n ticks, stopping early at a breakpoint. It
+    // returns the index of the breakpoint hit, if any.
+    pub fn step(&mut self, n: u32) -> Option<usize> {
+        for _ in 0..n {
+            if !self.sim.tick() {
+                break;
+            }
+            if let Some(i) = self.hit() {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    // Debugger.resume runs the simulation until a breakpoint is hit or it completes.
+    pub fn resume(&mut self) -> Option<usize> {
+        let breakpoints = &self.breakpoints;
+        if self.sim.run_until(|sim| breakpoints.iter().any(|b| b.hit(sim))) {
+            self.hit()
+        } else {
+            None
+        }
+    }
+
+    fn hit(&self) -> Option<usize> {
+        self.breakpoints.iter().position(|b| b.hit(&self.sim))
+    }
+
+    // Debugger.execute interprets a single command (see HELP), returning its output.
+    pub fn execute(&mut self, line: &str) -> String {
+        let mut tokens = line.split_whitespace();
+        let command = match tokens.next() {
+            Some(c) => c,
+            None => return String::new(),
+        };
+        let args: Vec<&str> = tokens.collect();
+        match command {
+            "step" | "s" => match args.first().map_or(Ok(1), |n| n.parse::<u32>()) {
+                Ok(n) => {
+                    let hit = self.step(n);
+                    self.position(hit)
+                }
+                Err(_) => format!("invalid number of ticks: {}", args[0]),
+            },
+            "continue" | "c" => {
+                let hit = self.resume();
+                self.position(hit)
+            }
+            "break" | "b" => match Breakpoint::parse(&args.join(" ")) {
+                Some(b) => {
+                    self.breakpoints.push(b);
+                    format!("breakpoint {} set", self.breakpoints.len() - 1)
+                }
+                None => format!("invalid breakpoint: {}", args.join(" ")),
+            },
+            "breakpoints" => {
+                let mut out = String::new();
+                for (i, b) in self.breakpoints.iter().enumerate() {
+                    let _ = writeln!(out, "{}: {:?} {:?} {}", i, b.metric, b.op, b.value);
+                }
+                out.trim_end().to_string()
+            }
+            "delete" => match args.first().map(|n| n.parse::<usize>()) {
+                None => {
+                    self.breakpoints.clear();
+                    "deleted all breakpoints".to_string()
+                }
+                Some(Ok(i)) if i < self.breakpoints.len() => {
+                    self.breakpoints.remove(i);
+                    format!("deleted breakpoint {}", i)
+                }
+                Some(_) => format!("no such breakpoint: {}", args[0]),
+            },
+            "queue" | "q" => {
+                let n = args.first().and_then(|n| n.parse().ok()).unwrap_or(10);
+                self.queue(n)
+            }
+            "stats" => {
+                let r = self.sim.results();
+                format!(
+                    "sojourn: {:.6} +/- {:.6}s, qlen: {:.2} +/- {:.2}, generated: {}, \
+                     processed: {}, dropped: {}, idle: {:.2}%",
+                    r.sojourn.mean(),
+                    r.sojourn.stddev(),
+                    r.qlen.mean(),
+                    r.qlen.stddev(),
+                    r.packets_generated,
+                    r.packets_processed,
+                    r.packets_dropped,
+                    r.idle_proportion
+                )
+            }
+            "set" if args.len() == 2 => self.set(args[0], args[1]),
+            "help" | "h" => HELP.to_string(),
+            _ => format!("unknown command: {} (try 'help')", line.trim()),
+        }
+    }
+
+    fn set(&mut self, param: &str, value: &str) -> String {
+        let set = match (param, value.parse::<u32>()) {
+            ("rate", Ok(v)) => self.sim.set_rate(v),
+            ("pspeed", Ok(v)) => self.sim.set_pspeed(v),
+            ("qlimit", Ok(v)) => {
+                self.sim.set_qlimit(Some(v as usize));
+                Ok(())
+            }
+            ("qlimit", Err(_)) if value == "none" => {
+                self.sim.set_qlimit(None);
+                Ok(())
+            }
+            _ => return format!("invalid parameter: {} {}", param, value),
+        };
+        match set {
+            Ok(()) => format!("{} set to {}", param, value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    fn position(&self, hit: Option<usize>) -> String {
+        let mut out = String::new();
+        if let Some(i) = hit {
+            let _ = write!(out, "breakpoint {} hit, ", i);
+        } else if self.sim.done() {
+            out.push_str("simulation complete, ");
+        }
+        let _ = write!(
+            out,
+            "t = {} ({:.6}s), qlen = {}",
+            self.sim.now(),
+            self.sim.now().seconds(self.sim.config().resolution),
+            self.sim.server().qlen()
+        );
+        out
+    }
+
+    fn queue(&self, n: usize) -> String {
+        let server = self.sim.server();
+        let mut out = String::new();
+        for p in server.in_service() {
+            let _ = writeln!(
+                out,
+                "in service: generated at {}, {} bits",
+                p.time_generated,
+                p.length
+            );
+        }
+        if server.in_service().next().is_none() {
+            out.push_str("in service: none\n");
+        }
+        let _ = write!(out, "queued: {}", server.qlen());
+        for (i, p) in server.queue().take(n).enumerate() {
+            let _ = write!(out, "\n  {}: generated at {}, {} bits", i, p.time_generated, p.length);
+        }
+        out
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Config;
+
+    fn debugger() -> Debugger {
+        Debugger::new(Simulation::new(Config {
+            rate: 2000,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        }))
+    }
+
+    #[test]
+    fn parse_breakpoints() {
+        assert_eq!(
+            Breakpoint::parse("qlen > 100"),
+            Some(Breakpoint {
+                metric: Metric::QueueLength,
+                op: Op::Gt,
+                value: 100.0,
+            })
+        );
+        assert_eq!(Breakpoint::parse("qlen >"), None);
+        assert_eq!(Breakpoint::parse("latency > 1"), None);
+        assert_eq!(Breakpoint::parse("qlen ~ 1"), None);
+    }
+
+    #[test]
+    fn stepping() {
+        let mut d = debugger();
+        assert!(d.execute("step 10").starts_with("t = 10 "));
+        assert!(d.execute("step").starts_with("t = 11 "));
+        assert!(d.execute("step ten").starts_with("invalid"));
+    }
+
+    #[test]
+    fn breakpoint_hit() {
+        // Arrivals at twice the service rate, the queue builds up steadily.
+        let mut d = debugger();
+        assert_eq!(d.execute("break qlen >= 20"), "breakpoint 0 set");
+        assert!(d.execute("continue").starts_with("breakpoint 0 hit"));
+        assert_eq!(d.simulation().server().qlen(), 20);
+        assert!(d.execute("queue 3").contains("queued: 20"));
+
+        assert_eq!(d.execute("delete"), "deleted all breakpoints");
+        assert!(d.execute("continue").starts_with("simulation complete"));
+    }
+
+    #[test]
+    fn set_parameters() {
+        let mut d = debugger();
+        assert_eq!(d.execute("set qlimit 5"), "qlimit set to 5");
+        assert_eq!(d.execute("set pspeed 100000"), "pspeed set to 100000");
+        assert!(d.execute("set speed 1").starts_with("invalid"));
+        assert_eq!(d.simulation().config().qlimit, Some(5));
+        assert_eq!(d.simulation().config().pspeed, 100_000);
+    }
+}
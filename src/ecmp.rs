@@ -0,0 +1,173 @@
+use batch;
+use error::QsimError;
+use generators::Distribution;
+use rng;
+use simulation::{Config, Results, Simulation};
+
+// Routing is how packets are spread across equal-cost paths, each a server as configured: by a
+// hash of their flow id, as ECMP next hops are picked. The imbalance is the share of the hash
+// space polarized onto the first path, as when the hop upstream hashes the same fields the same
+// way and only hands this one flows hashing alike; the rest is split evenly across all paths.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Routing {
+    pub paths: u32,
+    pub imbalance: f64,
+}
+
+impl Routing {
+    // Routing.path returns the path the given flow is routed over.
+    pub fn path(&self, flow: u32) -> u32 {
+        let u = (rng::derive(u64::from(flow), 0) >> 11) as f64 / (1u64 << 53) as f64;
+        if u < self.imbalance {
+            return 0;
+        }
+        let path = (u - self.imbalance) / (1.0 - self.imbalance) * f64::from(self.paths);
+        (path as u32).min(self.paths - 1)
+    }
+}
+
+// Route is how a single path fared: the flows hashed onto it, the share of the traffic they make
+// up, and the results of the run over it, if any flow was.
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub flows: u32,
+    pub share: f64,
+    pub results: Option<Results>,
+}
+
+// Ecmp is what routing a run's traffic across equal-cost paths yields: how each path fared, and
+// how one would've had the traffic been split evenly across them instead.
+#[derive(Clone, Debug)]
+pub struct Ecmp {
+    pub routes: Vec<Route>,
+    pub even: Results,
+}
+
+impl Ecmp {
+    // Ecmp.sojourn returns the average sojourn time across all packets processed, over whichever
+    // path they took, in seconds.
+    pub fn sojourn(&self) -> f64 {
+        let (mut total, mut packets) = (0.0, 0.0);
+        for r in self.routes.iter().filter_map(|r| r.results.as_ref()) {
+            total += r.sojourn.mean() * f64::from(r.packets_processed);
+            packets += f64::from(r.packets_processed);
+        }
+        total / packets
+    }
+
+    // Ecmp.penalty returns the relative increase in the average sojourn time over that of an even
+    // split, i.e. what the hash imbalance (and the flows' granularity) costs in latency.
+    pub fn penalty(&self) -> f64 {
+        self.sojourn() / self.even.sojourn.mean() - 1.0
+    }
+}
+
+// run routes the traffic of the configured run across the paths, each flow over the one its id
+// hashes to. Splitting Poisson arrivals by flow yields independent Poisson processes, so each path
+// is run on its own at the rate of the flows hashed onto it (rounded to whole packets/s), seeded
+// apart from the others; the even split is run at an equal share of the rate. Being independent,
+// the paths are run on threads of their own, as many at a time as there are jobs, the even split
+// alongside them.
+pub fn run(config: &Config, routing: Routing, jobs: usize) -> Result<Ecmp, QsimError> {
+    config.validate()?;
+    if routing.paths == 0 {
+        return Err(QsimError::invalid("paths", 0, "traffic needs at least a path to take"));
+    }
+    if !(routing.imbalance >= 0.0 && routing.imbalance < 1.0) {
+        let reason = "the share of the hash space polarized has to be below 1";
+        return Err(QsimError::invalid("hash imbalance", routing.imbalance, reason));
+    }
+    if config.arrival != Distribution::Exponential || config.trace.is_some() {
+        let reason = "traffic can only be split across paths by flow if arrivals are Poisson";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+
+    let mut flows = vec![0; routing.paths as usize];
+    for flow in 0..config.flows.max(1) {
+        flows[routing.path(flow) as usize] += 1;
+    }
+    let run = |share: f64, path: u64| {
+        let rate = (f64::from(config.rate) * share).round() as u32;
+        let seed = config.seed.map(|s| rng::derive(s, path));
+        if rate == 0 {
+            return None;
+        }
+        Some(Simulation::new(Config { rate, seed, ..config.clone() }).run())
+    };
+    let shares: Vec<f64> = flows
+        .iter()
+        .map(|&f| f64::from(f) / f64::from(config.flows.max(1)))
+        .chain(Some(1.0 / f64::from(routing.paths)))
+        .collect();
+    let mut outcomes = batch::parallel(shares.len(), jobs, |i| {
+        // The even split is seeded as the first path is.
+        let path = if i < flows.len() { i as u64 } else { 0 };
+        run(shares[i], path)
+    });
+    let even = outcomes.pop().expect("no even split");
+    let routes = flows
+        .iter()
+        .zip(&shares)
+        .zip(outcomes)
+        .map(|((&flows, &share), results)| Route { flows, share, results })
+        .collect();
+    let even = even.ok_or_else(|| {
+        QsimError::Config("the rate is too low to split across as many paths".to_string())
+    })?;
+    Ok(Ecmp { routes, even })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashing() {
+        // Flows hash evenly across paths, bar those polarized onto the first.
+        let even = Routing { paths: 4, imbalance: 0.0 };
+        let skewed = Routing { paths: 4, imbalance: 0.5 };
+        let (mut a, mut b) = ([0; 4], [0; 4]);
+        for flow in 0..10_000 {
+            assert_eq!(even.path(flow), even.path(flow));
+            a[even.path(flow) as usize] += 1;
+            b[skewed.path(flow) as usize] += 1;
+        }
+        assert!(a.iter().all(|&n| (f64::from(n) / 10_000.0 - 0.25).abs() < 0.02));
+        assert!((f64::from(b[0]) / 10_000.0 - 0.625).abs() < 0.02);
+        assert!(b[1..].iter().all(|&n| (f64::from(n) / 10_000.0 - 0.125).abs() < 0.02));
+    }
+
+    #[test]
+    fn polarization() {
+        let config = Config {
+            rate: 12_000,
+            flows: 64,
+            duration: 4,
+            resolution: 1e5,
+            seed: Some(1),
+            ..Config::default()
+        };
+        let even = run(&config, Routing { paths: 2, imbalance: 0.0 }, 2).unwrap();
+        let skewed = run(&config, Routing { paths: 2, imbalance: 0.4 }, 2).unwrap();
+        // Run on a single thread, each path fares the same.
+        let serial = run(&config, Routing { paths: 2, imbalance: 0.4 }, 1).unwrap();
+        let sojourns = |e: &Ecmp| {
+            e.routes.iter().map(|r| r.results.as_ref().unwrap().sojourn.mean()).collect::<Vec<_>>()
+        };
+        assert_eq!(sojourns(&serial), sojourns(&skewed));
+        assert_eq!(serial.even.sojourn.mean(), skewed.even.sojourn.mean());
+        assert_eq!(even.routes.iter().map(|r| r.flows).sum::<u32>(), 64);
+        // The busier path runs hotter, and the latency penalty outgrows that of the flows merely
+        // not hashing evenly.
+        let (hot, cold) = (&skewed.routes[0], &skewed.routes[1]);
+        assert!(hot.share > 0.6, "{}", hot.share);
+        let utilization = |r: &Route| r.results.as_ref().unwrap().utilization;
+        assert!(utilization(hot) > utilization(cold));
+        assert!(skewed.penalty() > even.penalty().max(0.1), "{}", skewed.penalty());
+
+        let routing = Routing { paths: 2, imbalance: 1.0 };
+        assert!(run(&config, routing, 2).is_err());
+        let config = Config { arrival: Distribution::Deterministic, ..config };
+        assert!(run(&config, Routing { paths: 2, imbalance: 0.0 }, 2).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// QsimError is what goes wrong setting up a run, described well enough to be reported as is.
+#[derive(Debug)]
+pub enum QsimError {
+    // An option given a value that doesn't parse, e.g. --rate fast.
+    Parse { option: String, value: String },
+    // A parameter outside of its valid range, e.g. a rate or speed of zero, along with why.
+    InvalidParameter {
+        parameter: String,
+        value: String,
+        reason: String,
+    },
+    // A configuration that's malformed as a whole, e.g. a file with an unknown option in it.
+    Config(String),
+    Io(io::Error),
+}
+
+impl QsimError {
+    pub fn parse<T: fmt::Display>(option: &str, value: T) -> QsimError {
+        QsimError::Parse {
+            option: option.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    pub fn invalid<T: fmt::Display>(parameter: &str, value: T, reason: &str) -> QsimError {
+        QsimError::InvalidParameter {
+            parameter: parameter.to_string(),
+            value: value.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for QsimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QsimError::Parse { ref option, ref value } => {
+                write!(f, "invalid value {:?} for --{}", value, option)
+            }
+            QsimError::InvalidParameter {
+                ref parameter,
+                ref value,
+                ref reason,
+            } => write!(f, "invalid {} {}: {}", parameter, value, reason),
+            QsimError::Config(ref msg) => f.write_str(msg),
+            QsimError::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for QsimError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            QsimError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for QsimError {
+    fn from(e: io::Error) -> QsimError {
+        QsimError::Io(e)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let e = QsimError::parse("rate", "fast");
+        assert_eq!(e.to_string(), "invalid value \"fast\" for --rate");
+        let e = QsimError::invalid("pspeed", 0, "it must be positive");
+        assert_eq!(e.to_string(), "invalid pspeed 0: it must be positive");
+        let e = QsimError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(e.to_string(), "no such file");
+        assert!(e.source().is_some());
+    }
+}
@@ -0,0 +1,205 @@
+// The C interface to the simulator, for embedding it in larger simulators written in C or C++, as
+// declared in include/qsim.h. Simulations are handed out as opaque pointers, configured with and
+// reporting on themselves in JSON (see json::configure and json::document). Strings returned are
+// the caller's, to be freed with qsim_free_string, and every pointer passed in has to be one
+// handed out here (or a NUL-terminated string, for the configuration) that's yet to be freed,
+// which is why the functions are all unsafe. Panics aren't let unwind into the caller, they're
+// reported as errors instead (see guarded).
+#![allow(clippy::missing_safety_doc)]
+
+use json;
+use simulation::Simulation;
+use std::any::Any;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use time::Ticks;
+
+// c_string hands the string over to the caller, dropping any NULs that'd cut it short.
+fn c_string(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', "")).unwrap().into_raw()
+}
+
+// fail writes out the error to *error, unless error is null.
+unsafe fn fail(error: *mut *mut c_char, e: String) {
+    if !error.is_null() {
+        *error = c_string(e);
+    }
+}
+
+// guarded returns what f does, or the given sentinel should it panic, writing out the panic's
+// message to *error unless error is null. Unwinding across the C boundary is undefined behavior,
+// so whatever the functions below do that might panic is run through here.
+unsafe fn guarded<T, F: FnOnce() -> T>(error: *mut *mut c_char, sentinel: T, f: F) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(t) => t,
+        Err(payload) => {
+            fail(error, format!("panicked: {}", message(&*payload)));
+            sentinel
+        }
+    }
+}
+
+// message returns the message a panic was raised with.
+fn message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(s) => s,
+        None => payload.downcast_ref::<String>().map_or("unknown cause", String::as_str),
+    }
+}
+
+// qsim_create returns a simulation of the configuration given as a JSON document (see
+// json::configure), run off of the given seed unless the configuration has its own. If the
+// configuration is invalid (or the simulator panics) it returns null, writing out why to *error
+// unless error is null.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_create(
+    config: *const c_char,
+    seed: u64,
+    error: *mut *mut c_char,
+) -> *mut Simulation {
+    let config = CStr::from_ptr(config)
+        .to_str()
+        .map_err(|_| "invalid configuration, expected UTF-8".to_string())
+        .and_then(|config| json::configure(config, seed));
+    match config {
+        Ok(config) => guarded(error, ptr::null_mut(), || {
+            Box::into_raw(Box::new(Simulation::new(config)))
+        }),
+        Err(e) => {
+            fail(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+// qsim_step moves the simulation forward by up to the given number of ticks, resuming it if it
+// was paused, and returns the number it moved forward by: fewer once it completes. Should the
+// simulator panic, it returns u32::MAX, writing out why to *error unless error is null; the
+// simulation's then only good for freeing.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_step(
+    sim: *mut Simulation,
+    ticks: u32,
+    error: *mut *mut c_char,
+) -> u32 {
+    let sim = &mut *sim;
+    guarded(error, u32::MAX, || {
+        sim.resume();
+        let start = sim.now();
+        let end = start.saturating_add(Ticks(ticks));
+        while sim.now() < end && sim.tick() {}
+        sim.now().since(start).0
+    })
+}
+
+// qsim_now returns the current simulated time, in ticks.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_now(sim: *const Simulation) -> u32 {
+    (*sim).now().0
+}
+
+// qsim_done returns whether the simulation has run to completion.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_done(sim: *const Simulation) -> bool {
+    (*sim).done()
+}
+
+// qsim_results returns the document describing the configuration and the results thus far (see
+// json::document). Should the simulator panic, it returns null, writing out why to *error unless
+// error is null.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_results(
+    sim: *const Simulation,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    let sim = &*sim;
+    guarded(error, ptr::null_mut(), || {
+        c_string(json::document(sim.config(), &sim.results()).to_string())
+    })
+}
+
+// qsim_destroy frees the simulation; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_destroy(sim: *mut Simulation) {
+    if !sim.is_null() {
+        drop(Box::from_raw(sim));
+    }
+}
+
+// qsim_free_string frees a string returned by any of the above; null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn qsim_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json::Json;
+
+    fn take(s: *mut c_char) -> String {
+        let owned = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string();
+        unsafe { qsim_free_string(s) };
+        owned
+    }
+
+    #[test]
+    fn lifecycle() {
+        let config = CString::new(r#"{"rate": 500, "pspeed": 1000, "duration": 1}"#).unwrap();
+        let sim = unsafe { qsim_create(config.as_ptr(), 1, ptr::null_mut()) };
+        assert!(!sim.is_null());
+        unsafe {
+            assert_eq!(qsim_step(sim, 250_000, ptr::null_mut()), 250_000);
+            assert_eq!(qsim_now(sim), 250_000);
+            assert!(!qsim_done(sim));
+            let doc = Json::parse(&take(qsim_results(sim, ptr::null_mut()))).unwrap();
+            let generated = doc.get("results").unwrap().get("packets_generated").unwrap();
+            assert!(generated.as_f64().unwrap() > 0.0);
+            // Stepping past the end stops at it.
+            assert_eq!(qsim_step(sim, 1_000_000, ptr::null_mut()), 750_000);
+            assert!(qsim_done(sim));
+            assert_eq!(qsim_step(sim, 1, ptr::null_mut()), 0);
+            qsim_destroy(sim);
+        }
+
+        let config = CString::new(r#"{"pspeed": 0}"#).unwrap();
+        let mut error = ptr::null_mut();
+        let sim = unsafe { qsim_create(config.as_ptr(), 1, &mut error) };
+        assert!(sim.is_null());
+        assert!(take(error).starts_with("invalid pspeed 0"));
+    }
+
+    #[test]
+    fn panics() {
+        // A panic is caught short of the caller, reported as an error instead.
+        let mut error = ptr::null_mut();
+        let n = unsafe { guarded(&mut error, u32::MAX, || panic!("out of {}", "bounds")) };
+        assert_eq!(n, u32::MAX);
+        assert_eq!(take(error), "panicked: out of bounds");
+        let results = || -> *mut c_char { panic!() };
+        assert!(unsafe { guarded(ptr::null_mut(), ptr::null_mut(), results) }.is_null());
+        assert_eq!(unsafe { guarded(&mut error, 0, || 1) }, 1);
+    }
+
+    #[test]
+    fn header() {
+        // Everything exported is declared.
+        let header = include_str!("../include/qsim.h");
+        for f in &[
+            "qsim_create",
+            "qsim_step",
+            "qsim_now",
+            "qsim_done",
+            "qsim_results",
+            "qsim_destroy",
+            "qsim_free_string",
+        ] {
+            assert!(header.contains(&format!("{}(", f)), "{} isn't declared", f);
+        }
+    }
+}
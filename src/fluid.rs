@@ -0,0 +1,247 @@
+use error::QsimError;
+use simulation::{Change, Config};
+use simulators::Preemption;
+
+// Fluid is what the fluid-flow approximation yields for a run: packets are treated as a continuous
+// fluid arriving and being served at their average rates, with none of the randomness of when they
+// arrive or how long they are. It captures how queues build up and drain as the load shifts (the
+// first-order behavior of an overloaded system), but not the queueing that variability causes
+// below capacity, where the fluid queue stays empty; its results are approximate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fluid {
+    // The simulated time the statistics were collected over, in seconds.
+    pub elapsed: f64,
+    // The time-average amount of fluid in the system, in packets, and the most there was at once.
+    pub occupancy: f64,
+    pub peak: f64,
+    // The average time (in seconds) spent queued, and in the system overall, by the fluid accepted.
+    pub wait: f64,
+    pub sojourn: f64,
+    // The amount of fluid generated, processed and dropped, in packets.
+    pub packets_generated: f64,
+    pub packets_processed: f64,
+    pub packets_dropped: f64,
+    pub utilization: f64,
+}
+
+// Flow integrates the fluid queue over time, piecewise linearly.
+#[derive(Default)]
+struct Flow {
+    // The amount of fluid in the system.
+    q: f64,
+    // Whether what flows is accounted for, the warm-up period being over.
+    measuring: bool,
+    elapsed: f64,
+    area: f64,
+    peak: f64,
+    generated: f64,
+    accepted: f64,
+    processed: f64,
+    busy: f64,
+    // The fluid accepted, weighted by the time it's to spend queued and in service.
+    waiting: f64,
+    serving: f64,
+}
+
+impl Flow {
+    // Flow.advance lets the fluid flow for the given time at the given arrival and service rates,
+    // in packets/s, into a system holding at most the given amount.
+    fn advance(&mut self, mut dt: f64, arrivals: f64, service: f64, capacity: f64) {
+        while dt > 0.0 {
+            // The rates at which fluid's accepted into the system and served, until the system
+            // empties, fills up, or drains down to within a lowered capacity.
+            let (accepted, served, limit) = if self.q > capacity {
+                (0.0, service, capacity)
+            } else if self.q == capacity && arrivals >= service {
+                (service, service, capacity)
+            } else if self.q == 0.0 && arrivals <= service {
+                (arrivals, arrivals, 0.0)
+            } else if arrivals > service {
+                (arrivals, service, capacity)
+            } else {
+                (arrivals, service, 0.0)
+            };
+            let rate = accepted - served;
+            let until = if rate == 0.0 { f64::INFINITY } else { (limit - self.q) / rate };
+            let step = dt.min(until);
+            let q = if step == until { limit } else { self.q + rate * step };
+            if self.measuring {
+                let area = (self.q + q) / 2.0 * step;
+                self.elapsed += step;
+                self.area += area;
+                self.peak = self.peak.max(q);
+                self.generated += arrivals * step;
+                self.accepted += accepted * step;
+                self.processed += served * step;
+                self.busy += served / service * step;
+                // Fluid accepted waits for what's ahead of it to be served, at the current rate.
+                self.waiting += accepted / service * area;
+                self.serving += accepted / service * step;
+            }
+            self.q = q;
+            dt -= step;
+        }
+    }
+}
+
+// run approximates the run configured as a fluid queue (see Fluid), following the changes scheduled
+// along the way. It takes next to no time no matter how many packets the run would've had, so it
+// scales to billions of them, but only the average arrival and service rates are taken into
+// account, not their distributions; nor are the traffic classes, the discipline, or sampling.
+pub fn run(config: &Config) -> Result<Fluid, QsimError> {
+    config.validate()?;
+    if config.trace.is_some() || config.packets.is_some() {
+        let reason = "the fluid approximation only runs for a duration, at the configured rates";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.servers > 1 {
+        let reason = "the fluid approximation drains the queue through a single server";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.retransmission.is_some() {
+        let reason = "the fluid approximation doesn't retransmit what it drops";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.backpressure {
+        let reason = "the fluid approximation doesn't block the source, it drops the overflow";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.ack.is_some() || config.link.is_some() {
+        let reason = "the fluid approximation has no links for packets to cross";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.preemption.is_some_and(|p| p != Preemption::Resume) {
+        let reason = "the fluid approximation conserves work, it can't restart or discard any";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.rate_limiting.is_some() {
+        let reason = "the fluid approximation has no rate limiter ahead of the server";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.marking.is_some() {
+        let reason = "the fluid approximation doesn't tell packets apart by color";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.tenants.iter().any(|t| t.share.is_some() || t.rate.is_some()) {
+        let reason = "the fluid approximation doesn't hold tenants to quotas";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    let mut changes = config.schedule.clone();
+    changes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut changes = changes.into_iter().peekable();
+
+    let end = f64::from(config.duration);
+    let (mut rate, mut pspeed, mut qlimit) = (config.rate, config.pspeed, config.qlimit);
+    let mut flow = Flow {
+        measuring: config.warmup == 0.0,
+        ..Flow::default()
+    };
+    let mut now = 0.0;
+    while now < end {
+        while let Some(&(_, change)) = changes.peek().filter(|&&(at, _)| at <= now) {
+            match change {
+                Change::Rate(r) => rate = r,
+                Change::Pspeed(p) => pspeed = p,
+                Change::Qlimit(l) => qlimit = l,
+                Change::Pause => {}
+            }
+            changes.next();
+        }
+        if now >= config.warmup {
+            flow.measuring = true;
+        }
+        let mut next = changes.peek().map_or(end, |&(at, _)| at.min(end));
+        if now < config.warmup {
+            next = next.min(config.warmup);
+        }
+        // The capacity of the system includes the packet in service.
+        let capacity = qlimit.map_or(f64::INFINITY, |l| l as f64 + 1.0);
+        let service = f64::from(pspeed) / f64::from(config.psize);
+        flow.advance(next - now, f64::from(rate), service, capacity);
+        now = next;
+    }
+
+    Ok(Fluid {
+        elapsed: flow.elapsed,
+        occupancy: flow.area / flow.elapsed,
+        peak: flow.peak,
+        wait: flow.waiting / flow.accepted,
+        sojourn: (flow.waiting + flow.serving) / flow.accepted,
+        packets_generated: flow.generated,
+        packets_processed: flow.processed,
+        packets_dropped: flow.generated - flow.accepted,
+        utilization: flow.busy / flow.elapsed,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn fluid() {
+        // Below capacity the fluid queue stays empty, the server as busy as the load has it.
+        let f = run(&Config {
+            rate: 5000,
+            ..Config::default()
+        })
+        .unwrap();
+        assert_eq!((f.occupancy, f.wait, f.packets_dropped), (0.0, 0.0, 0.0));
+        assert!(close(f.utilization, 0.5));
+        assert!(close(f.sojourn, 1e-4));
+
+        // Overloaded, the queue builds up at the difference in rates: 2000 packets a second, with
+        // what arrives at time t waiting for 0.2t.
+        let f = run(&Config {
+            rate: 12_000,
+            duration: 1,
+            ..Config::default()
+        })
+        .unwrap();
+        assert!(close(f.occupancy, 1000.0));
+        assert!(close(f.peak, 2000.0));
+        assert!(close(f.wait, 0.1));
+        assert!(close(f.packets_processed, 10_000.0));
+        assert!(close(f.utilization, 1.0));
+
+        // With a limited buffer, once it's full whatever the server can't keep up with is dropped,
+        // until the rate drops back and the queue drains.
+        let f = run(&Config {
+            rate: 12_000,
+            duration: 2,
+            qlimit: Some(999),
+            schedule: vec![(1.0, Change::Rate(8000))],
+            ..Config::default()
+        })
+        .unwrap();
+        assert!(close(f.peak, 1000.0));
+        assert!(close(f.packets_generated, 20_000.0));
+        assert!(close(f.packets_dropped, 1000.0));
+        // Filling up over half a second, full for another half, draining over the next half.
+        assert!(close(f.occupancy, (250.0 + 500.0 + 250.0) / 2.0));
+        assert!(close(f.packets_processed, 19_000.0));
+
+        // The warm-up period is left out.
+        let f = run(&Config {
+            rate: 12_000,
+            duration: 2,
+            warmup: 1.0,
+            ..Config::default()
+        })
+        .unwrap();
+        assert!(close(f.elapsed, 1.0));
+        assert!(close(f.occupancy, 3000.0));
+
+        let packets = Config {
+            packets: Some(100),
+            ..Config::default()
+        };
+        assert!(run(&packets).is_err());
+        assert!(run(&Config { servers: 2, ..Config::default() }).is_err());
+    }
+}
@@ -1,68 +1,338 @@
 extern crate rand;
 
+use self::rand::{Rng, XorShiftRng};
 use self::rand::distributions::{Exp, IndependentSample};
+use rng;
+use std::f64;
+use std::sync::Arc;
+use trace::TraceEntry;
 
 // Generators generate events, the generation of which is dictated by which specific Generator is
 // used. The underlying RNG distribution, if configured (consider λ in an exponentially distributed
 // generator for e.g.), should map to an events/s parameter.
 pub trait Generator {
+    // next_interval returns how many discrete time units of the specified resolution (1e6 for a
+    // µs scale for e.g.) would need to pass until the next such event, including the fractional
+    // part. Callers accumulating these intervals retain sub-unit precision, and with it the
+    // ability to have several events occur within a single time unit.
+    fn next_interval(&mut self, resolution: f64) -> f64;
+
     // next_event returns an u32 integer corresponding to how many discrete time units of the
-    // specified resolution (1e6 for a µs scale for e.g.) would need to pass until
-    // the next such event.
+    // specified resolution would need to pass until the next such event.
     //
     // NB: If the resolution is too course (1 for e.g. corresponding to a 1s resolution), the
     // return value might be 0, this just means we've potentially lost useful information due to
     // rounding up errors. If the next event was to occur after 5ms, a specified resolution of a 1s
     // scale (asking for the next second the event would occur) would return 0 -- hardly useful
     // information.
-    fn next_event(&self, resolution: f64) -> u32;
+    fn next_event(&mut self, resolution: f64) -> u32 {
+        self.next_interval(resolution) as u32
+    }
+
+    // set_rate changes the events/s parameter of the generator, applying to all subsequently
+    // generated events.
+    fn set_rate(&mut self, rate: f64);
+
+    // clone_box returns a copy of the generator as it stands, random state and all, so that a copy
+    // of a run picks up exactly where the original left off (see Simulation.snapshot).
+    fn clone_box(&self) -> Box<dyn Generator>;
+}
+
+impl<G: Generator + ?Sized> Generator for Box<G> {
+    fn next_interval(&mut self, resolution: f64) -> f64 {
+        (**self).next_interval(resolution)
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        (**self).set_rate(rate)
+    }
+
+    fn clone_box(&self) -> Box<dyn Generator> {
+        (**self).clone_box()
+    }
+}
+
+impl Clone for Box<dyn Generator> {
+    fn clone(&self) -> Box<dyn Generator> {
+        self.clone_box()
+    }
 }
 
 // generators::Markov generates events where the interarrival time between subsequent events is
 // dictated by an exponential distribution.
+#[derive(Clone)]
 pub struct Markov {
     exp: Exp,
+    rng: XorShiftRng,
 }
 
 impl Markov {
     pub fn new(lambda: f64) -> Markov {
-        Markov { exp: Exp::new(lambda) }
+        Markov::with_rng(lambda, rng::unseeded())
+    }
+
+    // Markov::with_rng returns a generator drawing from the provided RNG, typically a substream
+    // (see src/rng.rs) so that runs are reproducible.
+    pub fn with_rng(lambda: f64, rng: XorShiftRng) -> Markov {
+        Markov {
+            exp: Exp::new(lambda),
+            rng,
+        }
     }
 }
 
 impl Generator for Markov {
-    fn next_event(&self, resolution: f64) -> u32 {
-        (self.exp.ind_sample(&mut rand::thread_rng()) * resolution) as u32
+    fn next_interval(&mut self, resolution: f64) -> f64 {
+        self.exp.ind_sample(&mut self.rng) * resolution
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        self.exp = Exp::new(rate);
+    }
+
+    fn clone_box(&self) -> Box<dyn Generator> {
+        Box::new(self.clone())
     }
 }
 
+#[derive(Clone)]
 pub struct Deterministic {
     rate: f64,
 }
 
 impl Deterministic {
     pub fn new(rate: f64) -> Deterministic {
-        Deterministic { rate: rate }
+        Deterministic { rate }
     }
 }
 
 impl Generator for Deterministic {
-    fn next_event(&self, resolution: f64) -> u32 {
-        (resolution / self.rate) as u32
+    fn next_interval(&mut self, resolution: f64) -> f64 {
+        resolution / self.rate
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        self.rate = rate;
+    }
+
+    fn clone_box(&self) -> Box<dyn Generator> {
+        Box::new(self.clone())
+    }
+}
+
+// generators::Pareto generates events where the interarrival time follows a Pareto distribution of
+// the given shape α > 1, heavy-tailed, with infinite variance for α <= 2. The scale (the minimum
+// interval) follows from the rate, as the mean is αx/(α - 1).
+#[derive(Clone)]
+pub struct Pareto {
+    shape: f64,
+    scale: f64,
+    rng: XorShiftRng,
+}
+
+impl Pareto {
+    pub fn with_rng(shape: f64, rate: f64, rng: XorShiftRng) -> Pareto {
+        let mut p = Pareto { shape, scale: 0.0, rng };
+        p.set_rate(rate);
+        p
+    }
+}
+
+impl Generator for Pareto {
+    fn next_interval(&mut self, resolution: f64) -> f64 {
+        // Inverse transform sampling, with u in (0, 1].
+        let u = 1.0 - self.rng.next_f64();
+        self.scale / u.powf(1.0 / self.shape) * resolution
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        self.scale = (self.shape - 1.0) / (self.shape * rate);
+    }
+
+    fn clone_box(&self) -> Box<dyn Generator> {
+        Box::new(self.clone())
+    }
+}
+
+// generators::LogNormal generates events where the interarrival time is log-normally distributed,
+// the logarithm having the given standard deviation σ. The mean of the logarithm follows from the
+// rate, as the mean is exp(μ + σ²/2).
+#[derive(Clone)]
+pub struct LogNormal {
+    sigma: f64,
+    distribution: self::rand::distributions::LogNormal,
+    rng: XorShiftRng,
+}
+
+impl LogNormal {
+    pub fn with_rng(sigma: f64, rate: f64, rng: XorShiftRng) -> LogNormal {
+        LogNormal {
+            sigma,
+            distribution: self::rand::distributions::LogNormal::new(
+                -rate.ln() - sigma * sigma / 2.0,
+                sigma,
+            ),
+            rng,
+        }
+    }
+}
+
+impl Generator for LogNormal {
+    fn next_interval(&mut self, resolution: f64) -> f64 {
+        self.distribution.ind_sample(&mut self.rng) * resolution
+    }
+
+    fn set_rate(&mut self, rate: f64) {
+        self.distribution = self::rand::distributions::LogNormal::new(
+            -rate.ln() - self.sigma * self.sigma / 2.0,
+            self.sigma,
+        );
+    }
+
+    fn clone_box(&self) -> Box<dyn Generator> {
+        Box::new(self.clone())
+    }
+}
+
+// generators::Trace replays the arrival times of a trace (see src/trace.rs). Once the trace runs
+// out no further events are generated. The rate is dictated by the trace, and can't be changed.
+#[derive(Clone)]
+pub struct Trace {
+    entries: Arc<Vec<TraceEntry>>,
+    next: usize,
+}
+
+impl Trace {
+    pub fn new(entries: Arc<Vec<TraceEntry>>) -> Trace {
+        Trace { entries, next: 0 }
+    }
+}
+
+impl Generator for Trace {
+    fn next_interval(&mut self, resolution: f64) -> f64 {
+        let previous = match self.next {
+            0 => 0.0,
+            n => self.entries[n - 1].time,
+        };
+        let interval = match self.entries.get(self.next) {
+            Some(entry) => (entry.time - previous) * resolution,
+            None => f64::INFINITY,
+        };
+        self.next += 1;
+        interval
+    }
+
+    fn set_rate(&mut self, _rate: f64) {}
+
+    fn clone_box(&self) -> Box<dyn Generator> {
+        Box::new(self.clone())
+    }
+}
+
+// Distribution is the distribution of the time between events (of interarrival times, say, or of
+// packet lengths and with them service times), the mean of which is set separately through the
+// rate of the generator it's turned into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    Exponential,
+    Deterministic,
+    Pareto { shape: f64 },
+    LogNormal { sigma: f64 },
+}
+
+impl Distribution {
+    // Distribution.generator returns a generator of events at the given rate, drawing from the
+    // provided RNG if random.
+    pub fn generator(&self, rate: f64, rng: XorShiftRng) -> Box<dyn Generator> {
+        match *self {
+            Distribution::Exponential => Box::new(Markov::with_rng(rate, rng)),
+            Distribution::Deterministic => Box::new(Deterministic::new(rate)),
+            Distribution::Pareto { shape } => Box::new(Pareto::with_rng(shape, rate, rng)),
+            Distribution::LogNormal { sigma } => Box::new(LogNormal::with_rng(sigma, rate, rng)),
+        }
+    }
+
+    // Distribution.kendall returns the symbol for the distribution in Kendall's notation.
+    pub fn kendall(&self) -> &'static str {
+        match *self {
+            Distribution::Exponential => "M",
+            Distribution::Deterministic => "D",
+            Distribution::Pareto { .. } | Distribution::LogNormal { .. } => "G",
+        }
+    }
+
+    // Distribution.scv returns the squared coefficient of variation of the distribution, its
+    // variance relative to its squared mean: 1 for exponential and 0 for deterministic times,
+    // infinite for Pareto ones of shape 2 or less.
+    pub fn scv(&self) -> f64 {
+        match *self {
+            Distribution::Exponential => 1.0,
+            Distribution::Deterministic => 0.0,
+            Distribution::Pareto { shape } if shape > 2.0 => 1.0 / (shape * (shape - 2.0)),
+            Distribution::Pareto { .. } => f64::INFINITY,
+            Distribution::LogNormal { sigma } => (sigma * sigma).exp() - 1.0,
+        }
+    }
+}
+
+// Classifier assigns generated packets a traffic class, with probabilities proportional to the
+// given weights, and a flow, uniformly at random. Splitting a Poisson process this way yields
+// independent Poisson processes, one per class (and flow).
+#[derive(Clone)]
+pub struct Classifier {
+    cumulative: Vec<f64>,
+    flows: u32,
+    rng: XorShiftRng,
+}
+
+impl Classifier {
+    pub fn new(weights: &[f64], flows: u32, rng: XorShiftRng) -> Classifier {
+        let total: f64 = weights.iter().sum();
+        let mut sum = 0.0;
+        Classifier {
+            cumulative: weights
+                .iter()
+                .map(|w| {
+                    sum += w / total;
+                    sum
+                })
+                .collect(),
+            flows: flows.max(1),
+            rng,
+        }
+    }
+
+    // Classifier.classify returns the class and flow of the next packet.
+    pub fn classify(&mut self) -> (u32, u32) {
+        let class = if self.cumulative.len() > 1 {
+            let u = self.rng.next_f64();
+            self.cumulative.iter().position(|&c| u < c).unwrap_or(self.cumulative.len() - 1)
+        } else {
+            0
+        };
+        let flow = if self.flows > 1 {
+            self.rng.gen_range(0, self.flows)
+        } else {
+            0
+        };
+        (class as u32, flow)
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use super::{Generator, Markov, Deterministic};
+    use super::{Classifier, Deterministic, Distribution, Generator, Markov, Trace};
+    use rng;
+    use std::sync::Arc;
+    use trace::TraceEntry;
 
     // Use `cargo test -- --nocapture` to verify the generation of exponentially distributed random
     // u32 integers, at 100 packets/s and a µs scale resolution, a typical generation would be
     // [8728, 12561, 4670, 5370, 9221].
     #[test]
     fn generate_markovian_events() {
-        let mg = Markov::new(100.0);
+        let mut mg = Markov::new(100.0);
         let mut events = vec![];
         for _ in 0..5 {
             events.push(mg.next_event(1e6));
@@ -72,11 +342,74 @@ mod tests {
 
     #[test]
     fn generate_deterministic_events() {
-        let dg = Deterministic::new(1000.0);
+        let mut dg = Deterministic::new(1000.0);
         let mut events = vec![];
         for _ in 0..5 {
             events.push(dg.next_event(1e6));
         }
         assert_eq!(events, vec![1000; 5]);
     }
+
+    #[test]
+    fn trace_events() {
+        let entries = [0.001, 0.001, 0.004].iter().map(|&time| TraceEntry { time, length: 1 });
+        let mut tg = Trace::new(Arc::new(entries.collect()));
+        let events: Vec<f64> = (0..4).map(|_| tg.next_interval(1e3)).collect();
+        assert_eq!(events[..3].to_vec(), vec![1.0, 0.0, 3.0]);
+        assert!(events[3].is_infinite());
+    }
+
+    #[test]
+    fn distributions() {
+        let mut d = Distribution::Deterministic.generator(4.0, rng::unseeded());
+        assert_eq!(d.next_interval(1e3), 250.0);
+        let mut e = Distribution::Exponential.generator(4.0, rng::substream(7, rng::ARRIVALS));
+        let mean = (0..10_000).map(|_| e.next_interval(1e3)).sum::<f64>() / 10_000.0;
+        assert!((mean - 250.0).abs() < 10.0, "{}", mean);
+
+        // The mean of a Pareto distribution converges slowly, more so the heavier its tail.
+        let mut p = Distribution::Pareto { shape: 3.0 }.generator(4.0, rng::substream(7, 0));
+        let samples: Vec<f64> = (0..100_000).map(|_| p.next_interval(1e3)).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        assert!((mean - 250.0).abs() < 10.0, "{}", mean);
+        assert!(samples.iter().all(|&x| x >= 250.0 * 2.0 / 3.0));
+
+        let mut l = Distribution::LogNormal { sigma: 1.0 }.generator(4.0, rng::substream(7, 0));
+        let mean = (0..100_000).map(|_| l.next_interval(1e3)).sum::<f64>() / 100_000.0;
+        assert!((mean - 250.0).abs() < 10.0, "{}", mean);
+        l.set_rate(2.0);
+        let mean = (0..100_000).map(|_| l.next_interval(1e3)).sum::<f64>() / 100_000.0;
+        assert!((mean - 500.0).abs() < 20.0, "{}", mean);
+
+        let samples: Vec<f64> = (0..100_000).map(|_| e.next_interval(1e3)).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / 100_000.0;
+        let scv = Distribution::Exponential.scv();
+        assert!((variance / (mean * mean) - scv).abs() < 0.05, "{}", variance / (mean * mean));
+        assert_eq!(Distribution::Pareto { shape: 2.0 }.scv(), f64::INFINITY);
+    }
+
+    #[test]
+    fn seeded_markovian_events_are_reproducible() {
+        let mut a = Markov::with_rng(100.0, rng::substream(7, rng::ARRIVALS));
+        let mut b = Markov::with_rng(100.0, rng::substream(7, rng::ARRIVALS));
+        for _ in 0..5 {
+            assert_eq!(a.next_event(1e6), b.next_event(1e6));
+        }
+    }
+
+    #[test]
+    fn classification() {
+        let mut c = Classifier::new(&[3.0, 1.0], 10, rng::substream(7, rng::CLASSES));
+        let mut classes = [0; 2];
+        let mut flows = [0; 10];
+        for _ in 0..10_000 {
+            let (class, flow) = c.classify();
+            classes[class as usize] += 1;
+            flows[flow as usize] += 1;
+        }
+        assert!((classes[0] as f64 / 10_000.0 - 0.75).abs() < 0.02);
+        assert!(flows.iter().all(|&f| (f as f64 / 10_000.0 - 0.1).abs() < 0.02));
+        assert_eq!(Classifier::new(&[], 1, rng::unseeded()).classify(), (0, 0));
+    }
 }
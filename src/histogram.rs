@@ -0,0 +1,342 @@
+// Bucketing determines the bucket boundaries of a Histogram. Linear bucketing splits [0, width *
+// count) into equally wide buckets. Exponential bucketing has a first bucket of [0, min), after
+// which every bucket is `factor` times wider than the last, bounding the relative error of
+// reported percentiles regardless of magnitude.
+//
+// Hdr bucketing is modeled after HdrHistogram: values are recorded as integer multiples of `unit`
+// seconds, exactly up to 2^s units and in log-linear buckets beyond that, each power-of-two range
+// being split into 2^(s - 1) equally wide buckets. We pick s such that the relative error is
+// bounded by 10^-digits. Buckets are allocated as needed and there is no upper limit, so a 1ns to
+// 1h range at three significant digits takes up ~35k buckets regardless of the number of samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bucketing {
+    Linear { width: f64, count: usize },
+    Exponential { min: f64, factor: f64, count: usize },
+    Hdr { unit: f64, digits: u32 },
+}
+
+impl Default for Bucketing {
+    // Buckets from 1µs to ~300s with a relative width of 5%.
+    fn default() -> Bucketing {
+        Bucketing::Exponential {
+            min: 1e-6,
+            factor: 1.05,
+            count: 400,
+        }
+    }
+}
+
+impl Bucketing {
+    // Bucketing::parse parses bucketing specifications of the form "linear:WIDTH,COUNT",
+    // "exp:MIN,FACTOR,COUNT" or "hdr:DIGITS[,UNIT]" (the unit defaulting to 1ns).
+    pub fn parse(s: &str) -> Option<Bucketing> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next()?;
+        let params: Vec<&str> = parts.next()?.split(',').collect();
+        match (kind, params.len()) {
+            ("linear", 2) => Some(Bucketing::Linear {
+                width: params[0].parse().ok()?,
+                count: params[1].parse().ok()?,
+            }),
+            ("exp", 3) => Some(Bucketing::Exponential {
+                min: params[0].parse().ok()?,
+                factor: params[1].parse().ok()?,
+                count: params[2].parse().ok()?,
+            }),
+            ("hdr", 1) | ("hdr", 2) => Some(Bucketing::Hdr {
+                digits: params[0].parse().ok()?,
+                unit: match params.get(1) {
+                    Some(unit) => unit.parse().ok()?,
+                    None => 1e-9,
+                },
+            }),
+            _ => None,
+        }
+        .filter(Bucketing::valid)
+    }
+
+    // Bucketing.valid returns whether the buckets are of any use: at least a bucket, linear ones of
+    // a positive width and exponential ones growing from a positive minimum by a factor above 1,
+    // and HDR ones counting positive units.
+    pub fn valid(&self) -> bool {
+        let positive = |x: f64| x > 0.0 && x.is_finite();
+        match *self {
+            Bucketing::Linear { width, count } => positive(width) && count > 0,
+            Bucketing::Exponential { min, factor, count } => {
+                positive(min) && factor > 1.0 && factor.is_finite() && count > 0
+            }
+            Bucketing::Hdr { unit, .. } => positive(unit),
+        }
+    }
+
+    // Bucketing.count returns the number of buckets, if fixed.
+    fn count(&self) -> Option<usize> {
+        match *self {
+            Bucketing::Linear { count, .. } | Bucketing::Exponential { count, .. } => Some(count),
+            Bucketing::Hdr { .. } => None,
+        }
+    }
+
+    // hdr_bits returns s (see above) for the given number of significant digits.
+    fn hdr_bits(digits: u32) -> u32 {
+        (10f64.powi(digits as i32)).log2().ceil() as u32 + 1
+    }
+
+    // Bucketing.index returns the index of the bucket the value falls in, which is out of bounds
+    // for values beyond the last bucket.
+    fn index(&self, value: f64) -> usize {
+        match *self {
+            Bucketing::Linear { width, .. } => (value / width) as usize,
+            Bucketing::Exponential { min, factor, .. } => {
+                if value < min {
+                    0
+                } else {
+                    (value / min).log(factor) as usize + 1
+                }
+            }
+            Bucketing::Hdr { unit, digits } => {
+                let s = Bucketing::hdr_bits(digits);
+                let v = (value / unit) as u64;
+                if v < 1 << s {
+                    return v as usize;
+                }
+                let e = 63 - v.leading_zeros() - (s - 1);
+                let half = 1u64 << (s - 1);
+                ((1u64 << s) + u64::from(e - 1) * half + (v >> e) - half) as usize
+            }
+        }
+    }
+
+    // Bucketing.bounds returns the lower (inclusive) and upper (exclusive) bounds of the i-th
+    // bucket.
+    fn bounds(&self, i: usize) -> (f64, f64) {
+        match *self {
+            Bucketing::Linear { width, .. } => (i as f64 * width, (i + 1) as f64 * width),
+            Bucketing::Exponential { min, factor, .. } => {
+                if i == 0 {
+                    (0.0, min)
+                } else {
+                    (min * factor.powi(i as i32 - 1), min * factor.powi(i as i32))
+                }
+            }
+            Bucketing::Hdr { unit, digits } => {
+                let s = Bucketing::hdr_bits(digits);
+                let i = i as u64;
+                if i < 1 << s {
+                    return (i as f64 * unit, (i + 1) as f64 * unit);
+                }
+                let half = 1u64 << (s - 1);
+                let k = i - (1 << s);
+                let (e, m) = (k / half + 1, k % half + half);
+                ((m << e) as f64 * unit, ((m + 1) << e) as f64 * unit)
+            }
+        }
+    }
+}
+
+// Histogram records the distribution of non-negative samples (sojourn times for e.g.) into
+// buckets, from which arbitrary percentiles can be estimated. Samples beyond the last bucket are
+// counted separately, percentiles falling among them are reported as the maximum observed value.
+// With Hdr bucketing, buckets are allocated on demand instead and nothing overflows.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bucketing: Bucketing,
+    counts: Vec<u64>,
+    overflow: u64,
+    total: u64,
+    min: f64,
+    max: f64,
+}
+
+impl Histogram {
+    pub fn new(bucketing: Bucketing) -> Histogram {
+        Histogram {
+            bucketing,
+            counts: vec![0; bucketing.count().unwrap_or(0)],
+            overflow: 0,
+            total: 0,
+            min: f64::INFINITY,
+            max: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, value: f64) {
+        let i = self.bucketing.index(value);
+        if self.bucketing.count().is_none() && i >= self.counts.len() {
+            self.counts.resize(i + 1, 0);
+        }
+        match self.counts.get_mut(i) {
+            Some(c) => *c += 1,
+            None => self.overflow += 1,
+        }
+        self.total += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    // Histogram.count returns the total number of recorded samples.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    // Histogram.overflow returns the number of samples beyond the last bucket.
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    // Histogram.size returns the number of buckets allocated.
+    pub fn size(&self) -> usize {
+        self.counts.len()
+    }
+
+    // Histogram.percentile estimates the p-th percentile (0 <= p <= 100), interpolating linearly
+    // within the bucket it falls in. It returns NaN for an empty histogram.
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.total == 0 {
+            return f64::NAN;
+        }
+        let rank = ((p / 100.0 * self.total as f64).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (i, &count) in self.counts.iter().enumerate() {
+            if cumulative + count >= rank {
+                let (lower, upper) = self.bucketing.bounds(i);
+                let fraction = (rank - cumulative) as f64 / count as f64;
+                let estimate = lower + (upper - lower) * fraction;
+                return estimate.max(self.min).min(self.max);
+            }
+            cumulative += count;
+        }
+        self.max
+    }
+
+    // Histogram.buckets returns the lower and upper bounds and counts of all non-empty buckets.
+    pub fn buckets(&self) -> Vec<(f64, f64, u64)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(i, &count)| {
+                let (lower, upper) = self.bucketing.bounds(i);
+                (lower, upper, count)
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bucketing() {
+        assert_eq!(
+            Bucketing::parse("linear:0.5,10"),
+            Some(Bucketing::Linear {
+                width: 0.5,
+                count: 10,
+            })
+        );
+        assert_eq!(
+            Bucketing::parse("exp:1e-6,1.1,100"),
+            Some(Bucketing::Exponential {
+                min: 1e-6,
+                factor: 1.1,
+                count: 100,
+            })
+        );
+        assert_eq!(
+            Bucketing::parse("hdr:3"),
+            Some(Bucketing::Hdr {
+                unit: 1e-9,
+                digits: 3,
+            })
+        );
+        assert_eq!(Bucketing::parse("linear:0.5"), None);
+        assert_eq!(Bucketing::parse("log:1,2,3"), None);
+        assert_eq!(Bucketing::parse("linear:0,0"), None);
+        assert_eq!(Bucketing::parse("linear:0.5,0"), None);
+        assert_eq!(Bucketing::parse("exp:0,1,0"), None);
+        assert_eq!(Bucketing::parse("exp:1e-6,1,100"), None);
+        assert_eq!(Bucketing::parse("hdr:3,0"), None);
+    }
+
+    #[test]
+    fn linear_percentiles() {
+        let mut h = Histogram::new(Bucketing::Linear {
+            width: 1.0,
+            count: 100,
+        });
+        for i in 0..1000 {
+            h.record(f64::from(i) / 10.0);
+        }
+        assert_eq!(h.count(), 1000);
+        assert!((h.percentile(50.0) - 50.0).abs() < 0.2);
+        assert!((h.percentile(90.0) - 90.0).abs() < 0.2);
+        assert!((h.percentile(99.9) - 99.9).abs() < 0.2);
+        assert_eq!(h.percentile(100.0), 99.9);
+        assert_eq!(h.buckets().len(), 100);
+    }
+
+    #[test]
+    fn exponential_percentiles() {
+        let mut h = Histogram::new(Bucketing::default());
+        for i in 1..10_001 {
+            h.record(f64::from(i) * 1e-4);
+        }
+        for &p in &[50.0, 90.0, 99.0, 99.9] {
+            let expected = p / 100.0;
+            assert!(((h.percentile(p) - expected) / expected).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn hdr_buckets() {
+        let b = Bucketing::Hdr {
+            unit: 1.0,
+            digits: 3,
+        };
+        // 2^11 exact buckets, followed by 2^10 buckets per power of two.
+        assert_eq!(Bucketing::hdr_bits(3), 11);
+        assert_eq!(b.index(2047.0), 2047);
+        assert_eq!(b.index(2048.0), 2048);
+        assert_eq!(b.index(2049.0), 2048);
+        assert_eq!(b.index(4096.0), 3072);
+        for &v in &[0.0, 1.0, 2047.0, 2048.0, 5000.0, 1e9, 3.6e12] {
+            let (lower, upper) = b.bounds(b.index(v));
+            assert!(lower <= v && v < upper, "{} not in [{}, {})", v, lower, upper);
+            assert!(upper - lower <= (v * 1e-3).max(1.0));
+        }
+    }
+
+    #[test]
+    fn hdr_percentiles() {
+        // Samples from 1ns to 1s, with three significant digits.
+        let mut h = Histogram::new(Bucketing::parse("hdr:3").unwrap());
+        for i in 0..10_000 {
+            h.record(1e-9 * 10f64.powf(f64::from(i) * 9.0 / 10_000.0));
+        }
+        assert_eq!(h.overflow(), 0);
+        assert!(h.size() < 30_000);
+        for &(p, expected) in &[(50.0, 10f64.powf(-4.5)), (99.0, 10f64.powf(-0.09))] {
+            assert!(((h.percentile(p) - expected) / expected).abs() < 2e-3);
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let mut h = Histogram::new(Bucketing::Linear {
+            width: 1.0,
+            count: 2,
+        });
+        h.record(0.5);
+        h.record(10.0);
+        assert_eq!(h.overflow(), 1);
+        assert_eq!(h.percentile(100.0), 10.0);
+        assert!(Histogram::new(Bucketing::default()).percentile(50.0).is_nan());
+    }
+}
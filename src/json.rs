@@ -0,0 +1,1502 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use analysis::{self, BatchSummary};
+use analytic::{self, Expected};
+use cost::Costs;
+use generators::Distribution;
+use histogram::Bucketing;
+use quantiles::Estimator;
+use simulation::{
+    AckPath, Batching, Breakdown, Change, Config, LinkPath, Marking, RateLimiting, Results,
+    Retransmission, Simulation, Tenant, WarmupDetection, CONFIDENCE,
+};
+use simulators::{
+    BusyPeriod, ClientStatistics, Discipline, GilbertElliott, Limiting, Preemption,
+    ServerStatistics, Visit,
+};
+use slo::Evaluation;
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use time::Ticks;
+
+// The version of the results document, bumped whenever existing fields change meaning or go away.
+// Adding fields is considered backwards compatible.
+pub const VERSION: u32 = 1;
+
+// Json is a JSON value, just enough of one to write out results without pulling in a
+// serialization framework. Objects keep their fields in insertion order, and non-finite numbers
+// are written out as null.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    // Json::parse parses a JSON document, such as those written out for runs, back into a value.
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut p = Parser { chars: s.chars().peekable(), offset: 0 };
+        let value = p.value()?;
+        p.skip_whitespace();
+        match p.chars.peek() {
+            None => Ok(value),
+            Some(&c) => Err(p.error(&format!("unexpected {:?}", c))),
+        }
+    }
+
+    // Json.get returns the value of the object's field of the given name, if there's one.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields.iter().find(|f| f.0 == key).map(|f| &f.1),
+            _ => None,
+        }
+    }
+
+    // Json.as_f64 returns the number, null being read back as the NaN it may have been written
+    // out for.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            Json::Null => Some(f64::NAN),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Number(f64::from(n))
+    }
+}
+
+impl From<Ticks> for Json {
+    fn from(t: Ticks) -> Json {
+        Json::Number(f64::from(t))
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(o: Option<T>) -> Json {
+        o.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(v: Vec<T>) -> Json {
+        Json::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref values) => {
+                f.write_str("[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(ref fields) => {
+                f.write_str("{")?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn bucketing(b: &Bucketing) -> Json {
+    match *b {
+        Bucketing::Linear { width, count } => Json::object(vec![
+            ("kind", "linear".into()),
+            ("width", width.into()),
+            ("count", count.into()),
+        ]),
+        Bucketing::Exponential { min, factor, count } => Json::object(vec![
+            ("kind", "exp".into()),
+            ("min", min.into()),
+            ("factor", factor.into()),
+            ("count", count.into()),
+        ]),
+        Bucketing::Hdr { unit, digits } => Json::object(vec![
+            ("kind", "hdr".into()),
+            ("unit", unit.into()),
+            ("digits", digits.into()),
+        ]),
+    }
+}
+
+fn distribution(d: &Distribution) -> Json {
+    match *d {
+        Distribution::Exponential => Json::object(vec![("kind", "exp".into())]),
+        Distribution::Deterministic => Json::object(vec![("kind", "det".into())]),
+        Distribution::Pareto { shape } => {
+            Json::object(vec![("kind", "pareto".into()), ("shape", shape.into())])
+        }
+        Distribution::LogNormal { sigma } => {
+            Json::object(vec![("kind", "lognormal".into()), ("sigma", sigma.into())])
+        }
+    }
+}
+
+fn discipline(d: &Discipline) -> Json {
+    match *d {
+        Discipline::Fifo => Json::object(vec![("kind", "fifo".into())]),
+        Discipline::Lifo => Json::object(vec![("kind", "lifo".into())]),
+        Discipline::Priority(ref order) => {
+            Json::object(vec![("kind", "prio".into()), ("classes", order.clone().into())])
+        }
+        // Visits are named, bar limited ones given by their limit.
+        Discipline::Polling(ref visits) => {
+            let visits = visits.iter().map(|v| match *v {
+                Visit::Limited(k) => k.into(),
+                v => Json::String(v.name()),
+            });
+            Json::object(vec![("kind", "poll".into()), ("visits", Json::Array(visits.collect()))])
+        }
+    }
+}
+
+fn change(at: f64, c: &Change) -> Json {
+    let (param, value) = match *c {
+        Change::Rate(v) => ("rate", Json::from(v)),
+        Change::Pspeed(v) => ("pspeed", Json::from(v)),
+        Change::Qlimit(v) => ("qlimit", Json::from(v)),
+        Change::Pause => ("pause", Json::Null),
+    };
+    Json::object(vec![("at", at.into()), ("param", param.into()), ("value", value)])
+}
+
+// config returns the JSON representation of the configuration, times being in seconds.
+pub fn config(c: &Config) -> Json {
+    Json::object(vec![
+        ("rate", c.rate.into()),
+        ("psize", c.psize.into()),
+        ("pspeed", c.pspeed.into()),
+        ("servers", c.servers.into()),
+        ("model", c.model().as_str().into()),
+        ("arrival", distribution(&c.arrival)),
+        ("service", distribution(&c.service)),
+        ("duration", c.duration.into()),
+        ("packets", c.packets.into()),
+        ("warmup", c.warmup.into()),
+        ("qlimit", c.qlimit.into()),
+        ("discipline", discipline(&c.discipline)),
+        ("preemption", c.preemption.map(|p| p.name()).into()),
+        ("resolution", c.resolution.into()),
+        ("seed", c.seed.into()),
+        (
+            "batching",
+            match c.batching {
+                Some(Batching::Count(n)) => Json::object(vec![("batches", n.into())]),
+                Some(Batching::Ticks(n)) => Json::object(vec![("batch_size", n.into())]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "warmup_detection",
+            c.warmup_detection
+                .map(|m| match m {
+                    WarmupDetection::Mser5 => "mser5",
+                    WarmupDetection::Welch => "welch",
+                })
+                .into(),
+        ),
+        (
+            "schedule",
+            Json::Array(c.schedule.iter().map(|&(at, ref c)| change(at, c)).collect()),
+        ),
+        ("histogram", bucketing(&c.histogram)),
+        ("percentiles", c.percentiles.clone().into()),
+        (
+            "estimator",
+            match c.estimator {
+                Estimator::Histogram => "histogram",
+                Estimator::P2 => "p2",
+            }
+            .into(),
+        ),
+        ("sample_interval", c.sample_interval.into()),
+        ("window", c.window.into()),
+        ("acf_lags", c.acf_lags.into()),
+        ("poisson_test", c.poisson_test.into()),
+        ("wait_threshold", c.wait_threshold.into()),
+        ("class_weights", c.class_weights.clone().into()),
+        ("flows", c.flows.into()),
+        ("top_flows", c.top_flows.into()),
+        (
+            "retransmission",
+            match c.retransmission {
+                Some(r) => Json::object(vec![
+                    ("timeout", r.timeout.into()),
+                    ("retries", r.retries.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        ("backpressure", c.backpressure.into()),
+        (
+            "ack",
+            match c.ack {
+                Some(a) => Json::object(vec![
+                    ("delay", a.delay.into()),
+                    ("length", a.length.into()),
+                    ("pspeed", a.pspeed.into()),
+                    ("window", a.window.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "link",
+            match c.link {
+                Some(l) => Json::object(vec![
+                    ("delay", l.delay.into()),
+                    (
+                        "loss",
+                        match l.loss {
+                            Some(c) => Json::object(vec![
+                                ("p", c.p.into()),
+                                ("r", c.r.into()),
+                                ("good_loss", c.good_loss.into()),
+                                ("bad_loss", c.bad_loss.into()),
+                            ]),
+                            None => Json::Null,
+                        },
+                    ),
+                    ("reorder", l.reorder.into()),
+                    ("reorder_delay", l.reorder_delay.into()),
+                    ("duplicate", l.duplicate.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "rate_limiting",
+            match c.rate_limiting {
+                Some(l) => Json::object(vec![
+                    ("algorithm", l.algorithm.name().into()),
+                    ("limit", l.limit.into()),
+                    ("burst", l.burst.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "marking",
+            match c.marking {
+                Some(m) => Json::object(vec![
+                    ("committed", m.committed.into()),
+                    ("committed_burst", m.committed_burst.into()),
+                    ("peak", m.peak.into()),
+                    ("peak_burst", m.peak_burst.into()),
+                    ("yellow_limit", m.yellow_limit.into()),
+                    ("red_limit", m.red_limit.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "tenants",
+            Json::Array(
+                c.tenants
+                    .iter()
+                    .map(|t| {
+                        Json::object(vec![
+                            ("weight", t.weight.into()),
+                            ("share", t.share.into()),
+                            ("rate", t.rate.into()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+        ("trace_entries", c.trace.as_ref().map(|t| t.len()).into()),
+    ])
+}
+
+// field returns the value of the object's field, erring if there's none.
+fn field<'a>(j: &'a Json, key: &str) -> Result<&'a Json, String> {
+    j.get(key).ok_or_else(|| format!("missing {}", key))
+}
+
+// optional returns the value of the object's field, if it's there and not null.
+fn optional<T, F>(j: &Json, key: &str, parse: F) -> Result<Option<T>, String>
+where
+    F: Fn(&Json, &str) -> Result<T, String>,
+{
+    match j.get(key) {
+        None | Some(Json::Null) => Ok(None),
+        Some(_) => parse(j, key).map(Some),
+    }
+}
+
+fn number(j: &Json, key: &str) -> Result<f64, String> {
+    match *field(j, key)? {
+        Json::Number(n) => Ok(n),
+        _ => Err(format!("invalid {}, expected a number", key)),
+    }
+}
+
+// whole returns the field as a whole number of the given type.
+fn whole<T: TryFrom<u64>>(j: &Json, key: &str) -> Result<T, String> {
+    let n = number(j, key)?;
+    let invalid = || format!("invalid {} {}, expected a whole number", key, n);
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(invalid());
+    }
+    T::try_from(n as u64).map_err(|_| invalid())
+}
+
+fn string<'a>(j: &'a Json, key: &str) -> Result<&'a str, String> {
+    match *field(j, key)? {
+        Json::String(ref s) => Ok(s),
+        _ => Err(format!("invalid {}, expected a string", key)),
+    }
+}
+
+fn boolean(j: &Json, key: &str) -> Result<bool, String> {
+    match *field(j, key)? {
+        Json::Bool(b) => Ok(b),
+        _ => Err(format!("invalid {}, expected a boolean", key)),
+    }
+}
+
+fn numbers(j: &Json, key: &str) -> Result<Vec<f64>, String> {
+    match *field(j, key)? {
+        Json::Array(ref values) => values
+            .iter()
+            .map(|v| match *v {
+                Json::Number(n) => Ok(n),
+                _ => Err(format!("invalid {}, expected numbers", key)),
+            })
+            .collect(),
+        _ => Err(format!("invalid {}, expected an array", key)),
+    }
+}
+
+fn parse_bucketing(j: &Json, key: &str) -> Result<Bucketing, String> {
+    let b = field(j, key)?;
+    match string(b, "kind")? {
+        "linear" => Ok(Bucketing::Linear {
+            width: number(b, "width")?,
+            count: whole(b, "count")?,
+        }),
+        "exp" => Ok(Bucketing::Exponential {
+            min: number(b, "min")?,
+            factor: number(b, "factor")?,
+            count: whole(b, "count")?,
+        }),
+        "hdr" => Ok(Bucketing::Hdr {
+            unit: number(b, "unit")?,
+            digits: whole(b, "digits")?,
+        }),
+        kind => Err(format!("unknown {} bucketing {}", key, kind)),
+    }
+}
+
+fn parse_distribution(j: &Json, key: &str) -> Result<Distribution, String> {
+    let d = field(j, key)?;
+    match string(d, "kind")? {
+        "exp" => Ok(Distribution::Exponential),
+        "det" => Ok(Distribution::Deterministic),
+        "pareto" => Ok(Distribution::Pareto { shape: number(d, "shape")? }),
+        "lognormal" => Ok(Distribution::LogNormal { sigma: number(d, "sigma")? }),
+        kind => Err(format!("unknown {} distribution {}", key, kind)),
+    }
+}
+
+fn parse_discipline(j: &Json, key: &str) -> Result<Discipline, String> {
+    let d = field(j, key)?;
+    match string(d, "kind")? {
+        "fifo" => Ok(Discipline::Fifo),
+        "lifo" => Ok(Discipline::Lifo),
+        "prio" => {
+            let classes = numbers(d, "classes")?;
+            let whole = |c: f64| if c >= 0.0 && c.fract() == 0.0 { Some(c as u32) } else { None };
+            let classes: Option<Vec<u32>> = classes.into_iter().map(whole).collect();
+            classes.map(Discipline::Priority).ok_or_else(|| "invalid classes".to_string())
+        }
+        "poll" => match *field(d, "visits")? {
+            Json::Array(ref visits) => visits
+                .iter()
+                .map(|v| match *v {
+                    Json::String(ref name) => Visit::parse(name),
+                    Json::Number(k) if k >= 1.0 && k.fract() == 0.0 => {
+                        Some(Visit::Limited(k as u32))
+                    }
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .map(Discipline::Polling)
+                .ok_or_else(|| "invalid visits".to_string()),
+            _ => Err("invalid visits, expected an array".to_string()),
+        },
+        kind => Err(format!("unknown {} {}", key, kind)),
+    }
+}
+
+fn parse_retransmission(j: &Json, key: &str) -> Result<Retransmission, String> {
+    let r = field(j, key)?;
+    Ok(Retransmission {
+        timeout: number(r, "timeout")?,
+        retries: whole(r, "retries")?,
+    })
+}
+
+fn parse_ack(j: &Json, key: &str) -> Result<AckPath, String> {
+    let a = field(j, key)?;
+    Ok(AckPath {
+        delay: number(a, "delay")?,
+        length: whole(a, "length")?,
+        pspeed: optional(a, "pspeed", whole)?,
+        window: optional(a, "window", whole)?,
+    })
+}
+
+fn parse_loss_channel(j: &Json, key: &str) -> Result<GilbertElliott, String> {
+    let c = field(j, key)?;
+    Ok(GilbertElliott {
+        p: number(c, "p")?,
+        r: number(c, "r")?,
+        good_loss: number(c, "good_loss")?,
+        bad_loss: number(c, "bad_loss")?,
+    })
+}
+
+fn parse_link(j: &Json, key: &str) -> Result<LinkPath, String> {
+    let l = field(j, key)?;
+    Ok(LinkPath {
+        delay: number(l, "delay")?,
+        loss: optional(l, "loss", parse_loss_channel)?,
+        reorder: number(l, "reorder")?,
+        reorder_delay: number(l, "reorder_delay")?,
+        duplicate: number(l, "duplicate")?,
+    })
+}
+
+fn parse_rate_limiting(j: &Json, key: &str) -> Result<RateLimiting, String> {
+    let l = field(j, key)?;
+    let name = string(l, "algorithm")?;
+    let algorithm = Limiting::parse(name)
+        .ok_or_else(|| format!("unknown rate limiting algorithm {}", name))?;
+    Ok(RateLimiting {
+        algorithm,
+        limit: number(l, "limit")?,
+        burst: whole(l, "burst")?,
+    })
+}
+
+fn parse_marking(j: &Json, key: &str) -> Result<Marking, String> {
+    let m = field(j, key)?;
+    Ok(Marking {
+        committed: number(m, "committed")?,
+        committed_burst: whole(m, "committed_burst")?,
+        peak: number(m, "peak")?,
+        peak_burst: whole(m, "peak_burst")?,
+        yellow_limit: optional(m, "yellow_limit", whole)?,
+        red_limit: optional(m, "red_limit", whole)?,
+    })
+}
+
+fn parse_tenants(j: &Json, key: &str) -> Result<Vec<Tenant>, String> {
+    match *field(j, key)? {
+        Json::Array(ref tenants) => tenants
+            .iter()
+            .map(|t| {
+                Ok(Tenant {
+                    weight: number(t, "weight")?,
+                    share: optional(t, "share", number)?,
+                    rate: optional(t, "rate", number)?,
+                })
+            })
+            .collect(),
+        _ => Err(format!("invalid {}, expected an array", key)),
+    }
+}
+
+fn parse_change(c: &Json) -> Result<(f64, Change), String> {
+    let change = match string(c, "param")? {
+        "rate" => Change::Rate(whole(c, "value")?),
+        "pspeed" => Change::Pspeed(whole(c, "value")?),
+        "qlimit" => Change::Qlimit(optional(c, "value", whole)?),
+        "pause" => Change::Pause,
+        param => return Err(format!("unknown change to {}", param)),
+    };
+    Ok((number(c, "at")?, change))
+}
+
+// parse_config returns the configuration described by its JSON representation, the inverse of
+// config. Seeds beyond 2^53 don't survive the trip, JSON numbers being doubles, and neither do
+// traces, of which only the length is kept.
+pub fn parse_config(j: &Json) -> Result<Config, String> {
+    if optional(j, "trace_entries", number)?.is_some() {
+        return Err("the trace isn't included, only its length".to_string());
+    }
+    let batching = match j.get("batching") {
+        None | Some(Json::Null) => None,
+        Some(b) if b.get("batches").is_some() => Some(Batching::Count(whole(b, "batches")?)),
+        Some(b) => Some(Batching::Ticks(whole(b, "batch_size")?)),
+    };
+    let warmup_detection = match j.get("warmup_detection") {
+        None | Some(Json::Null) => None,
+        Some(_) => match string(j, "warmup_detection")? {
+            "mser5" => Some(WarmupDetection::Mser5),
+            "welch" => Some(WarmupDetection::Welch),
+            x => return Err(format!("unknown warm-up detection method {}", x)),
+        },
+    };
+    let schedule = match *field(j, "schedule")? {
+        Json::Array(ref changes) => changes.iter().map(parse_change).collect::<Result<_, _>>()?,
+        _ => return Err("invalid schedule, expected an array".to_string()),
+    };
+    let preemption = match j.get("preemption") {
+        None | Some(Json::Null) => None,
+        Some(_) => {
+            let x = string(j, "preemption")?;
+            Some(Preemption::parse(x).ok_or_else(|| format!("unknown preemption {}", x))?)
+        }
+    };
+    let estimator = match string(j, "estimator")? {
+        "histogram" => Estimator::Histogram,
+        "p2" => Estimator::P2,
+        x => return Err(format!("unknown quantile estimator {}", x)),
+    };
+    Ok(Config {
+        rate: whole(j, "rate")?,
+        psize: whole(j, "psize")?,
+        pspeed: whole(j, "pspeed")?,
+        arrival: parse_distribution(j, "arrival")?,
+        service: parse_distribution(j, "service")?,
+        servers: optional(j, "servers", whole)?.unwrap_or(1),
+        duration: whole(j, "duration")?,
+        packets: optional(j, "packets", whole)?,
+        qlimit: optional(j, "qlimit", whole)?,
+        discipline: parse_discipline(j, "discipline")?,
+        preemption,
+        resolution: number(j, "resolution")?,
+        seed: optional(j, "seed", whole)?,
+        batching,
+        warmup_detection,
+        warmup: number(j, "warmup")?,
+        schedule,
+        histogram: parse_bucketing(j, "histogram")?,
+        percentiles: numbers(j, "percentiles")?,
+        estimator,
+        sample_interval: optional(j, "sample_interval", whole)?,
+        window: optional(j, "window", whole)?,
+        acf_lags: optional(j, "acf_lags", whole)?,
+        poisson_test: optional(j, "poisson_test", boolean)?.unwrap_or(false),
+        wait_threshold: optional(j, "wait_threshold", number)?,
+        class_weights: numbers(j, "class_weights")?,
+        flows: whole(j, "flows")?,
+        top_flows: whole(j, "top_flows")?,
+        trace: None,
+        retransmission: optional(j, "retransmission", parse_retransmission)?,
+        backpressure: optional(j, "backpressure", boolean)?.unwrap_or(false),
+        ack: optional(j, "ack", parse_ack)?,
+        link: optional(j, "link", parse_link)?,
+        tenants: optional(j, "tenants", parse_tenants)?.unwrap_or_default(),
+        rate_limiting: optional(j, "rate_limiting", parse_rate_limiting)?,
+        marking: optional(j, "marking", parse_marking)?,
+    })
+}
+
+pub fn client_statistics(s: &ClientStatistics) -> Json {
+    Json::object(vec![
+        ("packets_generated", s.packets_generated.into()),
+        ("ticks_blocked", s.ticks_blocked.into()),
+    ])
+}
+
+pub fn parse_client_statistics(j: &Json) -> Result<ClientStatistics, String> {
+    Ok(ClientStatistics {
+        packets_generated: whole(j, "packets_generated")?,
+        ticks_blocked: optional(j, "ticks_blocked", whole)?.unwrap_or(0),
+    })
+}
+
+pub fn server_statistics(s: &ServerStatistics) -> Json {
+    let busy_periods = s
+        .busy_periods
+        .iter()
+        .map(|p| Json::object(vec![("ticks", p.ticks.into()), ("packets", p.packets.into())]))
+        .collect();
+    Json::object(vec![
+        ("packets_processed", s.packets_processed.into()),
+        ("packets_dropped", s.packets_dropped.into()),
+        ("idle_count", s.idle_count.into()),
+        ("process_count", s.process_count.into()),
+        ("preemptions", s.preemptions.into()),
+        ("packets_discarded", s.packets_discarded.into()),
+        ("busy_periods", Json::Array(busy_periods)),
+    ])
+}
+
+pub fn parse_server_statistics(j: &Json) -> Result<ServerStatistics, String> {
+    let busy_periods = match *field(j, "busy_periods")? {
+        Json::Array(ref periods) => periods
+            .iter()
+            .map(|p| {
+                Ok(BusyPeriod {
+                    ticks: Ticks(whole(p, "ticks")?),
+                    packets: whole(p, "packets")?,
+                })
+            })
+            .collect::<Result<_, String>>()?,
+        _ => return Err("invalid busy_periods, expected an array".to_string()),
+    };
+    Ok(ServerStatistics {
+        packets_processed: whole(j, "packets_processed")?,
+        packets_dropped: whole(j, "packets_dropped")?,
+        idle_count: whole(j, "idle_count")?,
+        process_count: whole(j, "process_count")?,
+        preemptions: optional(j, "preemptions", whole)?.unwrap_or(0),
+        packets_discarded: optional(j, "packets_discarded", whole)?.unwrap_or(0),
+        busy_periods,
+    })
+}
+
+fn percentiles(ps: &[(f64, f64)]) -> Json {
+    Json::Array(
+        ps.iter()
+            .map(|&(p, v)| Json::object(vec![("percentile", p.into()), ("value", v.into())]))
+            .collect(),
+    )
+}
+
+fn breakdown(b: &Breakdown) -> Json {
+    Json::object(vec![
+        ("packets_generated", b.packets_generated.into()),
+        ("packets_processed", b.packets_processed.into()),
+        ("packets_dropped", b.packets_dropped.into()),
+        ("loss_probability", b.loss_probability().into()),
+        (
+            "sojourn",
+            Json::object(vec![
+                ("mean", b.sojourn.mean().into()),
+                ("stddev", b.sojourn.stddev().into()),
+            ]),
+        ),
+        (
+            "wait",
+            Json::object(vec![
+                ("mean", b.wait.mean().into()),
+                ("stddev", b.wait.stddev().into()),
+                ("max", b.max_wait.into()),
+            ]),
+        ),
+        ("starved", b.starved.into()),
+    ])
+}
+
+fn flows(flows: &[(u32, Breakdown)]) -> Json {
+    Json::Array(
+        flows
+            .iter()
+            .map(|&(flow, ref b)| {
+                let mut json = breakdown(b);
+                if let Json::Object(ref mut fields) = json {
+                    fields.insert(0, ("flow".to_string(), flow.into()));
+                }
+                json
+            })
+            .collect(),
+    )
+}
+
+fn batch_summary(s: &BatchSummary) -> Json {
+    Json::object(vec![
+        ("batches", s.batches.into()),
+        ("mean", s.mean.into()),
+        ("stderr", s.stderr.into()),
+        ("half_width", s.half_width.into()),
+        ("lag1", s.lag1.into()),
+        ("correlated", s.correlated().into()),
+    ])
+}
+
+// results returns the JSON representation of the results, times being in seconds and proportions
+// in percent.
+pub fn results(r: &Results) -> Json {
+    let batch_means = match (r.sojourn_batches, r.qlen_batches) {
+        (Some(ref sojourn), Some(ref qlen)) => Json::object(vec![
+            ("confidence", CONFIDENCE.into()),
+            ("sojourn", batch_summary(sojourn)),
+            ("wait", r.wait_batches.as_ref().map_or(Json::Null, batch_summary)),
+            ("qlen", batch_summary(qlen)),
+            ("loss_probability", r.loss_batches.as_ref().map_or(Json::Null, batch_summary)),
+            (
+                "utilization",
+                r.utilization_batches.as_ref().map_or(Json::Null, batch_summary),
+            ),
+        ]),
+        _ => Json::Null,
+    };
+    let warmup = match r.warmup {
+        Some(ref w) => Json::object(vec![
+            (
+                "method",
+                match w.method {
+                    WarmupDetection::Mser5 => "mser5",
+                    WarmupDetection::Welch => "welch",
+                }.into(),
+            ),
+            ("truncation", w.truncation.into()),
+        ]),
+        None => Json::Null,
+    };
+    Json::object(vec![
+        (
+            "sojourn",
+            Json::object(vec![
+                ("mean", r.sojourn.mean().into()),
+                ("stddev", r.sojourn.stddev().into()),
+                ("percentiles", percentiles(&r.sojourn_percentiles)),
+            ]),
+        ),
+        (
+            "wait",
+            Json::object(vec![
+                ("mean", r.wait.mean().into()),
+                ("stddev", r.wait.stddev().into()),
+                ("percentiles", percentiles(&r.wait_percentiles)),
+            ]),
+        ),
+        (
+            "service",
+            Json::object(vec![
+                ("mean", r.service.mean().into()),
+                ("stddev", r.service.stddev().into()),
+                ("percentiles", percentiles(&r.service_percentiles)),
+            ]),
+        ),
+        (
+            "qlen",
+            Json::object(vec![
+                ("mean", r.qlen.mean().into()),
+                ("stddev", r.qlen.stddev().into()),
+            ]),
+        ),
+        ("packets_generated", r.packets_generated.into()),
+        ("packets_processed", r.packets_processed.into()),
+        ("packets_dropped", r.packets_dropped.into()),
+        ("elapsed", r.elapsed.into()),
+        ("loss_probability", r.loss_probability().into()),
+        ("idle_proportion", r.idle_proportion.into()),
+        ("utilization", r.utilization.into()),
+        ("leftover", r.leftover.into()),
+        ("unbounded", r.unbounded.into()),
+        (
+            "busy_periods",
+            Json::object(vec![
+                ("count", r.busy_periods.count.into()),
+                (
+                    "duration",
+                    Json::object(vec![
+                        ("mean", r.busy_periods.duration.mean().into()),
+                        ("stddev", r.busy_periods.duration.stddev().into()),
+                        ("percentiles", percentiles(&r.busy_periods.duration_percentiles)),
+                    ]),
+                ),
+                (
+                    "packets",
+                    Json::object(vec![
+                        ("mean", r.busy_periods.packets.mean().into()),
+                        ("stddev", r.busy_periods.packets.stddev().into()),
+                        ("percentiles", percentiles(&r.busy_periods.packets_percentiles)),
+                    ]),
+                ),
+            ]),
+        ),
+        (
+            "little",
+            Json::object(vec![
+                ("l", r.little.l.into()),
+                ("lambda", r.little.lambda.into()),
+                ("w", r.little.w.into()),
+                ("relative_error", r.little.relative_error().into()),
+                ("holds", r.little.holds().into()),
+            ]),
+        ),
+        (
+            "jitter",
+            Json::object(vec![
+                (
+                    "interdeparture",
+                    Json::object(vec![
+                        ("mean", r.interdeparture.mean().into()),
+                        ("variance", r.interdeparture.variance().into()),
+                        ("scv", r.interdeparture_scv().into()),
+                        ("percentiles", percentiles(&r.interdeparture_percentiles)),
+                    ]),
+                ),
+                ("rfc3550", r.jitter.into()),
+                (
+                    "delay_variation",
+                    Json::object(vec![
+                        ("mean", r.delay_variation.mean().into()),
+                        ("stddev", r.delay_variation.stddev().into()),
+                        ("percentiles", percentiles(&r.delay_variation_percentiles)),
+                    ]),
+                ),
+            ]),
+        ),
+        (
+            "poisson_test",
+            match r.poisson_test {
+                Some(ref t) => Json::object(vec![
+                    ("intervals", t.len().into()),
+                    ("statistic", t.statistic().into()),
+                    ("lag1", t.lag1().into()),
+                    ("exponential", t.exponential().into()),
+                    ("independent", t.independent().into()),
+                    ("holds", t.holds().into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        ("classes", Json::Array(r.classes.iter().map(breakdown).collect())),
+        ("tenants", Json::Array(r.tenants.iter().map(breakdown).collect())),
+        ("colors", Json::Array(r.colors.iter().map(breakdown).collect())),
+        (
+            "starvation",
+            Json::object(vec![
+                ("wait_threshold", r.wait_threshold.into()),
+                ("max_wait", r.max_wait().into()),
+                ("starved", r.starved().into()),
+            ]),
+        ),
+        ("top_flows", flows(&r.top_flows)),
+        ("bottom_flows", flows(&r.bottom_flows)),
+        (
+            "fairness",
+            Json::object(vec![
+                ("classes", r.class_fairness.into()),
+                ("flows", r.flow_fairness.into()),
+            ]),
+        ),
+        (
+            "acf",
+            Json::object(vec![
+                ("wait", r.wait_acf.clone().into()),
+                ("interdeparture", r.interdeparture_acf.clone().into()),
+            ]),
+        ),
+        (
+            "collected",
+            Json::object(
+                r.collected
+                    .iter()
+                    .map(|(name, v)| (name.as_str(), (*v).into()))
+                    .collect(),
+            ),
+        ),
+        (
+            "retransmissions",
+            match r.retransmissions {
+                Some(ref t) => Json::object(vec![
+                    ("retransmitted", t.retransmitted.into()),
+                    ("abandoned", t.abandoned.into()),
+                    ("pending", t.pending.into()),
+                    (
+                        "recovered",
+                        Json::object(vec![
+                            ("count", t.recovered.len().into()),
+                            ("mean", t.recovered.mean().into()),
+                            ("stddev", t.recovered.stddev().into()),
+                        ]),
+                    ),
+                    ("throughput", t.throughput.into()),
+                    ("goodput", t.goodput.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "blocking",
+            match r.blocking {
+                Some(ref b) => Json::object(vec![
+                    ("time", b.time.into()),
+                    ("proportion", b.proportion.into()),
+                    (
+                        "delay",
+                        Json::object(vec![
+                            ("count", b.delay.len().into()),
+                            ("mean", b.delay.mean().into()),
+                            ("stddev", b.delay.stddev().into()),
+                        ]),
+                    ),
+                    ("pending", b.pending.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "round_trips",
+            match r.round_trips {
+                Some(ref t) => Json::object(vec![
+                    ("count", t.rtt.len().into()),
+                    ("mean", t.rtt.mean().into()),
+                    ("stddev", t.rtt.stddev().into()),
+                    ("percentiles", percentiles(&t.rtt_percentiles)),
+                    ("in_flight", t.in_flight.into()),
+                    ("unacked", t.unacked.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "delivery",
+            match r.delivery {
+                Some(ref d) => Json::object(vec![
+                    ("delivered", d.delivered.into()),
+                    ("lost", d.lost.into()),
+                    ("duplicates", d.duplicates.into()),
+                    ("out_of_order", d.out_of_order.into()),
+                    (
+                        "depth",
+                        Json::object(vec![
+                            ("mean", d.depth.mean().into()),
+                            ("stddev", d.depth.stddev().into()),
+                            ("max", d.max_depth.into()),
+                        ]),
+                    ),
+                    (
+                        "delay",
+                        Json::object(vec![
+                            ("mean", d.delay.mean().into()),
+                            ("stddev", d.delay.stddev().into()),
+                        ]),
+                    ),
+                    ("in_flight", d.in_flight.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "preemptions",
+            match r.preemptions {
+                Some(p) => Json::object(vec![
+                    ("count", p.count.into()),
+                    ("discarded", p.discarded.into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        (
+            "admission",
+            match r.admission {
+                Some(a) => Json::object(vec![
+                    ("admitted", a.admitted.into()),
+                    ("rejected", a.rejected.into()),
+                    ("rejection_probability", a.rejection_probability().into()),
+                ]),
+                None => Json::Null,
+            },
+        ),
+        ("batch_means", batch_means),
+        ("warmup", warmup),
+    ])
+}
+
+// validation returns the JSON representation of the comparison between the simulated and the
+// expected metrics, and between the distributions of the number of packets in the system if the
+// configuration maps onto an M/M/1(/K) queue.
+pub fn validation(c: &Config, e: &Expected, r: &Results) -> Json {
+    let metric = |expected: f64, simulated: f64| {
+        Json::object(vec![
+            ("expected", expected.into()),
+            ("simulated", simulated.into()),
+            ("relative_error", ((simulated - expected) / expected).into()),
+        ])
+    };
+    Json::object(vec![
+        ("model", e.model.as_str().into()),
+        ("sojourn", metric(e.sojourn, r.sojourn.mean())),
+        ("qlen", metric(e.qlen, r.qlen.mean())),
+        ("loss_probability", metric(e.loss_probability, r.loss_probability())),
+        (
+            "occupancy",
+            match analytic::occupancy(c, r.occupancy.len()) {
+                Some(expected) => {
+                    let simulated = r.occupancy_distribution();
+                    Json::object(vec![
+                        ("chi_square", analysis::chi_square_distance(&simulated, &expected).into()),
+                        ("expected", expected.into()),
+                        ("simulated", simulated.into()),
+                    ])
+                }
+                None => Json::Null,
+            },
+        ),
+    ])
+}
+
+// slo returns the JSON representation of the evaluated service level objectives.
+pub fn slo(evaluations: &[Evaluation]) -> Json {
+    Json::Array(
+        evaluations
+            .iter()
+            .map(|e| {
+                Json::object(vec![
+                    ("objective", e.objective.spec.as_str().into()),
+                    ("value", e.value.into()),
+                    ("met", e.met.into()),
+                ])
+            })
+            .collect(),
+    )
+}
+
+// costs returns the JSON representation of a run priced as per a cost model.
+pub fn costs(c: &Costs) -> Json {
+    Json::object(vec![
+        ("revenue", c.revenue.into()),
+        ("penalties", c.penalties.into()),
+        ("busy_cost", c.busy_cost.into()),
+        ("server_cost", c.server_cost.into()),
+        ("net", c.net().into()),
+    ])
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    // The number of characters consumed, for error messages.
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> String {
+        format!("offset {}: {}", self.offset, msg)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        self.offset += 1;
+        self.chars.next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected {:?}, found {:?}", expected, c))),
+            None => Err(self.error(&format!("expected {:?}", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some(&'"') => Ok(Json::String(self.string()?)),
+            Some(&'[') => {
+                self.next();
+                let mut values = vec![];
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&']') {
+                    self.next();
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Json::Array(values)),
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(&'{') => {
+                self.next();
+                let mut fields = vec![];
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&'}') {
+                    self.next();
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.chars.peek() != Some(&'"') {
+                        return Err(self.error("expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+                        break;
+                    }
+                    token.push(c);
+                    self.next();
+                }
+                match token.as_str() {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => match token.parse::<f64>() {
+                        Ok(n) if n.is_finite() => Ok(Json::Number(n)),
+                        _ => Err(self.error(&format!("invalid value {:?}", token))),
+                    },
+                }
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.next();
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some(c @ '"') | Some(c @ '\\') | Some(c @ '/') => s.push(c),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        s.push(c.ok_or_else(|| self.error("invalid escape sequence"))?);
+                    }
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+// document returns the versioned JSON document describing a run, its configuration and results.
+pub fn document(c: &Config, r: &Results) -> Json {
+    Json::object(vec![
+        ("version", VERSION.into()),
+        ("config", config(c)),
+        ("results", results(r)),
+    ])
+}
+
+// configure returns the configuration given as a JSON document, in the form written out by config
+// with any fields left out taking on their defaults, and validated. Unseeded configurations are
+// given the seed passed in, so that embedders without a source of entropy the library can draw on
+// supply their own.
+pub fn configure(doc: &str, seed: u64) -> Result<Config, String> {
+    let given = match Json::parse(doc)? {
+        Json::Object(fields) => fields,
+        _ => return Err("invalid configuration, expected an object".to_string()),
+    };
+    let mut fields = match config(&Config::default()) {
+        Json::Object(fields) => fields,
+        _ => unreachable!(),
+    };
+    for (key, value) in given {
+        match fields.iter_mut().find(|f| f.0 == key) {
+            Some(f) => f.1 = value,
+            None => return Err(format!("unknown option {}", key)),
+        }
+    }
+    let mut c = parse_config(&Json::Object(fields))?;
+    c.seed = c.seed.or(Some(seed));
+    c.validate().map_err(|e| e.to_string())?;
+    Ok(c)
+}
+
+// run runs the configuration given as a JSON document (see configure) and returns the results
+// document (see document). It's the simulator behind a string-in, string-out interface, for
+// embedding where Rust types can't be passed across (e.g. in the browser).
+pub fn run(doc: &str, seed: u64) -> Result<String, String> {
+    let c = configure(doc, seed)?;
+    let results = Simulation::new(c.clone()).run();
+    Ok(document(&c, &results).to_string())
+}
+
+// replicated returns the document describing a replicated run: the mean of each of the metrics
+// observed across the replications (see sweep::metrics), along with the half-width of its
+// confidence interval and the values observed in each replication (see sweep::samples).
+pub fn replicated(c: &Config, replications: u32, names: &[String], samples: &[Vec<f64>]) -> Json {
+    let metrics = names
+        .iter()
+        .zip(samples)
+        .map(|(name, values)| {
+            let m = OnlineStats::from_slice(values);
+            let m = Json::object(vec![
+                ("mean", m.mean().into()),
+                ("stddev", m.stddev().into()),
+                ("half_width", analysis::half_width(&m, CONFIDENCE).into()),
+                ("values", values.clone().into()),
+            ]);
+            (name.clone(), m)
+        })
+        .collect();
+    Json::object(vec![
+        ("version", VERSION.into()),
+        ("config", config(c)),
+        ("replications", replications.into()),
+        ("confidence", CONFIDENCE.into()),
+        ("metrics", Json::Object(metrics)),
+    ])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::DEFAULT_PSIZE;
+
+    #[test]
+    fn encoding() {
+        let v = Json::object(vec![
+            ("s", "a \"quoted\"\n\\string".into()),
+            ("n", Json::Array(vec![1u32.into(), 0.5.into(), f64::NAN.into()])),
+            ("o", Json::from(None::<u32>)),
+            ("b", true.into()),
+        ]);
+        assert_eq!(
+            v.to_string(),
+            r#"{"s":"a \"quoted\"\n\\string","n":[1,0.5,null],"o":null,"b":true}"#
+        );
+    }
+
+    #[test]
+    fn document_fields() {
+        let c = Config {
+            duration: 1,
+            resolution: 1e4,
+            seed: Some(1),
+            schedule: vec![(0.5, Change::Qlimit(None))],
+            ..Config::default()
+        };
+        let r = Simulation::new(c.clone()).run();
+        let doc = document(&c, &r).to_string();
+        assert!(doc.starts_with(r#"{"version":1,"config":{"rate":10000,"#));
+        assert!(doc.contains(r#""seed":1,"#));
+        assert!(doc.contains(r#""schedule":[{"at":0.5,"param":"qlimit","value":null}]"#));
+        assert!(doc.contains(&format!(r#""packets_generated":{},"#, r.packets_generated)));
+        assert!(doc.contains(r#""batch_means":null,"warmup":null}}"#));
+        assert_eq!(Json::parse(&doc).unwrap().to_string(), doc);
+    }
+
+    #[test]
+    fn round_trips() {
+        let c = Config {
+            arrival: Distribution::Pareto { shape: 1.5 },
+            service: Distribution::LogNormal { sigma: 0.5 },
+            packets: Some(100),
+            qlimit: Some(8),
+            discipline: Discipline::Priority(vec![2, 0]),
+            preemption: Some(Preemption::Restart),
+            seed: Some(42),
+            batching: Some(Batching::Ticks(500)),
+            warmup_detection: Some(WarmupDetection::Welch),
+            warmup: 0.5,
+            schedule: vec![(1.0, Change::Qlimit(None)), (2.5, Change::Rate(900))],
+            histogram: Bucketing::Hdr { unit: 1e-6, digits: 2 },
+            estimator: Estimator::P2,
+            window: Some(1000),
+            class_weights: vec![0.25, 0.75],
+            flows: 4,
+            top_flows: 2,
+            retransmission: Some(Retransmission {
+                timeout: 0.01,
+                retries: 2,
+            }),
+            backpressure: true,
+            ack: Some(AckPath {
+                delay: 0.002,
+                length: 40,
+                pspeed: Some(20_000),
+                window: Some(16),
+            }),
+            link: Some(LinkPath {
+                delay: 0.001,
+                loss: Some(GilbertElliott {
+                    p: 0.01,
+                    r: 0.2,
+                    good_loss: 0.0,
+                    bad_loss: 0.75,
+                }),
+                reorder: 0.1,
+                reorder_delay: 0.0005,
+                duplicate: 0.01,
+            }),
+            tenants: vec![
+                Tenant { weight: 3.0, share: Some(0.5), rate: Some(5000.0) },
+                Tenant { weight: 1.0, share: None, rate: None },
+            ],
+            rate_limiting: Some(RateLimiting {
+                algorithm: Limiting::SlidingWindow,
+                limit: 2000.0,
+                burst: 20,
+            }),
+            marking: Some(Marking {
+                committed: 8000.0,
+                committed_burst: 16,
+                peak: 12_000.0,
+                peak_burst: 32,
+                yellow_limit: Some(20),
+                red_limit: None,
+            }),
+            wait_threshold: Some(0.01),
+            poisson_test: true,
+            ..Config::default()
+        };
+        let doc = config(&c);
+        let parsed = parse_config(&Json::parse(&doc.to_string()).unwrap()).unwrap();
+        assert_eq!(config(&parsed), doc);
+        assert_eq!(parsed.model(), c.model());
+        let default = config(&Config::default());
+        assert_eq!(config(&parse_config(&default).unwrap()), default);
+        let visits = vec![Visit::Exhaustive, Visit::Gated, Visit::Limited(2)];
+        let discipline = Discipline::Polling(visits);
+        let polling = config(&Config { discipline, ..Config::default() });
+        assert_eq!(config(&parse_config(&polling).unwrap()), polling);
+
+        let mut sim = Simulation::new(Config { duration: 1, resolution: 1e4, seed: Some(1), ..c });
+        sim.run_until(|s| s.now() >= Ticks(5000));
+        let s = &sim.server().statistics;
+        assert!(!s.busy_periods.is_empty());
+        assert_eq!(parse_server_statistics(&server_statistics(s)).unwrap(), *s);
+        let s = ClientStatistics {
+            packets_generated: 7,
+            ticks_blocked: 3,
+        };
+        assert_eq!(parse_client_statistics(&client_statistics(&s)).unwrap(), s);
+
+        let err = |s: &str| parse_config(&Json::parse(s).unwrap()).unwrap_err();
+        assert_eq!(err("{}"), "missing schedule");
+        assert_eq!(err(r#"{"trace_entries":10}"#), "the trace isn't included, only its length");
+        let doc = doc.to_string().replace(r#""flows":4"#, r#""flows":-4"#);
+        assert_eq!(err(&doc), "invalid flows -4, expected a whole number");
+    }
+
+    #[test]
+    fn runs() {
+        let doc = r#"{"rate": 500, "pspeed": 1000, "duration": 1, "resolution": 1e4}"#;
+        let out = run(doc, 1).unwrap();
+        assert_eq!(run(doc, 1).unwrap(), out);
+        assert_ne!(run(doc, 2).unwrap(), out);
+        let out = Json::parse(&out).unwrap();
+        let c = parse_config(out.get("config").unwrap()).unwrap();
+        assert_eq!((c.rate, c.psize, c.seed), (500, DEFAULT_PSIZE, Some(1)));
+        let generated = out.get("results").and_then(|r| r.get("packets_generated"));
+        assert!(generated.and_then(Json::as_f64).is_some_and(|n| n > 400.0 && n < 600.0));
+
+        // Seeded configurations keep their own.
+        let seeded = run(r#"{"rate": 500, "pspeed": 1000, "duration": 1, "seed": 7}"#, 1);
+        assert!(seeded.unwrap().contains(r#""seed":7"#));
+        assert_eq!(run("[]", 1).unwrap_err(), "invalid configuration, expected an object");
+        assert_eq!(run(r#"{"speed": 1}"#, 1).unwrap_err(), "unknown option speed");
+        let err = run(r#"{"pspeed": 0}"#, 1).unwrap_err();
+        assert!(err.starts_with("invalid pspeed 0"), "{}", err);
+    }
+
+    #[test]
+    fn parsing() {
+        let doc = r#" {"s": "a \"q\"\n\u00e9", "n": [1, -0.5e1, null], "o": {}, "b": false} "#;
+        let v = Json::parse(doc).unwrap();
+        assert_eq!(v.get("s"), Some(&Json::String("a \"q\"\né".to_string())));
+        let n = Json::Array(vec![1u32.into(), (-5.0).into(), Json::Null]);
+        assert_eq!(v.get("n"), Some(&n));
+        assert_eq!(v.get("o"), Some(&Json::Object(vec![])));
+        assert_eq!(v.get("b"), Some(&false.into()));
+        assert_eq!(v.get("x"), None);
+        assert!(Json::Null.as_f64().unwrap().is_nan());
+
+        let err = |s: &str| Json::parse(s).unwrap_err();
+        assert_eq!(err("[1 2]"), "offset 4: expected ',' or ']'");
+        assert_eq!(err("{\"a\" 1}"), "offset 6: expected ':', found '1'");
+        assert_eq!(err("{1: 2}"), "offset 1: expected a key");
+        assert_eq!(err("\"abc"), "offset 5: unterminated string");
+        assert_eq!(err("nan"), "offset 3: invalid value \"nan\"");
+        assert_eq!(err("{}\n{}"), "offset 3: unexpected '{'");
+    }
+}
@@ -0,0 +1,121 @@
+use generators::Distribution;
+use simulation::Config;
+use simulators::Discipline;
+
+// Model is a queueing model in Kendall's notation, A/S/c/K/N/D: the interarrival and service time
+// distributions, the number of servers, the capacity of the system (including the packets in
+// service), the size of the population packets arrive from and the queue discipline. Only the
+// first three are required, the rest default to an infinite capacity and population and FIFO.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub arrival: Distribution,
+    pub service: Distribution,
+    pub servers: usize,
+    pub capacity: Option<usize>,
+    pub discipline: Discipline,
+}
+
+impl Model {
+    // Model::parse parses a model in Kendall's notation, e.g. M/D/1 or M/M/2/50. Only what can be
+    // simulated is accepted: Markovian or deterministic distributions, an infinite population, and
+    // FIFO, LIFO, (non-preemptive) priority by class or, with a single server, polling.
+    pub fn parse(s: &str) -> Result<Model, String> {
+        let invalid = |msg: &str| format!("invalid model {}: {}", s, msg);
+        let distribution = |d: &str| match d {
+            "M" => Ok(Distribution::Exponential),
+            "D" => Ok(Distribution::Deterministic),
+            d => Err(invalid(&format!("unsupported distribution {}", d))),
+        };
+        let infinite = |x: &str| x == "inf" || x == "∞";
+        let parts: Vec<&str> = s.split('/').map(str::trim).collect();
+        if parts.len() < 3 || parts.len() > 6 {
+            return Err(invalid("expected A/S/c[/K[/N[/D]]]"));
+        }
+        let arrival = distribution(parts[0])?;
+        let service = distribution(parts[1])?;
+        let servers = match parts[2].parse::<usize>() {
+            Ok(c) if c >= 1 => c,
+            _ => return Err(invalid("the number of servers must be at least 1")),
+        };
+        let capacity = match parts.get(3) {
+            Some(&k) if infinite(k) => None,
+            Some(k) => match k.parse::<usize>() {
+                Ok(k) if k >= servers => Some(k),
+                _ => return Err(invalid("the capacity must be at least the number of servers")),
+            },
+            None => None,
+        };
+        if parts.get(4).is_some_and(|&n| !infinite(n)) {
+            return Err(invalid("only infinite populations are simulated"));
+        }
+        let discipline = match parts.get(5) {
+            None | Some(&"FIFO") | Some(&"FCFS") => Discipline::Fifo,
+            Some(&"LIFO") | Some(&"LCFS") => Discipline::Lifo,
+            Some(&"PRIO") => Discipline::Priority(vec![]),
+            Some(&"POLL") => Discipline::Polling(vec![]),
+            Some(d) => return Err(invalid(&format!("unsupported discipline {}", d))),
+        };
+        if servers > 1 && matches!(discipline, Discipline::Polling(_)) {
+            return Err(invalid("a polling system has a single server"));
+        }
+        Ok(Model {
+            arrival,
+            service,
+            servers,
+            capacity,
+            discipline,
+        })
+    }
+
+    // Model.apply configures the distributions, number of servers, buffer limit (which doesn't
+    // count the packets in service) and discipline as per the model.
+    pub fn apply(&self, config: &mut Config) {
+        config.arrival = self.arrival;
+        config.service = self.service;
+        config.servers = self.servers;
+        config.qlimit = self.capacity.map(|k| k - self.servers);
+        config.discipline = self.discipline.clone();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let m = Model::parse("M/D/1/50").unwrap();
+        assert_eq!(m.arrival, Distribution::Exponential);
+        assert_eq!(m.service, Distribution::Deterministic);
+        assert_eq!(m.capacity, Some(50));
+        let mut c = Config::default();
+        m.apply(&mut c);
+        assert_eq!(c.qlimit, Some(49));
+        assert_eq!(c.model(), "M/D/1/50");
+
+        Model::parse("D/M/1/inf/∞/FCFS").unwrap().apply(&mut c);
+        assert_eq!(c.model(), "D/M/1");
+        Model::parse("M/M/1/∞/∞/LIFO").unwrap().apply(&mut c);
+        assert_eq!(c.model(), "M/M/1/∞/∞/LIFO");
+        assert_eq!(c.discipline, Discipline::Lifo);
+        Model::parse("M/D/1/10/∞/PRIO").unwrap().apply(&mut c);
+        assert_eq!(c.model(), "M/D/1/10/∞/PRIO");
+        Model::parse("M/M/2/50").unwrap().apply(&mut c);
+        assert_eq!((c.servers, c.qlimit), (2, Some(48)));
+        assert_eq!(c.model(), "M/M/2/50");
+        Model::parse("M/D/3").unwrap().apply(&mut c);
+        assert_eq!((c.servers, c.qlimit), (3, None));
+
+        let err = |s: &str| Model::parse(s).unwrap_err();
+        assert_eq!(err("M/D"), "invalid model M/D: expected A/S/c[/K[/N[/D]]]");
+        assert_eq!(err("G/D/1"), "invalid model G/D/1: unsupported distribution G");
+        assert!(err("M/M/0").ends_with("the number of servers must be at least 1"));
+        assert!(err("M/M/c").ends_with("the number of servers must be at least 1"));
+        assert!(err("M/M/1/0").ends_with("the capacity must be at least the number of servers"));
+        assert!(err("M/M/3/2").ends_with("the capacity must be at least the number of servers"));
+        assert!(err("M/M/2/∞/∞/POLL").ends_with("a polling system has a single server"));
+        assert!(err("M/M/1/5/100").ends_with("only infinite populations are simulated"));
+        assert!(err("M/M/1/5/inf/PS").ends_with("unsupported discipline PS"));
+    }
+}
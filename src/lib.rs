@@ -1,2 +1,99 @@
+// qlib is the simulator behind the qsim command, for use from other programs: a client generating
+// packets as per a Generator, feeding a Server that queues and processes them, wired together and
+// moved forward in lockstep by a Simulation. Runs are put together with a SimulationBuilder (or a
+// Config directly), and the types needed to do so are re-exported here; everything else (analysis
+// of the results, sweeps, searches and the like) lives in the modules below.
+//
+//   let results = qlib::SimulationBuilder::new()
+//       .arrivals(9000, qlib::Distribution::Exponential)
+//       .server(10_000, Some(64), qlib::Discipline::Fifo)
+//       .seed(1)
+//       .build()?
+//       .run();
+
+pub mod analysis;
+pub mod analytic;
+pub mod batch;
+pub mod collectors;
+pub mod cost;
+pub mod dashboard;
+pub mod ecmp;
+pub mod debugger;
+pub mod error;
+pub mod ffi;
+pub mod fluid;
 pub mod generators;
+pub mod histogram;
+pub mod json;
+pub mod kendall;
+pub mod lindley;
+pub mod logging;
+pub mod middleware;
+pub mod metrics;
+pub mod plot;
+pub mod progress;
+pub mod quantiles;
+pub mod records;
+pub mod ring;
+pub mod report;
+pub mod rng;
+pub mod scaling;
+pub mod search;
+pub mod serve;
+pub mod sensitivity;
+pub mod simulation;
 pub mod simulators;
+pub mod slo;
+pub mod sweep;
+pub mod switch;
+pub mod tandem;
+pub mod time;
+pub mod toml;
+pub mod trace;
+pub mod transient;
+pub mod ttest;
+
+pub use collectors::StatsCollector;
+pub use error::QsimError;
+pub use generators::{Distribution, Generator};
+pub use simulation::{Change, Config, Event, Results, Simulation, SimulationBuilder};
+pub use simulators::{Client, Discipline, Packet, QueueBackend, Server, ServerBuilder};
+pub use time::Ticks;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generators::Deterministic;
+
+    #[test]
+    fn library() {
+        // A run put together from the top-level names alone.
+        let results = SimulationBuilder::new()
+            .arrivals(500, Distribution::Deterministic)
+            .server(1000, None, Discipline::Fifo)
+            .resolution(1e4)
+            .duration(1)
+            .seed(1)
+            .build()
+            .unwrap()
+            .run();
+        assert_eq!(results.packets_generated, 500);
+        assert_eq!(results.packets_dropped, 0);
+
+        // As are the components it's made of.
+        let mut client = Client::new(Deterministic::new(0.5), 1.0);
+        let mut server = ServerBuilder::new().resolution(1.0).pspeed(1.0).build();
+        for t in 0..4 {
+            for _ in 0..client.tick() {
+                server.enqueue(Packet {
+                    time_generated: Ticks(t),
+                    length: 1,
+                    ..Packet::default()
+                });
+            }
+            server.tick();
+        }
+        assert_eq!(server.packets_processed(), 2);
+    }
+}
@@ -0,0 +1,222 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use error::QsimError;
+use generators::Distribution;
+use quantiles::Quantiles;
+use simulation::Config;
+use simulators::{Client, Discipline};
+use time::Ticks;
+
+// Lindley is what the Lindley recursion yields for a run: the waiting, sojourn and service times
+// (in seconds), the number of packets generated and processed, and the server's utilization, over
+// the simulated time the run lasted.
+#[derive(Clone, Debug)]
+pub struct Lindley {
+    pub sojourn: OnlineStats,
+    pub sojourn_percentiles: Vec<(f64, f64)>,
+    pub wait: OnlineStats,
+    pub wait_percentiles: Vec<(f64, f64)>,
+    pub service: OnlineStats,
+    pub service_percentiles: Vec<(f64, f64)>,
+    pub packets_generated: u32,
+    pub packets_processed: u32,
+    pub elapsed: f64,
+    pub utilization: f64,
+}
+
+// run computes the statistics of a run of a single FIFO queue with an unlimited buffer directly,
+// as per the Lindley recursion: a packet enters service at the later of its arrival and the
+// departure of the packet ahead of it, W(n+1) = max(0, W(n) + S(n) - A(n+1)). Interarrival times
+// and packet lengths are drawn exactly as the tick-by-tick simulation draws them, and arrivals and
+// services are rounded to whole ticks the same way, so the statistics come out identical to those
+// of Simulation.run (bar rounding error in the work done, where the speed or resolution isn't a
+// whole number); but it only takes the time to draw a packet and serve it, rather than that to
+// tick through every moment it spends in the system. Only the statistics above are computed, and
+// runs with a warm-up period or scheduled changes are out of scope.
+pub fn run(config: &Config) -> Result<Lindley, QsimError> {
+    config.validate()?;
+    if config.discipline != Discipline::Fifo || config.qlimit.is_some() {
+        let reason = "the Lindley recursion only applies to FIFO queues with an unlimited buffer";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.servers > 1 {
+        let reason = "the Lindley recursion only applies to single-server queues";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.warmup > 0.0 || !config.schedule.is_empty() {
+        let reason = "the Lindley recursion doesn't support warm-up periods or scheduled changes";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+
+    let resolution = config.resolution;
+    let mut client = Client::new(config.arrivals(), resolution);
+    let mut lengths = config.lengths();
+    let (mut sojourn_quantiles, mut wait_quantiles) = (config.quantiles(), config.quantiles());
+    let mut service_quantiles = config.quantiles();
+    let mut l = Lindley {
+        sojourn: OnlineStats::new(),
+        sojourn_percentiles: vec![],
+        wait: OnlineStats::new(),
+        wait_percentiles: vec![],
+        service: OnlineStats::new(),
+        service_percentiles: vec![],
+        packets_generated: 0,
+        packets_processed: 0,
+        elapsed: 0.0,
+        utilization: 0.0,
+    };
+    // The run ends at the end of its duration, or the tick after the last of the packets it's to
+    // run for departs. Service is accounted for in units of work, the server doing pspeed of them
+    // a tick, work left over in a tick once a packet's done going to the next one in line (see
+    // Server.tick); the server's free once it's done the work of every packet so far, and busy
+    // in every tick it does any, up until the last it was busy in.
+    let mut end = config.ticks();
+    let pspeed = f64::from(config.pspeed);
+    let (mut now, mut free, mut busy, mut busy_until) = (Ticks::ZERO, 0.0, 0u64, Ticks::ZERO);
+    loop {
+        // Skip straight to the next tick a packet's generated in, nothing else happening between.
+        let idle = client.idle_ticks().min(end.saturating_sub(now));
+        client.skip(idle);
+        now += idle;
+        if now >= end {
+            break;
+        }
+        let arrived = now;
+        let first = client.packets_generated();
+        now += Ticks(1);
+        for id in first..first + client.tick() {
+            l.packets_generated += 1;
+            let length = match config.trace {
+                Some(ref trace) => trace[id as usize].length,
+                None if config.service == Distribution::Deterministic => config.psize,
+                None => lengths.next_interval(1.0).round().max(1.0) as u32,
+            };
+            // The packet enters service in the tick the server gets to its work in, and departs
+            // in the tick that work's done in.
+            let begun = (f64::from(arrived) * pspeed).max(free);
+            let start = Ticks((begun / pspeed) as u32);
+            if start >= end {
+                continue;
+            }
+            free = begun + f64::from(length) * resolution;
+            let departure = Ticks((free / pspeed).ceil() as u32 - 1);
+            let (from, until) = (start.max(busy_until), (departure + Ticks(1)).min(end));
+            busy += u64::from(until.saturating_sub(from));
+            busy_until = busy_until.max(until);
+            let wait = start.since(arrived).seconds(resolution);
+            l.wait.add(wait);
+            wait_quantiles.record(wait);
+            if departure >= end {
+                continue;
+            }
+            let sojourn = departure.since(arrived).seconds(resolution);
+            l.sojourn.add(sojourn);
+            sojourn_quantiles.record(sojourn);
+            let service = departure.since(start).seconds(resolution);
+            l.service.add(service);
+            service_quantiles.record(service);
+            l.packets_processed += 1;
+            if config.packets.is_some_and(|n| l.packets_processed >= n) {
+                end = departure + Ticks(1);
+            }
+        }
+    }
+
+    let percentiles = |quantiles: &Quantiles| {
+        config.percentiles.iter().map(|&p| (p, quantiles.percentile(p))).collect()
+    };
+    l.sojourn_percentiles = percentiles(&sojourn_quantiles);
+    l.wait_percentiles = percentiles(&wait_quantiles);
+    l.service_percentiles = percentiles(&service_quantiles);
+    l.elapsed = end.seconds(resolution);
+    l.utilization = busy as f64 / f64::from(end);
+    Ok(l)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Simulation;
+
+    #[test]
+    fn identical() {
+        let configs = vec![
+            // M/D/1, arrivals often landing in the same tick.
+            Config {
+                rate: 9000,
+                duration: 2,
+                resolution: 1e5,
+                seed: Some(1),
+                ..Config::default()
+            },
+            // M/M/1, run for a number of packets.
+            Config {
+                rate: 800,
+                psize: 100,
+                pspeed: 100_000,
+                service: Distribution::Exponential,
+                duration: 10,
+                packets: Some(5000),
+                seed: Some(2),
+                ..Config::default()
+            },
+            // A server fast enough to process several packets a tick.
+            Config {
+                rate: 9000,
+                resolution: 1e3,
+                duration: 2,
+                seed: Some(4),
+                ..Config::default()
+            },
+            // A packet's work taking a fraction of a tick more than a whole number of them.
+            Config {
+                rate: 25,
+                pspeed: 30_000,
+                resolution: 1e4,
+                duration: 10,
+                service: Distribution::Exponential,
+                seed: Some(5),
+                ..Config::default()
+            },
+            // Overloaded, so that packets are left queued and in service at the end.
+            Config {
+                rate: 12_000,
+                duration: 1,
+                seed: Some(3),
+                ..Config::default()
+            },
+        ];
+        for config in configs {
+            let l = run(&config).unwrap();
+            let r = Simulation::new(config).run();
+            assert!(l.packets_processed > 0);
+            assert_eq!(l.packets_generated, r.packets_generated);
+            assert_eq!(l.packets_processed, r.packets_processed);
+            assert_eq!(l.elapsed, r.elapsed);
+            assert_eq!(l.utilization, r.utilization);
+            for &(a, b) in &[(&l.wait, &r.wait), (&l.sojourn, &r.sojourn), (&l.service, &r.service)]
+            {
+                assert_eq!((a.len(), a.mean(), a.stddev()), (b.len(), b.mean(), b.stddev()));
+            }
+            assert_eq!(l.wait_percentiles, r.wait_percentiles);
+            assert_eq!(l.sojourn_percentiles, r.sojourn_percentiles);
+        }
+    }
+
+    #[test]
+    fn unsupported() {
+        let config = Config {
+            qlimit: Some(10),
+            ..Config::default()
+        };
+        assert!(run(&config).is_err());
+        let config = Config {
+            discipline: Discipline::Lifo,
+            ..Config::default()
+        };
+        assert!(run(&config).is_err());
+        assert!(run(&Config { servers: 2, ..Config::default() }).is_err());
+        assert!(run(&Config::default()).is_ok());
+    }
+}
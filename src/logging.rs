@@ -0,0 +1,175 @@
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use time::Ticks;
+
+// logging is a small structured logger for the goings-on inside a simulation, so that debugging one
+// doesn't take sprinkling println!s through qlib. Records come from a component (the client, the
+// server, the simulation driving them), at a level, and carry the simulated time and the packet
+// they're about where there's one, written out to stderr one per line as key=value pairs:
+//
+//   level=debug component=server time=1234 packet=42 message="drop, 10 queued"
+//
+// What's logged is configured through the RUST_LOG environment variable, as with env_logger: a
+// comma-separated list of a default level and per-component ones, e.g. RUST_LOG=warn,server=trace.
+// Nothing is logged by default.
+
+// Level is the severity of a record, the more verbose levels coming later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Option<Level> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+// Filter is the most verbose level logged for each component, None if it's off.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Filter {
+    default: Option<Level>,
+    components: Vec<(String, Option<Level>)>,
+    // The most verbose level logged for any component, so as to tell cheaply when nothing is.
+    max: Option<Level>,
+}
+
+impl Filter {
+    // Filter::parse parses a RUST_LOG-style specification. A directive of a level alone sets the
+    // default; one of the form component=level (or off) sets that of the component. Directives
+    // that don't parse are ignored.
+    pub fn parse(spec: &str) -> Filter {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let level = |s: &str| match s {
+                "off" => Some(None),
+                s => Level::parse(s).map(Some),
+            };
+            match directive.find('=') {
+                Some(i) => {
+                    if let Some(l) = level(&directive[i + 1..]) {
+                        filter.components.push((directive[..i].to_string(), l));
+                    }
+                }
+                None => {
+                    if let Some(l) = level(directive) {
+                        filter.default = l;
+                    }
+                }
+            }
+        }
+        filter.max = filter.components.iter().map(|&(_, l)| l).fold(filter.default, Ord::max);
+        filter
+    }
+
+    // Filter.level returns the most verbose level logged for the component, the last directive
+    // naming it taking precedence.
+    pub fn level(&self, component: &str) -> Option<Level> {
+        match self.components.iter().rev().find(|(c, _)| c == component) {
+            Some(&(_, level)) => level,
+            None => self.default,
+        }
+    }
+
+    pub fn enabled(&self, component: &str, level: Level) -> bool {
+        self.max >= Some(level) && self.level(component) >= Some(level)
+    }
+}
+
+// Record is a single entry in the log.
+pub struct Record<'a> {
+    pub level: Level,
+    pub component: &'a str,
+    pub time: Option<Ticks>,
+    pub packet: Option<u32>,
+    pub message: fmt::Arguments<'a>,
+}
+
+impl<'a> fmt::Display for Record<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "level={} component={}", self.level.name(), self.component)?;
+        if let Some(time) = self.time {
+            write!(f, " time={}", time)?;
+        }
+        if let Some(packet) = self.packet {
+            write!(f, " packet={}", packet)?;
+        }
+        write!(f, " message={:?}", self.message.to_string())
+    }
+}
+
+// filter returns the filter configured through RUST_LOG, read once on first use.
+pub fn filter() -> &'static Filter {
+    static FILTER: OnceLock<Filter> = OnceLock::new();
+    FILTER.get_or_init(|| Filter::parse(&env::var("RUST_LOG").unwrap_or_default()))
+}
+
+// enabled returns whether what the component logs at the given level is written out, for callers
+// to check first where putting together a record is costly.
+pub fn enabled(component: &str, level: Level) -> bool {
+    filter().enabled(component, level)
+}
+
+// log writes out the record, if its level is enabled for its component.
+pub fn log(record: &Record) {
+    if enabled(record.component, record.level) {
+        let _ = writeln!(io::stderr(), "{}", record);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter() {
+        let f = Filter::parse("warn, server=trace,client=off,simulation=loud");
+        assert_eq!(f.level("server"), Some(Level::Trace));
+        assert_eq!(f.level("client"), None);
+        assert_eq!(f.level("simulation"), Some(Level::Warn));
+        assert!(f.enabled("server", Level::Debug));
+        assert!(!f.enabled("simulation", Level::Info));
+        assert!(f.enabled("simulation", Level::Error));
+        assert!(!Filter::parse("").enabled("server", Level::Error));
+        assert_eq!(Filter::parse("server=debug,server=info").level("server"), Some(Level::Info));
+    }
+
+    #[test]
+    fn record() {
+        let record = Record {
+            level: Level::Debug,
+            component: "server",
+            time: Some(Ticks(1234)),
+            packet: Some(42),
+            message: format_args!("dropped, {} queued", 10),
+        };
+        assert_eq!(
+            record.to_string(),
+            "level=debug component=server time=1234 packet=42 message=\"dropped, 10 queued\""
+        );
+    }
+}
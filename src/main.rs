@@ -3,178 +3,3356 @@ extern crate getopts;
 extern crate stats;
 
 use getopts::Options;
-use qlib::generators::*;
-use qlib::simulators::*;
+use qlib::analysis;
+use qlib::analytic::{self, Expected};
+use qlib::batch;
+use qlib::cost::{CostModel, Costs};
+use qlib::dashboard::Dashboard;
+use qlib::error::QsimError;
+use qlib::generators::Distribution;
+use qlib::debugger::Debugger;
+use qlib::ecmp::{self, Routing};
+use qlib::histogram::{Bucketing, Histogram};
+use qlib::json;
+use qlib::kendall::Model;
+use qlib::metrics::Exporter;
+use qlib::plot::{self, Chart};
+use qlib::progress::Progress;
+use qlib::quantiles::Estimator;
+use qlib::report::{self, FluidReport, LindleyReport, Report};
+use qlib::records::{self, EventLog, Recorder, RecordWriter};
+use qlib::rng;
+use qlib::scaling::{self, Point};
+use qlib::search;
+use qlib::sensitivity;
+use qlib::serve::Service;
+use qlib::simulation::*;
+use qlib::simulators::{Discipline, GilbertElliott, Limiting, Preemption, Visit};
+use qlib::slo::{self, Evaluation};
+use qlib::sweep::{self, Sweep};
+use qlib::switch::{Queueing, Switch};
+use qlib::tandem::{Execution, Tandem};
+use qlib::time::Ticks;
+use qlib::toml;
+use qlib::trace::{self, TraceEntry, TraceWriter};
+use qlib::transient;
+use qlib::ttest::{self, Replications, Test};
 use stats::OnlineStats;
 use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, IsTerminal, Read, Write};
+use std::slice;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-const DEFAULT_RATE: u32 = 10_000;
-const DEFAULT_PSIZE: u32 = 1;
-const DEFAULT_PSPEED: u32 = 10_000;
-const DEFAULT_DURATION: u32 = 5;
-const DEFAULT_QLIMIT: Option<usize> = None;
+// The simulated time (in seconds) a paced run advances by between checking the wall clock and
+// refreshing the exported metrics.
+const PACE_STEP: f64 = 0.01;
+
+// The wall-clock interval between redraws of the live dashboard.
+const DASHBOARD_INTERVAL: Duration = Duration::from_millis(100);
+// The simulated time (in seconds) between checks on whether the progress line is due a redraw, and
+// the wall-clock interval between redraws.
+const PROGRESS_STEP: f64 = 0.1;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+// The mass left in the tail of the occupancy distribution past which it's no longer printed.
+const OCCUPANCY_TAIL: f64 = 0.01;
+// The number of replications of either configuration when comparing them.
+const DEFAULT_REPLICATIONS: u32 = 10;
+// The run options that only make sense of a single run, and can't be given along with several
+// replications.
+const SINGLE_RUN_OPTIONS: [&str; 29] = [
+    "batches",
+    "batch-size",
+    "warmup-detection",
+    "histogram-output",
+    "pace",
+    "metrics-addr",
+    "tui",
+    "cdf-output",
+    "occupancy-output",
+    "overlay-mm1",
+    "plot-output",
+    "sample-interval",
+    "qlen-output",
+    "window",
+    "window-output",
+    "top-flows",
+    "acf-lags",
+    "acf-output",
+    "poisson-test",
+    "departure-trace",
+    "packet-output",
+    "packet-format",
+    "event-log",
+    "validate",
+    "slo",
+    "packet-value",
+    "drop-penalty",
+    "busy-cost",
+    "server-cost",
+];
+// The number of replications run at each step of a search.
+const DEFAULT_SEARCH_REPLICATIONS: u32 = 5;
+// The largest relative error (%) a metric may show when validating a run.
+const DEFAULT_TOLERANCE: f64 = 5.0;
+
+// model_options registers the options describing the system simulated and how its statistics are
+// estimated, shared by the subcommands that run simulations.
+fn model_options(opts: &mut Options) {
+    opts.optflag("h", "help", "Display this message");
+    opts.optopt(
+        "",
+        "config",
+        "Read options from a TOML file, keyed by their long names; flags given on the command \
+         line override those in the file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "rate",
+        &format!(
+            "Average number of generated packets/s (def: {})",
+            DEFAULT_RATE
+        ),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "psize",
+        &format!("Packet size; bits (def: {})", DEFAULT_PSIZE),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "pspeed",
+        &format!("Packet processing speed of each server; bits/s (def: {})", DEFAULT_PSPEED),
+        "NUM",
+    );
+    opts.optopt("", "servers", "Number of servers packets are processed by (def: 1)", "NUM");
+    opts.optopt(
+        "",
+        "model",
+        "Queueing model in Kendall's notation, e.g. M/D/1 or M/M/2/50, setting the arrival and \
+         service distributions (M or D), the number of servers and the capacity (def: M/D/1)",
+        "MODEL",
+    );
+    opts.optopt(
+        "",
+        "arrival",
+        "Interarrival time distribution; exp[:RATE], det[:RATE], pareto:SHAPE[,SCALE] or \
+         lognormal:SIGMA[,MU], times in seconds, setting the rate if given (def: exp)",
+        "DIST",
+    );
+    opts.optopt(
+        "",
+        "service",
+        "Service time distribution, as for --arrival; setting the packet size if the rate or \
+         scale is given (def: det)",
+        "DIST",
+    );
+    opts.optopt(
+        "",
+        "duration",
+        &format!(
+            "Duration of simulation; seconds (def: {})",
+            DEFAULT_DURATION
+        ),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "packets",
+        "Stop once this many packets have departed, the duration then only capping the run (def: \
+         as long as the resolution allows)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "warmup",
+        "Length of the warm-up period, the statistics collected over which are deleted; seconds \
+         (def: 0)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "resolution",
+        &format!(
+            "Number of ticks simulated per second; the duration can't span more than 2^32 ticks \
+             (def: {}, or as per --time-unit)",
+            DEFAULT_RESOLUTION
+        ),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "time-unit",
+        "Length of a tick, setting the resolution, and the unit times are reported in; s, ms, us \
+         or ns (def: us ticks, times in seconds, or whichever unit fits them in a run's \
+         report)",
+        "UNIT",
+    );
+    opts.optopt(
+        "",
+        "qlimit",
+        &format!(
+            "Limit on of the buffer queue length; int (def: {:?})",
+            DEFAULT_QLIMIT
+        ),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "discipline",
+        "Order queued packets are served in; fifo, lifo, prio[:CLASSES], strictly prioritizing \
+         the traffic classes listed from the highest down, or poll[:VISITS], visiting each class's \
+         queue in turn and serving it as per its setting listed: exhaustive, gated or at most K \
+         packets (def: fifo)",
+        "NAME",
+    );
+    opts.optopt(
+        "",
+        "preemption",
+        "Preempt the packet in service for one arriving under lifo, or of a higher priority \
+         class under prio, the one preempted then resuming, restarting or discarded \
+         (def: none, service isn't preempted)",
+        "POLICY",
+    );
+    opts.optopt(
+        "",
+        "seed",
+        "Seed for the random number generators, for reproducible runs (def: random)",
+        "NUM",
+    );
+    opts.optmulti(
+        "",
+        "at",
+        "Change a parameter mid-run, e.g. '60:rate=20000'; rate, pspeed or qlimit",
+        "SECS:PARAM=VALUE",
+    );
+    opts.optopt(
+        "",
+        "histogram",
+        "Bucketing of the sojourn/waiting/service time histograms; linear:WIDTH,COUNT, \
+         exp:MIN,FACTOR,COUNT or hdr:DIGITS[,UNIT], in seconds (def: exp:1e-6,1.05,400)",
+        "SPEC",
+    );
+    opts.optopt(
+        "",
+        "percentiles",
+        "Comma-separated sojourn/waiting/service time percentiles to report (def: 50,90,99,99.9)",
+        "LIST",
+    );
+    opts.optopt(
+        "",
+        "quantiles",
+        "How percentiles are estimated; histogram, or p2 for bounded memory on long runs without \
+         histograms (def: histogram)",
+        "METHOD",
+    );
+    opts.optopt(
+        "",
+        "classes",
+        "Comma-separated relative weights of the traffic classes packets are split into \
+         (def: a single class)",
+        "LIST",
+    );
+    opts.optopt(
+        "",
+        "flows",
+        "Number of flows packets are spread across uniformly (def: 1)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "tenants",
+        "Comma-separated relative weights of the tenants packets are split between, each held to \
+         at most SHARE of the buffer and RATE packets/s if given; packets over quota are dropped \
+         (def: a single tenant)",
+        "W[:SHARE[:RATE]],...",
+    );
+    opts.optopt(
+        "",
+        "rate-limit",
+        &format!(
+            "Pass packets through a rate limiter ahead of the server, admitting LIMIT packets/s in \
+             bursts of up to BURST by fixed-window, sliding-window or token-bucket, and rejecting \
+             the rest (def: none, bursts of {})",
+            DEFAULT_BURST
+        ),
+        "ALGO,LIMIT[,BURST]",
+    );
+    opts.optopt(
+        "",
+        "marking",
+        "Color packets past the rate limiter with a two-rate three-color marker: green within CIR \
+         bits/s in bursts of CBS bits, yellow within PIR bits/s in bursts of PBS bits, and red \
+         beyond; yellow and red packets are dropped once YELLOW and RED packets are queued, if \
+         given (def: none)",
+        "CIR,CBS,PIR,PBS[,YELLOW[,RED]]",
+    );
+    opts.optopt(
+        "",
+        "wait-threshold",
+        "Count the packets waiting longer than this as starved, by class, flow, tenant and color, \
+         along with the longest wait; seconds (def: none)",
+        "SECS",
+    );
+    opts.optopt(
+        "",
+        "retransmit",
+        &format!(
+            "Retransmit packets dropped once the timeout is up, up to RETRIES times, counting them \
+             as dropped only once given up on; seconds (def: none, {} retries)",
+            DEFAULT_RETRIES
+        ),
+        "SECS[,RETRIES]",
+    );
+    opts.optflag(
+        "",
+        "backpressure",
+        "Block the client while the buffer's full instead of dropping its packets, as with a \
+         flow-controlled producer; needs --qlimit",
+    );
+    opts.optopt(
+        "",
+        "ack",
+        "Acknowledge every packet delivered over a reverse path with this propagation delay, \
+         reporting round-trip times; seconds (def: none)",
+        "SECS",
+    );
+    opts.optopt(
+        "",
+        "ack-queue",
+        &format!(
+            "Queue ACKs, LENGTH bits long, for a server of this speed on the reverse path; \
+             bits/s (def: none, {} bit ACKs)",
+            DEFAULT_ACK_LENGTH
+        ),
+        "SPEED[,LENGTH]",
+    );
+    opts.optopt(
+        "",
+        "ack-window",
+        "Block the client while this many packets are unacknowledged, as a sliding-window \
+         transport would; needs --ack (def: none)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "link",
+        "Have packets processed cross a link with this propagation delay to the receiver, \
+         reporting the order they're received in; seconds (def: none)",
+        "SECS",
+    );
+    opts.optopt(
+        "",
+        "link-loss",
+        "Lose packets on the link in bursts, as per a Gilbert-Elliott channel moving from the good \
+         state to the bad one with probability P and back with probability R, losing packets \
+         with probability GOOD and BAD in each; needs --link (def: none, GOOD 0 and BAD 1)",
+        "P,R[,GOOD,BAD]",
+    );
+    opts.optopt(
+        "",
+        "reorder",
+        "Hold packets back on the link with this probability, for up to SECS longer, letting \
+         those behind them overtake them; needs --link (def: 0)",
+        "PROB,SECS",
+    );
+    opts.optopt(
+        "",
+        "duplicate",
+        "Duplicate packets on the link with this probability; needs --link (def: 0)",
+        "PROB",
+    );
+    opts.optopt(
+        "",
+        "trace",
+        "Replay the arrivals of a trace (time,length per line, in seconds and bits) instead of \
+         generating them; the rate and packet size are ignored",
+        "FILE",
+    );
+}
+
+// run_options registers the options of single runs: what's collected and reported, and where to.
+fn run_options(opts: &mut Options) {
+    opts.optopt(
+        "",
+        "replications",
+        "Number of independent replications to run, reporting the mean of each metric across \
+         them along with its confidence interval (def: 1)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "batches",
+        "Number of batches for batch means confidence intervals (def: none)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "batch-size",
+        "Length of a batch for batch means confidence intervals; ticks",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "warmup-detection",
+        "Detect the end of the warm-up period; mser5 or welch (def: none)",
+        "METHOD",
+    );
+    opts.optopt(
+        "",
+        "welch-output",
+        "Write the Welch plot data of the queue length and sojourn time to a CSV file, per \
+         replication and averaged across them",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "histogram-output",
+        "Write the sojourn time histogram to a CSV file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "pace",
+        "Pace the simulation against the wall clock, running FACTOR simulated seconds per second \
+         (def: unpaced)",
+        "FACTOR",
+    );
+    opts.optopt(
+        "",
+        "metrics-addr",
+        "Serve Prometheus metrics at http://ADDR/metrics while the simulation runs",
+        "ADDR",
+    );
+    opts.optflag(
+        "",
+        "tui",
+        "Show a live dashboard of the simulation as it progresses, paced to real time unless \
+         --pace is given",
+    );
+    opts.optopt(
+        "",
+        "cdf-output",
+        "Write the empirical CDF and complementary CDF of the sojourn time to a CSV file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "occupancy-output",
+        "Write the distribution of the number of packets in the system, P(N = k), and its \
+         complementary CDF to a CSV file",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "overlay-mm1",
+        "Add the distributions expected of an M/M/1 queue at the same load (M/M/1/K given a \
+         buffer limit, for the occupancy only) to the CDF and occupancy outputs",
+    );
+    opts.optopt(
+        "",
+        "plot-output",
+        "Render the queue length over time, the latency CDF and the throughput as SVG charts, \
+         written to PREFIX-qlen.svg, PREFIX-latency.svg and PREFIX-throughput.svg",
+        "PREFIX",
+    );
+    opts.optopt(
+        "",
+        "sample-interval",
+        "Interval at which the queue length and server state are sampled; ticks (def: 1000)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "qlen-output",
+        "Write the sampled queue length and server state time series to a CSV file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "window",
+        &format!(
+            "Window over which arrivals, departures and drops are counted; seconds (def: {})",
+            DEFAULT_WINDOW
+        ),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "window-output",
+        "Write the per-window arrivals, departures, drops, throughput and loss to a CSV file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "top-flows",
+        "Report statistics for this many of the busiest and least served flows (def: 0)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "acf-lags",
+        "Compute the autocorrelation of waiting and interdeparture times up to this lag",
+        "NUM",
+    );
+    opts.optflag(
+        "",
+        "poisson-test",
+        "Test the interdeparture times for being those of a Poisson process (Burke's theorem)",
+    );
+    opts.optopt(
+        "",
+        "acf-output",
+        "Write the autocorrelation functions to a CSV file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "departure-trace",
+        "Write the departures to a trace file, to be replayed by another run with --trace",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "packet-output",
+        "Write a record per departed or dropped packet to a file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "packet-format",
+        "Format of the per-packet records; csv or jsonl (def: csv)",
+        "FORMAT",
+    );
+    opts.optopt(
+        "",
+        "event-log",
+        "Write every arrival, enqueue, drop, service start and departure to a JSONL file",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "validate",
+        "Compare the results against the closed-form solution of the M/D/1(/K) or M/M/1(/K) model",
+    );
+    opts.optflag(
+        "",
+        "lindley",
+        "Compute the waiting, sojourn and service times directly with the Lindley recursion, \
+         orders of magnitude faster and with identical results; FIFO queues with an unlimited \
+         buffer only, with no warm-up or scheduled changes",
+    );
+    opts.optflag(
+        "",
+        "fluid",
+        "Approximate the run as a fluid queue driven by the average arrival and service rates, \
+         in next to no time however many packets it would have; only how the queue builds up \
+         and drains under overload is captured, so the results are approximate",
+    );
+    opts.optopt(
+        "",
+        "slo",
+        "Comma-separated objectives checked after the run, e.g. p99<=50ms,loss<=0.1%; exits with \
+         status 2 if any is violated",
+        "LIST",
+    );
+    opts.optopt(
+        "",
+        "packet-value",
+        "Value of each packet delivered, for the cost model (def: 0)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "drop-penalty",
+        "Penalty for each packet dropped, for the cost model (def: 0)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "busy-cost",
+        "Cost of each second the server is busy, for the cost model (def: 0)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "server-cost",
+        "Cost of each second the server is provisioned for, for the cost model (def: 0)",
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "output-format",
+        "Format of the configuration and results printed; text or json (def: text)",
+        "FORMAT",
+    );
+    opts.optflag(
+        "q",
+        "quiet",
+        "Print nothing but the final results, as JSON unless --output-format says otherwise; no \
+         configuration, progress or warm-up report",
+    );
+    out_options(opts);
+}
+
+// out_options registers the options writing results out to a file.
+fn out_options(opts: &mut Options) {
+    opts.optopt(
+        "",
+        "out",
+        "Write the results to a file as well, as JSON (one document per line) or a row of CSV, \
+         going by the extension: .json, .jsonl or .csv",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "append",
+        "Add to the --out file instead of overwriting it, the CSV header only being written to an \
+         empty file",
+    );
+}
 
 fn construct_options() -> Options {
     let mut opts = Options::new();
-    opts.optflag("h", "help", "Display this message");
+    model_options(&mut opts);
+    run_options(&mut opts);
+    opts
+}
+
+// opt returns the value of the option, if given, erring if it doesn't parse.
+fn opt<T: FromStr>(matches: &getopts::Matches, name: &str) -> Result<Option<T>, QsimError> {
+    match matches.opt_str(name) {
+        Some(x) => x.parse::<T>().map(Some).map_err(|_| QsimError::parse(name, x)),
+        None => Ok(None),
+    }
+}
+
+// opt_list returns the comma-separated values of the option, if given, erring if any doesn't
+// parse.
+fn opt_list<T: FromStr>(
+    matches: &getopts::Matches,
+    name: &str,
+) -> Result<Option<Vec<T>>, QsimError> {
+    match matches.opt_str(name) {
+        Some(x) => x
+            .split(',')
+            .map(|v| v.trim().parse::<T>().map_err(|_| QsimError::parse(name, &x)))
+            .collect::<Result<Vec<T>, _>>()
+            .map(Some),
+        None => Ok(None),
+    }
+}
+
+// or_exit returns the result's value, or reports the error and exits.
+fn or_exit<T>(program: &str, result: Result<T, QsimError>) -> T {
+    result.unwrap_or_else(|e| {
+        println!("{}: {}", program, e);
+        std::process::exit(1)
+    })
+}
+
+// replications returns the number of replications to run, the given number by default.
+fn replications(matches: &getopts::Matches, default: u32) -> Result<u32, QsimError> {
+    match opt(matches, "replications")?.unwrap_or(default) {
+        0 => Err(QsimError::invalid("number of replications", 0, "at least one has to be run")),
+        n => Ok(n),
+    }
+}
+
+fn parse_params(matches: &getopts::Matches) -> Result<Config, QsimError> {
+    let rate = opt(matches, "rate")?.unwrap_or(DEFAULT_RATE);
+    let psize = opt(matches, "psize")?.unwrap_or(DEFAULT_PSIZE);
+    let pspeed = opt(matches, "pspeed")?.unwrap_or(DEFAULT_PSPEED);
+    let servers = opt(matches, "servers")?.unwrap_or(1);
+    let packets = opt(matches, "packets")?;
+    let resolution = match (opt(matches, "resolution")?, matches.opt_present("time-unit")) {
+        (Some(x), _) => x,
+        (None, true) => time_unit(matches)?.per_second(),
+        (None, false) => DEFAULT_RESOLUTION,
+    };
+    let duration = match opt(matches, "duration")? {
+        Some(x) => x,
+        // Runs sized by the number of packets last as long as it takes.
+        None if packets.is_some() => (f64::from(u32::MAX) / resolution) as u32,
+        None => DEFAULT_DURATION,
+    };
+    let warmup = opt(matches, "warmup")?.unwrap_or(0.0);
+    let qlimit = match opt::<u32>(matches, "qlimit")? {
+        Some(x) => Some(x as usize),
+        None => DEFAULT_QLIMIT,
+    };
+    let discipline = match matches.opt_str("discipline") {
+        Some(x) => parse_discipline(&x)?,
+        None => Discipline::Fifo,
+    };
+    let preemption = match matches.opt_str("preemption") {
+        Some(x) => Some(Preemption::parse(&x).ok_or_else(|| QsimError::parse("preemption", x))?),
+        None => None,
+    };
+    let seed = opt(matches, "seed")?;
+    let schedule = matches.opt_strs("at").iter().map(|x| parse_change(x)).collect::<Result<_, _>>();
+    let schedule = schedule?;
+    let histogram = match matches.opt_str("histogram") {
+        Some(x) => Bucketing::parse(&x).ok_or_else(|| QsimError::parse("histogram", x))?,
+        None => Bucketing::default(),
+    };
+    let class_weights = opt_list(matches, "classes")?.unwrap_or_default();
+    let flows = opt(matches, "flows")?.unwrap_or(1);
+    let percentiles =
+        opt_list(matches, "percentiles")?.unwrap_or_else(|| DEFAULT_PERCENTILES.to_vec());
+    let estimator = match matches.opt_str("quantiles") {
+        Some(x) => Estimator::parse(&x).ok_or_else(|| QsimError::parse("quantiles", x))?,
+        None => Estimator::Histogram,
+    };
+    let retransmission = match matches.opt_str("retransmit") {
+        Some(x) => Some(parse_retransmission(&x)?),
+        None => None,
+    };
+    let tenants = match matches.opt_str("tenants") {
+        Some(x) => parse_tenants(&x)?,
+        None => vec![],
+    };
+    let rate_limiting = match matches.opt_str("rate-limit") {
+        Some(x) => Some(parse_rate_limiting(&x)?),
+        None => None,
+    };
+    let marking = match matches.opt_str("marking") {
+        Some(x) => Some(parse_marking(&x)?),
+        None => None,
+    };
+    let ack = parse_ack(matches)?;
+    let link = parse_link(matches)?;
+
+    let mut config = Config {
+        rate,
+        psize,
+        pspeed,
+        servers,
+        duration,
+        packets,
+        warmup,
+        qlimit,
+        discipline,
+        preemption,
+        resolution,
+        seed,
+        schedule,
+        histogram,
+        percentiles,
+        estimator,
+        class_weights,
+        flows,
+        tenants,
+        rate_limiting,
+        marking,
+        wait_threshold: opt(matches, "wait-threshold")?,
+        retransmission,
+        backpressure: matches.opt_present("backpressure"),
+        ack,
+        link,
+        ..Config::default()
+    };
+    if let Some(x) = matches.opt_str("model") {
+        let model = Model::parse(&x).map_err(QsimError::Config)?;
+        if matches.opt_present("servers") && config.servers != model.servers {
+            let reason = format!("--servers {} contradicts the model {}", config.servers, x);
+            return Err(QsimError::Config(reason));
+        }
+        let (qlimit, discipline) = (config.qlimit, config.discipline.clone());
+        model.apply(&mut config);
+        // An explicit buffer limit holds if the model leaves the capacity out, as does an explicit
+        // discipline.
+        if model.capacity.is_none() {
+            config.qlimit = qlimit;
+        }
+        if matches.opt_present("discipline") {
+            config.discipline = discipline;
+        }
+    }
+    // Distributions given in full override the model's.
+    if let Some(x) = matches.opt_str("arrival") {
+        let (distribution, mean) = parse_distribution("arrival", &x)?;
+        config.arrival = distribution;
+        if let Some(mean) = mean {
+            config.rate = (1.0 / mean).round() as u32;
+        }
+    }
+    if let Some(x) = matches.opt_str("service") {
+        let (distribution, mean) = parse_distribution("service", &x)?;
+        config.service = distribution;
+        if let Some(mean) = mean {
+            config.psize = ((mean * f64::from(config.pspeed)).round() as u32).max(1);
+        }
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+// jobs returns the number of things to run at a time, as many as there are CPUs by default.
+fn jobs(matches: &getopts::Matches) -> Result<usize, QsimError> {
+    let cpus = || thread::available_parallelism().map_or(1, |n| n.get());
+    Ok(opt(matches, "jobs")?.unwrap_or_else(cpus))
+}
+
+// time_unit returns the unit times are reported in.
+fn time_unit(matches: &getopts::Matches) -> Result<TimeUnit, QsimError> {
+    match matches.opt_str("time-unit") {
+        Some(x) => TimeUnit::parse(&x).ok_or_else(|| QsimError::parse("time-unit", x)),
+        None => Ok(TimeUnit::Seconds),
+    }
+}
+
+// parse_run_params sets what's collected over a single run as per the run options, validating the
+// configuration once they're set.
+fn parse_run_params(matches: &getopts::Matches, config: &mut Config) -> Result<(), QsimError> {
+    config.batching = match (opt(matches, "batches")?, opt(matches, "batch-size")?) {
+        (Some(x), _) => Some(Batching::Count(x)),
+        (None, Some(x)) => Some(Batching::Ticks(x)),
+        (None, None) => None,
+    };
+    config.warmup_detection = match matches.opt_str("warmup-detection") {
+        Some(ref x) if x == "mser5" => Some(WarmupDetection::Mser5),
+        Some(ref x) if x == "welch" => Some(WarmupDetection::Welch),
+        Some(x) => return Err(QsimError::parse("warmup-detection", x)),
+        None if matches.opt_present("welch-output") => Some(WarmupDetection::Welch),
+        None => None,
+    };
+    config.sample_interval = match opt(matches, "sample-interval")? {
+        Some(x) => Some(x),
+        None if matches.opt_present("qlen-output") || matches.opt_present("plot-output") => {
+            Some(DEFAULT_SAMPLE_INTERVAL)
+        }
+        None => None,
+    };
+    let window = match opt(matches, "window")? {
+        Some(x) => Some(x),
+        None if matches.opt_present("window-output") || matches.opt_present("plot-output") => {
+            Some(DEFAULT_WINDOW)
+        }
+        None => None,
+    };
+    config.window = window.map(|w| ((w * config.resolution) as u32).max(1));
+    config.top_flows = opt(matches, "top-flows")?.unwrap_or(0);
+    config.acf_lags = match opt(matches, "acf-lags")? {
+        Some(x) => Some(x),
+        None if matches.opt_present("acf-output") => Some(DEFAULT_ACF_LAGS),
+        None => None,
+    };
+    config.poisson_test = matches.opt_present("poisson-test");
+    config.validate()
+}
+
+// parse_distribution parses distributions of the form NAME[:PARAMS], returning the mean (in
+// seconds) if the parameters pin it down: exp:RATE and det:RATE (events/s), pareto:SHAPE,SCALE
+// (the minimum, in seconds) and lognormal:SIGMA,MU (of the logarithm of the time in seconds).
+fn parse_distribution(option: &str, s: &str) -> Result<(Distribution, Option<f64>), QsimError> {
+    let mut parts = s.splitn(2, ':');
+    let name = parts.next().unwrap();
+    let params: Vec<f64> = match parts.next() {
+        Some(p) => p
+            .split(',')
+            .map(|x| x.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| QsimError::parse(option, s))?,
+        None => vec![],
+    };
+    // The generator's built off of the parameters, which have to be finite, rates and scales
+    // positive.
+    let invalid = |parameter: &str, x: f64, reason: &str| {
+        Err(QsimError::invalid(&format!("{} {}", option, parameter), x, reason))
+    };
+    if let Some(&x) = params.iter().find(|x| !x.is_finite()) {
+        return invalid("parameter", x, "it must be finite");
+    }
+    Ok(match (name, &params[..]) {
+        ("exp", &[rate]) | ("det", &[rate]) if rate <= 0.0 => {
+            return invalid("rate", rate, "it must be positive");
+        }
+        ("pareto", &[_, scale]) if scale <= 0.0 => {
+            return invalid("scale", scale, "it must be positive");
+        }
+        ("lognormal", &[sigma, ..]) if sigma < 0.0 => {
+            return invalid("sigma", sigma, "it can't be negative");
+        }
+        ("exp", &[]) => (Distribution::Exponential, None),
+        ("exp", &[rate]) => (Distribution::Exponential, Some(1.0 / rate)),
+        ("det", &[]) => (Distribution::Deterministic, None),
+        ("det", &[rate]) => (Distribution::Deterministic, Some(1.0 / rate)),
+        ("pareto", &[shape, ..]) if shape <= 1.0 => {
+            let reason = "it must exceed 1 for the mean to be finite";
+            return Err(QsimError::invalid("Pareto shape", shape, reason));
+        }
+        ("pareto", &[shape]) => (Distribution::Pareto { shape }, None),
+        ("pareto", &[shape, scale]) => {
+            (Distribution::Pareto { shape }, Some(shape * scale / (shape - 1.0)))
+        }
+        ("lognormal", &[sigma]) => (Distribution::LogNormal { sigma }, None),
+        ("lognormal", &[sigma, mu]) => {
+            (Distribution::LogNormal { sigma }, Some((mu + sigma * sigma / 2.0).exp()))
+        }
+        _ => return Err(QsimError::parse(option, s)),
+    })
+}
+
+// config_args returns the options set in the given TOML file as command-line arguments, leaving
+// out those also given on the command line, and those of a single run that the subcommand doesn't
+// take (so that one file can serve them all). Keys are the options' long names (with dashes or
+// underscores); flags take booleans, lists take arrays, as does --at, one element per change.
+fn config_args(path: &str, matches: &getopts::Matches) -> Result<Vec<String>, String> {
+    let contents = read_to_string(path)?;
+    option_args(toml::parse(&contents)?, matches)
+}
+
+// option_args returns the options given as command-line arguments, as config_args does.
+fn option_args(
+    options: Vec<(String, toml::Value)>,
+    matches: &getopts::Matches,
+) -> Result<Vec<String>, String> {
+    let known = construct_options().parse(Vec::<String>::new()).unwrap();
+    let mut args = vec![];
+    for (key, value) in options {
+        let name = key.replace('_', "-");
+        if name == "config" || name == "help" || !known.opt_defined(&name) {
+            return Err(format!("unknown option {}", key));
+        }
+        if !matches.opt_defined(&name) || matches.opt_present(&name) {
+            continue;
+        }
+        let flag = format!("--{}", name);
+        match value {
+            toml::Value::Boolean(true) => args.push(flag),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Array(ref values) if name == "at" => {
+                for v in values {
+                    args.push(flag.clone());
+                    args.push(v.to_string());
+                }
+            }
+            v => {
+                args.push(flag);
+                args.push(v.to_string());
+            }
+        }
+    }
+    Ok(args)
+}
+
+// parse_discipline parses a queue discipline: fifo, lifo, prio[:CLASSES], the classes being
+// comma-separated and listed from the highest priority down, or poll[:VISITS], the visits being
+// comma-separated and listed by class.
+fn parse_discipline(s: &str) -> Result<Discipline, QsimError> {
+    let invalid = || QsimError::parse("discipline", s);
+    let mut parts = s.splitn(2, ':');
+    match (parts.next().unwrap(), parts.next()) {
+        ("fifo", None) => Ok(Discipline::Fifo),
+        ("lifo", None) => Ok(Discipline::Lifo),
+        ("prio", None) => Ok(Discipline::Priority(vec![])),
+        ("prio", Some(classes)) => {
+            let classes = classes.split(',').map(|c| c.trim().parse().map_err(|_| invalid()));
+            Ok(Discipline::Priority(classes.collect::<Result<_, _>>()?))
+        }
+        ("poll", None) => Ok(Discipline::Polling(vec![])),
+        ("poll", Some(visits)) => {
+            let visits = visits.split(',').map(|v| Visit::parse(v.trim()).ok_or_else(invalid));
+            Ok(Discipline::Polling(visits.collect::<Result<_, _>>()?))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// parse_tenants parses tenants of the form W[:SHARE[:RATE]], separated by commas.
+fn parse_tenants(s: &str) -> Result<Vec<Tenant>, QsimError> {
+    let invalid = || QsimError::parse("tenants", s);
+    s.split(',')
+        .map(|t| {
+            let values: Vec<f64> = t
+                .split(':')
+                .map(|x| x.trim().parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            if values.len() > 3 {
+                return Err(invalid());
+            }
+            Ok(Tenant {
+                weight: values[0],
+                share: values.get(1).cloned(),
+                rate: values.get(2).cloned(),
+            })
+        })
+        .collect()
+}
+
+// parse_rate_limiting parses rate limiters of the form ALGO,LIMIT[,BURST].
+fn parse_rate_limiting(s: &str) -> Result<RateLimiting, QsimError> {
+    let invalid = || QsimError::parse("rate-limit", s);
+    let mut parts = s.splitn(3, ',');
+    let algorithm = Limiting::parse(parts.next().unwrap().trim()).ok_or_else(invalid)?;
+    let limit = parts.next().ok_or_else(invalid)?.trim().parse().map_err(|_| invalid())?;
+    let burst = match parts.next() {
+        Some(x) => x.trim().parse().map_err(|_| invalid())?,
+        None => DEFAULT_BURST,
+    };
+    Ok(RateLimiting { algorithm, limit, burst })
+}
+
+// parse_marking parses markers of the form CIR,CBS,PIR,PBS[,YELLOW[,RED]], a limit left empty
+// meaning none.
+fn parse_marking(s: &str) -> Result<Marking, QsimError> {
+    let invalid = || QsimError::parse("marking", s);
+    let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    if parts.len() < 4 || parts.len() > 6 {
+        return Err(invalid());
+    }
+    let limit = |i: usize| match parts.get(i) {
+        Some(x) if !x.is_empty() => x.parse().map(Some).map_err(|_| invalid()),
+        _ => Ok(None),
+    };
+    Ok(Marking {
+        committed: parts[0].parse().map_err(|_| invalid())?,
+        committed_burst: parts[1].parse().map_err(|_| invalid())?,
+        peak: parts[2].parse().map_err(|_| invalid())?,
+        peak_burst: parts[3].parse().map_err(|_| invalid())?,
+        yellow_limit: limit(4)?,
+        red_limit: limit(5)?,
+    })
+}
+
+// parse_retransmission parses retransmission settings of the form SECS[,RETRIES].
+fn parse_retransmission(s: &str) -> Result<Retransmission, QsimError> {
+    let invalid = || QsimError::parse("retransmit", s);
+    let mut parts = s.splitn(2, ',');
+    let timeout = parts.next().unwrap().trim().parse().map_err(|_| invalid())?;
+    let retries = match parts.next() {
+        Some(x) => x.trim().parse().map_err(|_| invalid())?,
+        None => DEFAULT_RETRIES,
+    };
+    Ok(Retransmission { timeout, retries })
+}
+
+// parse_ack returns the reverse path, if packets are to be acknowledged, from --ack along with the
+// --ack-queue and --ack-window that only go with it.
+fn parse_ack(matches: &getopts::Matches) -> Result<Option<AckPath>, QsimError> {
+    let delay = match opt(matches, "ack")? {
+        Some(delay) => delay,
+        None => {
+            if let Some(name) = ["ack-queue", "ack-window"].iter().find(|&&n| matches.opt_present(n)) {
+                return Err(QsimError::Config(format!("--{} needs --ack", name)));
+            }
+            return Ok(None);
+        }
+    };
+    let (pspeed, length) = match matches.opt_str("ack-queue") {
+        Some(x) => {
+            let invalid = || QsimError::parse("ack-queue", &x);
+            let mut parts = x.splitn(2, ',');
+            let pspeed = parts.next().unwrap().trim().parse().map_err(|_| invalid())?;
+            let length = match parts.next() {
+                Some(l) => l.trim().parse().map_err(|_| invalid())?,
+                None => DEFAULT_ACK_LENGTH,
+            };
+            (Some(pspeed), length)
+        }
+        None => (None, DEFAULT_ACK_LENGTH),
+    };
+    Ok(Some(AckPath {
+        delay,
+        length,
+        pspeed,
+        window: opt(matches, "ack-window")?,
+    }))
+}
+
+// parse_link returns the link to the receiver, if there's one, from --link along with the
+// --link-loss, --reorder and --duplicate that only go with it.
+fn parse_link(matches: &getopts::Matches) -> Result<Option<LinkPath>, QsimError> {
+    let delay = match opt(matches, "link")? {
+        Some(delay) => delay,
+        None => {
+            let names = ["link-loss", "reorder", "duplicate"];
+            if let Some(name) = names.iter().find(|&&n| matches.opt_present(n)) {
+                return Err(QsimError::Config(format!("--{} needs --link", name)));
+            }
+            return Ok(None);
+        }
+    };
+    let (reorder, reorder_delay) = match matches.opt_str("reorder") {
+        Some(x) => {
+            let invalid = || QsimError::parse("reorder", &x);
+            let mut parts = x.splitn(2, ',');
+            let reorder = parts.next().unwrap().trim().parse().map_err(|_| invalid())?;
+            let delay = parts.next().ok_or_else(invalid)?.trim().parse().map_err(|_| invalid())?;
+            (reorder, delay)
+        }
+        None => (0.0, 0.0),
+    };
+    let loss = match matches.opt_str("link-loss") {
+        Some(x) => Some(parse_loss_channel(&x)?),
+        None => None,
+    };
+    Ok(Some(LinkPath {
+        delay,
+        loss,
+        reorder,
+        reorder_delay,
+        duplicate: opt(matches, "duplicate")?.unwrap_or(0.0),
+    }))
+}
+
+// parse_loss_channel parses Gilbert-Elliott channels of the form P,R[,GOOD,BAD].
+fn parse_loss_channel(s: &str) -> Result<GilbertElliott, QsimError> {
+    let invalid = || QsimError::parse("link-loss", s);
+    let values: Vec<f64> = s
+        .split(',')
+        .map(|x| x.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (good_loss, bad_loss) = match values.len() {
+        2 => (0.0, 1.0),
+        4 => (values[2], values[3]),
+        _ => return Err(invalid()),
+    };
+    Ok(GilbertElliott {
+        p: values[0],
+        r: values[1],
+        good_loss,
+        bad_loss,
+    })
+}
+
+// parse_cost_model returns the cost model, if any of its prices were given.
+fn parse_cost_model(matches: &getopts::Matches) -> Result<Option<CostModel>, QsimError> {
+    let names = ["packet-value", "drop-penalty", "busy-cost", "server-cost"];
+    if !names.iter().any(|name| matches.opt_present(name)) {
+        return Ok(None);
+    }
+    let price = |name: &str| opt(matches, name).map(|x| x.unwrap_or(0.0));
+    Ok(Some(CostModel {
+        packet_value: price("packet-value")?,
+        drop_penalty: price("drop-penalty")?,
+        busy_cost: price("busy-cost")?,
+        server_cost: price("server-cost")?,
+    }))
+}
+
+// parse_change parses scheduled changes of the form SECS:PARAM=VALUE.
+fn parse_change(s: &str) -> Result<(f64, Change), QsimError> {
+    let invalid = || QsimError::parse("at", s);
+    let mut parts = s.splitn(2, ':');
+    let at = parts.next().unwrap().parse::<f64>().map_err(|_| invalid())?;
+    if !at.is_finite() {
+        return Err(QsimError::invalid("change time", at, "it must be finite"));
+    }
+    let mut assignment = parts.next().ok_or_else(invalid)?.splitn(2, '=');
+    let param = assignment.next().unwrap();
+    let value = assignment.next().ok_or_else(invalid)?;
+    let change = match (param, value) {
+        ("rate", v) => Change::Rate(v.parse().map_err(|_| invalid())?),
+        ("pspeed", v) => Change::Pspeed(v.parse().map_err(|_| invalid())?),
+        ("qlimit", "none") => Change::Qlimit(None),
+        ("qlimit", v) => Change::Qlimit(Some(v.parse().map_err(|_| invalid())?)),
+        (param, _) => {
+            let reason = "only the rate, pspeed and qlimit can change";
+            return Err(QsimError::invalid("parameter", param, reason));
+        }
+    };
+    change.validate()?;
+    Ok((at, change))
+}
+
+// The subcommands, along with their usage.
+const SUBCOMMANDS: &str = "Subcommands:
+    run [options]                       Simulate a single run (the default)
+    sweep --vary RANGE [options]        Write a CSV of metrics over parameter ranges
+    compare [options] A.toml B.toml     Test the differences between two configurations
+    ttest [options] A.json B.json       Test the differences between two replicated runs
+    batch [options] MANIFEST            Write a table of metrics over named scenarios
+    validate [options]                  Check a run against the closed-form solution
+    sensitivity [options]               Estimate the elasticities of the metrics
+    scaling [options]                   Measure how waits grow as the load approaches 1
+    transient [options]                 Track the queue over time across replications
+    ecmp --paths NUM [options]          Route flows across equal-cost paths by hash
+    tandem --stages LIST [options]      Pass the packets processed through further servers
+    solve --slo LIST [options]          Find the slowest server or smallest buffer meeting
+                                        the objectives
+    analyze [options] TRACE             Summarize a packet trace
+    debug [options]                     Step through a run interactively
+    calc erlang-b|erlang-c|mva ARGS     Evaluate queueing formulas
+    switch [options]                    Measure head-of-line blocking in an input-queued switch
+    serve [options]                     Run simulations submitted over HTTP";
+
+fn print_usage(program: &str, usage: &str, opts: &Options) {
+    let brief = format!("Usage: {} {}", program, usage);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    // Without a subcommand, the arguments are those of a single run.
+    let (command, args) = match args.get(1) {
+        Some(command) if !command.starts_with('-') => (command.as_str(), &args[2..]),
+        _ => ("run", &args[1..]),
+    };
+    match command {
+        "run" => run(&program, args),
+        "sweep" => run_sweep(&program, args),
+        "compare" => run_compare(&program, args),
+        "ttest" => run_ttest(&program, args),
+        "batch" => run_batch(&program, args),
+        "validate" => run_validate(&program, args),
+        "solve" => run_solve(&program, args),
+        "sensitivity" => run_sensitivity(&program, args),
+        "scaling" => run_scaling(&program, args),
+        "transient" => run_transient(&program, args),
+        "ecmp" => run_ecmp(&program, args),
+        "tandem" => run_tandem(&program, args),
+        "analyze" => run_analyze(&program, args),
+        "debug" => run_debug(&program, args),
+        "calc" => run_calc(&program, args),
+        "switch" => run_switch(&program, args),
+        "serve" => run_serve(&program, args),
+        command => {
+            println!("{}: unknown subcommand {}\n\n{}", program, command, SUBCOMMANDS);
+            std::process::exit(1)
+        }
+    }
+}
+
+// run simulates a single run, reporting the results and writing out whatever else is asked for.
+fn run(program: &str, args: &[String]) {
+    let opts = construct_options();
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, &format!("[run] [options]\n\n{}", SUBCOMMANDS), &opts);
+        return;
+    }
+
+    let mut config = or_exit(program, parse_params(&matches));
+    or_exit(program, parse_run_params(&matches, &mut config));
+    load_trace(program, &matches, &mut config);
+    let objectives = match matches.opt_str("slo").map(|x| slo::parse(&x)) {
+        Some(Ok(objectives)) => objectives,
+        Some(Err(e)) => {
+            println!("{}: {}", program, e);
+            std::process::exit(1)
+        }
+        None => vec![],
+    };
+    // The percentiles objectives are set on have to be estimated along with the others.
+    for o in &objectives {
+        if let slo::Metric::Sojourn(p) = o.metric {
+            if !config.percentiles.contains(&p) {
+                config.percentiles.push(p);
+            }
+        }
+    }
+    let quiet = matches.opt_present("quiet");
+    let unit = or_exit(program, time_unit(&matches));
+    let json = or_exit(program, output_json(&matches));
+    check_out(program, &matches, &[".json", ".jsonl", ".csv"]);
+    let replications = or_exit(program, replications(&matches, 1));
+    if replications > 1 {
+        if let Some(o) = SINGLE_RUN_OPTIONS.iter().find(|&&o| matches.opt_present(o)) {
+            println!("{}: --{} only applies to single runs, not to replications", program, o);
+            std::process::exit(1)
+        }
+    }
+    if matches.opt_present("lindley") && matches.opt_present("fluid") {
+        println!("{}: --lindley and --fluid are mutually exclusive", program);
+        std::process::exit(1)
+    }
+    // An unstable queue is still simulated, but its statistics are meaningless, which is worth
+    // pointing out on stderr, away from the results. The fluid approximation is for overload.
+    if config.unstable() && !matches.opt_present("fluid") {
+        eprintln!(
+            "{}: warning: the offered load (ρ = {:.4}) is at least what the server can take and \
+             the buffer is unlimited, so the queue grows without bound and there's no steady \
+             state to measure",
+            program,
+            config.offered_load()
+        );
+    }
+    if matches.opt_present("fluid") {
+        let mut others = SINGLE_RUN_OPTIONS.iter().chain(&["replications", "out", "welch-output"]);
+        if let Some(o) = others.find(|&&o| matches.opt_present(o)) {
+            println!("{}: --{} doesn't apply to the fluid approximation", program, o);
+            std::process::exit(1)
+        }
+        if json {
+            println!("{}: --fluid only reports its results as text", program);
+            std::process::exit(1)
+        }
+        let results = or_exit(program, qlib::fluid::run(&config));
+        if !quiet {
+            print_config(&config, replications, unit);
+        }
+        let report = FluidReport::new(&results);
+        if matches.opt_present("time-unit") {
+            print!("{}", report.unit(unit));
+        } else {
+            print!("{}", report);
+        }
+        return;
+    }
+    if matches.opt_present("lindley") {
+        let mut others = SINGLE_RUN_OPTIONS.iter().chain(&["replications", "out", "welch-output"]);
+        if let Some(o) = others.find(|&&o| matches.opt_present(o)) {
+            println!("{}: --{} doesn't apply to the Lindley recursion", program, o);
+            std::process::exit(1)
+        }
+        if json {
+            println!("{}: --lindley only reports its results as text", program);
+            std::process::exit(1)
+        }
+        let results = or_exit(program, qlib::lindley::run(&config));
+        if !quiet {
+            print_config(&config, replications, unit);
+        }
+        let report = LindleyReport::new(&results);
+        if matches.opt_present("time-unit") {
+            print!("{}", report.unit(unit));
+        } else {
+            print!("{}", report);
+        }
+        return;
+    }
+    if !json && !quiet {
+        print_config(&config, replications, unit);
+    }
+    if replications > 1 {
+        run_replications(program, &matches, &config, replications, unit);
+        return;
+    }
+    // Progress is reported on stderr, and only to a terminal, so as not to interfere with the
+    // results.
+    let tui = matches.opt_present("tui");
+    let mut progress = if !quiet && !tui && io::stderr().is_terminal() {
+        Some(Progress::new())
+    } else {
+        None
+    };
+
+    let packet_output = matches.opt_str("packet-output");
+    let event_log = matches.opt_str("event-log");
+    let departure_trace = matches.opt_str("departure-trace");
+    let results = if packet_output.is_some() || event_log.is_some() || departure_trace.is_some() {
+        let format = match matches.opt_str("packet-format") {
+            Some(x) => or_exit(
+                program,
+                records::Format::parse(&x).ok_or_else(|| QsimError::parse("packet-format", x)),
+            ),
+            None => records::Format::Csv,
+        };
+        let packet_output = packet_output.as_deref().map(|path| (path, format));
+        let sim = Simulation::new(config.clone());
+        match run_recorded(sim, packet_output, event_log.as_deref(), departure_trace.as_deref()) {
+            Ok(results) => results,
+            Err((path, e)) => {
+                println!("{}: unable to write {} -- {}", program, path, e);
+                std::process::exit(1)
+            }
+        }
+    } else if matches.opt_present("pace") ||
+               matches.opt_present("metrics-addr") ||
+               matches.opt_present("tui")
+    {
+        let pace = match or_exit(program, opt(&matches, "pace")) {
+            Some(x) => Some(x),
+            None if tui => Some(1.0),
+            None => None,
+        };
+        let exporter = matches.opt_str("metrics-addr").map(|addr| {
+            Exporter::bind(&addr).unwrap_or_else(|e| {
+                println!("{}: unable to listen on {} -- {}", program, addr, e);
+                std::process::exit(1)
+            })
+        });
+        let mut dashboard = Dashboard::new();
+        let mut drawn: Option<Instant> = None;
+        let start = Instant::now();
+        let results = run_paced(Simulation::new(config.clone()), pace, |sim| {
+            if let Some(ref exporter) = exporter {
+                exporter.update(sim);
+            }
+            if tui && (sim.done() || drawn.is_none_or(|t| t.elapsed() >= DASHBOARD_INTERVAL)) {
+                print!("{}", dashboard.render(sim));
+                io::stdout().flush().unwrap();
+                drawn = Some(Instant::now());
+            }
+            if let Some(ref mut progress) = progress {
+                if drawn.is_none_or(|t| t.elapsed() >= PROGRESS_INTERVAL) {
+                    eprint!("{}", progress.render(sim, start.elapsed()));
+                    drawn = Some(Instant::now());
+                }
+            }
+        });
+        if let Some(ref progress) = progress {
+            eprint!("{}", progress.finish());
+        }
+        results
+    } else if let Some(ref mut progress) = progress {
+        run_with_progress(Simulation::new(config.clone()), progress)
+    } else {
+        Simulation::new(config.clone()).run()
+    };
+    let expected = if matches.opt_present("validate") {
+        analytic::expected(&config)
+    } else {
+        None
+    };
+    let evaluations = slo::evaluate(&objectives, &results);
+    let costs = or_exit(program, parse_cost_model(&matches))
+        .map(|m| m.evaluate(&results, results.elapsed));
+    let mut doc = json::document(&config, &results);
+    if let json::Json::Object(ref mut fields) = doc {
+        if let Some(ref e) = expected {
+            fields.push(("validation".to_string(), json::validation(&config, e, &results)));
+        }
+        if !evaluations.is_empty() {
+            fields.push(("slo".to_string(), json::slo(&evaluations)));
+        }
+        if let Some(ref c) = costs {
+            fields.push(("cost".to_string(), json::costs(c)));
+        }
+    }
+    let observed = sweep::observe(&results).into_iter().map(|v| analysis::repeated(v, 1));
+    write_out(program, &matches, &doc, &sweep::single(&config, 1, observed.collect()));
+    if json {
+        println!("{}", doc);
+    } else {
+        // Times are given in whichever unit fits them best, unless one's asked for.
+        let report = Report::new(&results);
+        if matches.opt_present("time-unit") {
+            print!("{}", report.unit(unit));
+        } else {
+            print!("{}", report);
+        }
+        if matches.opt_present("validate") {
+            print_validation(&config, expected.as_ref(), &results, unit);
+        }
+        if !evaluations.is_empty() {
+            print_slo(&evaluations);
+        }
+        if let Some(ref c) = costs {
+            print_costs(c);
+        }
+    }
+
+    if let Some(path) = matches.opt_str("histogram-output") {
+        let histogram = match results.sojourn_histogram {
+            Some(ref h) => h,
+            None => {
+                println!("{}: --histogram-output requires --quantiles histogram", program);
+                std::process::exit(1)
+            }
+        };
+        if let Err(e) = write_histogram(&path, histogram) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+
+    // The M/M/1 queue at the same load, to overlay onto the empirical distributions. With a buffer
+    // limit, the occupancy is that of the M/M/1/K queue instead, and the sojourn time CDF isn't
+    // overlaid.
+    let mm1 = if matches.opt_present("overlay-mm1") {
+        let lambda = f64::from(config.rate);
+        let mu = f64::from(config.pspeed) / f64::from(config.psize);
+        if config.servers > 1 {
+            println!("{}: --overlay-mm1 requires a single server", program);
+            std::process::exit(1)
+        }
+        if lambda >= mu && config.qlimit.is_none() {
+            println!("{}: --overlay-mm1 requires a stable queue (ρ < 1) or a buffer limit", program);
+            std::process::exit(1)
+        }
+        Some((lambda, mu))
+    } else {
+        None
+    };
+
+    if let Some(path) = matches.opt_str("cdf-output") {
+        let histogram = match results.sojourn_histogram {
+            Some(ref h) => h,
+            None => {
+                println!("{}: --cdf-output requires --quantiles histogram", program);
+                std::process::exit(1)
+            }
+        };
+        if let Err(e) = write_cdf(&path, histogram, mm1.filter(|_| config.qlimit.is_none())) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+
+    if let Some(path) = matches.opt_str("occupancy-output") {
+        let occupancy = results.occupancy_distribution();
+        let capacity = config.qlimit.map(|l| l + 1);
+        let expected = mm1.map(|(lambda, mu)| {
+            analytic::mm1_distribution(lambda / mu, capacity, occupancy.len())
+        });
+        if let Err(e) = write_occupancy(&path, &occupancy, expected.as_ref()) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+
+    if let Some(prefix) = matches.opt_str("plot-output") {
+        let charts = vec![
+            ("qlen", plot::queue_length(&results, config.resolution)),
+            ("latency", Some(plot::latency_cdf(&results))),
+            ("throughput", plot::throughput(&results, &config)),
+        ];
+        for (name, chart) in charts {
+            let path = format!("{}-{}.svg", prefix, name);
+            if let Err(e) = chart.map_or(Ok(()), |c| write_chart(&path, &c)) {
+                println!("{}: unable to write {} -- {}", program, path, e);
+                std::process::exit(1)
+            }
+        }
+    }
+
+    if let Some(path) = matches.opt_str("qlen-output") {
+        if let Err(e) = write_samples(&path, &results.samples, config.resolution) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+
+    if let Some(path) = matches.opt_str("acf-output") {
+        if let Err(e) = write_acf(&path, &results) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+
+    if let Some(path) = matches.opt_str("window-output") {
+        if let Err(e) = write_windows(&path, &results.windows, &config) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+
+    if let Some(ref warmup) = results.warmup {
+        if !json && !quiet {
+            print_warmup(warmup, 1, unit);
+        }
+        if let Some(path) = matches.opt_str("welch-output") {
+            if let Err(e) = write_welch(&path, warmup, slice::from_ref(warmup)) {
+                println!("{}: unable to write {} -- {}", program, path, e);
+                std::process::exit(1)
+            }
+        }
+    }
+
+    if evaluations.iter().any(|e| !e.met) {
+        std::process::exit(2)
+    }
+}
+
+// print_warmup prints the end of the warm-up period detected over the given number of
+// replications.
+fn print_warmup(warmup: &Warmup, replications: u32, unit: TimeUnit) {
+    println!();
+    let method = match warmup.method {
+        WarmupDetection::Mser5 => "MSER-5",
+        WarmupDetection::Welch => "Welch",
+    };
+    if replications > 1 {
+        println!("Warm-up detection ({}, across {} replications):", method, replications);
+    } else {
+        println!("Warm-up detection ({}):", method);
+    }
+    println!(
+        "\t End of warm-up period:             {:.4} {}",
+        warmup.truncation * unit.per_second(),
+        unit.name()
+    );
+}
+
+// run_replications runs the configuration for the given number of independent replications, and
+// reports the mean of each metric across them along with the half-width of its confidence interval.
+// With --welch-output, the end of the warm-up period is detected from the series of every
+// replication averaged together, and the Welch plot data written out.
+fn run_replications(
+    program: &str,
+    matches: &getopts::Matches,
+    config: &Config,
+    replications: u32,
+    unit: TimeUnit,
+) {
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    let names = sweep::metrics(config);
+    let mut warmups = vec![];
+    let samples = sweep::samples(config, replications, seed, |r| {
+        warmups.extend(r.warmup.clone());
+        sweep::observe(r)
+    });
+    let metrics: Vec<OnlineStats> = samples.iter().map(|s| OnlineStats::from_slice(s)).collect();
+    let doc = json::replicated(config, replications, &names, &samples);
+    write_out(program, matches, &doc, &sweep::single(config, replications, metrics.clone()));
+    let warmup = Warmup::averaged(&warmups);
+    if let (Some(path), Some(ref warmup)) = (matches.opt_str("welch-output"), &warmup) {
+        if let Err(e) = write_welch(&path, warmup, &warmups) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+    if or_exit(program, output_json(matches)) {
+        println!("{}", doc);
+        return;
+    }
+    println!(
+        "Simulation results ({} replications, {:.0}% confidence):",
+        replications,
+        CONFIDENCE * 100.0
+    );
+    print_replicated(&names, &metrics, unit);
+    if let Some(ref warmup) = warmup {
+        print_warmup(warmup, replications, unit);
+    }
+}
+
+// print_replicated prints the mean of each of the metrics (see sweep::metrics) across replications,
+// along with the half-width of its confidence interval.
+fn print_replicated(names: &[String], metrics: &[OnlineStats], unit: TimeUnit) {
+    println!("\t {:<35}{:>14}{:>14}", "", "mean", "+/-");
+    for (name, m) in names.iter().zip(metrics) {
+        let (label, scale) = metric_label(name, unit);
+        println!(
+            "\t {:<35}{:>14.6}{:>14.6}",
+            label,
+            m.mean() * scale,
+            analysis::half_width(m, CONFIDENCE) * scale
+        );
+    }
+}
+
+// metric_label returns the label of the metric (see sweep::metrics), and what it's scaled by when
+// reported, times being reported in the given unit.
+fn metric_label(name: &str, unit: TimeUnit) -> (String, f64) {
+    match name {
+        n if n == "sojourn" || n == "wait" || n.starts_with("sojourn_p") => {
+            (format!("{} ({}):", n, unit.symbol()), unit.per_second())
+        }
+        "loss" => ("loss (%):".to_string(), 1.0),
+        "throughput" => ("throughput (packets/s):".to_string(), 1.0),
+        n => (format!("{}:", n), 1.0),
+    }
+}
+
+// output_json returns whether the configuration and results are to be printed as JSON rather than
+// text, as they are by default when quiet.
+fn output_json(matches: &getopts::Matches) -> Result<bool, QsimError> {
+    match matches.opt_str("output-format") {
+        Some(ref x) if x == "json" => Ok(true),
+        Some(ref x) if x == "text" => Ok(false),
+        Some(x) => Err(QsimError::parse("output-format", x)),
+        None => Ok(matches.opt_present("quiet")),
+    }
+}
+
+// write_out writes the results to the file given by --out, if any, exiting on error: the JSON
+// document on a line of its own if the file is named *.json or *.jsonl, or a row of CSV (see
+// write_sweep) if it's named *.csv. With --append, the file is added to rather than overwritten,
+// and the CSV header is only written if the file is empty.
+fn write_out(program: &str, matches: &getopts::Matches, doc: &json::Json, sweep: &Sweep) {
+    let path = match matches.opt_str("out") {
+        Some(path) => path,
+        None => return,
+    };
+    let append = matches.opt_present("append");
+    let written = if path.ends_with(".csv") {
+        open_out(&path, append).and_then(|(w, empty)| write_sweep(w, sweep, empty))
+    } else {
+        open_out(&path, append).and_then(|(mut w, _)| {
+            writeln!(w, "{}", doc)?;
+            w.flush()
+        })
+    };
+    if let Err(e) = written {
+        println!("{}: unable to write {} -- {}", program, path, e);
+        std::process::exit(1)
+    }
+}
+
+// check_out exits if the file given by --out, if any, isn't named after one of the extensions
+// given, before any time is spent simulating.
+fn check_out(program: &str, matches: &getopts::Matches, extensions: &[&str]) {
+    if let Some(path) = matches.opt_str("out") {
+        if !extensions.iter().any(|e| path.ends_with(e)) {
+            println!("{}: unknown format of {}, expected one of {:?}", program, path, extensions);
+            std::process::exit(1)
+        }
+    }
+}
+
+// open_out opens the file results are written out to, returning whether it's empty.
+fn open_out(path: &str, append: bool) -> io::Result<(BufWriter<File>, bool)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    let empty = file.metadata()?.len() == 0;
+    Ok((BufWriter::new(file), empty))
+}
+
+// load_trace reads the trace packets are to arrive as per, if one is given, exiting on error.
+fn load_trace(program: &str, matches: &getopts::Matches, config: &mut Config) {
+    if let Some(path) = matches.opt_str("trace") {
+        match read_trace(&path) {
+            Ok(entries) => config.trace = Some(Arc::new(entries)),
+            Err(e) => {
+                println!("{}: unable to read {} -- {}", program, path, e);
+                std::process::exit(1)
+            }
+        }
+    }
+}
+
+fn read_trace(path: &str) -> io::Result<Vec<TraceEntry>> {
+    File::open(path).and_then(|f| trace::read(io::BufReader::new(f)))
+}
+
+// parse_matches parses the arguments, along with the options in the configuration file if one is
+// given, exiting on error.
+fn parse_matches(program: &str, opts: &Options, args: &[String]) -> getopts::Matches {
+    let matches = match opts.parse(args) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}: illegal usage -- {}", program, f);
+            std::process::exit(1)
+        }
+    };
+    if !matches.opt_defined("config") {
+        return matches;
+    }
+    let path = match matches.opt_str("config") {
+        Some(path) => path,
+        None => return matches,
+    };
+    let parsed = config_args(&path, &matches).and_then(|mut file_args| {
+        file_args.extend_from_slice(args);
+        opts.parse(&file_args).map_err(|f| f.to_string())
+    });
+    match parsed {
+        Ok(m) => m,
+        Err(e) => {
+            println!("{}: invalid configuration file {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+}
+
+// run_sweep runs the configuration given by the remaining options at every combination of the
+// values of the parameters varied, and writes out a CSV of the metrics at each point. With
+// several replications, each metric is followed by the half-width of its confidence interval.
+fn run_sweep(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optmulti(
+        "",
+        "vary",
+        "Parameter to sweep over (rate, psize, pspeed, qlimit or duration) and its values, as \
+         PARAM=FROM:TO:STEP or PARAM=V1,V2,...; may be given more than once",
+        "RANGE",
+    );
+    opts.optopt("", "replications", "Number of replications run at each point (def: 1)", "NUM");
+    out_options(&mut opts);
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || !matches.opt_present("vary") {
+        print_usage(program, "sweep --vary RANGE [options]", &opts);
+        return;
+    }
+    let ranges: Result<Vec<_>, _> =
+        matches.opt_strs("vary").iter().map(|r| sweep::Range::parse(r)).collect();
+    let ranges = match ranges {
+        Ok(ranges) => ranges,
+        Err(e) => {
+            println!("{}: {}", program, e);
+            std::process::exit(1)
+        }
+    };
+    check_out(program, &matches, &[".csv"]);
+    let replications = or_exit(program, replications(&matches, 1));
+    let config = or_exit(program, parse_params(&matches));
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    let sweep = sweep::sweep(&config, &ranges, replications, seed);
+    let stdout = io::stdout();
+    let written = match matches.opt_str("out") {
+        Some(path) => open_out(&path, matches.opt_present("append"))
+            .and_then(|(w, empty)| write_sweep(w, &sweep, empty)),
+        None => write_sweep(stdout.lock(), &sweep, true),
+    };
+    if let Err(e) = written {
+        println!("{}: unable to write the sweep -- {}", program, e);
+        std::process::exit(1)
+    }
+}
+
+// run_compare runs the configurations in the two given files (with the remaining options applied
+// to both) under common random numbers, and reports the difference in each metric along with
+// whether it's statistically significant.
+fn run_compare(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optopt(
+        "",
+        "replications",
+        &format!("Number of replications of either configuration (def: {})", DEFAULT_REPLICATIONS),
+        "NUM",
+    );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || matches.free.len() != 2 {
+        print_usage(program, "compare [options] A.toml B.toml", &opts);
+        return;
+    }
+    let replications = or_exit(program, replications(&matches, DEFAULT_REPLICATIONS));
+    // The options given on the command line override those in either file.
+    let flags: Vec<String> = args.iter().filter(|a| !matches.free.contains(a)).cloned().collect();
+    let configs: Vec<Config> = matches
+        .free
+        .iter()
+        .map(|path| {
+            let mut args = flags.clone();
+            args.push("--config".to_string());
+            args.push(path.clone());
+            or_exit(program, parse_params(&parse_matches(program, &opts, &args)))
+        })
+        .collect();
+    let seed = or_exit(program, opt(&matches, "seed")).unwrap_or_else(rng::random_seed);
+    let c = compare(&configs[0], &configs[1], replications, seed);
+
+    println!(
+        "Comparison of {} against {} ({} replications, {:.0}% confidence):",
+        matches.free[1],
+        matches.free[0],
+        replications,
+        CONFIDENCE * 100.0
+    );
+    println!("\t {:<35}{:>14}{:>14}  significant", "", "difference", "+/-");
+    for (name, d) in c.metrics() {
+        println!(
+            "\t {:<35}{:>14.6}{:>14.6}  {}",
+            format!("{}:", name),
+            d.mean(),
+            analysis::half_width(d, CONFIDENCE),
+            if analysis::significant(d, CONFIDENCE) { "yes" } else { "no" }
+        );
+    }
+}
+
+// run_ttest tests the differences between the metrics of two replicated runs, as written out by
+// --out, reporting the p-value and effect size of each. Runs off of the same seed are paired up
+// replication by replication unless told otherwise.
+fn run_ttest(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this message");
+    opts.optopt(
+        "",
+        "test",
+        "Test to run; paired or welch (def: paired if the runs share a seed and replication count)",
+        "TEST",
+    );
+    opts.optopt("", "time-unit", "Unit times are reported in; s, ms, us or ns (def: s)", "UNIT");
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || matches.free.len() != 2 {
+        print_usage(program, "ttest [options] A.json B.json", &opts);
+        return;
+    }
+    let runs: Vec<Replications> = matches
+        .free
+        .iter()
+        .map(|path| {
+            read_replications(path).unwrap_or_else(|e| {
+                println!("{}: invalid results {} -- {}", program, path, e);
+                std::process::exit(1)
+            })
+        })
+        .collect();
+    let (a, b) = (&runs[0], &runs[1]);
+    let test = match matches.opt_str("test") {
+        Some(x) => Test::parse(&x).unwrap_or_else(|e| {
+            println!("{}: {}", program, e);
+            std::process::exit(1)
+        }),
+        None if a.paired_with(b) => Test::Paired,
+        None => Test::Welch,
+    };
+    if test == Test::Paired && a.len() != b.len() {
+        println!(
+            "{}: unable to pair up {} replications with {}",
+            program,
+            a.len(),
+            b.len()
+        );
+        std::process::exit(1)
+    }
+    if a.len() < 2 || b.len() < 2 {
+        println!("{}: at least two replications of either run are needed", program);
+        std::process::exit(1)
+    }
+    let outcomes = ttest::ttest(a, b, test);
+    if outcomes.is_empty() {
+        println!("{}: the runs have no metrics in common", program);
+        std::process::exit(1)
+    }
+
+    let unit = or_exit(program, time_unit(&matches));
+    let replications = if a.len() == b.len() {
+        format!("{} replications each", a.len())
+    } else {
+        format!("{} and {} replications", a.len(), b.len())
+    };
+    println!(
+        "T-test of {} against {} ({}, {}, {:.0}% confidence):",
+        matches.free[1],
+        matches.free[0],
+        test.name(),
+        replications,
+        CONFIDENCE * 100.0
+    );
+    println!(
+        "\t {:<35}{:>14}{:>14}{:>14}  significant",
+        "", "difference", "p-value", "effect size"
+    );
+    for o in &outcomes {
+        let (label, scale) = metric_label(&o.metric, unit);
+        println!(
+            "\t {:<35}{:>14.6}{:>14.6}{:>14.3}  {}",
+            label,
+            o.difference * scale,
+            o.p,
+            o.effect,
+            if o.p < 1.0 - CONFIDENCE { "yes" } else { "no" }
+        );
+    }
+}
+
+// read_replications reads back the replications of a run from its results, a single JSON document
+// (see json::replicated).
+fn read_replications(path: &str) -> Result<Replications, String> {
+    let contents = read_to_string(path)?;
+    match json::Json::parse(&contents) {
+        Ok(doc) => Replications::from_json(&doc),
+        Err(e) => {
+            // Results appended to with --append hold a document per line.
+            let lines: Vec<&str> = contents.lines().filter(|l| !l.trim().is_empty()).collect();
+            if lines.len() > 1 && lines.iter().all(|l| json::Json::parse(l).is_ok()) {
+                Err(format!("{} results, expected those of a single run", lines.len()))
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+// run_batch runs every scenario listed in the manifest (with the remaining options applied to all
+// of them), in parallel, and writes out a CSV of the metrics of each keyed by its name. With
+// several replications, each metric is followed by the half-width of its confidence interval.
+fn run_batch(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optopt("", "replications", "Number of replications of every scenario (def: 1)", "NUM");
+    opts.optopt(
+        "",
+        "jobs",
+        "Number of scenarios run at a time (def: the number of CPUs)",
+        "NUM",
+    );
+    out_options(&mut opts);
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print_usage(program, "batch [options] MANIFEST", &opts);
+        return;
+    }
+    check_out(program, &matches, &[".json", ".jsonl", ".csv"]);
+    let path = &matches.free[0];
+    let manifest = read_to_string(path).and_then(|contents| batch::Manifest::parse(&contents));
+    let manifest = manifest.unwrap_or_else(|e| {
+        println!("{}: invalid manifest {} -- {}", program, path, e);
+        std::process::exit(1)
+    });
+    let replications = or_exit(program, replications(&matches, 1));
+    let jobs = or_exit(program, jobs(&matches));
+    // The options given on the command line override those in the manifest.
+    let flags: Vec<String> = args.iter().filter(|a| *a != path).cloned().collect();
+    let (names, configs): (Vec<String>, Vec<Config>) = manifest
+        .scenarios
+        .into_iter()
+        .map(|(name, options)| {
+            let mut args = option_args(options, &matches).unwrap_or_else(|e| {
+                println!("{}: invalid scenario {} -- {}", program, name, e);
+                std::process::exit(1)
+            });
+            args.extend_from_slice(&flags);
+            let matches = parse_matches(program, &opts, &args);
+            let mut config = or_exit(program, parse_params(&matches));
+            load_trace(program, &matches, &mut config);
+            (name, config)
+        })
+        .unzip();
+    let columns = sweep::metrics(&configs[0]);
+    if let Some(i) = configs.iter().position(|c| sweep::metrics(c) != columns) {
+        println!(
+            "{}: scenarios {} and {} report different percentiles",
+            program, names[0], names[i]
+        );
+        std::process::exit(1)
+    }
+    let seed = or_exit(program, opt(&matches, "seed")).unwrap_or_else(rng::random_seed);
+    let samples = batch::run(&configs, replications, seed, jobs);
+
+    let scenarios: Vec<(String, Sweep)> = names
+        .into_iter()
+        .zip(configs.iter().zip(&samples))
+        .map(|(name, (config, samples))| {
+            let metrics = samples.iter().map(|s| OnlineStats::from_slice(s)).collect();
+            (name, sweep::single(config, replications, metrics))
+        })
+        .collect();
+    let append = matches.opt_present("append");
+    let stdout = io::stdout();
+    let written = match matches.opt_str("out") {
+        Some(ref path) if !path.ends_with(".csv") => open_out(path, append).and_then(|(mut w, _)| {
+            for (i, (name, _)) in scenarios.iter().enumerate() {
+                let mut doc = json::replicated(&configs[i], replications, &columns, &samples[i]);
+                if let json::Json::Object(ref mut fields) = doc {
+                    fields.insert(0, ("scenario".to_string(), name.as_str().into()));
+                }
+                writeln!(w, "{}", doc)?;
+            }
+            w.flush()
+        }),
+        Some(path) => {
+            open_out(&path, append).and_then(|(w, empty)| write_batch(w, &scenarios, empty))
+        }
+        None => write_batch(stdout.lock(), &scenarios, true),
+    };
+    if let Err(e) = written {
+        println!("{}: unable to write the results -- {}", program, e);
+        std::process::exit(1)
+    }
+}
+
+// read_to_string returns the contents of the file, as an error message on failure.
+fn read_to_string(path: &str) -> Result<String, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut contents))
+        .map_err(|e| e.to_string())?;
+    Ok(contents)
+}
+
+// run_validate runs the configuration and compares the results against the closed-form solution of
+// its model, exiting with status 2 should any metric be further off than the tolerance.
+fn run_validate(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optopt(
+        "",
+        "tolerance",
+        &format!(
+            "Largest relative error a metric may show and still pass; % (def: {})",
+            DEFAULT_TOLERANCE
+        ),
+        "PCT",
+    );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, "validate [options]", &opts);
+        return;
+    }
+    let tolerance = or_exit(program, opt(&matches, "tolerance")).unwrap_or(DEFAULT_TOLERANCE);
+    let mut config = or_exit(program, parse_params(&matches));
+    load_trace(program, &matches, &mut config);
+    let unit = or_exit(program, time_unit(&matches));
+    print_config(&config, 1, unit);
+    let expected = analytic::expected(&config);
+    let results = Simulation::new(config.clone()).run();
+    print_validation(&config, expected.as_ref(), &results, unit);
+    let e = match expected {
+        Some(e) => e,
+        None => std::process::exit(1),
+    };
+    // A NaN error (e.g. no packets processed) fails too.
+    if validation(&e, &results, unit).iter().any(|m| m.3.is_nan() || m.3.abs() > tolerance) {
+        std::process::exit(2)
+    }
+}
+
+// run_solve searches for the slowest processing speed, or the smallest buffer, at which the
+// objectives are met on average across replications, and reports the steps taken along with the
+// metrics at the value found. It exits with status 2 if no value meets them.
+fn run_solve(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optopt(
+        "",
+        "slo",
+        "Comma-separated objectives to meet, e.g. p99<=50ms or mean<=1ms,loss<=0.1%",
+        "LIST",
+    );
+    opts.optopt(
+        "",
+        "for",
+        "Parameter searched over; pspeed (the processing speed) or qlimit (the buffer limit) \
+         (def: pspeed)",
+        "PARAM",
+    );
+    opts.optopt(
+        "",
+        "replications",
+        &format!(
+            "Number of replications run at each step, the objectives being met on average across \
+             them (def: {})",
+            DEFAULT_SEARCH_REPLICATIONS
+        ),
+        "NUM",
+    );
+    opts.optopt(
+        "",
+        "precision",
+        &format!(
+            "Precision the speed is found to, relative to it; % (def: {}, buffer limits are \
+             found exactly)",
+            search::DEFAULT_PRECISION * 100.0
+        ),
+        "PCT",
+    );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || !matches.opt_present("slo") {
+        print_usage(program, "solve --slo LIST [options]", &opts);
+        return;
+    }
+    let objectives = slo::parse(&matches.opt_str("slo").unwrap()).unwrap_or_else(|e| {
+        println!("{}: {}", program, e);
+        std::process::exit(1)
+    });
+    let replications = or_exit(program, replications(&matches, DEFAULT_SEARCH_REPLICATIONS));
+    let parameter = match matches.opt_str("for").as_deref() {
+        Some("pspeed") | None => sweep::Parameter::Pspeed,
+        Some("qlimit") => sweep::Parameter::Qlimit,
+        Some(x) => {
+            println!("{}: unable to search over {}, expected pspeed or qlimit", program, x);
+            std::process::exit(1)
+        }
+    };
+    let precision = match or_exit(program, opt::<f64>(&matches, "precision")) {
+        Some(x) => x / 100.0,
+        None => search::default_precision(parameter),
+    };
+    let mut config = or_exit(program, parse_params(&matches));
+    load_trace(program, &matches, &mut config);
+    let unit = or_exit(program, time_unit(&matches));
+    print_config(&config, replications, unit);
+
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    let s = search::solve(&config, parameter, &objectives, replications, precision, seed);
+    println!("Search for the smallest {} meeting the objectives:", parameter.name());
+    let specs: Vec<String> = objectives.iter().map(|o| format!("{:>14}", o.spec)).collect();
+    println!("\t {:<14}{}  met", parameter.name(), specs.join(""));
+    for step in &s.steps {
+        let values: Vec<String> = objectives
+            .iter()
+            .zip(&step.objectives)
+            .map(|(o, m)| format!("{:>14.6}", m.mean() * objective_scale(o, unit)))
+            .collect();
+        let met = if step.met { "yes" } else { "no" };
+        println!("\t {:<14}{}  {}", step.value, values.join(""), met);
+    }
+    println!();
+    let found = match s.result() {
+        Some(found) => found,
+        None => {
+            println!("No {} meets the objectives.", parameter.name());
+            std::process::exit(2)
+        }
+    };
+    println!(
+        "Smallest {} meeting the objectives: {} {} ({} replications, {:.0}% confidence):",
+        parameter.name(),
+        found.value,
+        if parameter == sweep::Parameter::Qlimit { "packets" } else { "bits/s" },
+        replications,
+        CONFIDENCE * 100.0
+    );
+    print_replicated(&s.metrics, &found.metrics, unit);
+}
+
+// run_sensitivity perturbs each parameter of the configuration in turn, and reports the elasticity
+// of each metric with respect to each, i.e. the relative change in the metric per relative change
+// in the parameter, along with the parameter each metric is most sensitive to.
+fn run_sensitivity(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optopt(
+        "",
+        "perturbation",
+        &format!(
+            "Proportion each parameter is lowered and raised by, at least by one; % (def: {})",
+            sensitivity::DEFAULT_PERTURBATION * 100.0
+        ),
+        "PCT",
+    );
+    opts.optopt(
+        "",
+        "replications",
+        &format!(
+            "Number of replications of every configuration (def: {})",
+            DEFAULT_REPLICATIONS
+        ),
+        "NUM",
+    );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, "sensitivity [options]", &opts);
+        return;
+    }
+    let perturbation = or_exit(program, opt::<f64>(&matches, "perturbation"))
+        .map_or(sensitivity::DEFAULT_PERTURBATION, |x| x / 100.0);
+    let replications = or_exit(program, replications(&matches, DEFAULT_REPLICATIONS));
+    let mut config = or_exit(program, parse_params(&matches));
+    load_trace(program, &matches, &mut config);
+    let unit = or_exit(program, time_unit(&matches));
+    print_config(&config, replications, unit);
+
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    let s = sensitivity::sensitivity(&config, perturbation, replications, seed);
+    println!("Elasticities (+/-{}%):", perturbation * 100.0);
+    let mut header = format!("\t {:<35}{:>14}", "", "base");
+    let mut values = format!("\t {:<35}{:>14}", "perturbed to:", "");
+    for p in &s.perturbations {
+        header.push_str(&format!("{:>14}", p.parameter.name()));
+        values.push_str(&format!("{:>14}", format!("{}-{}", p.values.0, p.values.1)));
+    }
+    println!("{}", header);
+    println!("{}", values);
+    for (i, name) in s.metrics.iter().enumerate() {
+        let (label, scale) = metric_label(name, unit);
+        let mut row = format!("\t {:<35}{:>14.6}", label, s.base[i].mean() * scale);
+        for p in &s.perturbations {
+            row.push_str(&format!("{:>14.3}", p.elasticities[i]));
+        }
+        println!("{}", row);
+    }
+    println!();
+    println!("Most sensitive to:");
+    for (i, name) in s.metrics.iter().enumerate() {
+        let dominant = s.dominant(i).map_or("none", |p| p.name());
+        println!("\t {:<35}{}", format!("{}:", name), dominant);
+    }
+}
+
+// run_scaling runs the configuration at loads approaching 1, scaling the run length along (see
+// scaling::run), and reports how the average waiting time grows against the heavy-traffic
+// prediction, writing the points out as CSV with --out.
+fn run_scaling(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    let loads: Vec<String> = scaling::DEFAULT_LOADS.iter().map(|l| l.to_string()).collect();
+    opts.optopt(
+        "",
+        "loads",
+        &format!("Loads to run at, the duration applying to the first (def: {})", loads.join(",")),
+        "LIST",
+    );
+    opts.optopt(
+        "",
+        "max-duration",
+        &format!(
+            "Longest any load is run for; seconds (def: {})",
+            scaling::DEFAULT_MAX_DURATION
+        ),
+        "SECS",
+    );
+    opts.optopt("", "replications", "Number of replications at every load (def: 1)", "NUM");
+    opts.optopt("", "out", "Write the points to a CSV file as well", "FILE");
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, "scaling [options]", &opts);
+        return;
+    }
+    check_out(program, &matches, &[".csv"]);
+    let loads = or_exit(program, opt_list(&matches, "loads"))
+        .unwrap_or_else(|| scaling::DEFAULT_LOADS.to_vec());
+    let max_duration = or_exit(program, opt(&matches, "max-duration"))
+        .unwrap_or(scaling::DEFAULT_MAX_DURATION);
+    let replications = or_exit(program, replications(&matches, 1));
+    let mut config = or_exit(program, parse_params(&matches));
+    let unit = or_exit(program, time_unit(&matches));
+    // The rate is set anew for every load; the configuration is shown at the first.
+    if let Some(&load) = loads.first() {
+        config.rate = scaling::rate(&config, load);
+    }
+    print_config(&config, replications, unit);
+
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    let points = or_exit(program, scaling::run(&config, &loads, max_duration, replications, seed));
+    if let Some(path) = matches.opt_str("out") {
+        if let Err(e) = write_scaling(&path, &points) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+    let (t, symbol) = (unit.per_second(), unit.symbol());
+    println!("Heavy-traffic scaling:");
+    println!(
+        "\t {:<10}{:>10}{:>12}{:>16}{:>16}{:>10}{:>16}",
+        "load", "rate", "duration", "wait", "predicted", "ratio", "wait·(1 - ρ)"
+    );
+    for p in &points {
+        println!(
+            "\t {:<10.4}{:>10}{:>10}s{}{:>13.6} {:<2}{:>13.6} {:<2}{:>10.3}{:>13.6} {:<2}",
+            p.offered,
+            p.rate,
+            p.duration,
+            if p.capped { "*" } else { " " },
+            p.wait.mean() * t,
+            symbol,
+            p.predicted * t,
+            symbol,
+            p.ratio(),
+            p.scaled() * t,
+            symbol
+        );
+    }
+    if points.iter().any(|p| p.capped) {
+        println!("\t * capped at --max-duration, short of the scaled run length");
+    }
+    if points.len() > 1 {
+        let exponent = format!("{:.3} (1 in heavy traffic)", scaling::exponent(&points));
+        println!("\t {:<35}{}", "Growth of the wait in 1/(1 - ρ):", exponent);
+    }
+}
+
+// write_scaling writes the points of a heavy-traffic scaling run as CSV, times in seconds, the
+// average waiting time followed by the half-width of its confidence interval with several
+// replications.
+fn write_scaling(path: &str, points: &[Point]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let replicated = points.iter().any(|p| p.wait.len() > 1);
+    write!(w, "load,offered,rate,duration,capped,wait")?;
+    if replicated {
+        write!(w, ",wait_ci")?;
+    }
+    writeln!(w, ",predicted,ratio,scaled_wait")?;
+    for p in points {
+        write!(w, "{},{},{},{},{}", p.load, p.offered, p.rate, p.duration, p.capped)?;
+        write!(w, ",{}", p.wait.mean())?;
+        if replicated {
+            write!(w, ",{}", analysis::half_width(&p.wait, CONFIDENCE))?;
+        }
+        writeln!(w, ",{},{},{}", p.predicted, p.ratio(), p.scaled())?;
+    }
+    w.flush()
+}
+
+// run_transient runs several replications of a configuration, changes in load scheduled into it
+// with --at, and reports the queue length and the probability the server is busy at fixed points
+// in simulated time, averaged across them.
+fn run_transient(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
     opts.optopt(
         "",
-        "rate",
+        "interval",
         &format!(
-            "Average number of generated packets/s (def: {})",
-            DEFAULT_RATE
+            "Interval between the points in time the system is observed at; ticks (def: a {}th \
+             of the run)",
+            transient::DEFAULT_POINTS
         ),
         "NUM",
     );
     opts.optopt(
         "",
-        "psize",
-        &format!("Packet size; bits (def: {})", DEFAULT_PSIZE),
+        "replications",
+        &format!("Number of replications averaged across (def: {})", DEFAULT_REPLICATIONS),
         "NUM",
     );
+    opts.optopt("", "out", "Write the points to a CSV file as well", "FILE");
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, "transient [options]", &opts);
+        return;
+    }
+    check_out(program, &matches, &[".csv"]);
+    let replications = or_exit(program, replications(&matches, DEFAULT_REPLICATIONS));
+    let config = or_exit(program, parse_params(&matches));
+    let unit = or_exit(program, time_unit(&matches));
+    let interval = or_exit(program, opt(&matches, "interval"))
+        .unwrap_or_else(|| (config.ticks().0 / transient::DEFAULT_POINTS).max(1));
+    print_config(&config, replications, unit);
+
+    let seed = config.seed.unwrap_or_else(rng::random_seed);
+    let points = or_exit(program, transient::run(&config, interval, replications, seed));
+    if let Some(path) = matches.opt_str("out") {
+        if let Err(e) = write_transient(&path, &points) {
+            println!("{}: unable to write {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    }
+    let t = unit.per_second();
+    println!("Transient behavior ({} replications):", replications);
+    println!(
+        "\t {:<16}{:>14}{:>14}{:>14}{:>14}",
+        format!("time ({})", unit.symbol()),
+        "E[Q(t)]",
+        "±",
+        "E[N(t)]",
+        "P(busy)"
+    );
+    for p in &points {
+        println!(
+            "\t {:<16.6}{:>14.4}{:>14.4}{:>14.4}{:>14.4}",
+            p.time * t,
+            p.qlen.mean(),
+            analysis::half_width(&p.qlen, CONFIDENCE),
+            p.occupancy(),
+            p.busy_probability()
+        );
+    }
+}
+
+// write_transient writes the points of a transient run as CSV, times in seconds, the average queue
+// length and probability the server is busy each followed by the half-width of its confidence
+// interval.
+fn write_transient(path: &str, points: &[transient::Point]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "time,qlen,qlen_ci,occupancy,busy,busy_ci")?;
+    for p in points {
+        writeln!(
+            w,
+            "{},{},{},{},{},{}",
+            p.time,
+            p.qlen.mean(),
+            analysis::half_width(&p.qlen, CONFIDENCE),
+            p.occupancy(),
+            p.busy_probability(),
+            analysis::half_width(&p.busy, CONFIDENCE)
+        )?;
+    }
+    w.flush()
+}
+
+// objective_scale returns what the objective's metric is scaled by when reported, times being
+// reported in the given unit.
+fn objective_scale(o: &slo::Objective, unit: TimeUnit) -> f64 {
+    match o.metric {
+        slo::Metric::Sojourn(_) | slo::Metric::MeanSojourn => unit.per_second(),
+        _ => 1.0,
+    }
+}
+
+// run_analyze summarizes the arrival process of a trace (in the form read by --trace), and the
+// load it'd put on a server of the given speed.
+fn run_analyze(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this message");
     opts.optopt(
         "",
         "pspeed",
-        &format!("Packet processing speed; bits/s (def: {})", DEFAULT_PSPEED),
+        "Packet processing speed to report the utilization at; bits/s",
         "NUM",
     );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print_usage(program, "analyze [options] TRACE", &opts);
+        return;
+    }
+    let path = &matches.free[0];
+    let s = match read_trace(path) {
+        Ok(entries) => trace::summarize(&entries),
+        Err(e) => {
+            println!("{}: unable to read {} -- {}", program, path, e);
+            std::process::exit(1)
+        }
+    };
+    let cv = analysis::coefficient_of_variation;
+    println!("Trace summary ({}):", path);
+    println!("\t {:<35}{}", "Packets:", s.packets);
+    println!("\t {:<35}{:.6} seconds", "Span:", s.span);
+    println!("\t {:<35}{:.4} packets/s", "Arrival rate:", s.rate());
+    println!("\t {:<35}{:.6} seconds", "Average interarrival time:", s.interarrival.mean());
+    println!("\t {:<35}{:.4}", "Interarrival time CV:", cv(&s.interarrival));
+    println!("\t {:<35}{:.4} bits", "Average packet length:", s.length.mean());
+    println!("\t {:<35}{:.4}", "Packet length CV:", cv(&s.length));
+    println!("\t {:<35}{:.4} bits/s", "Offered load:", s.bitrate());
+    if let Some(x) = or_exit(program, opt::<f64>(&matches, "pspeed")) {
+        let utilization = s.bitrate() / x;
+        println!("\t {:<35}{:.4}", "Utilization (ρ):", utilization);
+    }
+}
+
+// run_ecmp routes the traffic of a run across equal-cost paths by a hash of the flow ids (see
+// ecmp::run), reporting how loaded each path was and the latency penalty of the imbalance.
+fn run_ecmp(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    opts.optopt("", "paths", "Number of equal-cost paths, each a server as configured", "NUM");
+    opts.optopt("", "jobs", "Number of paths run at a time (def: the number of CPUs)", "NUM");
+    opts.optopt(
+        "",
+        "hash-imbalance",
+        "Share of the hash space polarized onto the first path; % (def: 0)",
+        "PCT",
+    );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || !matches.opt_present("paths") {
+        print_usage(program, "ecmp --paths NUM [options]", &opts);
+        return;
+    }
+    let routing = Routing {
+        paths: or_exit(program, opt(&matches, "paths")).unwrap_or(1),
+        imbalance: or_exit(program, opt::<f64>(&matches, "hash-imbalance")).unwrap_or(0.0) / 100.0,
+    };
+    let mut config = or_exit(program, parse_params(&matches));
+    load_trace(program, &matches, &mut config);
+    let unit = or_exit(program, time_unit(&matches));
+    print_config(&config, 1, unit);
+    let jobs = or_exit(program, jobs(&matches));
+    let e = or_exit(program, ecmp::run(&config, routing, jobs));
+
+    let t = unit.per_second();
+    let polarized = routing.imbalance * 100.0;
+    println!("Paths ({}, {}% of the hash space polarized):", routing.paths, polarized);
+    println!(
+        "\t {:<10}{:>8}{:>12}{:>14}{:>16}{:>12}",
+        "path", "flows", "share", "utilization", "sojourn", "loss"
+    );
+    for (path, r) in e.routes.iter().enumerate() {
+        let (utilization, sojourn, loss) = match r.results {
+            Some(ref r) => (r.utilization, r.sojourn.mean() * t, r.loss_probability()),
+            None => (0.0, f64::NAN, 0.0),
+        };
+        println!(
+            "\t {:<10}{:>8}{:>11.2}%{:>14.4}{:>13.6} {:<2}{:>11.2}%",
+            path,
+            r.flows,
+            r.share * 100.0,
+            utilization,
+            sojourn,
+            unit.symbol(),
+            loss
+        );
+    }
+    println!("\t {:<35}{:.6} {}", "Average sojourn time:", e.sojourn() * t, unit.symbol());
+    let even = e.even.sojourn.mean() * t;
+    println!("\t {:<35}{:.6} {}", "Average sojourn time (even split):", even, unit.symbol());
+    println!("\t {:<35}{:.2}%", "Polarization penalty:", e.penalty() * 100.0);
+}
+
+// run_tandem passes the packets processed by the configured run through further stages (see
+// tandem::Tandem), reporting how each stage fared and how long packets took to get through them
+// all.
+fn run_tandem(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
     opts.optopt(
         "",
-        "duration",
-        &format!(
-            "Duration of simulation; seconds (def: {})",
-            DEFAULT_DURATION
-        ),
-        "NUM",
+        "stages",
+        "Comma-separated processing speeds of the stages past the first; bits/s",
+        "LIST",
     );
     opts.optopt(
         "",
-        "qlimit",
-        &format!(
-            "Limit on of the buffer queue length; int (def: {:?})",
-            DEFAULT_QLIMIT
-        ),
+        "sync-window",
+        "Ticks stages run apart at most, each on a thread of its own (def: 1000)",
         "NUM",
     );
-    opts
-}
-
-fn parse_params(matches: &getopts::Matches) -> (u32, u32, u32, u32, Option<usize>) {
-    let rate = match matches.opt_str("rate") {
-        Some(x) => x.parse::<u32>().unwrap(),
-        None => DEFAULT_RATE,
-    };
-    let psize = match matches.opt_str("psize") {
-        Some(x) => x.parse::<u32>().unwrap(),
-        None => DEFAULT_PSIZE,
+    opts.optflag("", "serial", "Run every stage on a single thread");
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || !matches.opt_present("stages") {
+        print_usage(program, "tandem --stages LIST [options]", &opts);
+        return;
+    }
+    let execution = match or_exit(program, opt(&matches, "sync-window")) {
+        _ if matches.opt_present("serial") => Execution::Serial,
+        window => Execution::Pipelined { window: window.unwrap_or(1000) },
     };
-    let pspeed = match matches.opt_str("pspeed") {
-        Some(x) => x.parse::<u32>().unwrap(),
-        None => DEFAULT_PSPEED,
+    let tandem = Tandem {
+        pspeeds: or_exit(program, opt_list(&matches, "stages")).unwrap_or_default(),
+        execution,
     };
-    let duration = match matches.opt_str("duration") {
-        Some(x) => x.parse::<u32>().unwrap(),
-        None => DEFAULT_DURATION,
+    let mut config = or_exit(program, parse_params(&matches));
+    load_trace(program, &matches, &mut config);
+    let unit = or_exit(program, time_unit(&matches));
+    print_config(&config, 1, unit);
+    let n = or_exit(program, tandem.run(&config));
+
+    let t = unit.per_second();
+    println!("Stages ({}, {} execution):", n.stages.len() + 1, execution.name());
+    println!(
+        "\t {:<10}{:>12}{:>12}{:>14}{:>16}{:>10}",
+        "stage", "pspeed", "processed", "utilization", "sojourn", "dropped"
+    );
+    let first = &n.first;
+    println!(
+        "\t {:<10}{:>12}{:>12}{:>14.4}{:>13.6} {:<2}{:>10}",
+        0,
+        config.pspeed,
+        first.packets_processed,
+        first.utilization,
+        first.sojourn.mean() * t,
+        unit.symbol(),
+        first.packets_dropped
+    );
+    for (i, s) in n.stages.iter().enumerate() {
+        println!(
+            "\t {:<10}{:>12}{:>12}{:>14.4}{:>13.6} {:<2}{:>10}",
+            i + 1,
+            s.pspeed,
+            s.packets_processed,
+            s.utilization,
+            s.sojourn.mean() * t,
+            unit.symbol(),
+            s.packets_dropped
+        );
+    }
+    let sojourn = n.sojourn.mean() * t;
+    println!("\t {:<35}{:.6} {}", "Average sojourn time (end to end):", sojourn, unit.symbol());
+}
+
+// run_switch simulates an input-queued switch (see switch::Switch), reporting its throughput and
+// the time packets spent waiting at its inputs, head-of-line blocked or otherwise.
+fn run_switch(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this message");
+    opts.optopt("", "ports", "Number of inputs (and outputs) of the switch (def: 16)", "NUM");
+    opts.optopt(
+        "",
+        "load",
+        "Probability a packet arrives at an input in a slot (def: 0.8)",
+        "NUM",
+    );
+    opts.optopt("", "slots", "Number of slots to simulate (def: 100000)", "NUM");
+    opts.optopt("", "buffer", "Packets an input holds at most (def: 64)", "NUM");
+    opts.optopt(
+        "",
+        "queueing",
+        "How inputs queue packets; fifo or per-flow (def: fifo)",
+        "KIND",
+    );
+    opts.optopt("", "seed", "Seed for the random number generators", "NUM");
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, "switch [options]", &opts);
+        return;
+    }
+    let queueing = match matches.opt_str("queueing") {
+        Some(x) => {
+            let queueing = Queueing::parse(&x).ok_or_else(|| QsimError::parse("queueing", &x));
+            or_exit(program, queueing)
+        }
+        None => Queueing::Fifo,
     };
-    let qlimit = match matches.opt_str("qlimit") {
-        Some(x) => Some(x.parse::<u32>().unwrap() as usize),
-        None => DEFAULT_QLIMIT,
+    let switch = Switch {
+        ports: or_exit(program, opt(&matches, "ports")).unwrap_or(16),
+        load: or_exit(program, opt(&matches, "load")).unwrap_or(0.8),
+        slots: or_exit(program, opt(&matches, "slots")).unwrap_or(100_000),
+        buffer: or_exit(program, opt(&matches, "buffer")).unwrap_or(64),
+        queueing,
+        seed: or_exit(program, opt(&matches, "seed")),
     };
+    let s = or_exit(program, switch.run());
+    println!("Switch ({}x{}, {} queueing):", s.ports, s.ports, queueing.name());
+    println!("\t {:<35}{}", "Packets offered:", s.packets_offered);
+    println!("\t {:<35}{}", "Packets dropped:", s.packets_dropped);
+    println!("\t {:<35}{}", "Packets switched:", s.packets_switched);
+    println!("\t {:<35}{:.4} packets/slot", "Throughput (per output):", s.throughput());
+    println!("\t {:<35}{:.4} slots", "Average waiting time:", s.wait.mean());
+    println!("\t {:<35}{:.4} slots", "Average HOL blocking time:", s.blocked.mean());
+    println!("\t {:<35}{:.2}%", "Waiting time HOL blocked:", s.blocked_share() * 100.0);
+}
+
+// run_serve runs simulations submitted over HTTP (see serve::Service) until it's killed.
+fn run_serve(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "Display this message");
+    opts.optopt("", "addr", "Address to listen on (def: 127.0.0.1:8080)", "ADDR");
+    opts.optopt(
+        "",
+        "jobs",
+        "Number of simulations run at a time (def: the number of CPUs)",
+        "NUM",
+    );
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        print_usage(program, "serve [options]", &opts);
+        return;
+    }
+    let addr = matches.opt_str("addr").unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let jobs = or_exit(program, jobs(&matches));
+    let service = Service::bind(&addr, jobs).unwrap_or_else(|e| {
+        println!("{}: unable to listen on {} -- {}", program, addr, e);
+        std::process::exit(1)
+    });
+    println!("Listening on http://{}", service.addr());
+    loop {
+        thread::park();
+    }
+}
 
-    (rate, psize, pspeed, duration, qlimit)
+// write_sweep writes out a CSV row per point of the sweep, preceded by the header if asked for.
+fn write_sweep<W: Write>(mut w: W, sweep: &Sweep, header: bool) -> io::Result<()> {
+    if header {
+        writeln!(w, "{}", sweep_columns(sweep).join(","))?;
+    }
+    for row in sweep_rows(sweep) {
+        writeln!(w, "{}", row.join(","))?;
+    }
+    w.flush()
 }
 
-fn print_usage(program: &str, opts: &Options) {
-    let brief = format!("Usage: {} [options]", program);
-    print!("{}", opts.usage(&brief));
+// sweep_columns returns the names of the columns of a sweep: the parameters, followed by the
+// metrics, each followed by the half-width of its confidence interval with several replications.
+fn sweep_columns(sweep: &Sweep) -> Vec<String> {
+    let mut columns: Vec<String> = sweep.parameters.iter().map(|p| p.name().to_string()).collect();
+    for name in &sweep.metrics {
+        columns.push(name.clone());
+        if sweep.replications > 1 {
+            columns.push(format!("{}_ci", name));
+        }
+    }
+    columns
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let program = args[0].clone();
+// sweep_rows returns a row per point of the sweep (see sweep_columns).
+fn sweep_rows(sweep: &Sweep) -> Vec<Vec<String>> {
+    sweep
+        .points
+        .iter()
+        .map(|point| {
+            let mut row: Vec<String> = point.values.iter().map(|v| v.to_string()).collect();
+            for m in &point.metrics {
+                row.push(m.mean().to_string());
+                if sweep.replications > 1 {
+                    row.push(analysis::half_width(m, CONFIDENCE).to_string());
+                }
+            }
+            row
+        })
+        .collect()
+}
 
-    let opts = construct_options();
-    let matches = match opts.parse(&args[1..]) {
-        Ok(m) => m,
-        Err(f) => {
-            println!("{}: illegal usage -- {}", program, f.to_string());
-            std::process::exit(1)
+// write_batch writes out a CSV row per scenario, keyed by its name (see sweep_columns), preceded by
+// the header if asked for.
+fn write_batch<W: Write>(
+    mut w: W,
+    scenarios: &[(String, Sweep)],
+    header: bool,
+) -> io::Result<()> {
+    if header {
+        let columns = sweep_columns(&scenarios[0].1);
+        writeln!(w, "scenario,{}", columns.join(","))?;
+    }
+    for (name, sweep) in scenarios {
+        for row in sweep_rows(sweep) {
+            writeln!(w, "{},{}", csv_field(name), row.join(","))?;
         }
-    };
+    }
+    w.flush()
+}
 
-    if matches.opt_present("h") {
-        print_usage(&program, &opts);
-        return;
+// csv_field quotes the field if it holds anything that would otherwise break up the row.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
     }
+}
+
+fn calc_usage(program: &str) -> ! {
+    println!(
+        "Usage: {0} calc erlang-b SERVERS LOAD\n       \
+         {0} calc erlang-c SERVERS RATE SRATE\n       \
+         {0} calc mva POPULATION THINK DEMANDS\n\n\
+         LOAD is the offered load in Erlangs, RATE and SRATE the arrival and per-server service \
+         rates in packets/s.\nDEMANDS is a comma-separated list of the service demand at each \
+         station of a closed network, in seconds, as is the THINK time.",
+        program
+    );
+    std::process::exit(1)
+}
 
-    let resolution = 1e6;
-    let (rate, psize, pspeed, duration, qlimit) = parse_params(&matches);
+// run_calc evaluates the Erlang B (M/M/c/c blocking) and Erlang C (M/M/c waiting) formulas, and
+// solves closed networks by Mean Value Analysis.
+fn run_calc(program: &str, args: &[String]) {
+    let servers = |s: &str| s.parse::<u32>().unwrap_or_else(|_| calc_usage(program));
+    let num = |s: &str| s.parse::<f64>().unwrap_or_else(|_| calc_usage(program));
+    match (args.first().map(String::as_str), args.len()) {
+        (Some("erlang-b"), 3) => {
+            let (c, a) = (servers(&args[1]), num(&args[2]));
+            let b = analytic::erlang_b(c, a);
+            println!("\t {:<35}{:.6}", "Blocking probability:", b);
+            println!("\t {:<35}{:.4} Erlangs", "Carried load:", a * (1.0 - b));
+        }
+        (Some("erlang-c"), 4) => {
+            let (c, lambda, mu) = (servers(&args[1]), num(&args[2]), num(&args[3]));
+            println!("\t {:<35}{:.4}", "Utilization:", lambda / mu / f64::from(c));
+            match (analytic::erlang_c(c, lambda / mu), analytic::mmc(lambda, mu, c)) {
+                (Some(p), Some(e)) => {
+                    println!("\t {:<35}{:.6}", "Probability of waiting:", p);
+                    let wait = e.sojourn - 1.0 / mu;
+                    println!("\t {:<35}{:.6} seconds", "Average waiting time:", wait);
+                    println!("\t {:<35}{:.6} seconds", "Average sojourn time:", e.sojourn);
+                    println!("\t {:<35}{:.4} packets", "Average # of queued packets:", e.qlen);
+                }
+                _ => println!("\t unstable, the offered load is at least the number of servers"),
+            }
+        }
+        (Some("mva"), 4) => {
+            let (n, think) = (servers(&args[1]), num(&args[2]));
+            let demands: Vec<f64> = args[3].split(',').map(&num).collect();
+            println!("{:>10}{:>14}{:>14}", "packets", "throughput", "response");
+            for s in analytic::mva(&demands, think, n) {
+                println!("{:>10}{:>14.4}{:>14.6}", s.population, s.throughput, s.response);
+            }
+        }
+        _ => calc_usage(program),
+    }
+}
 
+fn print_config(config: &Config, replications: u32, unit: TimeUnit) {
     println!("Simulation configuration:");
-    println!("\t Rate:                  {} packets/s", rate);
-    println!("\t Packet size:           {} bits", psize);
-    println!("\t Server speed:          {} bits/s", pspeed);
-    println!("\t Simulation time:       {}s", duration);
-    println!("\t Resolution:            1µs");
-    println!("\t Queue size limit:      {:?}", qlimit);
+    println!("\t Rate:                  {} packets/s", config.rate);
+    println!("\t Packet size:           {}", report::bits(f64::from(config.psize)));
+    println!("\t Server speed:          {}/s", report::bits(f64::from(config.pspeed)));
+    if config.servers > 1 {
+        println!("\t Servers:               {}", config.servers);
+    }
+    match config.packets {
+        Some(n) => {
+            println!("\t Simulation length:     {} packets (at most {}s)", n, config.duration)
+        }
+        None => println!("\t Simulation time:       {}s", config.duration),
+    }
+    if config.warmup > 0.0 {
+        println!("\t Warm-up deleted:       {}s", config.warmup);
+    } else {
+        println!("\t Warm-up deleted:       none");
+    }
+    println!("\t Replications:          {}", replications);
+    let tick_unit = TimeUnit::fitting(1.0 / config.resolution);
+    let tick = (tick_unit.per_second() / config.resolution * 1e3).round() / 1e3;
+    println!("\t Resolution:            {}{}", tick, tick_unit.symbol());
+    if unit != TimeUnit::Seconds {
+        println!("\t Times reported in:     {}", unit.name());
+    }
+    println!("\t Queue size limit:      {:?}", config.qlimit);
+    println!("\t Model:                 {}", config.model());
+    println!("\t Offered load (ρ):      {:.4}", config.offered_load());
+    match config.discipline {
+        Discipline::Priority(ref order) if !order.is_empty() => {
+            println!("\t Class priorities:      {:?}", order);
+        }
+        Discipline::Polling(ref visits) if !visits.is_empty() => {
+            let visits: Vec<String> = visits.iter().map(|v| v.name()).collect();
+            println!("\t Class visits:          {}", visits.join(", "));
+        }
+        _ => {}
+    }
+    if let Some(p) = config.preemption {
+        println!("\t Preemption:            {}", p.name());
+    }
     println!(
         "\t Ticks per packet:      {}",
-        f64::from(psize) / f64::from(pspeed) * resolution
+        f64::from(config.psize) / f64::from(config.pspeed) * config.resolution
     );
+    for (i, t) in config.tenants.iter().enumerate() {
+        let mut quota = vec![];
+        if let Some(share) = t.share {
+            quota.push(format!("{}% of the buffer", share * 100.0));
+        }
+        if let Some(rate) = t.rate {
+            quota.push(format!("{} packets/s", rate));
+        }
+        let quota = if quota.is_empty() { "no quota".to_string() } else { quota.join(", ") };
+        println!("\t {:<23}weight {}, {}", format!("Tenant {}:", i), t.weight, quota);
+    }
+    if let Some(l) = config.rate_limiting {
+        println!(
+            "\t Rate limit:            {} packets/s in bursts of up to {} ({})",
+            l.limit,
+            l.burst,
+            l.algorithm.name()
+        );
+    }
+    if let Some(m) = config.marking {
+        println!(
+            "\t Marking:               committed {}/s in bursts of {}, peak {}/s in bursts of {}",
+            report::bits(m.committed),
+            report::bits(f64::from(m.committed_burst)),
+            report::bits(m.peak),
+            report::bits(f64::from(m.peak_burst))
+        );
+        let limit = |l: Option<usize>| {
+            l.map_or("no limit".to_string(), |n| format!("{} queued", n))
+        };
+        println!(
+            "\t Color drop limits:     yellow at {}, red at {}",
+            limit(m.yellow_limit),
+            limit(m.red_limit)
+        );
+    }
+    if let Some(t) = config.wait_threshold {
+        println!("\t Starved past:          {}s of waiting", t);
+    }
+    if let Some(r) = config.retransmission {
+        println!("\t Retransmission:        after {}s, up to {} times", r.timeout, r.retries);
+    }
+    if config.backpressure {
+        println!("\t Backpressure:          the client blocks while the buffer's full");
+    }
+    if let Some(a) = config.ack {
+        println!("\t ACK delay:             {}s", a.delay);
+        if let Some(pspeed) = a.pspeed {
+            println!("\t ACK queue:             {} bit ACKs at {} bits/s", a.length, pspeed);
+        }
+        if let Some(window) = a.window {
+            println!("\t Window:                {} packets", window);
+        }
+    }
+    if let Some(l) = config.link {
+        println!("\t Link delay:            {}s", l.delay);
+        if let Some(c) = l.loss {
+            println!(
+                "\t Link loss:             {:.4} of packets on average, with probability {} in \
+                 the good state and {} in the bad one",
+                c.loss_rate(),
+                c.good_loss,
+                c.bad_loss
+            );
+        }
+        if l.reorder > 0.0 {
+            println!(
+                "\t Reordering:            {} of packets, by up to {}s",
+                l.reorder, l.reorder_delay
+            );
+        }
+        if l.duplicate > 0.0 {
+            println!("\t Duplication:           {} of packets", l.duplicate);
+        }
+    }
+    if let Some(seed) = config.seed {
+        println!("\t Seed:                  {}", seed);
+    }
+    for &(at, change) in &config.schedule {
+        println!("\t {:<23}{:?}", format!("At {}s:", at), change);
+    }
     println!();
+}
 
-    let ticks = duration * resolution as u32;
+// run_debug reads debugger commands from stdin until EOF or 'quit'.
+fn run_debug(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    model_options(&mut opts);
+    let matches = parse_matches(program, &opts, args);
+    if matches.opt_present("h") {
+        print_usage(program, "debug [options]", &opts);
+        return;
+    }
+    let mut config = or_exit(program, parse_params(&matches));
+    load_trace(program, &matches, &mut config);
+    let mut debugger = Debugger::new(Simulation::new(config));
+    let stdin = io::stdin();
+    println!("qsim debugger, type 'help' for a list of commands");
+    loop {
+        print!("(qsim) ");
+        io::stdout().flush().unwrap();
 
-    let mut client = Client::new(Markov::new(f64::from(rate)), resolution);
-    let mut server = Server::new(resolution, f64::from(pspeed), qlimit);
-    let mut pstats = OnlineStats::new();
-    let mut qstats = OnlineStats::new();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        match line.trim() {
+            "quit" | "exit" => break,
+            "" => continue,
+            command => println!("{}", debugger.execute(command)),
+        }
+    }
+}
 
-    for i in 0..ticks {
-        qstats.add(server.qlen());
+// run_paced runs the simulation to completion in steps of PACE_STEP simulated seconds, sleeping in
+// between so as to keep to the given pace (if any) and observing the simulation after each.
+fn run_paced<F>(mut sim: Simulation, pace: Option<f64>, mut observe: F) -> Results
+where
+    F: FnMut(&Simulation),
+{
+    let resolution = sim.config().resolution;
+    let step = Ticks::from_seconds(PACE_STEP, resolution).max(Ticks(1));
+    let start = Instant::now();
+    loop {
+        let target = sim.now().saturating_add(step);
+        if !sim.run_until(|s| s.now() >= target) {
+            if sim.paused() {
+                sim.resume();
+                continue;
+            }
+            break;
+        }
+        observe(&sim);
+        if let Some(pace) = pace {
+            let due = Duration::from_secs_f64(sim.now().seconds(resolution) / pace);
+            if let Some(remaining) = due.checked_sub(start.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+    }
+    observe(&sim);
+    sim.results()
+}
 
-        if client.tick() {
-            server.enqueue(Packet {
-                time_generated: i,
-                length: psize,
-            });
+// run_with_progress runs the simulation to completion like Simulation.run, pausing it every
+// PROGRESS_STEP simulated seconds to redraw the progress line on stderr if it's due.
+fn run_with_progress(mut sim: Simulation, progress: &mut Progress) -> Results {
+    let resolution = sim.config().resolution;
+    let start = Instant::now();
+    let mut drawn: Option<Instant> = None;
+    loop {
+        sim.schedule(sim.now().seconds(resolution) + PROGRESS_STEP, Change::Pause)
+            .expect("pausing is always valid");
+        if !sim.advance() {
+            break;
         }
-        if let Some(p) = server.tick() {
-            // We record the time it took for the processed packet to get processed.
-            pstats.add(f64::from(i - p.time_generated) / resolution);
+        sim.resume();
+        if drawn.is_none_or(|t| t.elapsed() >= PROGRESS_INTERVAL) {
+            eprint!("{}", progress.render(&sim, start.elapsed()));
+            drawn = Some(Instant::now());
         }
     }
+    eprint!("{}", progress.finish());
+    sim.results()
+}
 
-    println!("Simulation results:");
-    println!(
-        "\t Average sojourn time:              {:.4} +/- {:.4} seconds",
-        pstats.mean(),
-        pstats.stddev()
-    );
-    println!(
-        "\t Average # of queued packets:       {:.2} +/- {:.2} packets",
-        qstats.mean(),
-        qstats.stddev()
-    );
-    println!(
-        "\t Packets generated:                 {} packets",
-        client.packets_generated()
-    );
-    println!(
-        "\t Packets processed:                 {} packets",
-        server.packets_processed()
-    );
-    println!(
-        "\t Packets droppped:                  {} packets",
-        server.packets_dropped()
-    );
-    println!(
-        "\t Packet loss probability:           {:.2}%",
-        f64::from(server.packets_dropped()) / f64::from(client.packets_generated()) * 100.0
-    );
-    println!(
-        "\t Server idle proportion:            {:.2}%",
-        server.idle_proportion()
-    );
-    println!("\t Packets leftover in queue:         {}", server.qlen());
+// run_recorded runs the simulation to completion, writing out per-packet records, the event log and
+// the departure trace as it goes, whichever are requested. Errors come with the path of the file
+// being written.
+fn run_recorded(
+    mut sim: Simulation,
+    packet_output: Option<(&str, records::Format)>,
+    event_log: Option<&str>,
+    departure_trace: Option<&str>,
+) -> Result<Results, (String, io::Error)> {
+    let at = |path: &str| {
+        let path = path.to_string();
+        move |e| (path, e)
+    };
+    let resolution = sim.config().resolution;
+    let mut records = match packet_output {
+        Some((path, format)) => {
+            let file = BufWriter::new(File::create(path).map_err(at(path))?);
+            Some((path, RecordWriter::new(file, format, resolution).map_err(at(path))?))
+        }
+        None => None,
+    };
+    let mut log = match event_log {
+        Some(path) => {
+            let file = BufWriter::new(File::create(path).map_err(at(path))?);
+            Some((path, EventLog::new(file, resolution)))
+        }
+        None => None,
+    };
+    let mut departures = match departure_trace {
+        Some(path) => {
+            let file = BufWriter::new(File::create(path).map_err(at(path))?);
+            Some((path, TraceWriter::new(file).map_err(at(path))?))
+        }
+        None => None,
+    };
+    let mut recorder = Recorder::new();
+    loop {
+        for event in sim.events() {
+            if let Some((path, ref mut w)) = log {
+                w.write(&event).map_err(at(path))?;
+            }
+            if let Some((path, ref mut w)) = records {
+                if let Some(record) = recorder.observe(&event) {
+                    w.write(&record).map_err(at(path))?;
+                }
+            }
+            if let Some((path, ref mut w)) = departures {
+                if let Event::Departure { time, ref packet } = event {
+                    let entry = TraceEntry {
+                        time: f64::from(time) / resolution,
+                        length: packet.length,
+                    };
+                    w.write(&entry).map_err(at(path))?;
+                }
+            }
+        }
+        if !sim.paused() {
+            break;
+        }
+        sim.resume();
+    }
+    if let Some((path, ref mut w)) = log {
+        w.flush().map_err(at(path))?;
+    }
+    if let Some((path, ref mut w)) = records {
+        w.flush().map_err(at(path))?;
+    }
+    if let Some((path, ref mut w)) = departures {
+        w.flush().map_err(at(path))?;
+    }
+    Ok(sim.results())
+}
+
+// write_histogram writes the non-empty buckets of the histogram as CSV.
+fn write_histogram(path: &str, histogram: &Histogram) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "lower,upper,count")?;
+    for (lower, upper, count) in histogram.buckets() {
+        writeln!(w, "{},{},{}", lower, upper, count)?;
+    }
+    if histogram.overflow() > 0 {
+        writeln!(w, "{},inf,{}", histogram.max(), histogram.overflow())?;
+    }
+    Ok(())
+}
+
+// write_cdf writes the empirical CDF and complementary CDF of the sojourn time as CSV, evaluated at
+// the upper bound of every non-empty histogram bucket, along with those of the M/M/1 queue with the
+// given arrival and service rates, if any.
+fn write_cdf(path: &str, histogram: &Histogram, mm1: Option<(f64, f64)>) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write!(w, "sojourn,cdf,ccdf")?;
+    if mm1.is_some() {
+        write!(w, ",mm1_cdf,mm1_ccdf")?;
+    }
+    writeln!(w)?;
+    let total = histogram.count() as f64;
+    let mut cumulative = 0;
+    for (_, upper, count) in histogram.buckets() {
+        cumulative += count;
+        let cdf = cumulative as f64 / total;
+        write!(w, "{},{},{}", upper, cdf, 1.0 - cdf)?;
+        if let Some((lambda, mu)) = mm1 {
+            let cdf = analytic::mm1_sojourn_cdf(lambda, mu, upper);
+            write!(w, ",{},{}", cdf, 1.0 - cdf)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+// write_occupancy writes the distribution of the number of packets in the system as CSV, along with
+// its complementary CDF, P(N > k), and the distribution of an M/M/1(/K) queue (see
+// analytic::mm1_distribution) over the same values, if given.
+fn write_occupancy(path: &str, occupancy: &[f64], mm1: Option<&Vec<f64>>) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write!(w, "k,probability,ccdf")?;
+    if mm1.is_some() {
+        write!(w, ",mm1_probability,mm1_ccdf")?;
+    }
+    writeln!(w)?;
+    let (mut cdf, mut mm1_cdf) = (0.0, 0.0);
+    for (k, p) in occupancy.iter().enumerate() {
+        cdf += p;
+        write!(w, "{},{},{}", k, p, (1.0 - cdf).max(0.0))?;
+        if let Some(mm1) = mm1 {
+            mm1_cdf += mm1[k];
+            write!(w, ",{},{}", mm1[k], (1.0 - mm1_cdf).max(0.0))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+fn write_chart(path: &str, chart: &Chart) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    chart.write(&mut w)?;
+    w.flush()
+}
+
+// write_samples writes the sampled server state as CSV, with times in seconds.
+fn write_samples(path: &str, samples: &[Sample], resolution: f64) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "time,qlen,busy")?;
+    for s in samples {
+        writeln!(
+            w,
+            "{},{},{}",
+            s.time.seconds(resolution),
+            s.qlen,
+            s.busy as u8
+        )?;
+    }
+    Ok(())
+}
+
+// write_windows writes the per-window counts as CSV, along with the throughput (in packets/s) and
+// the loss probability (as a percentage) over each window.
+fn write_windows(path: &str, windows: &[Window], config: &Config) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "time,arrivals,departures,drops,throughput,loss_probability")?;
+    for window in windows {
+        let end = (window.start + Ticks(config.window.unwrap())).min(config.ticks());
+        let length = end.since(window.start).seconds(config.resolution);
+        writeln!(
+            w,
+            "{},{},{},{},{},{}",
+            window.start.seconds(config.resolution),
+            window.arrivals,
+            window.departures,
+            window.drops,
+            f64::from(window.departures) / length,
+            if window.arrivals > 0 {
+                f64::from(window.drops) / f64::from(window.arrivals) * 100.0
+            } else {
+                0.0
+            }
+        )?;
+    }
+    Ok(())
+}
+
+// write_acf writes the autocorrelation functions of the waiting and interdeparture times as CSV.
+fn write_acf(path: &str, results: &Results) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "lag,wait,interdeparture")?;
+    for (i, (a, d)) in results.wait_acf.iter().zip(&results.interdeparture_acf).enumerate() {
+        writeln!(w, "{},{},{}", i + 1, a, d)?;
+    }
+    Ok(())
+}
+
+// write_welch writes the Welch plot data as CSV: the average queue length and sojourn time (in
+// seconds) over each observation interval, averaged across replications, along with their Welch
+// moving averages and, if there are several replications, the series of each. Values missing, at
+// the end of the moving averages or over intervals without departures, are left empty.
+fn write_welch(path: &str, averaged: &Warmup, warmups: &[Warmup]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write!(w, "time,qlen,moving_average,sojourn,sojourn_moving_average")?;
+    let replications = if warmups.len() > 1 { warmups } else { &[] };
+    for i in 1..=replications.len() {
+        write!(w, ",qlen_{},sojourn_{}", i, i)?;
+    }
+    writeln!(w)?;
+    let cell = |x: Option<&f64>| match x {
+        Some(x) if !x.is_nan() => format!(",{}", x),
+        _ => ",".to_string(),
+    };
+    let sojourn_average = analysis::moving_average(&averaged.sojourn_series, WELCH_WINDOW);
+    for (i, qlen) in averaged.series.iter().enumerate() {
+        write!(w, "{},{}", i as f64 * averaged.interval, qlen)?;
+        write!(w, "{}", cell(averaged.moving_average.get(i)))?;
+        write!(w, "{}", cell(averaged.sojourn_series.get(i)))?;
+        write!(w, "{}", cell(sojourn_average.get(i)))?;
+        for r in replications {
+            write!(w, "{}{}", cell(r.series.get(i)), cell(r.sojourn_series.get(i)))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+fn print_validation(
+    config: &Config,
+    expected: Option<&Expected>,
+    results: &Results,
+    unit: TimeUnit,
+) {
+    println!();
+    let e = match expected {
+        Some(e) => e,
+        None => {
+            println!(
+                "Analytical comparison: no closed-form solution (unstable, changes scheduled, or \
+                 non-Poisson arrivals)"
+            );
+            return;
+        }
+    };
+    println!("Analytical comparison ({}):", e.model);
+    println!("\t {:<35}{:>12}{:>12}{:>10}", "", "expected", "simulated", "error");
+    for (name, expected, simulated, error) in validation(e, results, unit) {
+        println!(
+            "\t {:<35}{:>12.6}{:>12.6}{:>9.2}%",
+            name,
+            expected,
+            simulated,
+            error
+        );
+    }
+    if let Some(occupancy) = analytic::occupancy(config, results.occupancy.len()) {
+        print_occupancy(&occupancy, results);
+    }
+}
+
+// print_occupancy prints the expected and simulated distributions of the number of packets in the
+// system side by side, up to where both are left with less than OCCUPANCY_TAIL of their mass, along
+// with the chi-square distance between them.
+fn print_occupancy(expected: &[f64], results: &Results) {
+    let simulated = results.occupancy_distribution();
+    println!();
+    println!("Occupancy distribution:");
+    println!("\t {:<35}{:>12}{:>12}", "", "expected", "simulated");
+    let (mut expected_cdf, mut simulated_cdf) = (0.0, 0.0);
+    for (k, (e, s)) in expected.iter().zip(&simulated).enumerate() {
+        if 1.0 - expected_cdf < OCCUPANCY_TAIL && 1.0 - simulated_cdf < OCCUPANCY_TAIL {
+            break;
+        }
+        println!("\t {:<35}{:>12.6}{:>12.6}", format!("P(N = {}):", k), e, s);
+        expected_cdf += e;
+        simulated_cdf += s;
+    }
+    let distance = analysis::chi_square_distance(&simulated, expected);
+    println!("\t {:<35}{:.6}", "Chi-square distance:", distance);
+}
+
+// validation returns the metrics that have a closed-form solution, along with their expected and
+// simulated values (times in the given unit) and the relative error (%) of the latter.
+fn validation(e: &Expected, results: &Results, unit: TimeUnit) -> Vec<(String, f64, f64, f64)> {
+    let t = unit.per_second();
+    vec![
+        (
+            format!("Average sojourn time ({}):", unit.name()),
+            e.sojourn * t,
+            results.sojourn.mean() * t,
+        ),
+        ("Average # of queued packets:".to_string(), e.qlen, results.qlen.mean()),
+        (
+            "Packet loss probability (%):".to_string(),
+            e.loss_probability,
+            results.loss_probability(),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, expected, simulated)| {
+        let error = if expected == 0.0 && simulated == 0.0 {
+            0.0
+        } else {
+            (simulated - expected) / expected * 100.0
+        };
+        (name, expected, simulated, error)
+    })
+    .collect()
 }
+
+fn print_slo(evaluations: &[Evaluation]) {
+    println!();
+    println!("Service level objectives:");
+    for e in evaluations {
+        let status = if e.met { "met" } else { "VIOLATED" };
+        println!("\t {:<35}{:>12.6}  {}", format!("{}:", e.objective.spec), e.value, status);
+    }
+}
+
+fn print_costs(c: &Costs) {
+    println!();
+    println!("Cost model:");
+    println!("\t {:<35}{:.2}", "Revenue (packets delivered):", c.revenue);
+    println!("\t {:<35}{:.2}", "Penalties (packets dropped):", c.penalties);
+    println!("\t {:<35}{:.2}", "Cost of busy server time:", c.busy_cost);
+    println!("\t {:<35}{:.2}", "Cost of provisioned server time:", c.server_cost);
+    println!("\t {:<35}{:.2}", "Net:", c.net());
+}
+
@@ -0,0 +1,200 @@
+use simulation::Simulation;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+// exposition returns the current state of the simulation in the Prometheus text exposition format:
+// gauges for the simulated time, queue length and utilization, counters for packets generated,
+// processed and dropped, and summaries of the sojourn and waiting times.
+pub fn exposition(sim: &Simulation) -> String {
+    let r = sim.results();
+    let resolution = sim.config().resolution;
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>| {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+        for (suffix, value) in samples {
+            writeln!(out, "{}{} {}", name, suffix, value).unwrap();
+        }
+    };
+    let gauge = |value: f64| vec![(String::new(), value)];
+    let summary = |percentiles: &[(f64, f64)], mean: f64, count: usize| {
+        let mut samples: Vec<(String, f64)> = percentiles
+            .iter()
+            .map(|&(p, v)| (format!("{{quantile=\"{}\"}}", (p * 1e4).round() / 1e6), v))
+            .collect();
+        let sum = if count > 0 { mean * count as f64 } else { 0.0 };
+        samples.push(("_sum".to_string(), sum));
+        samples.push(("_count".to_string(), count as f64));
+        samples
+    };
+
+    metric(
+        "qsim_time_seconds",
+        "gauge",
+        "Simulated time elapsed.",
+        gauge(f64::from(sim.now()) / resolution),
+    );
+    metric(
+        "qsim_queue_length",
+        "gauge",
+        "Packets currently queued, not including the one in service.",
+        gauge(sim.server().qlen() as f64),
+    );
+    metric(
+        "qsim_server_busy",
+        "gauge",
+        "Whether a packet is currently in service.",
+        gauge(if sim.server().idle() { 0.0 } else { 1.0 }),
+    );
+    metric(
+        "qsim_utilization",
+        "gauge",
+        "Proportion of time the server has been busy.",
+        gauge(r.utilization),
+    );
+    metric(
+        "qsim_packets_generated_total",
+        "counter",
+        "Packets generated.",
+        gauge(f64::from(r.packets_generated)),
+    );
+    metric(
+        "qsim_packets_processed_total",
+        "counter",
+        "Packets processed.",
+        gauge(f64::from(r.packets_processed)),
+    );
+    metric(
+        "qsim_packets_dropped_total",
+        "counter",
+        "Packets dropped for want of buffer space.",
+        gauge(f64::from(r.packets_dropped)),
+    );
+    metric(
+        "qsim_sojourn_seconds",
+        "summary",
+        "Time from arrival to departure.",
+        summary(&r.sojourn_percentiles, r.sojourn.mean(), r.sojourn.len()),
+    );
+    metric(
+        "qsim_wait_seconds",
+        "summary",
+        "Time from arrival to the start of service.",
+        summary(&r.wait_percentiles, r.wait.mean(), r.wait.len()),
+    );
+    out
+}
+
+// Exporter serves the latest metrics over HTTP at /metrics for Prometheus to scrape, from a
+// background thread. The metrics are only as fresh as the last call to Exporter.update.
+pub struct Exporter {
+    addr: SocketAddr,
+    body: Arc<Mutex<String>>,
+}
+
+impl Exporter {
+    pub fn bind(addr: &str) -> io::Result<Exporter> {
+        let listener = TcpListener::bind(addr)?;
+        let body = Arc::new(Mutex::new(String::new()));
+        let exporter = Exporter {
+            addr: listener.local_addr()?,
+            body: body.clone(),
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A misbehaving client shouldn't take the exporter down with it.
+                let _ = serve(stream, &body);
+            }
+        });
+        Ok(exporter)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn update(&self, sim: &Simulation) {
+        *self.body.lock().unwrap() = exposition(sim);
+    }
+}
+
+fn serve(mut stream: TcpStream, body: &Mutex<String>) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Drain the headers, closing the connection with unread data would reset it.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let (status, content_type, body) = if request.starts_with("GET /metrics ") {
+        (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            body.lock().unwrap().clone(),
+        )
+    } else {
+        ("404 Not Found", "text/plain", "not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Config;
+    use std::io::Read;
+    use time::Ticks;
+
+    fn simulation() -> Simulation {
+        let mut sim = Simulation::new(Config {
+            rate: 900,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        });
+        sim.run_until(|s| s.now() >= Ticks(5000));
+        sim
+    }
+
+    #[test]
+    fn exposition_format() {
+        let sim = simulation();
+        let text = exposition(&sim);
+        assert!(text.contains("# TYPE qsim_queue_length gauge\n"));
+        assert!(text.contains("qsim_time_seconds 0.5\n"));
+        assert!(text.contains("qsim_sojourn_seconds{quantile=\"0.999\"} "));
+        let count = format!("qsim_sojourn_seconds_count {}\n", sim.results().packets_processed);
+        assert!(text.contains(&count));
+    }
+
+    #[test]
+    fn scrape() {
+        let exporter = Exporter::bind("127.0.0.1:0").unwrap();
+        exporter.update(&simulation());
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(exporter.addr()).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("qsim_packets_processed_total "));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
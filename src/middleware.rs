@@ -0,0 +1,348 @@
+use simulators::{Packet, Server};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Layer wraps the operations of a server, much like a layer of middleware: it's handed every packet
+// arriving at the server and every tick of it, along with what comes next (the layers within it,
+// and the server itself at the core), and is free to pass them on as is, change them, or act on
+// them itself. Layers compose cross-cutting concerns (rate limiting, fault injection, mirroring
+// traffic to a shadow queue, tracing) around any server, without the server knowing of them; see
+// Layered, and Simulation.add_layer. Both operations default to passing straight through.
+pub trait Layer {
+    // Layer.enqueue is called with every packet arriving at the server, returning whether it was
+    // enqueued (see Server.enqueue).
+    fn enqueue(&mut self, packet: Packet, next: &mut dyn FnMut(Packet) -> bool) -> bool {
+        next(packet)
+    }
+
+    // Layer.tick is called on every tick of the server, with the buffer the packets departing
+    // within it are appended to (see Server.tick_into).
+    fn tick(&mut self, departed: &mut Vec<Packet>, next: &mut dyn FnMut(&mut Vec<Packet>)) {
+        next(departed)
+    }
+}
+
+// A shared layer can be kept a handle to, so as to inspect it along the way.
+impl<L: Layer> Layer for Rc<RefCell<L>> {
+    fn enqueue(&mut self, packet: Packet, next: &mut dyn FnMut(Packet) -> bool) -> bool {
+        self.borrow_mut().enqueue(packet, next)
+    }
+
+    fn tick(&mut self, departed: &mut Vec<Packet>, next: &mut dyn FnMut(&mut Vec<Packet>)) {
+        self.borrow_mut().tick(departed, next)
+    }
+}
+
+// enqueue passes the packet through the layers, outermost first, on to the server.
+pub fn enqueue(layers: &mut [Box<dyn Layer>], server: &mut Server, packet: Packet) -> bool {
+    match layers.split_first_mut() {
+        Some((layer, rest)) => layer.enqueue(packet, &mut |p| enqueue(rest, server, p)),
+        None => server.enqueue(packet),
+    }
+}
+
+// tick ticks the server through the layers, outermost first, appending the packets departing to
+// the given buffer.
+pub fn tick(layers: &mut [Box<dyn Layer>], server: &mut Server, departed: &mut Vec<Packet>) {
+    match layers.split_first_mut() {
+        Some((layer, rest)) => layer.tick(departed, &mut |d| tick(rest, server, d)),
+        None => server.tick_into(departed),
+    }
+}
+
+// Layered is a server wrapped in layers, the first added outermost.
+//
+//   let mut server = Layered::new(Server::new(1e6, 1e4, None)).layer(RateLimit::new(1e-3, 10.0));
+pub struct Layered {
+    server: Server,
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl Layered {
+    pub fn new(server: Server) -> Layered {
+        Layered {
+            server,
+            layers: vec![],
+        }
+    }
+
+    // Layered.layer wraps the server in another layer, within those added before it.
+    pub fn layer<L: Layer + 'static>(mut self, layer: L) -> Layered {
+        self.layers.push(Box::new(layer));
+        self
+    }
+
+    pub fn enqueue(&mut self, packet: Packet) -> bool {
+        enqueue(&mut self.layers, &mut self.server, packet)
+    }
+
+    pub fn tick(&mut self) -> Vec<Packet> {
+        let mut departed = vec![];
+        tick(&mut self.layers, &mut self.server, &mut departed);
+        departed
+    }
+
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+}
+
+// RateLimit polices the arrivals with a token bucket: tokens accrue at the given rate (per tick),
+// up to the given burst, and packets arriving when there's less than a whole token to spend are
+// dropped before reaching the server. They're counted by the layer rather than the server (though
+// a simulation counts them among its drops).
+pub struct RateLimit {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    pub dropped: u32,
+}
+
+impl RateLimit {
+    // RateLimit::new returns a rate limiter starting out with a full bucket.
+    pub fn new(rate: f64, burst: f64) -> RateLimit {
+        RateLimit {
+            rate,
+            burst,
+            tokens: burst,
+            dropped: 0,
+        }
+    }
+}
+
+impl Layer for RateLimit {
+    fn enqueue(&mut self, packet: Packet, next: &mut dyn FnMut(Packet) -> bool) -> bool {
+        if self.tokens < 1.0 {
+            self.dropped += 1;
+            return false;
+        }
+        self.tokens -= 1.0;
+        next(packet)
+    }
+
+    fn tick(&mut self, departed: &mut Vec<Packet>, next: &mut dyn FnMut(&mut Vec<Packet>)) {
+        self.tokens = (self.tokens + self.rate).min(self.burst);
+        next(departed)
+    }
+}
+
+// Mirror copies the traffic reaching the server on to a shadow server, ticked alongside it, so as
+// to compare how another configuration would've fared under the exact same arrivals. The shadow's
+// departures go nowhere; its statistics are what's of interest.
+pub struct Mirror {
+    pub shadow: Server,
+    // Where the shadow's departures go, reused from one tick to the next.
+    discarded: Vec<Packet>,
+}
+
+impl Mirror {
+    pub fn new(shadow: Server) -> Mirror {
+        Mirror {
+            shadow,
+            discarded: vec![],
+        }
+    }
+}
+
+impl Layer for Mirror {
+    fn enqueue(&mut self, packet: Packet, next: &mut dyn FnMut(Packet) -> bool) -> bool {
+        self.shadow.enqueue(packet.clone());
+        next(packet)
+    }
+
+    fn tick(&mut self, departed: &mut Vec<Packet>, next: &mut dyn FnMut(&mut Vec<Packet>)) {
+        self.shadow.tick_into(&mut self.discarded);
+        self.discarded.clear();
+        next(departed)
+    }
+}
+
+// Quota is what a tenant sharing a server is allowed: at most the given number of packets in the
+// server (queued or in service) at once, and arrivals at most at the given rate (per tick), policed
+// with a token bucket holding up to the given burst.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quota {
+    pub packets: Option<usize>,
+    pub rate: Option<f64>,
+    pub burst: f64,
+}
+
+// TenantQuotas isolates the tenants sharing a server from one another, dropping the packets of a
+// tenant over its quota before they reach the server, so that a noisy neighbour can't crowd the
+// others out. Tenants past the quotas given are unrestricted. The packets dropped are counted by
+// tenant, by the layer rather than the server (as with RateLimit).
+#[derive(Clone, Debug)]
+pub struct TenantQuotas {
+    quotas: Vec<Quota>,
+    // The number of packets of each tenant in the server, and the tokens in each one's bucket.
+    held: Vec<usize>,
+    tokens: Vec<f64>,
+    pub dropped: Vec<u32>,
+}
+
+impl TenantQuotas {
+    // TenantQuotas::new returns the quotas of the tenants numbered 0 up, their buckets full.
+    pub fn new(quotas: Vec<Quota>) -> TenantQuotas {
+        TenantQuotas {
+            held: vec![0; quotas.len()],
+            tokens: quotas.iter().map(|q| q.burst).collect(),
+            dropped: vec![0; quotas.len()],
+            quotas,
+        }
+    }
+
+    // TenantQuotas.skip accounts for the given number of ticks of an idle server skipped over, as
+    // if TenantQuotas.tick was called that many times.
+    pub fn skip(&mut self, ticks: u32) {
+        self.refill(f64::from(ticks));
+    }
+
+    fn refill(&mut self, ticks: f64) {
+        for (tokens, quota) in self.tokens.iter_mut().zip(&self.quotas) {
+            if let Some(rate) = quota.rate {
+                *tokens = (*tokens + rate * ticks).min(quota.burst);
+            }
+        }
+    }
+}
+
+impl Layer for TenantQuotas {
+    fn enqueue(&mut self, packet: Packet, next: &mut dyn FnMut(Packet) -> bool) -> bool {
+        let t = packet.tenant as usize;
+        let quota = match self.quotas.get(t) {
+            Some(&quota) => quota,
+            None => return next(packet),
+        };
+        let over = quota.packets.is_some_and(|n| self.held[t] >= n) ||
+            quota.rate.is_some() && self.tokens[t] < 1.0;
+        if over {
+            self.dropped[t] += 1;
+            return false;
+        }
+        if quota.rate.is_some() {
+            self.tokens[t] -= 1.0;
+        }
+        let enqueued = next(packet);
+        self.held[t] += usize::from(enqueued);
+        enqueued
+    }
+
+    fn tick(&mut self, departed: &mut Vec<Packet>, next: &mut dyn FnMut(&mut Vec<Packet>)) {
+        self.refill(1.0);
+        let first = departed.len();
+        next(departed);
+        for p in &departed[first..] {
+            if let Some(held) = self.held.get_mut(p.tenant as usize) {
+                *held -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::{Config, Simulation};
+
+    // Records the order layers see packets in.
+    struct Trace(&'static str, Rc<RefCell<Vec<String>>>);
+
+    impl Layer for Trace {
+        fn enqueue(&mut self, packet: Packet, next: &mut dyn FnMut(Packet) -> bool) -> bool {
+            self.1.borrow_mut().push(format!("{} {}", self.0, packet.id));
+            next(packet)
+        }
+    }
+
+    fn packet(id: u32) -> Packet {
+        Packet {
+            id,
+            length: 1,
+            ..Packet::default()
+        }
+    }
+
+    #[test]
+    fn layers() {
+        let trace = Rc::new(RefCell::new(vec![]));
+        let mirror = Rc::new(RefCell::new(Mirror::new(Server::new(1.0, 1.0, Some(1)))));
+        let mut server = Layered::new(Server::new(1.0, 1.0, None))
+            .layer(Trace("outer", trace.clone()))
+            .layer(RateLimit::new(0.5, 1.0))
+            .layer(Trace("inner", trace.clone()))
+            .layer(mirror.clone());
+        // With a single token to start with, accruing one every other tick, only every other of
+        // the packets arriving a tick makes it past the rate limiter.
+        let mut departed = vec![];
+        for id in 0..4 {
+            server.enqueue(packet(id));
+            departed.extend(server.tick().iter().map(|p| p.id));
+        }
+        assert_eq!(departed, vec![0, 2]);
+        assert_eq!(
+            *trace.borrow(),
+            vec!["outer 0", "inner 0", "outer 1", "outer 2", "inner 2", "outer 3"]
+        );
+        // The shadow, innermost, only sees what made it past the rate limiter.
+        assert_eq!(mirror.borrow().shadow.packets_processed(), 2);
+        assert_eq!(server.server().packets_processed(), 2);
+    }
+
+    #[test]
+    fn tenant_quotas() {
+        // Tenant 0 may have two packets in the server at once, and tenant 1 may send a packet
+        // every other tick; tenant 2 has no quota at all.
+        let quotas = TenantQuotas::new(vec![
+            Quota { packets: Some(2), ..Quota::default() },
+            Quota { rate: Some(0.5), burst: 1.0, ..Quota::default() },
+        ]);
+        let quotas = Rc::new(RefCell::new(quotas));
+        let mut server = Layered::new(Server::new(1.0, 1.0, None)).layer(quotas.clone());
+        let tenant = |id, tenant| Packet { tenant, ..packet(id) };
+        let enqueued: Vec<bool> = (0..3).map(|id| server.enqueue(tenant(id, 0))).collect();
+        assert_eq!(enqueued, vec![true, true, false]);
+        assert!(server.enqueue(tenant(3, 1)));
+        assert!(!server.enqueue(tenant(4, 1)));
+        assert!((5..10).all(|id| server.enqueue(tenant(id, 2))));
+        assert_eq!(quotas.borrow().dropped, vec![1, 1]);
+
+        // Once one of its packets departs, tenant 0 may send another, and a tick's worth of
+        // tokens is only half of what tenant 1 needs.
+        assert_eq!(server.tick().iter().map(|p| p.id).collect::<Vec<_>>(), vec![0]);
+        assert!(server.enqueue(tenant(10, 0)));
+        assert!(!server.enqueue(tenant(11, 0)));
+        assert!(!server.enqueue(tenant(12, 1)));
+        quotas.borrow_mut().skip(1);
+        assert!(server.enqueue(tenant(13, 1)));
+        assert_eq!(quotas.borrow().dropped, vec![2, 2]);
+    }
+
+    #[test]
+    fn simulation() {
+        let config = Config {
+            rate: 500,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        };
+        let mirror = Rc::new(RefCell::new(Mirror::new(Server::new(1e4, 1000.0, None))));
+        let mut sim = Simulation::new(config.clone());
+        sim.add_layer(Box::new(mirror.clone()));
+        let results = sim.run();
+        // The same server, fed the same packets, fares the same.
+        assert_eq!(mirror.borrow().shadow.packets_processed(), results.packets_processed);
+        let unlayered = Simulation::new(config.clone()).run();
+        assert_eq!(results.packets_processed, unlayered.packets_processed);
+
+        // Packets dropped by the layers count as dropped all the same.
+        let limit = Rc::new(RefCell::new(RateLimit::new(0.01, 1.0)));
+        let mut sim = Simulation::new(config);
+        sim.add_layer(Box::new(limit.clone()));
+        let results = sim.run();
+        assert!(limit.borrow().dropped > 0);
+        assert_eq!(results.packets_dropped, limit.borrow().dropped);
+        assert_eq!(results.packets_processed + results.packets_dropped, results.packets_generated);
+    }
+}
@@ -0,0 +1,302 @@
+use histogram::Histogram;
+use simulation::{Config, Results, Window};
+use std::io::{self, Write};
+use time::Ticks;
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 400.0;
+const MARGIN_LEFT: f64 = 80.0;
+const MARGIN_RIGHT: f64 = 20.0;
+const MARGIN_TOP: f64 = 40.0;
+const MARGIN_BOTTOM: f64 = 50.0;
+const TICKS: usize = 5;
+const COLORS: [&str; 4] = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e"];
+
+// Series is a labelled sequence of (x, y) points, drawn as a line in order.
+#[derive(Clone, Debug)]
+pub struct Series {
+    pub label: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+// Chart is a line chart of one or more series, rendered as SVG; just enough of a plotting library
+// to eyeball the results of a run without a separate analysis pipeline. Stepped charts hold each
+// value until the next point, as befits sampled or windowed data.
+#[derive(Clone, Debug)]
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub stepped: bool,
+    pub series: Vec<Series>,
+}
+
+impl Chart {
+    // Chart.write renders the chart as SVG. Axes start at zero for non-negative data.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let points = || self.series.iter().flat_map(|s| s.points.iter());
+        let (x_min, x_max) = range(points().map(|p| p.0));
+        let (y_min, y_max) = range(points().map(|p| p.1));
+        let (plot_w, plot_h) = (
+            WIDTH - MARGIN_LEFT - MARGIN_RIGHT,
+            HEIGHT - MARGIN_TOP - MARGIN_BOTTOM,
+        );
+        let x = |v: f64| MARGIN_LEFT + (v - x_min) / (x_max - x_min) * plot_w;
+        let y = |v: f64| MARGIN_TOP + plot_h - (v - y_min) / (y_max - y_min) * plot_h;
+
+        writeln!(
+            w,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             font-family=\"sans-serif\" font-size=\"12\">",
+            WIDTH,
+            HEIGHT
+        )?;
+        writeln!(w, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>")?;
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"24\" text-anchor=\"middle\" font-size=\"16\">{}</text>",
+            WIDTH / 2.0,
+            escape(&self.title)
+        )?;
+
+        // The axes, their ticks and labels.
+        writeln!(
+            w,
+            "<path d=\"M{0},{1}V{2}H{3}\" stroke=\"black\" fill=\"none\"/>",
+            MARGIN_LEFT,
+            MARGIN_TOP,
+            MARGIN_TOP + plot_h,
+            MARGIN_LEFT + plot_w
+        )?;
+        for t in ticks(x_min, x_max) {
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                x(t),
+                MARGIN_TOP + plot_h + 16.0,
+                t
+            )?;
+        }
+        for t in ticks(y_min, y_max) {
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                MARGIN_LEFT - 6.0,
+                y(t) + 4.0,
+                t
+            )?;
+            writeln!(
+                w,
+                "<line x1=\"{0}\" y1=\"{1:.1}\" x2=\"{2}\" y2=\"{1:.1}\" stroke=\"#ddd\"/>",
+                MARGIN_LEFT,
+                y(t),
+                MARGIN_LEFT + plot_w
+            )?;
+        }
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            MARGIN_LEFT + plot_w / 2.0,
+            HEIGHT - 12.0,
+            escape(&self.x_label)
+        )?;
+        writeln!(
+            w,
+            "<text transform=\"translate(16,{}) rotate(-90)\" text-anchor=\"middle\">{}</text>",
+            MARGIN_TOP + plot_h / 2.0,
+            escape(&self.y_label)
+        )?;
+
+        for (i, s) in self.series.iter().enumerate() {
+            let color = COLORS[i % COLORS.len()];
+            let mut path = String::new();
+            for (j, &(px, py)) in s.points.iter().enumerate() {
+                if j == 0 {
+                    path.push_str(&format!("M{:.1},{:.1}", x(px), y(py)));
+                } else if self.stepped {
+                    path.push_str(&format!("H{:.1}V{:.1}", x(px), y(py)));
+                } else {
+                    path.push_str(&format!("L{:.1},{:.1}", x(px), y(py)));
+                }
+            }
+            writeln!(w, "<path d=\"{}\" stroke=\"{}\" fill=\"none\"/>", path, color)?;
+            if self.series.len() > 1 {
+                let ly = MARGIN_TOP + 14.0 * i as f64;
+                writeln!(
+                    w,
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"end\" fill=\"{}\">{}</text>",
+                    MARGIN_LEFT + plot_w - 4.0,
+                    ly + 10.0,
+                    color,
+                    escape(&s.label)
+                )?;
+            }
+        }
+        writeln!(w, "</svg>")
+    }
+}
+
+// range returns the extent of the given values, widened to include zero for non-negative data and
+// to be non-empty.
+fn range<I: Iterator<Item = f64>>(values: I) -> (f64, f64) {
+    let (min, max) = values
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if min > max {
+        return (0.0, 1.0);
+    }
+    let min = if min >= 0.0 { 0.0 } else { min };
+    if max > min {
+        (min, max)
+    } else {
+        (min, min + 1.0)
+    }
+}
+
+// ticks returns roughly TICKS evenly spaced round values within [min, max], i.e. multiples of 1, 2
+// or 5 times a power of ten.
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+    let raw = (max - min) / TICKS as f64;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&s| s >= raw)
+        .unwrap();
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    // Rounding to the step's precision keeps the labels free of floating-point noise.
+    let digits = (-step.log10().floor()).max(0.0) as i32;
+    let scale = 10f64.powi(digits);
+    (first..last + 1)
+        .map(|i| (i as f64 * step * scale).round() / scale)
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// queue_length returns the chart of the sampled queue length over time, if sampled.
+pub fn queue_length(results: &Results, resolution: f64) -> Option<Chart> {
+    if results.samples.is_empty() {
+        return None;
+    }
+    Some(Chart {
+        title: "Queue length".to_string(),
+        x_label: "Time (seconds)".to_string(),
+        y_label: "Packets queued".to_string(),
+        stepped: true,
+        series: vec![Series {
+            label: "qlen".to_string(),
+            points: results
+                .samples
+                .iter()
+                .map(|s| (s.time.seconds(resolution), s.qlen as f64))
+                .collect(),
+        }],
+    })
+}
+
+// latency_cdf returns the chart of the cumulative distribution of the sojourn and waiting times,
+// from the histograms if kept, or else from the estimated percentiles alone.
+pub fn latency_cdf(results: &Results) -> Chart {
+    let cdf = |label: &str, histogram: Option<&Histogram>, percentiles: &[(f64, f64)]| {
+        let points = match histogram {
+            Some(h) => {
+                let total = h.count() as f64;
+                let mut cumulative = 0;
+                h.buckets()
+                    .into_iter()
+                    .map(|(_, upper, count)| {
+                        cumulative += count;
+                        (upper, cumulative as f64 / total)
+                    })
+                    .collect()
+            }
+            None => percentiles.iter().map(|&(p, v)| (v, p / 100.0)).collect(),
+        };
+        Series {
+            label: label.to_string(),
+            points,
+        }
+    };
+    Chart {
+        title: "Latency CDF".to_string(),
+        x_label: "Time (seconds)".to_string(),
+        y_label: "Cumulative probability".to_string(),
+        stepped: false,
+        series: vec![
+            cdf(
+                "sojourn",
+                results.sojourn_histogram.as_ref(),
+                &results.sojourn_percentiles,
+            ),
+            cdf("wait", results.wait_histogram.as_ref(), &results.wait_percentiles),
+        ],
+    }
+}
+
+// throughput returns the chart of the arrival and departure rates over each window, if counted.
+pub fn throughput(results: &Results, config: &Config) -> Option<Chart> {
+    let length = config.window?;
+    let rate = |start: Ticks, count: u32| {
+        let end = (start + Ticks(length)).min(config.ticks());
+        let secs = end.since(start).seconds(config.resolution);
+        (start.seconds(config.resolution), f64::from(count) / secs)
+    };
+    let series = |label: &str, count: fn(&Window) -> u32| Series {
+        label: label.to_string(),
+        points: results.windows.iter().map(|w| rate(w.start, count(w))).collect(),
+    };
+    Some(Chart {
+        title: "Throughput".to_string(),
+        x_label: "Time (seconds)".to_string(),
+        y_label: "Packets/s".to_string(),
+        stepped: true,
+        series: vec![series("arrivals", |w| w.arrivals), series("departures", |w| w.departures)],
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Simulation;
+
+    #[test]
+    fn round_ticks() {
+        assert_eq!(ticks(0.0, 1.0), vec![0.0, 0.2, 0.4, 0.6, 0.8, 1.0]);
+        assert_eq!(ticks(0.0, 37.0), vec![0.0, 10.0, 20.0, 30.0]);
+        assert_eq!(ticks(0.0, 0.003), vec![0.0, 0.001, 0.002, 0.003]);
+        assert_eq!(ticks(0.0, 0.0025), vec![0.0, 0.0005, 0.001, 0.0015, 0.002, 0.0025]);
+        assert_eq!(range(vec![3.0, 3.0].into_iter()), (0.0, 3.0));
+        assert_eq!(range(vec![].into_iter()), (0.0, 1.0));
+    }
+
+    #[test]
+    fn charts() {
+        let c = Config {
+            rate: 900,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            seed: Some(1),
+            sample_interval: Some(100),
+            window: Some(1000),
+            ..Config::default()
+        };
+        let r = Simulation::new(c.clone()).run();
+        let cdf = latency_cdf(&r);
+        let last = *cdf.series[0].points.last().unwrap();
+        assert!((last.1 - 1.0).abs() < 1e-9);
+        assert_eq!(throughput(&r, &c).unwrap().series[0].points.len(), 10);
+
+        let mut svg = vec![];
+        queue_length(&r, c.resolution).unwrap().write(&mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("Queue length"));
+        assert!(queue_length(&Simulation::new(Config::default()).results(), 1e6).is_none());
+    }
+}
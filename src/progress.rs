@@ -0,0 +1,120 @@
+use dashboard;
+use simulation::Simulation;
+use std::time::Duration;
+
+// The width of the progress bar, in characters.
+const BAR_WIDTH: usize = 20;
+
+// Moves the cursor to the start of the line and clears it.
+const CLEAR_LINE: &str = "\r\x1b[K";
+
+// Progress renders a single status line for long runs, redrawn in place: the simulated time and
+// percentage complete, the simulation's throughput in events (arrivals and departures) per
+// wall-clock second, measured since the previous line, and the current queue length.
+pub struct Progress {
+    // The wall-clock time of, and the number of events as of, the previous line.
+    last: Option<(Duration, u64)>,
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress { last: None }
+    }
+
+    // Progress.render returns the next status line, the given wall-clock time having elapsed since
+    // the run started.
+    pub fn render(&mut self, sim: &Simulation, elapsed: Duration) -> String {
+        let c = sim.config();
+        let events = u64::from(sim.packets_generated()) +
+                     u64::from(sim.server().statistics.packets_processed);
+        let (then, before) = self.last.unwrap_or((Duration::from_secs(0), 0));
+        let secs = (elapsed - then.min(elapsed)).as_secs_f64();
+        let throughput = if secs > 0.0 { (events - before) as f64 / secs } else { 0.0 };
+        self.last = Some((elapsed, events));
+        let fraction = sim.progress();
+        let now = sim.now().seconds(c.resolution);
+        let simulated = match c.packets {
+            Some(n) => {
+                let departed = sim.server().statistics.packets_processed;
+                format!("{:.2}s simulated, {}/{} packets", now, departed, n)
+            }
+            None => format!("{:.2}/{}s simulated", now, c.duration),
+        };
+        format!(
+            "{}{} {:>3.0}% {}, {} events/s, qlen {}",
+            CLEAR_LINE,
+            dashboard::bar(fraction, BAR_WIDTH),
+            fraction * 100.0,
+            simulated,
+            si(throughput),
+            sim.server().qlen()
+        )
+    }
+
+    // Progress.finish returns what clears the status line once the run is over.
+    pub fn finish(&self) -> &'static str {
+        CLEAR_LINE
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress::new()
+    }
+}
+
+// si formats the number with an SI prefix (k, M or G) and two decimals, or as a whole number if
+// below a thousand.
+fn si(x: f64) -> String {
+    let (value, prefix) = if x >= 1e9 {
+        (x / 1e9, "G")
+    } else if x >= 1e6 {
+        (x / 1e6, "M")
+    } else if x >= 1e3 {
+        (x / 1e3, "k")
+    } else {
+        return format!("{:.0}", x);
+    };
+    format!("{:.2}{}", value, prefix)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Config;
+    use time::Ticks;
+
+    #[test]
+    fn lines() {
+        let mut sim = Simulation::new(Config {
+            rate: 900,
+            pspeed: 1000,
+            duration: 2,
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        });
+        let mut progress = Progress::new();
+        let first = progress.render(&sim, Duration::from_secs(0));
+        assert!(first.starts_with(CLEAR_LINE));
+        assert!(first.ends_with("  0% 0.00/2s simulated, 0 events/s, qlen 0"));
+
+        sim.run_until(|s| s.now() >= Ticks(10_000));
+        let line = progress.render(&sim, Duration::from_secs(2));
+        let bar = dashboard::bar(0.5, BAR_WIDTH);
+        assert!(line.contains(&format!("{}  50% 1.00/2s simulated, ", bar)));
+        // About 900 arrivals and as many departures over two wall-clock seconds.
+        let events = line.split(", ").nth(1).unwrap().split(' ').next().unwrap();
+        assert!((events.parse::<f64>().unwrap() - 900.0).abs() < 100.0, "{}", line);
+    }
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(si(0.0), "0");
+        assert_eq!(si(999.4), "999");
+        assert_eq!(si(1500.0), "1.50k");
+        assert_eq!(si(2.345e6), "2.35M");
+        assert_eq!(si(1e10), "10.00G");
+    }
+}
@@ -0,0 +1,237 @@
+use histogram::{Bucketing, Histogram};
+
+// Estimator selects how percentiles are estimated: from a histogram of all samples, or by a P²
+// estimator per percentile, which takes constant memory regardless of the range of the samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Estimator {
+    Histogram,
+    P2,
+}
+
+impl Estimator {
+    pub fn parse(s: &str) -> Option<Estimator> {
+        match s {
+            "histogram" => Some(Estimator::Histogram),
+            "p2" => Some(Estimator::P2),
+            _ => None,
+        }
+    }
+}
+
+// P2 estimates a single quantile of a stream of samples without storing them, as per Jain &
+// Chlamtac's P² algorithm ("The P² Algorithm for Dynamic Calculation of Quantiles and Histograms
+// Without Storing Observations", 1985). It maintains five markers: the minimum, the maximum, the
+// quantile itself and the quantiles halfway to either extreme. As samples come in, the markers'
+// heights are adjusted by piecewise-parabolic interpolation so as to stay at their desired
+// positions.
+#[derive(Clone, Debug)]
+pub struct P2 {
+    p: f64,
+    count: usize,
+    // Marker heights, actual positions, desired positions and desired position increments.
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2 {
+    // P2::new returns an estimator for the p-th quantile (0 <= p <= 1).
+    pub fn new(p: f64) -> P2 {
+        P2 {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + 2.0 * p, 4.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    // P2.add adds the sample to the estimate. NaN samples are left out, as they fall in no cell.
+    pub fn add(&mut self, x: f64) {
+        if x.is_nan() {
+            return;
+        }
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.total_cmp(b));
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Find the cell the sample falls in, extending the extremes if need be.
+        let h = &mut self.heights;
+        let k = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            (1..5).position(|i| x < h[i]).unwrap()
+        };
+        for i in k + 1..5 {
+            self.positions[i] += 1.0;
+        }
+        for i in 0..5 {
+            self.desired[i] += self.increments[i];
+        }
+
+        // Move the middle markers towards their desired positions, by one at most.
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            let (n, q) = (&mut self.positions, &mut self.heights);
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = q[i] +
+                    d / (n[i + 1] - n[i - 1]) *
+                        ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i]) +
+                             (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+                q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    let j = (i as f64 + d) as usize;
+                    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+                };
+                n[i] += d;
+            }
+        }
+    }
+
+    // P2.quantile returns the current estimate, exact while there are five samples or fewer. It
+    // returns NaN if there are none.
+    pub fn quantile(&self) -> f64 {
+        if self.count == 0 {
+            return f64::NAN;
+        }
+        if self.count < 5 {
+            let mut samples = self.heights[..self.count].to_vec();
+            samples.sort_by(|a, b| a.total_cmp(b));
+            let rank = (self.p * self.count as f64).ceil() as usize;
+            return samples[rank.clamp(1, self.count) - 1];
+        }
+        self.heights[2]
+    }
+}
+
+// Quantiles tracks the configured percentiles of a stream of samples using the selected
+// estimator.
+#[derive(Clone, Debug)]
+pub enum Quantiles {
+    Histogram(Histogram),
+    P2(Vec<(f64, P2)>),
+}
+
+impl Quantiles {
+    pub fn new(estimator: Estimator, bucketing: Bucketing, percentiles: &[f64]) -> Quantiles {
+        match estimator {
+            Estimator::Histogram => Quantiles::Histogram(Histogram::new(bucketing)),
+            Estimator::P2 => {
+                Quantiles::P2(percentiles.iter().map(|&p| (p, P2::new(p / 100.0))).collect())
+            }
+        }
+    }
+
+    pub fn record(&mut self, value: f64) {
+        match *self {
+            Quantiles::Histogram(ref mut h) => h.record(value),
+            Quantiles::P2(ref mut estimators) => {
+                for &mut (_, ref mut e) in estimators {
+                    e.add(value);
+                }
+            }
+        }
+    }
+
+    // Quantiles.percentile returns the estimated p-th percentile (0 <= p <= 100), which for P²
+    // estimation is only available for the percentiles configured upfront (NaN otherwise).
+    pub fn percentile(&self, p: f64) -> f64 {
+        match *self {
+            Quantiles::Histogram(ref h) => h.percentile(p),
+            Quantiles::P2(ref estimators) => estimators
+                .iter()
+                .find(|&&(q, _)| q == p)
+                .map_or(f64::NAN, |(_, e)| e.quantile()),
+        }
+    }
+
+    // Quantiles.histogram returns the underlying histogram, if percentiles are estimated from one.
+    pub fn histogram(&self) -> Option<&Histogram> {
+        match *self {
+            Quantiles::Histogram(ref h) => Some(h),
+            Quantiles::P2(_) => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rng;
+    use std::f64;
+
+    extern crate rand;
+    use self::rand::Rng;
+
+    #[test]
+    fn p2_uniform() {
+        let mut r = rng::substream(1, rng::ARRIVALS);
+        let mut estimators: Vec<P2> = [0.5, 0.9, 0.99].iter().map(|&p| P2::new(p)).collect();
+        for _ in 0..100_000 {
+            let x = r.next_f64();
+            for e in &mut estimators {
+                e.add(x);
+            }
+        }
+        for (e, &p) in estimators.iter().zip(&[0.5, 0.9, 0.99]) {
+            assert!((e.quantile() - p).abs() < 0.005, "{} vs {}", e.quantile(), p);
+        }
+    }
+
+    #[test]
+    fn p2_exponential() {
+        // The p-th quantile of an exponential distribution with unit mean is -ln(1 - p).
+        let mut r = rng::substream(2, rng::ARRIVALS);
+        let mut e = P2::new(0.99);
+        for _ in 0..100_000 {
+            e.add(-(1.0 - r.next_f64()).ln());
+        }
+        let expected = -(0.01f64).ln();
+        assert!(((e.quantile() - expected) / expected).abs() < 0.02);
+    }
+
+    #[test]
+    fn p2_few_samples() {
+        let mut e = P2::new(0.5);
+        assert!(e.quantile().is_nan());
+        for &x in &[3.0, 1.0, 2.0] {
+            e.add(x);
+        }
+        assert_eq!(e.quantile(), 2.0);
+
+        // A NaN sample is left out rather than bringing the estimator down.
+        for &x in &[f64::NAN, 4.0, 5.0, f64::NAN, 6.0] {
+            e.add(x);
+        }
+        assert_eq!(e.quantile(), 3.0);
+    }
+
+    #[test]
+    fn selectable() {
+        let mut h = Quantiles::new(Estimator::Histogram, Bucketing::default(), &[50.0]);
+        let mut p = Quantiles::new(Estimator::P2, Bucketing::default(), &[50.0]);
+        for i in 1..1001 {
+            h.record(f64::from(i) * 1e-3);
+            p.record(f64::from(i) * 1e-3);
+        }
+        assert!((h.percentile(50.0) - 0.5).abs() < 0.025);
+        assert!((p.percentile(50.0) - 0.5).abs() < 0.025);
+        assert!(p.percentile(90.0).is_nan());
+        assert!(h.histogram().is_some() && p.histogram().is_none());
+    }
+}
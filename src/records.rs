@@ -0,0 +1,404 @@
+use simulation::Event;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use time::Ticks;
+
+// DropReason is why a packet was dropped (see Event::Drop).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropReason {
+    // The server's buffer was full.
+    BufferFull,
+    // The packet's tenant was over its quota.
+    Quota,
+    // The rate limiter ahead of the server rejected it (see RateLimiting).
+    RateLimited,
+    // It was discarded on being preempted (see Preemption::Discard).
+    Preempted,
+    // Its color was over its limit (see Marking).
+    Color,
+    // A layer wrapping the server turned it away (see Simulation.add_layer).
+    Layer,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            DropReason::BufferFull => "buffer_full",
+            DropReason::Quota => "quota",
+            DropReason::RateLimited => "rate_limited",
+            DropReason::Preempted => "preempted",
+            DropReason::Color => "color",
+            DropReason::Layer => "layer",
+        }
+    }
+}
+
+// Record is the life of a single packet through the system, with all times in ticks. Dropped
+// packets never depart, and bar those discarded on being preempted, never enter service either.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub arrival: Ticks,
+    pub service_start: Option<Ticks>,
+    pub departure: Option<Ticks>,
+    pub length: u32,
+    pub class: u32,
+    pub flow: u32,
+    pub dropped: Option<DropReason>,
+}
+
+impl Record {
+    pub fn wait(&self) -> Option<Ticks> {
+        self.service_start.map(|s| s.since(self.arrival))
+    }
+
+    pub fn service(&self) -> Option<Ticks> {
+        match (self.service_start, self.departure) {
+            (Some(s), Some(d)) => Some(d.since(s)),
+            _ => None,
+        }
+    }
+}
+
+// Recorder pieces together per-packet records from the event stream of a simulation (see
+// Simulation.events), yielding a record once the packet either departs or is dropped. Departures
+// are matched up with service starts by packet id, whatever the order packets are served in. A
+// packet preempted enters service again once resumed or restarted, and its record is of the last
+// time it did: its service is the last stretch of it, its wait everything before.
+pub struct Recorder {
+    started: HashMap<u32, Ticks>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder { started: HashMap::new() }
+    }
+
+    pub fn observe(&mut self, event: &Event) -> Option<Record> {
+        match *event {
+            Event::Arrival { .. } | Event::Enqueue { .. } => None,
+            Event::ServiceStart { time, ref packet } => {
+                self.started.insert(packet.id, time);
+                None
+            }
+            // Packets discarded on being preempted have entered service, but never depart.
+            Event::Drop { ref packet, reason, .. } => Some(Record {
+                arrival: packet.time_generated,
+                service_start: self.started.remove(&packet.id),
+                departure: None,
+                length: packet.length,
+                class: packet.class,
+                flow: packet.flow,
+                dropped: Some(reason),
+            }),
+            Event::Departure { time, ref packet } => Some(Record {
+                arrival: packet.time_generated,
+                service_start: self.started.remove(&packet.id),
+                departure: Some(time),
+                length: packet.length,
+                class: packet.class,
+                flow: packet.flow,
+                dropped: None,
+            }),
+        }
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Recorder {
+        Recorder::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Format> {
+        match s {
+            "csv" => Some(Format::Csv),
+            "jsonl" => Some(Format::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+// RecordWriter writes records in the given format, one per line, with times in seconds. Missing
+// values are left empty in CSV and null in JSONL.
+pub struct RecordWriter<W: Write> {
+    w: W,
+    format: Format,
+    resolution: f64,
+}
+
+impl<W: Write> RecordWriter<W> {
+    // RecordWriter::new writes out the CSV header, if applicable.
+    pub fn new(mut w: W, format: Format, resolution: f64) -> io::Result<RecordWriter<W>> {
+        if format == Format::Csv {
+            writeln!(
+                w,
+                "arrival,service_start,departure,wait,service,length,class,flow,drop_reason"
+            )?;
+        }
+        Ok(RecordWriter {
+            w,
+            format,
+            resolution,
+        })
+    }
+
+    pub fn write(&mut self, r: &Record) -> io::Result<()> {
+        let secs = |t: Option<Ticks>| t.map(|t| t.seconds(self.resolution));
+        let arrival = r.arrival.seconds(self.resolution);
+        let fields = [
+            secs(r.service_start),
+            secs(r.departure),
+            secs(r.wait()),
+            secs(r.service()),
+        ];
+        let reason = r.dropped.map(|d| d.as_str());
+        match self.format {
+            Format::Csv => {
+                let f: Vec<String> = fields
+                    .iter()
+                    .map(|f| f.map_or_else(String::new, |v| v.to_string()))
+                    .collect();
+                writeln!(
+                    self.w,
+                    "{},{},{},{},{},{},{},{},{}",
+                    arrival,
+                    f[0],
+                    f[1],
+                    f[2],
+                    f[3],
+                    r.length,
+                    r.class,
+                    r.flow,
+                    reason.unwrap_or("")
+                )
+            }
+            Format::Jsonl => {
+                let f: Vec<String> = fields
+                    .iter()
+                    .map(|f| f.map_or_else(|| "null".to_string(), |v| v.to_string()))
+                    .collect();
+                writeln!(
+                    self.w,
+                    "{{\"arrival\":{},\"service_start\":{},\"departure\":{},\"wait\":{},\
+                     \"service\":{},\"length\":{},\"class\":{},\"flow\":{},\
+                     \"drop_reason\":{}}}",
+                    arrival,
+                    f[0],
+                    f[1],
+                    f[2],
+                    f[3],
+                    r.length,
+                    r.class,
+                    r.flow,
+                    reason.map_or_else(|| "null".to_string(), |r| format!("\"{}\"", r))
+                )
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+// EventLog writes out every event of a simulation (see Simulation.events) as a JSON object per
+// line, with times in seconds, so individual packets can be traced through the system by id.
+pub struct EventLog<W: Write> {
+    w: W,
+    resolution: f64,
+}
+
+impl<W: Write> EventLog<W> {
+    pub fn new(w: W, resolution: f64) -> EventLog<W> {
+        EventLog { w, resolution }
+    }
+
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        let p = event.packet();
+        write!(
+            self.w,
+            "{{\"time\":{},\"event\":\"{}\",\"id\":{},\"length\":{},\"class\":{},\"flow\":{}",
+            f64::from(event.time()) / self.resolution,
+            event.kind(),
+            p.id,
+            p.length,
+            p.class,
+            p.flow
+        )?;
+        if let Event::Drop { reason, .. } = *event {
+            write!(self.w, ",\"drop_reason\":\"{}\"", reason.as_str())?;
+        }
+        writeln!(self.w, "}}")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generators::Distribution;
+    use simulation::{Config, Retransmission, Simulation};
+    use simulators::{Discipline, Packet, Preemption};
+
+    #[test]
+    fn records() {
+        let mut sim = Simulation::new(Config {
+            rate: 1100,
+            pspeed: 1000,
+            duration: 1,
+            qlimit: Some(5),
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        });
+        let mut recorder = Recorder::new();
+        let records: Vec<Record> = sim.events().filter_map(|e| recorder.observe(&e)).collect();
+        let results = sim.results();
+
+        let dropped = records.iter().filter(|r| r.dropped.is_some()).count();
+        assert_eq!(dropped as u32, results.packets_dropped);
+        assert_eq!(records.len() as u32 - dropped as u32, results.packets_processed);
+        for r in records.iter().filter(|r| r.dropped.is_none()) {
+            // A single-bit packet at 1000 bits/s is in service for ten ticks, departing in the
+            // tenth, nine after entering service.
+            assert_eq!(r.service(), Some(Ticks(9)));
+            assert!(r.arrival <= r.service_start.unwrap());
+        }
+    }
+
+    #[test]
+    fn retransmission() {
+        // Packets dropped are retransmitted, and only recorded as dropped once given up on.
+        let mut sim = Simulation::new(Config {
+            rate: 1200,
+            pspeed: 1000,
+            duration: 1,
+            qlimit: Some(5),
+            resolution: 1e4,
+            retransmission: Some(Retransmission { timeout: 0.001, retries: 3 }),
+            seed: Some(1),
+            ..Config::default()
+        });
+        let mut recorder = Recorder::new();
+        let records: Vec<Record> = sim.events().filter_map(|e| recorder.observe(&e)).collect();
+        let results = sim.results();
+        assert!(results.retransmissions.unwrap().retransmitted > results.packets_dropped);
+
+        let dropped = records.iter().filter(|r| r.dropped.is_some()).count();
+        assert!(dropped > 0);
+        assert_eq!(dropped as u32, results.packets_dropped);
+        assert_eq!(records.len() as u32 - dropped as u32, results.packets_processed);
+        assert!(records.iter().all(|r| r.dropped.is_none_or(|d| d == DropReason::BufferFull)));
+    }
+
+    #[test]
+    fn preemption() {
+        // Under LIFO service with preemption, packets are served out of order, in stretches, and
+        // those discarded may have been in service; each record is of a single packet all the same.
+        for &preemption in &[Preemption::Resume, Preemption::Restart, Preemption::Discard] {
+            let mut sim = Simulation::new(Config {
+                rate: 700,
+                pspeed: 1000,
+                duration: 1,
+                resolution: 1e4,
+                discipline: Discipline::Lifo,
+                service: Distribution::Exponential,
+                preemption: Some(preemption),
+                seed: Some(1),
+                ..Config::default()
+            });
+            let mut recorder = Recorder::new();
+            let records: Vec<Record> = sim.events().filter_map(|e| recorder.observe(&e)).collect();
+            let results = sim.results();
+            assert!(results.preemptions.unwrap().count > 0);
+
+            let dropped = records.iter().filter(|r| r.dropped.is_some()).count();
+            assert_eq!(dropped as u32, results.packets_dropped);
+            assert_eq!(records.len() as u32 - dropped as u32, results.packets_processed);
+            for r in &records {
+                match r.dropped {
+                    Some(reason) => assert_eq!(reason, DropReason::Preempted),
+                    None => assert!(r.departure >= r.service_start),
+                }
+                assert!(r.service_start.is_none_or(|s| r.arrival <= s));
+            }
+        }
+    }
+
+    #[test]
+    fn event_log() {
+        let packet = Packet {
+            id: 7,
+            length: 1,
+            ..Packet::default()
+        };
+        let mut log = vec![];
+        {
+            let mut w = EventLog::new(&mut log, 10.0);
+            w.write(&Event::Enqueue { time: Ticks(5), packet: packet.clone() }).unwrap();
+            let reason = DropReason::Quota;
+            w.write(&Event::Drop { time: Ticks(6), packet, reason }).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(log).unwrap(),
+            "{\"time\":0.5,\"event\":\"enqueue\",\"id\":7,\"length\":1,\"class\":0,\"flow\":0}\n\
+             {\"time\":0.6,\"event\":\"drop\",\"id\":7,\"length\":1,\"class\":0,\"flow\":0,\
+             \"drop_reason\":\"quota\"}\n"
+        );
+    }
+
+    #[test]
+    fn formats() {
+        let r = Record {
+            arrival: Ticks(10),
+            service_start: Some(Ticks(15)),
+            departure: Some(Ticks(20)),
+            length: 1,
+            class: 0,
+            flow: 0,
+            dropped: None,
+        };
+        let d = Record {
+            arrival: Ticks(10),
+            service_start: None,
+            departure: None,
+            length: 1,
+            class: 0,
+            flow: 0,
+            dropped: Some(DropReason::BufferFull),
+        };
+
+        let mut csv = vec![];
+        {
+            let mut w = RecordWriter::new(&mut csv, Format::Csv, 10.0).unwrap();
+            w.write(&r).unwrap();
+            w.write(&d).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "arrival,service_start,departure,wait,service,length,class,flow,drop_reason\n\
+             1,1.5,2,0.5,0.5,1,0,0,\n\
+             1,,,,,1,0,0,buffer_full\n"
+        );
+
+        let mut jsonl = vec![];
+        RecordWriter::new(&mut jsonl, Format::Jsonl, 10.0).unwrap().write(&d).unwrap();
+        assert_eq!(
+            String::from_utf8(jsonl).unwrap(),
+            "{\"arrival\":1,\"service_start\":null,\"departure\":null,\"wait\":null,\
+             \"service\":null,\"length\":1,\"class\":0,\"flow\":0,\
+             \"drop_reason\":\"buffer_full\"}\n"
+        );
+    }
+}
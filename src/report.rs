@@ -0,0 +1,556 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use analysis::BatchSummary;
+use fluid::Fluid;
+use lindley::Lindley;
+use simulation::{Breakdown, BusyPeriods, LittlesLaw, Results, TimeUnit};
+use simulation::{CONFIDENCE, LITTLE_TOLERANCE};
+use simulators::{ClientStatistics, Color, ServerStatistics};
+use std::fmt;
+
+// The (decimal) prefixes bits are given with, largest first.
+const BIT_PREFIXES: [(f64, &str); 3] = [(1e9, "Gb"), (1e6, "Mb"), (1e3, "Kb")];
+
+// Report renders the results of a run as a table, the way the qsim command prints them: a row per
+// statistic, followed by the per-class and per-flow breakdowns, the autocorrelations, the collected
+// metrics and the batch means, where there are any. Times are given in whichever unit fits them
+// best (a statistic's percentiles in that of its mean), unless the unit's set.
+//
+//   print!("{}", Report::new(&results).unit(TimeUnit::Milliseconds));
+pub struct Report<'a> {
+    results: &'a Results,
+    unit: Option<TimeUnit>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(results: &'a Results) -> Report<'a> {
+        Report {
+            results,
+            unit: None,
+        }
+    }
+
+    // Report.unit sets the unit all times are given in.
+    pub fn unit(mut self, unit: TimeUnit) -> Report<'a> {
+        self.unit = Some(unit);
+        self
+    }
+
+    // Report.unit_for returns the unit to give a time (in seconds) in, and those alongside it.
+    fn unit_for(&self, seconds: f64) -> TimeUnit {
+        self.unit.unwrap_or_else(|| fitting(seconds))
+    }
+
+    fn breakdowns<'b, K, I>(&self, f: &mut fmt::Formatter, key: &str, rows: I) -> fmt::Result
+    where
+        K: fmt::Display,
+        I: Iterator<Item = (K, &'b Breakdown)>,
+    {
+        let unit = self.unit_for(self.results.sojourn.mean());
+        let t = unit.per_second();
+        // The packets starved are only counted against a threshold.
+        let starved = self.results.wait_threshold.is_some();
+        write!(
+            f,
+            "\t {:<8}{:>12}{:>12}{:>14}{:>14}{:>14}{:>16}",
+            key,
+            "generated",
+            "loss (%)",
+            "throughput",
+            format!("sojourn ({})", unit.symbol()),
+            format!("wait ({})", unit.symbol()),
+            format!("max wait ({})", unit.symbol())
+        )?;
+        writeln!(f, "{}", if starved { format!("{:>10}", "starved") } else { String::new() })?;
+        for (key, b) in rows {
+            write!(
+                f,
+                "\t {:<8}{:>12}{:>12.2}{:>14.2}{:>14.6}{:>14.6}{:>16.6}",
+                key,
+                b.packets_generated,
+                b.loss_probability(),
+                f64::from(b.packets_processed) / self.results.elapsed,
+                b.sojourn.mean() * t,
+                b.wait.mean() * t,
+                b.max_wait * t
+            )?;
+            writeln!(f, "{}", if starved { format!("{:>10}", b.starved) } else { String::new() })?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> fmt::Display for Report<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = self.results;
+        writeln!(f, "Simulation results:")?;
+        times(f, self.unit, "sojourn time", &r.sojourn, &r.sojourn_percentiles, 4)?;
+        times(f, self.unit, "waiting time", &r.wait, &r.wait_percentiles, 4)?;
+        times(f, self.unit, "service time", &r.service, &r.service_percentiles, 4)?;
+        let qlen = format!("{:.2} +/- {:.2} packets", r.qlen.mean(), r.qlen.stddev());
+        row(f, "Average # of queued packets", qlen)?;
+        row(f, "Packets generated", format!("{} packets", r.packets_generated))?;
+        row(f, "Packets processed", format!("{} packets", r.packets_processed))?;
+        row(f, "Packets dropped", format!("{} packets", r.packets_dropped))?;
+        row(f, "Packet loss probability", format!("{:.2}%", r.loss_probability()))?;
+        row(f, "Server idle proportion", format!("{:.2}%", r.idle_proportion))?;
+        row(f, "Server utilization (ρ)", format!("{:.4}", r.utilization))?;
+        row(f, "Packets leftover in queue", r.leftover)?;
+        if r.unbounded {
+            writeln!(
+                f,
+                "\t   WARNING: the queue grew without bound over the run, there's no steady state \
+                 and the averages only reflect how long the run was"
+            )?;
+        }
+        let busy = &r.busy_periods;
+        row(f, "Busy periods", busy.count)?;
+        times(f, self.unit, "busy period", &busy.duration, &busy.duration_percentiles, 4)?;
+        let (mean, stddev) = (busy.packets.mean(), busy.packets.stddev());
+        let packets = format!("{:.2} +/- {:.2} packets", mean, stddev);
+        row(f, "Average packets per busy period", packets)?;
+        for &(p, value) in &busy.packets_percentiles {
+            row(f, &format!("p{} packets per busy period", p), format!("{} packets", value))?;
+        }
+        row(f, "Little's Law (L vs. λW)", r.little)?;
+        if !r.little.holds() {
+            writeln!(
+                f,
+                "\t   WARNING: Little's Law is off by more than {}%, the system is overloaded, the \
+                 run is too short to have settled or the statistics are broken",
+                LITTLE_TOLERANCE * 100.0
+            )?;
+        }
+        let percentiles = &r.interdeparture_percentiles;
+        times(f, self.unit, "interdeparture time", &r.interdeparture, percentiles, 6)?;
+        row(f, "Interdeparture time SCV", format!("{:.4}", r.interdeparture_scv()))?;
+        let unit = self.unit_for(r.jitter);
+        let jitter = format!("{:.6} {}", r.jitter * unit.per_second(), unit.symbol());
+        row(f, "Jitter (RFC 3550)", jitter)?;
+        let variation = &r.delay_variation_percentiles;
+        times(f, self.unit, "delay variation", &r.delay_variation, variation, 6)?;
+
+        if let Some(ref t) = r.poisson_test {
+            writeln!(f, "\nDeparture process vs. Poisson (5% significance):")?;
+            let verdict = |holds| if holds { "consistent" } else { "rejected" };
+            let ks = format!("{:.4} ({})", t.statistic(), verdict(t.exponential()));
+            row(f, "Kolmogorov-Smirnov (exponential)", ks)?;
+            let lag1 = format!("{:.4} ({})", t.lag1(), verdict(t.independent()));
+            row(f, "Lag-1 autocorrelation", lag1)?;
+            row(f, "Poisson departures", if t.holds() { "yes" } else { "no" })?;
+        }
+
+        if let Some(threshold) = r.wait_threshold {
+            writeln!(f, "\nStarvation:")?;
+            let unit = self.unit_for(r.max_wait());
+            let t = unit.per_second();
+            row(f, "Longest wait", format!("{:.6} {}", r.max_wait() * t, unit.symbol()))?;
+            let label = format!("Waits over {} {}", threshold * t, unit.symbol());
+            let share = f64::from(r.starved()) / f64::from(r.packets_generated.max(1)) * 100.0;
+            row(f, &label, format!("{} packets ({:.2}%)", r.starved(), share))?;
+        }
+
+        if r.classes.len() > 1 {
+            writeln!(f, "\nPer-class breakdown:")?;
+            self.breakdowns(f, "Class", r.classes.iter().enumerate().map(|(i, b)| (i as u32, b)))?;
+        }
+        if r.tenants.len() > 1 {
+            writeln!(f, "\nPer-tenant breakdown:")?;
+            self.breakdowns(f, "Tenant", r.tenants.iter().enumerate().map(|(i, b)| (i as u32, b)))?;
+        }
+        if !r.colors.is_empty() {
+            writeln!(f, "\nPer-color breakdown:")?;
+            let colors = [Color::Green, Color::Yellow, Color::Red];
+            self.breakdowns(f, "Color", colors.iter().map(|c| c.name()).zip(&r.colors))?;
+        }
+        if !r.top_flows.is_empty() {
+            writeln!(f, "\nBusiest flows:")?;
+            self.breakdowns(f, "Flow", r.top_flows.iter().map(|&(flow, ref b)| (flow, b)))?;
+            writeln!(f, "\nLeast served flows:")?;
+            self.breakdowns(f, "Flow", r.bottom_flows.iter().map(|&(flow, ref b)| (flow, b)))?;
+        }
+        if r.class_fairness.is_some() || r.flow_fairness.is_some() {
+            writeln!(f, "\nFairness (Jain's index over throughputs):")?;
+            if let Some(fairness) = r.class_fairness {
+                row(f, "Classes", format!("{:.4}", fairness))?;
+            }
+            if let Some(fairness) = r.flow_fairness {
+                row(f, "Flows", format!("{:.4}", fairness))?;
+            }
+        }
+
+        if !r.wait_acf.is_empty() {
+            writeln!(f, "\nAutocorrelation:")?;
+            writeln!(f, "\t {:<35}{:>12}{:>16}", "Lag", "waiting", "interdeparture")?;
+            for (i, (w, d)) in r.wait_acf.iter().zip(&r.interdeparture_acf).enumerate() {
+                writeln!(f, "\t {:<35}{:>12.4}{:>16.4}", i + 1, w, d)?;
+            }
+        }
+
+        if let Some(p) = r.preemptions {
+            writeln!(f, "\nPreemption:")?;
+            row(f, "Times preempted", p.count)?;
+            row(f, "Packets discarded", format!("{} packets", p.discarded))?;
+        }
+
+        if let Some(a) = r.admission {
+            writeln!(f, "\nRate limiting:")?;
+            row(f, "Packets admitted", format!("{} packets", a.admitted))?;
+            let share = a.rejection_probability();
+            row(f, "Packets rejected", format!("{} packets ({:.2}%)", a.rejected, share))?;
+        }
+
+        if let Some(ref t) = r.retransmissions {
+            writeln!(f, "\nRetransmissions:")?;
+            row(f, "Packets retransmitted", format!("{} times", t.retransmitted))?;
+            row(f, "Packets given up on", format!("{} packets", t.abandoned))?;
+            row(f, "Packets awaiting retransmission", format!("{} packets", t.pending))?;
+            row(f, "Packets recovered", format!("{} packets", t.recovered.len()))?;
+            times(f, self.unit, "recovered sojourn time", &t.recovered, &[], 4)?;
+            row(f, "Throughput", format!("{:.2} packets/s", t.throughput))?;
+            row(f, "Goodput", format!("{:.2} packets/s", t.goodput))?;
+        }
+
+        if let Some(ref b) = r.blocking {
+            writeln!(f, "\nBlocking:")?;
+            let blocked = format!("{} ({:.2}%)", time(b.time), b.proportion * 100.0);
+            row(f, "Time the client was blocked", blocked)?;
+            row(f, "Packets blocked", format!("{} packets", b.delay.len()))?;
+            row(f, "Packets still blocked", format!("{} packets", b.pending))?;
+            times(f, self.unit, "blocking time", &b.delay, &[], 4)?;
+        }
+
+        if let Some(ref t) = r.round_trips {
+            writeln!(f, "\nRound trips:")?;
+            row(f, "Packets acknowledged", format!("{} packets", t.rtt.len()))?;
+            times(f, self.unit, "round-trip time", &t.rtt, &t.rtt_percentiles, 4)?;
+            row(f, "ACKs in flight", format!("{} ACKs", t.in_flight))?;
+            row(f, "Packets unacknowledged", format!("{} packets", t.unacked))?;
+        }
+
+        if let Some(ref d) = r.delivery {
+            writeln!(f, "\nDelivery:")?;
+            row(f, "Packets delivered", format!("{} packets", d.delivered))?;
+            let share = f64::from(d.lost) / f64::from((d.delivered + d.lost).max(1)) * 100.0;
+            row(f, "Packets lost on the link", format!("{} packets ({:.2}%)", d.lost, share))?;
+            row(f, "Duplicates delivered", format!("{} packets", d.duplicates))?;
+            let share = f64::from(d.out_of_order) / f64::from(d.delivered.max(1)) * 100.0;
+            row(f, "Packets out of order", format!("{} packets ({:.2}%)", d.out_of_order, share))?;
+            let depth = format!("{:.2} packets (at most {})", d.depth.mean(), d.max_depth);
+            row(f, "Average reordering depth", depth)?;
+            times(f, self.unit, "end-to-end delay", &d.delay, &[], 4)?;
+            row(f, "Packets on the link", format!("{} packets", d.in_flight))?;
+        }
+
+        if !r.collected.is_empty() {
+            writeln!(f, "\nCollected metrics:")?;
+            for &(ref name, value) in &r.collected {
+                row(f, name, value)?;
+            }
+        }
+
+        if let (Some(sojourn), Some(qlen)) = (r.sojourn_batches, r.qlen_batches) {
+            writeln!(
+                f,
+                "\nBatch means ({} batches, {:.0}% confidence):",
+                qlen.batches,
+                CONFIDENCE * 100.0
+            )?;
+            let unit = self.unit_for(sojourn.mean);
+            let t = unit.per_second();
+            batch_summary(f, &format!("Sojourn time ({})", unit.symbol()), &scaled(&sojourn, t))?;
+            if let Some(ref wait) = r.wait_batches {
+                batch_summary(f, &format!("Waiting time ({})", unit.symbol()), &scaled(wait, t))?;
+            }
+            batch_summary(f, "# of queued packets", &qlen)?;
+            if let Some(ref loss) = r.loss_batches {
+                batch_summary(f, "Packet loss probability (%)", loss)?;
+            }
+            if let Some(ref utilization) = r.utilization_batches {
+                batch_summary(f, "Server utilization (ρ)", utilization)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// times writes out a row with the mean and standard deviation of the time, followed by a row per
+// percentile, all in the given unit (or that fitting the mean best) and to the given precision.
+fn times(
+    f: &mut fmt::Formatter,
+    unit: Option<TimeUnit>,
+    name: &str,
+    stats: &OnlineStats,
+    percentiles: &[(f64, f64)],
+    precision: usize,
+) -> fmt::Result {
+    let unit = unit.unwrap_or_else(|| fitting(stats.mean()));
+    let (t, symbol) = (unit.per_second(), unit.symbol());
+    let average = format!(
+        "{:.*} +/- {:.*} {}",
+        precision,
+        stats.mean() * t,
+        precision,
+        stats.stddev() * t,
+        symbol
+    );
+    row(f, &format!("Average {}", name), average)?;
+    for &(p, value) in percentiles {
+        row(f, &format!("p{} {}", p, name), format!("{:.*} {}", precision, value * t, symbol))?;
+    }
+    Ok(())
+}
+
+fn row<T: fmt::Display>(f: &mut fmt::Formatter, label: &str, value: T) -> fmt::Result {
+    writeln!(f, "\t {:<35}{}", format!("{}:", label), value)
+}
+
+// batch_summary writes out a row with the summary, warning if the batches are too short for it to
+// be trusted.
+fn batch_summary(f: &mut fmt::Formatter, label: &str, s: &BatchSummary) -> fmt::Result {
+    row(f, label, s)?;
+    if s.correlated() {
+        writeln!(f, "\t   warning: batch means are correlated, consider fewer (longer) batches")?;
+    }
+    Ok(())
+}
+
+// scaled returns the summary with its mean and spread multiplied by the given scale (e.g. to
+// convert times out of seconds).
+fn scaled(s: &BatchSummary, scale: f64) -> BatchSummary {
+    BatchSummary {
+        mean: s.mean * scale,
+        stderr: s.stderr * scale,
+        half_width: s.half_width * scale,
+        ..*s
+    }
+}
+
+// fitting returns the largest unit the time (in seconds) is at least one of, as with
+// TimeUnit::fitting, bar zero which is in seconds.
+fn fitting(seconds: f64) -> TimeUnit {
+    if seconds > 0.0 {
+        TimeUnit::fitting(seconds)
+    } else {
+        TimeUnit::Seconds
+    }
+}
+
+// decimal formats the number to at most three decimal places, dropping trailing zeros.
+fn decimal(x: f64) -> String {
+    let s = format!("{:.3}", x);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+// time formats the time (in seconds) in the largest unit it's at least one of, e.g. 1.25 ms.
+pub fn time(seconds: f64) -> String {
+    let unit = fitting(seconds);
+    format!("{} {}", decimal(seconds * unit.per_second()), unit.symbol())
+}
+
+// bits formats the number of bits with the largest (decimal) prefix it's at least one of, e.g.
+// 1.5 Kb; bit rates are formatted the same, with a /s tacked on.
+pub fn bits(n: f64) -> String {
+    match BIT_PREFIXES.iter().find(|&&(scale, _)| n >= scale) {
+        Some(&(scale, prefix)) => format!("{} {}", decimal(n / scale), prefix),
+        None => format!("{} bits", decimal(n)),
+    }
+}
+
+impl fmt::Display for Results {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Report::new(self).fmt(f)
+    }
+}
+
+// LindleyReport renders the statistics computed by the Lindley recursion (see lindley::run), as
+// Report does those of a full run, marking them as such.
+pub struct LindleyReport<'a> {
+    lindley: &'a Lindley,
+    unit: Option<TimeUnit>,
+}
+
+impl<'a> LindleyReport<'a> {
+    pub fn new(lindley: &'a Lindley) -> LindleyReport<'a> {
+        LindleyReport {
+            lindley,
+            unit: None,
+        }
+    }
+
+    // LindleyReport.unit sets the unit all times are given in.
+    pub fn unit(mut self, unit: TimeUnit) -> LindleyReport<'a> {
+        self.unit = Some(unit);
+        self
+    }
+}
+
+impl<'a> fmt::Display for LindleyReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let l = self.lindley;
+        writeln!(f, "Simulation results (Lindley recursion):")?;
+        times(f, self.unit, "sojourn time", &l.sojourn, &l.sojourn_percentiles, 4)?;
+        times(f, self.unit, "waiting time", &l.wait, &l.wait_percentiles, 4)?;
+        times(f, self.unit, "service time", &l.service, &l.service_percentiles, 4)?;
+        row(f, "Packets generated", format!("{} packets", l.packets_generated))?;
+        row(f, "Packets processed", format!("{} packets", l.packets_processed))?;
+        row(f, "Server utilization (ρ)", format!("{:.4}", l.utilization))
+    }
+}
+
+impl fmt::Display for Lindley {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        LindleyReport::new(self).fmt(f)
+    }
+}
+
+// FluidReport renders the results of the fluid-flow approximation (see fluid::run), marking them
+// as approximate. Amounts of fluid are given in packets, rounded.
+pub struct FluidReport<'a> {
+    fluid: &'a Fluid,
+    unit: Option<TimeUnit>,
+}
+
+impl<'a> FluidReport<'a> {
+    pub fn new(fluid: &'a Fluid) -> FluidReport<'a> {
+        FluidReport {
+            fluid,
+            unit: None,
+        }
+    }
+
+    // FluidReport.unit sets the unit all times are given in.
+    pub fn unit(mut self, unit: TimeUnit) -> FluidReport<'a> {
+        self.unit = Some(unit);
+        self
+    }
+}
+
+impl<'a> fmt::Display for FluidReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = self.fluid;
+        writeln!(f, "Fluid approximation results (APPROXIMATE, average rates only):")?;
+        let unit = self.unit.unwrap_or_else(|| fitting(r.sojourn));
+        let time = |t: f64| format!("{:.4} {}", t * unit.per_second(), unit.symbol());
+        row(f, "Average sojourn time", time(r.sojourn))?;
+        row(f, "Average waiting time", time(r.wait))?;
+        row(f, "Average # of packets in system", format!("{:.2} packets", r.occupancy))?;
+        row(f, "Peak # of packets in system", format!("{:.2} packets", r.peak))?;
+        row(f, "Packets generated", format!("{:.0} packets", r.packets_generated))?;
+        row(f, "Packets processed", format!("{:.0} packets", r.packets_processed))?;
+        row(f, "Packets dropped", format!("{:.0} packets", r.packets_dropped))?;
+        let loss = r.packets_dropped / r.packets_generated * 100.0;
+        row(f, "Packet loss probability", format!("{:.2}%", loss))?;
+        row(f, "Server utilization (ρ)", format!("{:.4}", r.utilization))
+    }
+}
+
+impl fmt::Display for Fluid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        FluidReport::new(self).fmt(f)
+    }
+}
+
+impl fmt::Display for LittlesLaw {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.4} vs. {:.4} ({:.2}% error)",
+            self.l,
+            self.lambda * self.w,
+            self.relative_error() * 100.0
+        )
+    }
+}
+
+impl fmt::Display for BatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.4} +/- {:.4} (lag-1 correlation: {:.2})",
+            self.mean,
+            self.half_width,
+            self.lag1
+        )
+    }
+}
+
+impl fmt::Display for BusyPeriods {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} busy periods, {} and {:.2} packets long on average",
+            self.count,
+            time(self.duration.mean()),
+            self.packets.mean()
+        )
+    }
+}
+
+impl fmt::Display for ClientStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} packets generated", self.packets_generated)?;
+        if self.ticks_blocked > 0 {
+            write!(f, ", blocked for {} ticks", self.ticks_blocked)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ServerStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ticks = self.idle_count + self.process_count;
+        write!(
+            f,
+            "{} packets processed, {} dropped, busy {:.2}% of the time",
+            self.packets_processed,
+            self.packets_dropped,
+            self.process_count as f64 / ticks.max(1) as f64 * 100.0
+        )?;
+        if self.preemptions > 0 {
+            write!(f, ", preempted {} times", self.preemptions)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::{Config, Simulation};
+
+    #[test]
+    fn units() {
+        assert_eq!(time(1.5), "1.5 s");
+        assert_eq!(time(0.00125), "1.25 ms");
+        assert_eq!(time(2e-7), "200 ns");
+        assert_eq!(time(0.0), "0 s");
+        assert_eq!(bits(512.0), "512 bits");
+        assert_eq!(bits(1500.0), "1.5 Kb");
+        assert_eq!(bits(1e7), "10 Mb");
+    }
+
+    #[test]
+    fn report() {
+        let results = Simulation::new(Config {
+            rate: 500,
+            pspeed: 1000,
+            duration: 10,
+            seed: Some(1),
+            ..Config::default()
+        })
+        .run();
+        // Times of a millisecond or so fit milliseconds, unless set otherwise.
+        let report = results.to_string();
+        assert!(report.starts_with("Simulation results:\n"));
+        let sojourn = report.lines().find(|l| l.contains("Average sojourn time:")).unwrap();
+        assert!(sojourn.ends_with(" ms"), "{}", sojourn);
+        let report = Report::new(&results).unit(TimeUnit::Seconds).to_string();
+        let sojourn = report.lines().find(|l| l.contains("Average sojourn time:")).unwrap();
+        assert!(sojourn.ends_with(" s"), "{}", sojourn);
+        let generated = format!("{} packets", results.packets_generated);
+        assert!(report.contains(&format!("\t {:<35}{}\n", "Packets generated:", generated)));
+    }
+}
@@ -0,0 +1,147 @@
+// Ring is a fixed-capacity ring buffer, allocated once up front: pushing onto a full ring hands the
+// value back rather than growing it, so nothing is ever allocated past construction. It supports
+// what a server's queue needs, pushing onto and popping off of either end, along with removal from
+// the middle (for priority disciplines).
+#[derive(Clone, Debug)]
+pub struct Ring<T> {
+    slots: Box<[Option<T>]>,
+    // The slot of the first element, and the number of elements.
+    head: usize,
+    len: usize,
+}
+
+impl<T> Ring<T> {
+    pub fn with_capacity(capacity: usize) -> Ring<T> {
+        Ring {
+            slots: (0..capacity).map(|_| None).collect(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // slot returns the slot of the i-th element, which must be within capacity. Wrapping around
+    // by subtracting rather than taking the remainder keeps a division out of the hot path.
+    fn slot(&self, i: usize) -> usize {
+        let slot = self.head + i;
+        if slot >= self.slots.len() {
+            slot - self.slots.len()
+        } else {
+            slot
+        }
+    }
+
+    // Ring.push_back appends the value, handing it back if the ring is full.
+    pub fn push_back(&mut self, value: T) -> Result<(), T> {
+        if self.len == self.capacity() {
+            return Err(value);
+        }
+        let slot = self.slot(self.len);
+        self.slots[slot] = Some(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    // Ring.push_front prepends the value, handing it back if the ring is full.
+    pub fn push_front(&mut self, value: T) -> Result<(), T> {
+        if self.len == self.capacity() {
+            return Err(value);
+        }
+        self.head = if self.head == 0 { self.capacity() - 1 } else { self.head - 1 };
+        self.slots[self.head] = Some(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = self.slots[self.head].take();
+        self.head = self.slot(1);
+        self.len -= 1;
+        value
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        let slot = self.slot(self.len);
+        self.slots[slot].take()
+    }
+
+    pub fn get(&self, i: usize) -> Option<&T> {
+        if i >= self.len {
+            return None;
+        }
+        self.slots[self.slot(i)].as_ref()
+    }
+
+    // Ring.remove removes the i-th element, shifting those after it forward.
+    pub fn remove(&mut self, i: usize) -> Option<T> {
+        if i >= self.len {
+            return None;
+        }
+        let slot = self.slot(i);
+        let value = self.slots[slot].take();
+        for j in i..self.len - 1 {
+            let (to, from) = (self.slot(j), self.slot(j + 1));
+            self.slots[to] = self.slots[from].take();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len).filter_map(move |i| self.get(i))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring() {
+        let mut r = Ring::with_capacity(3);
+        assert_eq!(r.pop_front(), None);
+        for i in 0..3 {
+            assert_eq!(r.push_back(i), Ok(()));
+        }
+        assert_eq!(r.push_back(3), Err(3));
+        assert_eq!(r.pop_front(), Some(0));
+        // Wrapping around the end.
+        assert_eq!(r.push_back(3), Ok(()));
+        assert_eq!(r.iter().cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(r.remove(1), Some(2));
+        assert_eq!(r.iter().cloned().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(r.pop_back(), Some(3));
+        assert_eq!(r.pop_back(), Some(1));
+        assert!(r.is_empty());
+        assert_eq!(r.capacity(), 3);
+        // Wrapping around the start.
+        assert_eq!(r.push_front(1), Ok(()));
+        assert_eq!(r.push_front(0), Ok(()));
+        assert_eq!(r.push_back(2), Ok(()));
+        assert_eq!(r.push_front(3), Err(3));
+        assert_eq!(r.iter().cloned().collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        let mut empty = Ring::with_capacity(0);
+        assert_eq!(empty.push_back(1), Err(1));
+        assert_eq!(empty.pop_front(), None);
+    }
+}
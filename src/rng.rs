@@ -0,0 +1,80 @@
+extern crate rand;
+
+use self::rand::{SeedableRng, XorShiftRng};
+
+// Streams identify the independent random number substreams handed out to the individual
+// components of a simulation. Giving each component its own substream (as opposed to sharing one
+// RNG) means that the random numbers drawn by, say, the arrival process are unaffected by
+// however many numbers some other component happens to consume. This is what lets two
+// configurations be run under common random numbers: the n-th interarrival time is the same in
+// both, regardless of how the rest of the system is configured.
+pub const ARRIVALS: u64 = 0;
+pub const SERVICE: u64 = 1;
+pub const CLASSES: u64 = 2;
+pub const LINK: u64 = 3;
+pub const TENANTS: u64 = 4;
+
+// splitmix64 is the output function of the SplitMix64 generator, used here to scramble seeds so
+// that nearby (master seed, stream) pairs map to statistically unrelated generator states.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// derive returns the seed for the given substream of the provided master seed.
+pub fn derive(seed: u64, stream: u64) -> u64 {
+    splitmix64(splitmix64(seed) ^ stream.wrapping_mul(0xD1B5_4A32_D192_ED03))
+}
+
+// substream returns a generator seeded deterministically from the master seed and the stream
+// identifier.
+pub fn substream(seed: u64, stream: u64) -> XorShiftRng {
+    let a = splitmix64(derive(seed, stream));
+    let b = splitmix64(a);
+    let mut state = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    // XorShiftRng cannot be seeded with all zeros.
+    if state.iter().all(|&s| s == 0) {
+        state[0] = 1;
+    }
+    XorShiftRng::from_seed(state)
+}
+
+// unseeded returns a generator seeded at random, for when reproducibility is not called for.
+pub fn unseeded() -> XorShiftRng {
+    substream(random_seed(), 0)
+}
+
+// random_seed returns a master seed drawn from the thread-local RNG, for runs that need one (e.g.
+// to derive the seeds of replications from) but weren't given any. It's the only place entropy is
+// drawn from, so seeded runs never depend on the platform providing any.
+pub fn random_seed() -> u64 {
+    use self::rand::Rng;
+    rand::thread_rng().next_u64()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::rand::Rng;
+
+    #[test]
+    fn substreams_are_reproducible() {
+        let mut a = substream(42, ARRIVALS);
+        let mut b = substream(42, ARRIVALS);
+        for _ in 0..10 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn substreams_are_distinct() {
+        let mut a = substream(42, ARRIVALS);
+        let mut b = substream(42, SERVICE);
+        let mut c = substream(43, ARRIVALS);
+        let (x, y, z) = (a.next_u64(), b.next_u64(), c.next_u64());
+        assert!(x != y && x != z && y != z);
+    }
+}
@@ -0,0 +1,158 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use error::QsimError;
+use simulation::Config;
+use sweep;
+
+// The loads run at by default, approaching 1.
+pub const DEFAULT_LOADS: [f64; 4] = [0.5, 0.9, 0.99, 0.999];
+// The longest any load is run for by default, in seconds.
+pub const DEFAULT_MAX_DURATION: u32 = 600;
+
+// Point is how a single load fared: the load aimed for and the one offered once the arrival rate
+// is rounded to whole packets/s, how long it was run for (the scaled duration, unless capped),
+// the average waiting time across replications and the heavy-traffic prediction of it, in
+// seconds.
+#[derive(Clone, Debug)]
+pub struct Point {
+    pub load: f64,
+    pub offered: f64,
+    pub rate: u32,
+    pub duration: u32,
+    pub capped: bool,
+    pub wait: OnlineStats,
+    pub predicted: f64,
+}
+
+impl Point {
+    // Point.ratio returns the average waiting time relative to its prediction, tending to 1 as the
+    // load does.
+    pub fn ratio(&self) -> f64 {
+        self.wait.mean() / self.predicted
+    }
+
+    // Point.scaled returns the average waiting time scaled by (1 - ρ), in seconds. If it grows as
+    // 1/(1 - ρ), it levels off as the load approaches 1.
+    pub fn scaled(&self) -> f64 {
+        self.wait.mean() * (1.0 - self.offered)
+    }
+}
+
+// rate returns the arrival rate (in whole packets/s) the configuration reaches the load at.
+pub fn rate(config: &Config, load: f64) -> u32 {
+    (load * f64::from(config.pspeed) / f64::from(config.psize)).round() as u32
+}
+
+// kingman returns Kingman's heavy-traffic approximation of the average waiting time of a G/G/1
+// queue at load rho, with the given mean service time and squared coefficients of variation of
+// the interarrival and service times: ρ/(1 - ρ) · (ca² + cs²)/2 · E[S]. It's exact for M/G/1
+// queues (the Pollaczek-Khinchine formula), and the leading term for the others as ρ → 1.
+pub fn kingman(rho: f64, service: f64, ca2: f64, cs2: f64) -> f64 {
+    rho / (1.0 - rho) * (ca2 + cs2) / 2.0 * service
+}
+
+// exponent returns the least-squares slope of the log of the average waiting time against the log
+// of 1/(1 - ρ) across the points, 1 if waits grow as heavy-traffic theory has it.
+pub fn exponent(points: &[Point]) -> f64 {
+    let xy: Vec<(f64, f64)> = points
+        .iter()
+        .map(|p| (-(1.0 - p.offered).ln(), p.wait.mean().ln()))
+        .collect();
+    let n = xy.len() as f64;
+    let (mx, my) = xy.iter().fold((0.0, 0.0), |(x, y), &(a, b)| (x + a / n, y + b / n));
+    let sxy: f64 = xy.iter().map(|&(x, y)| (x - mx) * (y - my)).sum();
+    let sxx: f64 = xy.iter().map(|&(x, _)| (x - mx) * (x - mx)).sum();
+    sxy / sxx
+}
+
+// run runs the configuration at each of the loads, setting the arrival rate to reach it, for the
+// given number of replications under common random numbers. The closer to 1 the load, the longer
+// the queue takes to settle, its relaxation time growing as 1/(1 - ρ)², so the run length (and
+// warm-up period) are scaled by the square of (1 - ρ₀)/(1 - ρ) from the configured ones, ρ₀ being
+// the first of the loads, up to max_duration seconds.
+pub fn run(
+    config: &Config,
+    loads: &[f64],
+    max_duration: u32,
+    replications: u32,
+    seed: u64,
+) -> Result<Vec<Point>, QsimError> {
+    config.validate()?;
+    if config.trace.is_some() || !config.schedule.is_empty() || config.qlimit.is_some() {
+        let reason = "heavy-traffic scaling needs a fixed arrival rate and an unlimited buffer";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if config.servers > 1 {
+        let reason = "Kingman's approximation only applies to single-server queues";
+        return Err(QsimError::Config(reason.to_string()));
+    }
+    if let Some(&load) = loads.iter().find(|&&l| !(l > 0.0 && l < 1.0)) {
+        return Err(QsimError::invalid("load", load, "loads have to be between 0 and 1"));
+    }
+    let first = match loads.first() {
+        Some(&load) => load,
+        None => return Err(QsimError::Config("no loads to run at".to_string())),
+    };
+    let service = f64::from(config.psize) / f64::from(config.pspeed);
+    let (ca2, cs2) = (config.arrival.scv(), config.service.scv());
+    let mut points = vec![];
+    for &load in loads {
+        let rate = rate(config, load);
+        let scale = ((1.0 - first) / (1.0 - load)).powi(2);
+        let scaled = (f64::from(config.duration) * scale).ceil();
+        let duration = scaled.min(f64::from(max_duration)) as u32;
+        let c = Config {
+            rate,
+            duration,
+            warmup: config.warmup * f64::from(duration) / f64::from(config.duration),
+            ..config.clone()
+        };
+        c.validate()?;
+        let offered = c.offered_load();
+        let wait = sweep::replicate(&c, replications, seed, |r| vec![r.wait.mean()]);
+        points.push(Point {
+            load,
+            offered,
+            rate,
+            duration,
+            capped: scaled > f64::from(max_duration),
+            wait: wait[0],
+            predicted: kingman(offered, service, ca2, cs2),
+        });
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heavy_traffic() {
+        let config = Config {
+            psize: 1,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            warmup: 0.1,
+            ..Config::default()
+        };
+        let points = run(&config, &[0.5, 0.8, 0.9], 20, 2, 1).unwrap();
+        let durations: Vec<(u32, bool)> = points.iter().map(|p| (p.duration, p.capped)).collect();
+        assert_eq!(durations, vec![(1, false), (7, false), (20, true)]);
+        assert_eq!(points[2].rate, 900);
+        // M/D/1 waits are ρ/(2μ(1 - ρ)), which the prediction is exact for.
+        assert!((points[1].predicted - 0.002).abs() < 1e-9, "{}", points[1].predicted);
+        for p in &points {
+            assert!((p.ratio() - 1.0).abs() < 0.25, "{:?}", p);
+        }
+        assert!(points[2].scaled() < 2.0 * points[0].scaled());
+        let e = exponent(&points);
+        assert!(e > 0.8 && e < 1.3, "{}", e);
+
+        assert!(run(&config, &[0.5, 1.0], 20, 1, 1).is_err());
+        assert!(run(&Config { servers: 2, ..config.clone() }, &[0.5], 20, 1, 1).is_err());
+        assert!(run(&Config { qlimit: Some(8), ..config }, &[0.5], 20, 1, 1).is_err());
+    }
+}
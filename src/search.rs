@@ -0,0 +1,197 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use simulation::Config;
+use slo::{Metric, Objective};
+use sweep::{self, Parameter};
+
+// The relative precision the smallest value meeting the objectives is found to by default, i.e.
+// the search stops once it's narrowed down to within this proportion of the value. Buffer limits
+// are small enough to always be found exactly.
+pub const DEFAULT_PRECISION: f64 = 0.01;
+
+// default_precision returns the precision the parameter is searched to by default.
+pub fn default_precision(parameter: Parameter) -> f64 {
+    match parameter {
+        Parameter::Qlimit => 0.0,
+        _ => DEFAULT_PRECISION,
+    }
+}
+
+// Step is a value of the parameter tried in the course of a search: the metrics (see
+// sweep::metrics) and the value of each objective's metric observed across the replications run
+// at it, and whether the objectives were met on average.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub value: u32,
+    pub metrics: Vec<OnlineStats>,
+    pub objectives: Vec<OnlineStats>,
+    pub met: bool,
+}
+
+// Search is the outcome of searching for the smallest value of a parameter that meets a set of
+// objectives.
+#[derive(Clone, Debug)]
+pub struct Search {
+    pub parameter: Parameter,
+    pub objectives: Vec<Objective>,
+    pub metrics: Vec<String>,
+    pub replications: u32,
+    // The steps taken, in the order they were taken in.
+    pub steps: Vec<Step>,
+    // The step at the smallest value found to meet the objectives, if any within bounds.
+    pub found: Option<usize>,
+}
+
+impl Search {
+    // Search.result returns the step at the smallest value found to meet the objectives.
+    pub fn result(&self) -> Option<&Step> {
+        self.found.map(|i| &self.steps[i])
+    }
+}
+
+// bounds returns the range of values the parameter is searched over: for the processing speed,
+// from the slowest at which the queue is stable up, and for the buffer limit, from none at all (the
+// packet in service aside) up.
+fn bounds(config: &Config, parameter: Parameter) -> (u32, u32) {
+    match parameter {
+        Parameter::Pspeed => {
+            let stable = (f64::from(config.rate) * f64::from(config.psize)).ceil();
+            (stable.clamp(1.0, f64::from(u32::MAX)) as u32, u32::MAX)
+        }
+        Parameter::Qlimit => (0, u32::MAX),
+        _ => (1, u32::MAX),
+    }
+}
+
+// solve searches for the smallest value of the parameter at which the objectives are met, on
+// average across the given number of replications, assuming they only get easier to meet the
+// larger the value. The upper end is found by doubling from the lower bound, and the smallest
+// value by bisection from there, to within the given relative precision. Every value is run with
+// the same seeds, so that the noise doesn't throw the comparisons between them off (see
+// sweep::sweep).
+pub fn solve(
+    base: &Config,
+    parameter: Parameter,
+    objectives: &[Objective],
+    replications: u32,
+    precision: f64,
+    seed: u64,
+) -> Search {
+    let mut base = base.clone();
+    // The percentiles objectives are set on have to be estimated.
+    for o in objectives {
+        if let Metric::Sojourn(p) = o.metric {
+            if !base.percentiles.contains(&p) {
+                base.percentiles.push(p);
+            }
+        }
+    }
+    let mut search = Search {
+        parameter,
+        objectives: objectives.to_vec(),
+        metrics: sweep::metrics(&base),
+        replications,
+        steps: vec![],
+        found: None,
+    };
+    let n = search.metrics.len();
+    let mut step = |value: u32| -> bool {
+        let mut config = base.clone();
+        parameter.apply(&mut config, f64::from(value));
+        let mut metrics = sweep::replicate(&config, replications, seed, |r| {
+            let mut values = sweep::observe(r);
+            values.extend(objectives.iter().map(|o| o.value(r)));
+            values
+        });
+        let objectives = metrics.split_off(n);
+        let met = search.objectives.iter().zip(&objectives).all(|(o, m)| o.met(m.mean()));
+        search.steps.push(Step { value, metrics, objectives, met });
+        met
+    };
+
+    let (min, max) = bounds(&base, parameter);
+    let (mut lo, mut hi) = (min, min);
+    while !step(hi) {
+        if hi == max {
+            return search;
+        }
+        lo = hi;
+        hi = hi.saturating_mul(2).clamp(1, max);
+    }
+    if hi != min {
+        // The objectives aren't met at lo, but are at hi.
+        while f64::from(hi - lo) > (f64::from(hi) * precision).max(1.0) {
+            let mid = lo + (hi - lo) / 2;
+            if step(mid) {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+    }
+    search.found = search.steps.iter().position(|s| s.value == hi && s.met);
+    search
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processing_speed() {
+        // The mean sojourn time of the M/D/1 queue is 1/μ + λ/(2μ(μ - λ)), 5ms at λ = 500 for a μ
+        // of about 620.
+        let config = Config {
+            rate: 500,
+            psize: 1,
+            duration: 10,
+            resolution: 1e4,
+            ..Config::default()
+        };
+        let objectives = vec![Objective::parse("mean<=5ms").unwrap()];
+        let s = solve(&config, Parameter::Pspeed, &objectives, 3, DEFAULT_PRECISION, 1);
+        let found = s.result().unwrap();
+        assert!((found.value as f64 - 620.0).abs() < 40.0, "{:?}", s.steps);
+        assert!(found.objectives[0].mean() <= 5e-3);
+        assert!(found.metrics.iter().all(|m| m.len() == 3));
+        // The steps bracket the value found, from the stability bound up.
+        assert_eq!(s.steps[0].value, 500);
+        assert!(s.steps.iter().all(|st| st.met == (st.value >= found.value)), "{:?}", s.steps);
+
+        // Percentile objectives are estimated even if not configured, and the unattainable aren't
+        // found.
+        let objectives = vec![Objective::parse("p42<=1ms").unwrap()];
+        let config = Config { percentiles: vec![], duration: 1, ..config };
+        let s = solve(&config, Parameter::Pspeed, &objectives, 2, 0.1, 1);
+        assert!(s.result().unwrap().objectives[0].mean() <= 1e-3);
+        let objectives = vec![Objective::parse("throughput>=1000").unwrap()];
+        let s = solve(&config, Parameter::Pspeed, &objectives, 1, 0.1, 1);
+        assert!(s.result().is_none());
+    }
+
+    #[test]
+    fn buffer_size() {
+        let config = Config {
+            rate: 900,
+            psize: 1,
+            pspeed: 1000,
+            duration: 5,
+            resolution: 1e4,
+            ..Config::default()
+        };
+        let objectives = vec![Objective::parse("loss<=1%").unwrap()];
+        let precision = default_precision(Parameter::Qlimit);
+        let s = solve(&config, Parameter::Qlimit, &objectives, 3, precision, 1);
+        let found = s.result().unwrap();
+        assert!(found.objectives[0].mean() <= 0.01);
+        assert!(found.value > 1 && found.value < 100, "{:?}", s.steps);
+        // The limit is exact, one packet less would see too many dropped.
+        assert_eq!(s.steps[0].value, 0);
+        let below = s.steps.iter().find(|st| st.value == found.value - 1).unwrap();
+        assert!(!below.met && below.objectives[0].mean() > 0.01);
+        // Loss is reported in percent, as with the other metrics.
+        assert!((found.metrics[3].mean() - found.objectives[0].mean() * 100.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,141 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use simulation::Config;
+use sweep::{self, Parameter};
+
+// The proportion each parameter is perturbed by, either way, by default.
+pub const DEFAULT_PERTURBATION: f64 = 0.1;
+
+// Perturbation is the outcome of perturbing a single parameter either way: the values it was
+// lowered and raised to, and the elasticity of each metric with respect to it.
+#[derive(Clone, Debug)]
+pub struct Perturbation {
+    pub parameter: Parameter,
+    pub values: (f64, f64),
+    pub elasticities: Vec<f64>,
+}
+
+// Sensitivity holds the metrics (see sweep::metrics) at the base configuration, along with their
+// elasticities with respect to each parameter: the relative change in the metric per relative
+// change in the parameter, e.g. 2 if the metric grows by 2% for every 1% the parameter grows by.
+#[derive(Clone, Debug)]
+pub struct Sensitivity {
+    pub metrics: Vec<String>,
+    pub replications: u32,
+    pub base: Vec<OnlineStats>,
+    pub perturbations: Vec<Perturbation>,
+}
+
+impl Sensitivity {
+    // Sensitivity.dominant returns the parameter the given metric is most sensitive to, if any of
+    // the elasticities are defined.
+    pub fn dominant(&self, metric: usize) -> Option<Parameter> {
+        self.perturbations
+            .iter()
+            .filter(|p| p.elasticities[metric].is_finite())
+            .max_by(|a, b| {
+                let (a, b) = (a.elasticities[metric].abs(), b.elasticities[metric].abs());
+                a.total_cmp(&b)
+            })
+            .map(|p| p.parameter)
+    }
+}
+
+// perturbed returns the values the parameter is lowered and raised to, by the given proportion of
+// its value but at least by one (bar going below the smallest valid value), as parameters are
+// whole numbers.
+fn perturbed(parameter: Parameter, value: f64, perturbation: f64) -> (f64, f64) {
+    let min = if parameter == Parameter::Qlimit { 0.0 } else { 1.0 };
+    let lower = (value * (1.0 - perturbation)).round().min(value - 1.0).max(min);
+    let upper = (value * (1.0 + perturbation)).round().max(value + 1.0);
+    (lower, upper)
+}
+
+// sensitivity perturbs each of the arrival rate, packet size, processing speed and buffer limit
+// (if there is one) of the base configuration in turn, lowering and raising it by the given
+// proportion, and estimates the elasticity of each metric from the central difference. Every
+// configuration is run for the same replications, under common random numbers, so that the
+// differences aren't drowned out by noise.
+pub fn sensitivity(base: &Config, perturbation: f64, replications: u32, seed: u64) -> Sensitivity {
+    let run = |config: &Config| sweep::replicate(config, replications, seed, sweep::observe);
+    let metrics = run(base);
+    let perturbations = Parameter::ALL
+        .iter()
+        .cloned()
+        .filter(|&p| p != Parameter::Duration)
+        .filter(|&p| p != Parameter::Qlimit || base.qlimit.is_some())
+        .map(|parameter| {
+            let value = parameter.value(base);
+            let (lower, upper) = perturbed(parameter, value, perturbation);
+            let mut config = base.clone();
+            parameter.apply(&mut config, lower);
+            let below = run(&config);
+            parameter.apply(&mut config, upper);
+            let above = run(&config);
+            let change = (upper - lower) / value;
+            let elasticities = metrics
+                .iter()
+                .zip(below.iter().zip(&above))
+                .map(|(m, (b, a))| (a.mean() - b.mean()) / m.mean() / change)
+                .collect();
+            Perturbation {
+                parameter,
+                values: (lower, upper),
+                elasticities,
+            }
+        })
+        .collect();
+    Sensitivity {
+        metrics: sweep::metrics(base),
+        replications,
+        base: metrics,
+        perturbations,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elasticities() {
+        let config = Config {
+            rate: 500,
+            psize: 10,
+            pspeed: 10_000,
+            duration: 5,
+            resolution: 1e5,
+            ..Config::default()
+        };
+        let s = sensitivity(&config, DEFAULT_PERTURBATION, 3, 1);
+        let parameters: Vec<Parameter> = s.perturbations.iter().map(|p| p.parameter).collect();
+        assert_eq!(parameters, vec![Parameter::Rate, Parameter::Psize, Parameter::Pspeed]);
+        assert_eq!(s.perturbations[0].values, (450.0, 550.0));
+        assert_eq!(s.perturbations[1].values, (9.0, 11.0));
+        // The utilization, λ·psize/pspeed, grows in proportion to the rate and packet size, and
+        // shrinks in proportion to the speed.
+        let utilization = |i: usize| s.perturbations[i].elasticities[4];
+        assert!((utilization(0) - 1.0).abs() < 0.1, "{:?}", s.perturbations);
+        assert!((utilization(1) - 1.0).abs() < 0.1, "{:?}", s.perturbations);
+        assert!((utilization(2) + 1.0).abs() < 0.1, "{:?}", s.perturbations);
+        // The sojourn time of the M/D/1 queue is 1/μ + λ/(2μ(μ - λ)); at ρ = 0.5 its elasticity is
+        // 2/3 with respect to λ, and -5/3 with respect to μ = pspeed/psize.
+        let sojourn = |i: usize| s.perturbations[i].elasticities[0];
+        assert!((sojourn(0) - 2.0 / 3.0).abs() < 0.2, "{:?}", s.perturbations);
+        assert!((sojourn(1) - 5.0 / 3.0).abs() < 0.2, "{:?}", s.perturbations);
+        assert!((sojourn(2) + 5.0 / 3.0).abs() < 0.2, "{:?}", s.perturbations);
+        assert_ne!(s.dominant(0), Some(Parameter::Rate));
+        // Nothing's lost, so the loss has no elasticity.
+        assert!(s.perturbations.iter().all(|p| p.elasticities[3].is_nan()));
+        assert_eq!(s.dominant(3), None);
+    }
+
+    #[test]
+    fn perturbations() {
+        assert_eq!(perturbed(Parameter::Psize, 1.0, 0.1), (1.0, 2.0));
+        assert_eq!(perturbed(Parameter::Qlimit, 0.0, 0.1), (0.0, 1.0));
+        assert_eq!(perturbed(Parameter::Rate, 1000.0, 0.05), (950.0, 1050.0));
+    }
+}
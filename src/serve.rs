@@ -0,0 +1,366 @@
+use collectors::StatsCollector;
+use json::{self, Json};
+use rng;
+use simulation::{Change, Config, Sample, Simulation, DEFAULT_SAMPLE_INTERVAL};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// The simulated time between updates of a running job's progress, in seconds.
+const STEP: f64 = 0.1;
+
+// How long a stream of samples waits for more before checking in on the job again.
+const POLL: Duration = Duration::from_millis(100);
+
+// The largest request body taken, in bytes; configurations are far smaller.
+const MAX_BODY: usize = 1 << 20;
+
+// Status is where a job is at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Queued,
+    Running,
+    Done,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Status::Queued => "queued",
+            Status::Running => "running",
+            Status::Done => "done",
+        }
+    }
+}
+
+// Job is a configuration submitted to be run, along with how far along it is, the samples of the
+// server's state taken thus far, and the results document once it's done.
+struct Job {
+    config: Config,
+    status: Status,
+    progress: f64,
+    samples: Vec<Sample>,
+    results: Option<String>,
+}
+
+// Jobs holds every job submitted, numbered in the order they were submitted in, and those yet to
+// be picked up by a worker.
+struct Jobs {
+    jobs: Vec<Job>,
+    queued: VecDeque<usize>,
+}
+
+// Shared is the state shared between the connections and the workers; the condition variable is
+// notified whenever a job is submitted or makes progress.
+type Shared = Arc<(Mutex<Jobs>, Condvar)>;
+
+// Service runs simulations on behalf of clients over HTTP, from background threads: configurations
+// are submitted as JSON documents (see json::configure) to be run by a fixed number of workers,
+// in the order they were submitted in. It serves:
+//
+//   POST /jobs               Submit a configuration, returning the job's id
+//   GET  /jobs/ID            The job's status and progress
+//   GET  /jobs/ID/results    The results document (see json::document), once done
+//   GET  /jobs/ID/samples    The samples of the server's state as they're taken, a JSON object
+//                            per line, until the job's done
+//
+// Jobs are sampled every DEFAULT_SAMPLE_INTERVAL ticks unless configured otherwise.
+pub struct Service {
+    addr: SocketAddr,
+}
+
+impl Service {
+    pub fn bind(addr: &str, workers: usize) -> io::Result<Service> {
+        let listener = TcpListener::bind(addr)?;
+        let service = Service { addr: listener.local_addr()? };
+        let shared: Shared = Arc::new((
+            Mutex::new(Jobs { jobs: vec![], queued: VecDeque::new() }),
+            Condvar::new(),
+        ));
+        for _ in 0..workers.max(1) {
+            let shared = shared.clone();
+            thread::spawn(move || work(&shared));
+        }
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = shared.clone();
+                // A misbehaving client shouldn't take the service down with it.
+                thread::spawn(move || {
+                    let _ = serve(stream, &shared);
+                });
+            }
+        });
+        Ok(service)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+// Sampler passes the samples of a job's simulation on to the job.
+struct Sampler {
+    shared: Shared,
+    id: usize,
+}
+
+impl StatsCollector for Sampler {
+    fn on_sample(&mut self, sample: &Sample) {
+        let (ref jobs, ref updated) = *self.shared;
+        jobs.lock().unwrap().jobs[self.id].samples.push(*sample);
+        updated.notify_all();
+    }
+}
+
+// work runs queued jobs, one at a time, for as long as the service is up.
+fn work(shared: &Shared) {
+    let (ref jobs, ref updated) = **shared;
+    loop {
+        let (id, config) = {
+            let mut jobs = jobs.lock().unwrap();
+            let id = loop {
+                match jobs.queued.pop_front() {
+                    Some(id) => break id,
+                    None => jobs = updated.wait(jobs).unwrap(),
+                }
+            };
+            jobs.jobs[id].status = Status::Running;
+            (id, jobs.jobs[id].config.clone())
+        };
+        let mut sim = Simulation::new(config.clone());
+        sim.add_collector(Box::new(Sampler { shared: shared.clone(), id }));
+        loop {
+            sim.schedule(sim.now().seconds(config.resolution) + STEP, Change::Pause)
+                .expect("pausing is always valid");
+            let paused = sim.advance();
+            jobs.lock().unwrap().jobs[id].progress = sim.progress();
+            updated.notify_all();
+            if !paused {
+                break;
+            }
+            sim.resume();
+        }
+        let results = json::document(&config, &sim.results()).to_string();
+        let mut jobs = jobs.lock().unwrap();
+        let job = &mut jobs.jobs[id];
+        job.status = Status::Done;
+        job.progress = 1.0;
+        job.results = Some(results);
+        updated.notify_all();
+    }
+}
+
+// Request is an HTTP request, as much of it as is needed here.
+struct Request {
+    method: String,
+    path: String,
+    body: String,
+}
+
+// read_request reads in the request, or returns the status and reason it's turned away with if
+// its body's length is malformed or too large (see MAX_BODY) to read in.
+fn read_request<R: BufRead>(mut r: R) -> io::Result<Result<Request, (&'static str, &'static str)>> {
+    let mut line = String::new();
+    r.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? <= 2 {
+            break;
+        }
+        let mut header = header.splitn(2, ':');
+        let name = header.next().unwrap().trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = match header.next().unwrap_or("").trim().parse() {
+                Ok(length) => length,
+                Err(_) => return Ok(Err(("400 Bad Request", "invalid Content-Length"))),
+            };
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(Err(("413 Payload Too Large", "the body's too large")));
+    }
+    let mut body = vec![0; length];
+    r.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Ok(Request { method, path, body }))
+}
+
+fn respond(mut w: &TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        w,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn error(message: &str) -> String {
+    Json::object(vec![("error", message.into())]).to_string()
+}
+
+fn serve(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let request = match read_request(BufReader::new(&stream))? {
+        Ok(request) => request,
+        Err((status, reason)) => return respond(&stream, status, &error(reason)),
+    };
+    let (ref jobs, ref updated) = **shared;
+    let route: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let id = route.get(1).and_then(|id| id.parse::<usize>().ok());
+    let id = id.filter(|&id| id < jobs.lock().unwrap().jobs.len());
+    match (request.method.as_str(), &route[..], id) {
+        ("POST", ["jobs"], _) => match json::configure(&request.body, rng::random_seed()) {
+            Ok(mut config) => {
+                config.sample_interval.get_or_insert(DEFAULT_SAMPLE_INTERVAL);
+                let mut jobs = jobs.lock().unwrap();
+                let id = jobs.jobs.len();
+                jobs.jobs.push(Job {
+                    config,
+                    status: Status::Queued,
+                    progress: 0.0,
+                    samples: vec![],
+                    results: None,
+                });
+                jobs.queued.push_back(id);
+                updated.notify_all();
+                let body = Json::object(vec![("id", id.into()), ("status", "queued".into())]);
+                respond(&stream, "201 Created", &body.to_string())
+            }
+            Err(e) => respond(&stream, "400 Bad Request", &error(&e)),
+        },
+        ("GET", ["jobs", _], Some(id)) => {
+            let body = {
+                let jobs = jobs.lock().unwrap();
+                let job = &jobs.jobs[id];
+                Json::object(vec![
+                    ("id", id.into()),
+                    ("status", job.status.name().into()),
+                    ("progress", job.progress.into()),
+                ])
+            };
+            respond(&stream, "200 OK", &body.to_string())
+        }
+        ("GET", ["jobs", _, "results"], Some(id)) => {
+            let results = jobs.lock().unwrap().jobs[id].results.clone();
+            match results {
+                Some(results) => respond(&stream, "200 OK", &results),
+                None => respond(&stream, "409 Conflict", &error("the job isn't done yet")),
+            }
+        }
+        ("GET", ["jobs", _, "samples"], Some(id)) => stream_samples(&stream, shared, id),
+        _ => respond(&stream, "404 Not Found", &error("not found")),
+    }
+}
+
+// stream_samples writes out the job's samples as they're taken, one JSON object per line (with
+// times in seconds), until the job's done. The response is delimited by closing the connection.
+fn stream_samples(mut w: &TcpStream, shared: &Shared, id: usize) -> io::Result<()> {
+    write!(
+        w,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n"
+    )?;
+    let (ref jobs, ref updated) = **shared;
+    let mut sent = 0;
+    loop {
+        let (samples, done, resolution) = {
+            let mut jobs = jobs.lock().unwrap();
+            if jobs.jobs[id].samples.len() == sent && jobs.jobs[id].status != Status::Done {
+                jobs = updated.wait_timeout(jobs, POLL).unwrap().0;
+            }
+            let job = &jobs.jobs[id];
+            (job.samples[sent..].to_vec(), job.status == Status::Done, job.config.resolution)
+        };
+        for s in &samples {
+            let sample = Json::object(vec![
+                ("time", s.time.seconds(resolution).into()),
+                ("qlen", s.qlen.into()),
+                ("busy", s.busy.into()),
+            ]);
+            writeln!(w, "{}", sample)?;
+        }
+        sent += samples.len();
+        // Samples are all taken before the job's marked done.
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (String, String) {
+        let head = format!(
+            "{} {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            body.len()
+        );
+        send(addr, &(head + body))
+    }
+
+    // send sends the request as is, returning the status line and body of the response.
+    fn send(addr: SocketAddr, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let mut parts = response.splitn(2, "\r\n\r\n");
+        let status = parts.next().unwrap().lines().next().unwrap().to_string();
+        (status, parts.next().unwrap_or("").to_string())
+    }
+
+    #[test]
+    fn jobs() {
+        let service = Service::bind("127.0.0.1:0", 2).unwrap();
+        let addr = service.addr();
+        let config = r#"{"rate": 500, "pspeed": 1000, "duration": 1, "resolution": 1e4,
+            "seed": 1}"#;
+        let (status, body) = request(addr, "POST", "/jobs", config);
+        assert_eq!(status, "HTTP/1.1 201 Created");
+        assert_eq!(body, r#"{"id":0,"status":"queued"}"#);
+
+        // The samples stream until the job's done, by which point the results are in.
+        let (status, samples) = request(addr, "GET", "/jobs/0/samples", "");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let samples: Vec<Json> = samples.lines().map(|l| Json::parse(l).unwrap()).collect();
+        assert_eq!(samples.len(), 10);
+        assert_eq!(samples[1].get("time").and_then(Json::as_f64), Some(0.1));
+        let (_, body) = request(addr, "GET", "/jobs/0", "");
+        assert_eq!(body, r#"{"id":0,"status":"done","progress":1}"#);
+        let (status, body) = request(addr, "GET", "/jobs/0/results", "");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let results = json::run(config, 1).unwrap();
+        // As run directly, there being no randomness beyond the seed.
+        let sojourn = |doc: &str| {
+            let doc = Json::parse(doc).unwrap();
+            doc.get("results").and_then(|r| r.get("sojourn")).cloned()
+        };
+        assert!(sojourn(&body).is_some());
+        assert_eq!(sojourn(&body), sojourn(&results));
+
+        let (status, body) = request(addr, "POST", "/jobs", r#"{"pspeed": 0}"#);
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert!(body.contains("invalid pspeed 0"), "{}", body);
+        assert_eq!(request(addr, "GET", "/jobs/1", "").0, "HTTP/1.1 404 Not Found");
+        assert_eq!(request(addr, "GET", "/", "").0, "HTTP/1.1 404 Not Found");
+
+        // Bodies too large to take are turned away before they're read in, as are malformed
+        // lengths.
+        let (status, _) = send(addr, "POST /jobs HTTP/1.1\r\nContent-Length: 100000000000\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 413 Payload Too Large");
+        let (status, body) = send(addr, "POST /jobs HTTP/1.1\r\nContent-Length: many\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+        assert!(body.contains("invalid Content-Length"), "{}", body);
+    }
+}
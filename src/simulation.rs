@@ -0,0 +1,4167 @@
+extern crate stats;
+
+use self::stats::{Commute, OnlineStats};
+use analysis::{self, BatchMeans, BatchSummary, PoissonTest};
+use collectors::StatsCollector;
+use error::QsimError;
+use generators::{Classifier, Distribution, Generator, Trace};
+use histogram::{Bucketing, Histogram};
+use logging::{self, Level, Record};
+use middleware::{self, Layer, Quota, TenantQuotas};
+use quantiles::{Estimator, Quantiles};
+use records::DropReason;
+use rng;
+use simulators::{
+    BusyPeriod, Client, Color, Discipline, GilbertElliott, Impairments, Limiting, Link, Marker,
+    Packet, RateLimiter, Preemption, Server, ServerBuilder,
+};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::mem::take;
+use time::Ticks;
+use trace::{self, TraceEntry};
+
+pub const DEFAULT_RATE: u32 = 10_000;
+pub const DEFAULT_PSIZE: u32 = 1;
+pub const DEFAULT_PSPEED: u32 = 10_000;
+pub const DEFAULT_DURATION: u32 = 5;
+pub const DEFAULT_QLIMIT: Option<usize> = None;
+pub const DEFAULT_RESOLUTION: f64 = 1e6;
+pub const DEFAULT_SAMPLE_INTERVAL: u32 = 1000;
+pub const DEFAULT_WINDOW: f64 = 0.1;
+pub const DEFAULT_ACF_LAGS: usize = 20;
+pub const DEFAULT_PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+pub const DEFAULT_RETRIES: u32 = 3;
+pub const DEFAULT_ACK_LENGTH: u32 = 1;
+pub const DEFAULT_BURST: u32 = 1;
+// The token bucket policing a tenant's rate holds up to this many seconds' worth of packets, and
+// at least one.
+pub const QUOTA_BURST: f64 = 0.01;
+
+// TimeUnit is a unit of simulated time, for ticks (setting the resolution) and reported times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl TimeUnit {
+    pub fn parse(s: &str) -> Option<TimeUnit> {
+        match s {
+            "s" => Some(TimeUnit::Seconds),
+            "ms" => Some(TimeUnit::Milliseconds),
+            "us" | "µs" => Some(TimeUnit::Microseconds),
+            "ns" => Some(TimeUnit::Nanoseconds),
+            _ => None,
+        }
+    }
+
+    // TimeUnit.per_second returns the number of units in a second, i.e. the resolution if ticks
+    // are a unit long.
+    pub fn per_second(&self) -> f64 {
+        match *self {
+            TimeUnit::Seconds => 1.0,
+            TimeUnit::Milliseconds => 1e3,
+            TimeUnit::Microseconds => 1e6,
+            TimeUnit::Nanoseconds => 1e9,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TimeUnit::Seconds => "seconds",
+            TimeUnit::Milliseconds => "milliseconds",
+            TimeUnit::Microseconds => "microseconds",
+            TimeUnit::Nanoseconds => "nanoseconds",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match *self {
+            TimeUnit::Seconds => "s",
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Microseconds => "µs",
+            TimeUnit::Nanoseconds => "ns",
+        }
+    }
+
+    // TimeUnit::fitting returns the largest unit the given time (in seconds) is at least one of,
+    // nanoseconds if none.
+    pub fn fitting(seconds: f64) -> TimeUnit {
+        *[TimeUnit::Seconds, TimeUnit::Milliseconds, TimeUnit::Microseconds]
+            .iter()
+            .find(|u| seconds * u.per_second() >= 1.0)
+            .unwrap_or(&TimeUnit::Nanoseconds)
+    }
+}
+
+// The confidence level of the reported confidence intervals.
+pub const CONFIDENCE: f64 = 0.95;
+
+// Batching determines how a run is split up into batches for the method of batch means, either
+// into a fixed number of equally long batches or into batches spanning a fixed number of ticks.
+#[derive(Clone, Copy, Debug)]
+pub enum Batching {
+    Count(u32),
+    Ticks(u32),
+}
+
+// The queue length series used for warm-up detection is recorded as the average over each of this
+// many equally long observation intervals.
+pub const WARMUP_INTERVALS: u32 = 1000;
+pub const MSER_BATCH_SIZE: usize = 5;
+pub const WELCH_WINDOW: usize = 10;
+pub const WELCH_TOLERANCE: f64 = 0.05;
+
+// A run with an unlimited buffer is taken to have grown its queue without bound if the queue ends
+// up holding at least this share of the packets generated, and half again as many as it held on
+// average: a queue growing at a steady pace from empty ends up with twice its average, whereas one
+// that's settled ends up with about as many.
+pub const UNBOUNDED_SHARE: f64 = 0.05;
+
+// WarmupDetection is the procedure used to detect the end of the initial transient.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WarmupDetection {
+    Mser5,
+    Welch,
+}
+
+// Change is a change to the parameters of a running simulation (see Simulation.schedule).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    Rate(u32),
+    Pspeed(u32),
+    Qlimit(Option<usize>),
+    Pause,
+}
+
+impl Change {
+    // Change.validate checks that the change leaves the simulation with a valid configuration:
+    // packets still arriving, and the server still processing them.
+    pub fn validate(&self) -> Result<(), QsimError> {
+        match *self {
+            Change::Rate(0) => {
+                Err(QsimError::invalid("rate", 0, "packets must arrive at a positive rate"))
+            }
+            Change::Pspeed(0) => {
+                let reason = "the server must process at least a bit per second";
+                Err(QsimError::invalid("pspeed", 0, reason))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Event is something that happened to a packet during the simulation, timestamped with the tick
+// it happened at.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Arrival { time: Ticks, packet: Packet },
+    Enqueue { time: Ticks, packet: Packet },
+    Drop { time: Ticks, packet: Packet, reason: DropReason },
+    ServiceStart { time: Ticks, packet: Packet },
+    Departure { time: Ticks, packet: Packet },
+}
+
+impl Event {
+    pub fn time(&self) -> Ticks {
+        match *self {
+            Event::Arrival { time, .. } |
+            Event::Enqueue { time, .. } |
+            Event::Drop { time, .. } |
+            Event::ServiceStart { time, .. } |
+            Event::Departure { time, .. } => time,
+        }
+    }
+
+    pub fn packet(&self) -> &Packet {
+        match *self {
+            Event::Arrival { ref packet, .. } |
+            Event::Enqueue { ref packet, .. } |
+            Event::Drop { ref packet, .. } |
+            Event::ServiceStart { ref packet, .. } |
+            Event::Departure { ref packet, .. } => packet,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Event::Arrival { .. } => "arrival",
+            Event::Enqueue { .. } => "enqueue",
+            Event::Drop { .. } => "drop",
+            Event::ServiceStart { .. } => "service_start",
+            Event::Departure { .. } => "departure",
+        }
+    }
+}
+
+// log logs the event, along with the number of packets queued just after: arrivals on behalf of the
+// client and everything else on behalf of the server, drops at the debug level and the rest at the
+// trace level.
+fn log(event: &Event, qlen: usize) {
+    let (component, level) = match *event {
+        Event::Arrival { .. } => ("client", Level::Trace),
+        Event::Drop { .. } => ("server", Level::Debug),
+        _ => ("server", Level::Trace),
+    };
+    logging::log(&Record {
+        level,
+        component,
+        time: Some(event.time()),
+        packet: Some(event.packet().id),
+        message: format_args!("{}, {} queued", event.kind(), qlen),
+    });
+}
+
+// Sample is a snapshot of the server's state, taken at the start of the tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    pub time: Ticks,
+    pub qlen: usize,
+    pub busy: bool,
+}
+
+// Window holds the number of arrivals, departures and drops over a fixed-length window of time,
+// starting at the given tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub start: Ticks,
+    pub arrivals: u32,
+    pub departures: u32,
+    pub drops: u32,
+}
+
+// Retransmission is how packets dropped are retransmitted, as by a reliable transport: offered to
+// the queue anew once the timeout (in seconds) is up, up to the given number of times, after which
+// they're given up on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retransmission {
+    pub timeout: f64,
+    pub retries: u32,
+}
+
+// AckPath is the reverse path acknowledgments take back to the client, one per packet delivered:
+// queued at a server of their own if it's given a speed (in bits/s, the ACKs being as many bits
+// long), then across a link with the given propagation delay (in seconds). With a window, the
+// client's blocked while as many packets are unacknowledged, as a sliding-window transport is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AckPath {
+    pub delay: f64,
+    pub length: u32,
+    pub pspeed: Option<u32>,
+    pub window: Option<u32>,
+}
+
+// LinkPath is the path packets cross once processed to get to the receiver, taking the given
+// propagation delay (in seconds). Packets are lost as per the loss channel, if any, which is apart
+// from the buffer overflowing; with the reorder probability a packet's held back for up to the
+// reordering delay longer (in seconds), and with the duplication probability it's sent twice.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkPath {
+    pub delay: f64,
+    pub loss: Option<GilbertElliott>,
+    pub reorder: f64,
+    pub reorder_delay: f64,
+    pub duplicate: f64,
+}
+
+// RateLimiting is the rate limiter packets pass through ahead of the server, as an API gateway
+// would have them: the algorithm it admits them by, the rate (in packets/s) it admits them at on
+// average, and the most it admits at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimiting {
+    pub algorithm: Limiting,
+    pub limit: f64,
+    pub burst: u32,
+}
+
+// Marking is the two-rate three-color marker packets are metered by ahead of the server (see
+// Marker): the committed and peak rates (in bits/s) and bursts (in bits) they're colored by, along
+// with the queue lengths at which yellow and red packets are dropped, if any, green ones only ever
+// dropped by the buffer overflowing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Marking {
+    pub committed: f64,
+    pub committed_burst: u32,
+    pub peak: f64,
+    pub peak_burst: u32,
+    pub yellow_limit: Option<usize>,
+    pub red_limit: Option<usize>,
+}
+
+impl Marking {
+    // Marking.limit returns the queue length at which packets of the given color are dropped, if
+    // any.
+    pub fn limit(&self, color: Color) -> Option<usize> {
+        match color {
+            Color::Green => None,
+            Color::Yellow => self.yellow_limit,
+            Color::Red => self.red_limit,
+        }
+    }
+}
+
+// Tenant is one of the tenants sharing the server: the relative weight of the traffic it generates,
+// and its quota, if any: the share of the buffer its packets may take up at most, and the rate (in
+// packets/s) they may arrive at at most.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tenant {
+    pub weight: f64,
+    pub share: Option<f64>,
+    pub rate: Option<f64>,
+}
+
+// Config is the full set of parameters describing a single simulation run. If a seed is
+// specified, every random component draws from its own substream of it (see src/rng.rs) and the
+// run is reproducible.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub rate: u32,
+    pub psize: u32,
+    pub pspeed: u32,
+    // The distribution of interarrival times (with mean 1/rate), and of packet lengths (with mean
+    // psize, rounded to whole bits) and with them service times.
+    pub arrival: Distribution,
+    pub service: Distribution,
+    // The number of servers taking packets off of the queue, each processing pspeed bits/s.
+    pub servers: usize,
+    pub duration: u32,
+    // If set, the run ends once this many packets have departed, or at the end of the duration if
+    // that comes first.
+    pub packets: Option<u32>,
+    pub qlimit: Option<usize>,
+    pub discipline: Discipline,
+    // If set, service is preemptive (under LIFO or priority), packets preempted dealt with as per
+    // the policy.
+    pub preemption: Option<Preemption>,
+    pub resolution: f64,
+    pub seed: Option<u64>,
+    pub batching: Option<Batching>,
+    pub warmup_detection: Option<WarmupDetection>,
+    // The length of the warm-up period, in seconds. The statistics collected over it are deleted
+    // once it's over (see Simulation.delete_warmup), bar the time series spanning the whole run.
+    pub warmup: f64,
+    // Changes applied at the given simulated times (in seconds) into the run.
+    pub schedule: Vec<(f64, Change)>,
+    // The bucketing of the sojourn and waiting time histograms (in seconds), and the percentiles
+    // reported. With P² estimation no histograms are kept, and the memory used for percentiles is
+    // bounded no matter how long the run.
+    pub histogram: Bucketing,
+    pub percentiles: Vec<f64>,
+    pub estimator: Estimator,
+    // If set, the server state is sampled every this many ticks.
+    pub sample_interval: Option<u32>,
+    // If set, arrivals, departures and drops are counted over windows of this many ticks.
+    pub window: Option<u32>,
+    // If set, the autocorrelation of waiting and interdeparture times is computed up to this lag.
+    pub acf_lags: Option<usize>,
+    // Whether the interdeparture times are tested for being those of a Poisson process, as they
+    // are out of a stable M/M/1 queue (Burke's theorem).
+    pub poisson_test: bool,
+    // If set, the packets waiting longer than this (in seconds) are counted as starved, by class,
+    // flow, tenant and color.
+    pub wait_threshold: Option<f64>,
+    // The relative weights of the traffic classes packets are split into (a single class if
+    // empty), the number of flows packets are spread across uniformly, and the number of flows
+    // (the busiest first) statistics are reported for.
+    pub class_weights: Vec<f64>,
+    pub flows: u32,
+    pub top_flows: usize,
+    // The tenants packets are split between, as per their weights, and the quotas they're held to
+    // (a single tenant with none if empty).
+    pub tenants: Vec<Tenant>,
+    // If set, packets pass through a rate limiter before reaching the server, those it rejects
+    // counting as dropped.
+    pub rate_limiting: Option<RateLimiting>,
+    // If set, packets are colored by a marker ahead of the server (past the rate limiter), and
+    // dropped as per their color.
+    pub marking: Option<Marking>,
+    // If set, packets arrive as per the trace instead, with the lengths given therein; the rate and
+    // packet size are then ignored.
+    pub trace: Option<Arc<Vec<TraceEntry>>>,
+    // If set, packets dropped are retransmitted, and only count as dropped once given up on.
+    pub retransmission: Option<Retransmission>,
+    // If set, the client is blocked while the buffer's full instead of having its packets dropped,
+    // as a flow-controlled producer writing to a bounded channel would be.
+    pub backpressure: bool,
+    // If set, every packet delivered is acknowledged back to the client over the reverse path, and
+    // round-trip times are collected.
+    pub ack: Option<AckPath>,
+    // If set, packets processed cross a link to the receiver, acknowledged once they get there,
+    // and the order they're received in is tracked.
+    pub link: Option<LinkPath>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            rate: DEFAULT_RATE,
+            psize: DEFAULT_PSIZE,
+            pspeed: DEFAULT_PSPEED,
+            arrival: Distribution::Exponential,
+            service: Distribution::Deterministic,
+            servers: 1,
+            duration: DEFAULT_DURATION,
+            packets: None,
+            qlimit: DEFAULT_QLIMIT,
+            discipline: Discipline::Fifo,
+            preemption: None,
+            resolution: DEFAULT_RESOLUTION,
+            seed: None,
+            batching: None,
+            warmup_detection: None,
+            warmup: 0.0,
+            schedule: vec![],
+            histogram: Bucketing::default(),
+            percentiles: DEFAULT_PERCENTILES.to_vec(),
+            estimator: Estimator::Histogram,
+            sample_interval: None,
+            window: None,
+            acf_lags: None,
+            poisson_test: false,
+            wait_threshold: None,
+            class_weights: vec![],
+            flows: 1,
+            top_flows: 0,
+            tenants: vec![],
+            rate_limiting: None,
+            marking: None,
+            trace: None,
+            retransmission: None,
+            backpressure: false,
+            ack: None,
+            link: None,
+        }
+    }
+}
+
+impl Config {
+    // Config.ticks returns the total number of time units the simulation runs for, at most.
+    pub fn ticks(&self) -> Ticks {
+        Ticks::from_seconds(f64::from(self.duration), self.resolution)
+    }
+
+    // Config.warmup_ticks returns the length of the warm-up period, in ticks.
+    pub fn warmup_ticks(&self) -> Ticks {
+        Ticks::from_seconds(self.warmup, self.resolution)
+    }
+
+    // Config.span returns the number of ticks the simulation is expected to run for: if it's to
+    // end after a number of departures, the time they'd take at the arrival rate (or the service
+    // rate, if lower).
+    fn span(&self) -> Ticks {
+        match self.packets {
+            Some(n) if self.trace.is_none() => {
+                let rate = f64::from(self.rate).min(f64::from(self.pspeed) / f64::from(self.psize));
+                let ticks = f64::from(n) / rate * self.resolution;
+                if ticks < f64::from(self.ticks()) { Ticks(ticks as u32) } else { self.ticks() }
+            }
+            _ => self.ticks(),
+        }
+    }
+
+    // Config.batch_ticks returns the number of ticks spanned by a single batch, if batching.
+    fn batch_ticks(&self) -> Option<Ticks> {
+        match self.batching {
+            Some(Batching::Count(n)) => Some(Ticks(::std::cmp::max(self.span().0 / n.max(1), 1))),
+            Some(Batching::Ticks(n)) => Some(Ticks(n.max(1))),
+            None => None,
+        }
+    }
+
+    // Config.retransmission_ticks returns the retransmission timeout in ticks, at least one.
+    fn retransmission_ticks(&self) -> Option<Ticks> {
+        self.retransmission.map(|r| Ticks::from_seconds(r.timeout, self.resolution).max(Ticks(1)))
+    }
+
+    // Config.ack_ticks returns the propagation delay of the reverse path in ticks, if there's one.
+    fn ack_ticks(&self) -> Option<Ticks> {
+        self.ack.map(|a| Ticks::from_seconds(a.delay, self.resolution))
+    }
+
+    // Config.window returns the number of packets the client may have unacknowledged, if limited.
+    fn window(&self) -> Option<u32> {
+        self.ack.and_then(|a| a.window)
+    }
+
+    // Config.observation_ticks returns the length of an observation interval of the queue length
+    // series used for warm-up detection.
+    fn observation_ticks(&self) -> Ticks {
+        Ticks(::std::cmp::max(self.span().0 / WARMUP_INTERVALS, 1))
+    }
+
+    // Config.arrivals returns the arrival process generator, drawing from the arrivals substream
+    // if the configuration is seeded, or replaying the trace if there is one.
+    pub fn arrivals(&self) -> Box<dyn Generator> {
+        if let Some(ref trace) = self.trace {
+            return Box::new(Trace::new(trace.clone()));
+        }
+        let rng = match self.seed {
+            Some(seed) => rng::substream(seed, rng::ARRIVALS),
+            None => rng::unseeded(),
+        };
+        self.arrival.generator(f64::from(self.rate), rng)
+    }
+
+    // Config.lengths returns the generator of packet lengths, drawing from the service substream
+    // if the configuration is seeded.
+    pub fn lengths(&self) -> Box<dyn Generator> {
+        let rng = match self.seed {
+            Some(seed) => rng::substream(seed, rng::SERVICE),
+            None => rng::unseeded(),
+        };
+        self.service.generator(1.0 / f64::from(self.psize), rng)
+    }
+
+    // Config.rate_limiter returns the rate limiter packets pass through, if there's one.
+    fn rate_limiter(&self) -> Option<RateLimiter> {
+        let l = self.rate_limiting?;
+        Some(RateLimiter::new(l.algorithm, l.limit / self.resolution, l.burst))
+    }
+
+    // Config.marker returns the marker packets are colored by, if there's one.
+    fn marker(&self) -> Option<Marker> {
+        let m = self.marking?;
+        let (committed, peak) = (m.committed / self.resolution, m.peak / self.resolution);
+        Some(Marker::new(committed, f64::from(m.committed_burst), peak, f64::from(m.peak_burst)))
+    }
+
+    // Config.forward_link returns the link packets cross to the receiver, if any, drawing from the
+    // link substream if the configuration is seeded.
+    fn forward_link(&self) -> Option<Link> {
+        let l = self.link?;
+        let impairments = Impairments {
+            loss: l.loss,
+            reorder: l.reorder,
+            reorder_delay: Ticks::from_seconds(l.reorder_delay, self.resolution),
+            duplicate: l.duplicate,
+        };
+        let rng = match self.seed {
+            Some(seed) => rng::substream(seed, rng::LINK),
+            None => rng::unseeded(),
+        };
+        let delay = Ticks::from_seconds(l.delay, self.resolution);
+        Some(Link::impaired(delay, impairments, rng))
+    }
+
+    // Config.offered_load returns the load offered to each server (ρ = λ/cμ): the average rate bits
+    // arrive at, going by the rate and packet size or by the trace replayed, over the speed they're
+    // processed at by all servers. Changes scheduled mid-run aren't accounted for.
+    pub fn offered_load(&self) -> f64 {
+        let bitrate = match self.trace {
+            Some(ref entries) => trace::summarize(entries).bitrate(),
+            None => f64::from(self.rate) * f64::from(self.psize),
+        };
+        bitrate / (f64::from(self.pspeed) * self.servers as f64)
+    }
+
+    // Config.unstable returns whether the queue is bound to grow without bound, there being no
+    // limit on the buffer and at least as much load offered as the server can take (ρ ≥ 1). Such a
+    // queue never reaches a steady state, and its averages only reflect how long the run was.
+    pub fn unstable(&self) -> bool {
+        self.qlimit.is_none() && self.offered_load() >= 1.0
+    }
+
+    // Config.model returns the model simulated in Kendall's notation, the capacity of the system
+    // including the packets in service.
+    pub fn model(&self) -> String {
+        let (arrival, service) = (self.arrival.kendall(), self.service.kendall());
+        let mut model = format!("{}/{}/{}", arrival, service, self.servers);
+        let capacity = self.qlimit.map(|limit| (limit + self.servers).to_string());
+        if self.discipline != Discipline::Fifo {
+            let capacity = capacity.unwrap_or_else(|| "∞".to_string());
+            model.push_str(&format!("/{}/∞/{}", capacity, self.discipline.kendall()));
+        } else if let Some(capacity) = capacity {
+            model.push_str(&format!("/{}", capacity));
+        }
+        model
+    }
+
+    // Config.classifier returns the classifier assigning packets their class and flow.
+    fn classifier(&self) -> Classifier {
+        let rng = match self.seed {
+            Some(seed) => rng::substream(seed, rng::CLASSES),
+            None => rng::unseeded(),
+        };
+        Classifier::new(&self.class_weights, self.flows, rng)
+    }
+
+    // Config.tenancy returns the classifier assigning packets their tenant (as its class).
+    fn tenancy(&self) -> Classifier {
+        let rng = match self.seed {
+            Some(seed) => rng::substream(seed, rng::TENANTS),
+            None => rng::unseeded(),
+        };
+        let weights: Vec<f64> = self.tenants.iter().map(|t| t.weight).collect();
+        Classifier::new(&weights, 1, rng)
+    }
+
+    // Config.quotas returns the layer holding tenants to their quotas, if any has one.
+    fn quotas(&self) -> Option<TenantQuotas> {
+        if self.tenants.iter().all(|t| t.share.is_none() && t.rate.is_none()) {
+            return None;
+        }
+        let quotas = self.tenants.iter().map(|t| Quota {
+            packets: t.share.and_then(|s| {
+                self.qlimit.map(|limit| ((s * limit as f64) as usize).max(1))
+            }),
+            rate: t.rate.map(|r| r / self.resolution),
+            burst: t.rate.map_or(0.0, |r| (r * QUOTA_BURST).max(1.0)),
+        });
+        Some(TenantQuotas::new(quotas.collect()))
+    }
+
+    // Config.quantiles returns a fresh estimator of the configured percentiles.
+    pub fn quantiles(&self) -> Quantiles {
+        Quantiles::new(self.estimator, self.histogram, &self.percentiles)
+    }
+
+    // Config.classes returns the number of traffic classes.
+    pub fn classes(&self) -> usize {
+        self.class_weights.len().max(1)
+    }
+
+    // Config.tenant_count returns the number of tenants packets are split between.
+    pub fn tenant_count(&self) -> usize {
+        self.tenants.len().max(1)
+    }
+
+    // Config.color_count returns the number of colors packets are marked with, none if they aren't.
+    pub fn color_count(&self) -> usize {
+        if self.marking.is_some() { 3 } else { 0 }
+    }
+
+    // Config.validate checks that the configuration describes a run that can be simulated.
+    pub fn validate(&self) -> Result<(), QsimError> {
+        if self.rate == 0 && self.trace.is_none() {
+            return Err(QsimError::invalid("rate", 0, "packets must arrive at a positive rate"));
+        }
+        if self.psize == 0 {
+            return Err(QsimError::invalid("psize", 0, "packets must be at least a bit long"));
+        }
+        if self.pspeed == 0 {
+            let reason = "the server must process at least a bit per second";
+            return Err(QsimError::invalid("pspeed", 0, reason));
+        }
+        if self.servers == 0 {
+            return Err(QsimError::invalid("servers", 0, "packets need a server to process them"));
+        }
+        if self.duration == 0 {
+            return Err(QsimError::invalid("duration", 0, "the run must last at least a second"));
+        }
+        for &(name, distribution) in &[("arrival", self.arrival), ("service", self.service)] {
+            match distribution {
+                Distribution::Pareto { shape } if !(shape > 1.0 && shape.is_finite()) => {
+                    let (parameter, reason) = (format!("{} Pareto shape", name), "it must exceed 1");
+                    return Err(QsimError::invalid(&parameter, shape, reason));
+                }
+                Distribution::LogNormal { sigma } if !(sigma >= 0.0 && sigma.is_finite()) => {
+                    let reason = "it must be finite, and can't be negative";
+                    return Err(QsimError::invalid(&format!("{} sigma", name), sigma, reason));
+                }
+                _ => {}
+            }
+        }
+        weights("class", self.class_weights.iter().copied())?;
+        weights("tenant", self.tenants.iter().map(|t| t.weight))?;
+        let resolution = self.resolution;
+        if resolution.is_nan() || resolution < 1.0 ||
+           f64::from(self.duration) * resolution > f64::from(u32::MAX) {
+            let reason = "the run must span 1 to 2^32 ticks";
+            return Err(QsimError::invalid("resolution", resolution, reason));
+        }
+        if self.warmup.is_nan() || self.warmup < 0.0 || self.warmup >= f64::from(self.duration) {
+            let reason = "it must be shorter than the duration";
+            return Err(QsimError::invalid("warm-up period", self.warmup, reason));
+        }
+        if let Some(r) = self.retransmission {
+            if r.timeout.is_nan() || r.timeout <= 0.0 {
+                let reason = "it must be positive";
+                return Err(QsimError::invalid("retransmission timeout", r.timeout, reason));
+            }
+        }
+        if self.backpressure {
+            match self.qlimit {
+                None => {
+                    let reason = "backpressure needs a buffer limit for the source to block at";
+                    return Err(QsimError::Config(reason.to_string()));
+                }
+                Some(0) => {
+                    let reason = "the source would block forever under backpressure";
+                    return Err(QsimError::invalid("qlimit", 0, reason));
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(a) = self.ack {
+            if a.delay.is_nan() || a.delay < 0.0 {
+                return Err(QsimError::invalid("ACK delay", a.delay, "it can't be negative"));
+            }
+            if a.length == 0 {
+                return Err(QsimError::invalid("ACK length", 0, "ACKs must be at least a bit long"));
+            }
+            if a.pspeed == Some(0) {
+                let reason = "the ACK queue must process at least a bit per second";
+                return Err(QsimError::invalid("ACK pspeed", 0, reason));
+            }
+            if a.window == Some(0) {
+                let reason = "the client could never send a packet";
+                return Err(QsimError::invalid("window", 0, reason));
+            }
+        }
+        for t in &self.tenants {
+            if t.share.is_some() && self.qlimit.is_none() {
+                let reason = "a tenant's share of the buffer needs a buffer limit";
+                return Err(QsimError::Config(reason.to_string()));
+            }
+            if let Some(share) = t.share.filter(|s| !(*s > 0.0 && *s <= 1.0)) {
+                let reason = "it must be a share of the buffer, above 0 and up to 1";
+                return Err(QsimError::invalid("tenant share", share, reason));
+            }
+            if let Some(rate) = t.rate.filter(|r| r.is_nan() || *r <= 0.0) {
+                return Err(QsimError::invalid("tenant rate", rate, "it must be positive"));
+            }
+        }
+        let preemptive = matches!(self.discipline, Discipline::Lifo | Discipline::Priority(_));
+        if self.preemption.is_some() && !preemptive {
+            let reason = "preemption needs a LIFO or priority discipline";
+            return Err(QsimError::Config(reason.to_string()));
+        }
+        if self.servers > 1 && self.preemption.is_some() {
+            let reason = "preemption is only supported with a single server";
+            return Err(QsimError::Config(reason.to_string()));
+        }
+        if self.servers > 1 && matches!(self.discipline, Discipline::Polling(_)) {
+            let reason = "a polling system has a single server visiting the queues";
+            return Err(QsimError::Config(reason.to_string()));
+        }
+        for &(at, change) in &self.schedule {
+            if !at.is_finite() {
+                return Err(QsimError::invalid("change time", at, "it must be finite"));
+            }
+            change.validate()?;
+        }
+        if let Some(&p) = self.percentiles.iter().find(|&&p| !(p > 0.0 && p <= 100.0)) {
+            return Err(QsimError::invalid("percentile", p, "it must be above 0 and up to 100"));
+        }
+        if !self.histogram.valid() {
+            let reason = "histogram buckets must be of a positive width, growing by a factor above \
+                          1 if exponential, and number at least one";
+            return Err(QsimError::Config(reason.to_string()));
+        }
+        if self.sample_interval == Some(0) {
+            let reason = "samples must be taken at least a tick apart";
+            return Err(QsimError::invalid("sample interval", 0, reason));
+        }
+        if self.window == Some(0) {
+            return Err(QsimError::invalid("window", 0, "windows must span at least a tick"));
+        }
+        if let Some(t) = self.wait_threshold {
+            if t.is_nan() || t < 0.0 {
+                return Err(QsimError::invalid("wait threshold", t, "it can't be negative"));
+            }
+        }
+        if let Some(l) = self.rate_limiting {
+            if l.limit.is_nan() || l.limit <= 0.0 {
+                return Err(QsimError::invalid("rate limit", l.limit, "it must be positive"));
+            }
+            if l.burst == 0 {
+                let reason = "not a single packet would get through";
+                return Err(QsimError::invalid("rate limit burst", 0, reason));
+            }
+        }
+        if let Some(m) = self.marking {
+            if m.committed.is_nan() || m.committed <= 0.0 {
+                let reason = "it must be positive";
+                return Err(QsimError::invalid("committed rate", m.committed, reason));
+            }
+            if m.peak.is_nan() || m.peak < m.committed {
+                let reason = "it can't be below the committed rate";
+                return Err(QsimError::invalid("peak rate", m.peak, reason));
+            }
+            if m.peak_burst < m.committed_burst {
+                let reason = "it can't be below the committed burst";
+                return Err(QsimError::invalid("peak burst", m.peak_burst, reason));
+            }
+        }
+        if let Some(l) = self.link {
+            if l.delay.is_nan() || l.delay < 0.0 {
+                return Err(QsimError::invalid("link delay", l.delay, "it can't be negative"));
+            }
+            if l.reorder_delay.is_nan() || l.reorder_delay < 0.0 {
+                let reason = "it can't be negative";
+                return Err(QsimError::invalid("reordering delay", l.reorder_delay, reason));
+            }
+            let mut probabilities = vec![("reordering", l.reorder), ("duplication", l.duplicate)];
+            if let Some(c) = l.loss {
+                probabilities.extend(vec![
+                    ("good-to-bad", c.p),
+                    ("bad-to-good", c.r),
+                    ("good state loss", c.good_loss),
+                    ("bad state loss", c.bad_loss),
+                ]);
+            }
+            for &(name, p) in &probabilities {
+                if !(0.0..=1.0).contains(&p) {
+                    let reason = "it must be a probability, from 0 to 1";
+                    return Err(QsimError::invalid(&format!("{} probability", name), p, reason));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// weights checks the weights packets are spread by (see generators::Classifier): each has to be
+// finite and can't be negative, and they can't all be zero.
+fn weights<I: IntoIterator<Item = f64>>(name: &str, weights: I) -> Result<(), QsimError> {
+    let mut total = None;
+    for w in weights {
+        if !(w.is_finite() && w >= 0.0) {
+            let reason = "it must be finite, and can't be negative";
+            return Err(QsimError::invalid(&format!("{} weight", name), w, reason));
+        }
+        *total.get_or_insert(0.0) += w;
+    }
+    if total == Some(0.0) {
+        let reason = format!("{} weights can't all be zero, packets would go nowhere", name);
+        return Err(QsimError::Config(reason));
+    }
+    Ok(())
+}
+
+// SimulationBuilder puts together the configuration of a run one group of settings at a time, for
+// programmatic use: the arrivals, the packets, the server, what ends the run, and what's collected
+// over it. Anything left unset takes on the same default as on the command line.
+//
+//   let sim = SimulationBuilder::new()
+//       .arrivals(9000, Distribution::Exponential)
+//       .server(10_000, Some(64), Discipline::Fifo)
+//       .duration(60)
+//       .seed(1)
+//       .build()?;
+#[derive(Clone, Debug, Default)]
+pub struct SimulationBuilder {
+    config: Config,
+}
+
+impl SimulationBuilder {
+    pub fn new() -> SimulationBuilder {
+        SimulationBuilder::default()
+    }
+
+    // SimulationBuilder.arrivals sets the arrival rate, in packets/s, and the distribution of the
+    // interarrival times.
+    pub fn arrivals(mut self, rate: u32, distribution: Distribution) -> SimulationBuilder {
+        self.config.rate = rate;
+        self.config.arrival = distribution;
+        self
+    }
+
+    // SimulationBuilder.trace has packets arrive as per the trace instead, with the lengths given
+    // therein.
+    pub fn trace(mut self, trace: Arc<Vec<TraceEntry>>) -> SimulationBuilder {
+        self.config.trace = Some(trace);
+        self
+    }
+
+    // SimulationBuilder.packets sets the mean packet size, in bits, and the distribution of packet
+    // lengths (and with them service times).
+    pub fn packets(mut self, psize: u32, distribution: Distribution) -> SimulationBuilder {
+        self.config.psize = psize;
+        self.config.service = distribution;
+        self
+    }
+
+    // SimulationBuilder.traffic sets the relative weights of the traffic classes packets are split
+    // into, and the number of flows they're spread across.
+    pub fn traffic(mut self, class_weights: Vec<f64>, flows: u32) -> SimulationBuilder {
+        self.config.class_weights = class_weights;
+        self.config.flows = flows;
+        self
+    }
+
+    // SimulationBuilder.tenants sets the tenants packets are split between, and their quotas.
+    pub fn tenants(mut self, tenants: Vec<Tenant>) -> SimulationBuilder {
+        self.config.tenants = tenants;
+        self
+    }
+
+    // SimulationBuilder.server sets the processing speed, in bits/s, the buffer limit, if any, and
+    // the discipline of the server.
+    pub fn server(
+        mut self,
+        pspeed: u32,
+        qlimit: Option<usize>,
+        discipline: Discipline,
+    ) -> SimulationBuilder {
+        self.config.pspeed = pspeed;
+        self.config.qlimit = qlimit;
+        self.config.discipline = discipline;
+        self
+    }
+
+    // SimulationBuilder.retransmit has packets dropped offered to the queue anew after the timeout,
+    // in seconds, up to the given number of times.
+    pub fn retransmit(mut self, timeout: f64, retries: u32) -> SimulationBuilder {
+        self.config.retransmission = Some(Retransmission { timeout, retries });
+        self
+    }
+
+    // SimulationBuilder.preemption makes service preemptive, packets preempted dealt with as per
+    // the policy.
+    pub fn preemption(mut self, policy: Preemption) -> SimulationBuilder {
+        self.config.preemption = Some(policy);
+        self
+    }
+
+    // SimulationBuilder.rate_limit has packets pass through a rate limiter ahead of the server,
+    // admitting them at the given rate (in packets/s) by the given algorithm, in bursts of at most
+    // the given number of them.
+    pub fn rate_limit(mut self, algorithm: Limiting, limit: f64, burst: u32) -> SimulationBuilder {
+        self.config.rate_limiting = Some(RateLimiting { algorithm, limit, burst });
+        self
+    }
+
+    // SimulationBuilder.marking has packets colored by a two-rate three-color marker ahead of the
+    // server, and dropped as per their color (see Marking).
+    pub fn marking(mut self, marking: Marking) -> SimulationBuilder {
+        self.config.marking = Some(marking);
+        self
+    }
+
+    // SimulationBuilder.backpressure has the client blocked while the buffer's full, rather than
+    // its packets dropped.
+    pub fn backpressure(mut self) -> SimulationBuilder {
+        self.config.backpressure = true;
+        self
+    }
+
+    // SimulationBuilder.ack has packets delivered acknowledged over a reverse path with the given
+    // propagation delay, in seconds, the client blocked while the window's worth of packets (if
+    // any) are unacknowledged.
+    pub fn ack(mut self, delay: f64, window: Option<u32>) -> SimulationBuilder {
+        let ack = self.config.ack.get_or_insert(AckPath {
+            delay,
+            length: DEFAULT_ACK_LENGTH,
+            pspeed: None,
+            window,
+        });
+        ack.delay = delay;
+        ack.window = window;
+        self
+    }
+
+    // SimulationBuilder.ack_queue has acknowledgments, as many bits long, queue up for a server of
+    // the given speed on the reverse path before crossing it (see SimulationBuilder.ack).
+    pub fn ack_queue(mut self, pspeed: u32, length: u32) -> SimulationBuilder {
+        let ack = self.config.ack.get_or_insert(AckPath {
+            delay: 0.0,
+            length,
+            pspeed: None,
+            window: None,
+        });
+        ack.pspeed = Some(pspeed);
+        ack.length = length;
+        self
+    }
+
+    // SimulationBuilder.seed makes the run reproducible.
+    pub fn seed(mut self, seed: u64) -> SimulationBuilder {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    // SimulationBuilder.resolution sets the number of ticks per second.
+    pub fn resolution(mut self, resolution: f64) -> SimulationBuilder {
+        self.config.resolution = resolution;
+        self
+    }
+
+    // SimulationBuilder.duration sets the length of the run, in seconds.
+    pub fn duration(mut self, duration: u32) -> SimulationBuilder {
+        self.config.duration = duration;
+        self
+    }
+
+    // SimulationBuilder.stop_after ends the run once the given number of packets have departed, or
+    // at the end of the duration if that comes first.
+    pub fn stop_after(mut self, packets: u32) -> SimulationBuilder {
+        self.config.packets = Some(packets);
+        self
+    }
+
+    // SimulationBuilder.warmup sets the length of the warm-up period, in seconds, the statistics
+    // collected over which are deleted.
+    pub fn warmup(mut self, warmup: f64) -> SimulationBuilder {
+        self.config.warmup = warmup;
+        self
+    }
+
+    // SimulationBuilder.at applies the change at the given time into the run, in seconds.
+    pub fn at(mut self, at: f64, change: Change) -> SimulationBuilder {
+        self.config.schedule.push((at, change));
+        self
+    }
+
+    // SimulationBuilder.percentiles sets the percentiles of the sojourn and waiting times reported,
+    // and how they're estimated.
+    pub fn percentiles(
+        mut self,
+        percentiles: Vec<f64>,
+        estimator: Estimator,
+        histogram: Bucketing,
+    ) -> SimulationBuilder {
+        self.config.percentiles = percentiles;
+        self.config.estimator = estimator;
+        self.config.histogram = histogram;
+        self
+    }
+
+    // SimulationBuilder.batching splits the run into batches for batch means confidence intervals.
+    pub fn batching(mut self, batching: Batching) -> SimulationBuilder {
+        self.config.batching = Some(batching);
+        self
+    }
+
+    // SimulationBuilder.warmup_detection detects the end of the initial transient.
+    pub fn warmup_detection(mut self, detection: WarmupDetection) -> SimulationBuilder {
+        self.config.warmup_detection = Some(detection);
+        self
+    }
+
+    // SimulationBuilder.series records the server state every sample_interval ticks and the
+    // arrivals, departures and drops over windows of window ticks, if set.
+    pub fn series(
+        mut self,
+        sample_interval: Option<u32>,
+        window: Option<u32>,
+    ) -> SimulationBuilder {
+        self.config.sample_interval = sample_interval;
+        self.config.window = window;
+        self
+    }
+
+    // SimulationBuilder.autocorrelation computes the autocorrelation of the waiting and
+    // interdeparture times up to the given lag.
+    pub fn autocorrelation(mut self, lags: usize) -> SimulationBuilder {
+        self.config.acf_lags = Some(lags);
+        self
+    }
+
+    // SimulationBuilder.poisson_test tests the interdeparture times for being those of a Poisson
+    // process.
+    pub fn poisson_test(mut self) -> SimulationBuilder {
+        self.config.poisson_test = true;
+        self
+    }
+
+    // SimulationBuilder.wait_threshold counts the packets waiting longer than the given time (in
+    // seconds) as starved.
+    pub fn wait_threshold(mut self, threshold: f64) -> SimulationBuilder {
+        self.config.wait_threshold = Some(threshold);
+        self
+    }
+
+    // SimulationBuilder.top_flows reports statistics for the given number of busiest flows.
+    pub fn top_flows(mut self, flows: usize) -> SimulationBuilder {
+        self.config.top_flows = flows;
+        self
+    }
+
+    // SimulationBuilder.config returns the configuration put together, if valid.
+    pub fn config(self) -> Result<Config, QsimError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+
+    // SimulationBuilder.build returns the simulation of the configuration put together, if valid.
+    pub fn build(self) -> Result<Simulation, QsimError> {
+        Ok(Simulation::new(self.config()?))
+    }
+}
+
+// Breakdown is the subset of the statistics kept for each traffic class, flow and tenant.
+#[derive(Clone, Copy, Debug)]
+pub struct Breakdown {
+    pub packets_generated: u32,
+    pub packets_processed: u32,
+    pub packets_dropped: u32,
+    pub sojourn: OnlineStats,
+    pub wait: OnlineStats,
+    // The longest wait seen (in seconds), and the packets that waited longer than the threshold,
+    // if there's one (see Config.wait_threshold); both take in the packets still queued at the end
+    // of the run, waiting as they are.
+    pub max_wait: f64,
+    pub starved: u32,
+}
+
+impl Breakdown {
+    fn new() -> Breakdown {
+        Breakdown {
+            packets_generated: 0,
+            packets_processed: 0,
+            packets_dropped: 0,
+            sojourn: OnlineStats::new(),
+            wait: OnlineStats::new(),
+            max_wait: 0.0,
+            starved: 0,
+        }
+    }
+
+    // Breakdown.waited accounts for a packet having waited the given time (in seconds), starved if
+    // longer than the threshold, if any.
+    fn waited(&mut self, wait: f64, threshold: Option<f64>) {
+        self.max_wait = self.max_wait.max(wait);
+        self.starved += u32::from(threshold.is_some_and(|t| wait > t));
+    }
+
+    // Breakdown.loss_probability returns the percentage of generated packets that were dropped.
+    pub fn loss_probability(&self) -> f64 {
+        f64::from(self.packets_dropped) / f64::from(self.packets_generated) * 100.0
+    }
+}
+
+// Results is the set of statistics collected over the course of a simulation run.
+#[derive(Clone, Debug)]
+pub struct Results {
+    pub sojourn: OnlineStats,
+    // Only kept if percentiles are estimated from histograms (see Config.estimator).
+    pub sojourn_histogram: Option<Histogram>,
+    // The configured percentiles of the sojourn time, as (percentile, value) pairs.
+    pub sojourn_percentiles: Vec<(f64, f64)>,
+    // The time spent queued before entering service, and the time spent in service thereafter;
+    // together they make up the sojourn time.
+    pub wait: OnlineStats,
+    pub wait_histogram: Option<Histogram>,
+    pub wait_percentiles: Vec<(f64, f64)>,
+    pub service: OnlineStats,
+    pub service_histogram: Option<Histogram>,
+    pub service_percentiles: Vec<(f64, f64)>,
+    pub qlen: OnlineStats,
+    // The number of ticks spent with k packets in the system (queued or in service), for k = 0 up
+    // to the most seen.
+    pub occupancy: Vec<u64>,
+    pub packets_generated: u32,
+    pub packets_processed: u32,
+    pub packets_dropped: u32,
+    // The simulated time the statistics were collected over (thus far), in seconds: the time the
+    // run lasted, less the warm-up period.
+    pub elapsed: f64,
+    pub idle_proportion: f64,
+    // The proportion of time the server was busy (ρ).
+    pub utilization: f64,
+    pub busy_periods: BusyPeriods,
+    pub leftover: usize,
+    // Whether the queue looks to have grown without bound over the run (see UNBOUNDED_SHARE), in
+    // which case the steady-state statistics are meaningless.
+    pub unbounded: bool,
+    // Batch means confidence intervals, if the run was batched.
+    pub sojourn_batches: Option<BatchSummary>,
+    pub wait_batches: Option<BatchSummary>,
+    pub qlen_batches: Option<BatchSummary>,
+    // The loss probability as a percentage, and the utilization as a proportion.
+    pub loss_batches: Option<BatchSummary>,
+    pub utilization_batches: Option<BatchSummary>,
+    // The detected end of the warm-up period, if detection was requested.
+    pub warmup: Option<Warmup>,
+    // The sampled server state, if sampling was requested.
+    pub samples: Vec<Sample>,
+    // The counts over each window, if requested, the last of which may be partial.
+    pub windows: Vec<Window>,
+    // The statistics broken down by traffic class, and for the busiest flows (by the number of
+    // packets generated), if requested.
+    pub classes: Vec<Breakdown>,
+    pub top_flows: Vec<(u32, Breakdown)>,
+    // The statistics broken down by tenant, a packet dropped for being over its tenant's quota
+    // counting as dropped.
+    pub tenants: Vec<Breakdown>,
+    // The statistics broken down by color (green, yellow, then red), if packets are marked, a
+    // packet dropped for its color counting as dropped.
+    pub colors: Vec<Breakdown>,
+    // The wait threshold packets were counted as starved past, if any.
+    pub wait_threshold: Option<f64>,
+    // As many of the least served flows (by the number of packets processed, including flows that
+    // saw no packets at all) as the busiest ones.
+    pub bottom_flows: Vec<(u32, Breakdown)>,
+    // Jain's fairness index over the throughputs of the traffic classes and of the flows, if there
+    // are several of them.
+    pub class_fairness: Option<f64>,
+    pub flow_fairness: Option<f64>,
+    // The autocorrelation function of the waiting and interdeparture times for lags 1 and up, if
+    // requested, as far as there are samples for.
+    pub wait_acf: Vec<f64>,
+    pub interdeparture_acf: Vec<f64>,
+    pub little: LittlesLaw,
+    // The time between consecutive departures, the smoothed jitter as computed by RTP receivers
+    // (RFC 3550, §6.4.1) at the end of the run, and the delay variation, i.e. the absolute
+    // difference between the sojourn times of consecutive departures, all in seconds.
+    pub interdeparture: OnlineStats,
+    pub interdeparture_histogram: Option<Histogram>,
+    pub interdeparture_percentiles: Vec<(f64, f64)>,
+    // The test of the interdeparture times against a Poisson process, if requested.
+    pub poisson_test: Option<PoissonTest>,
+    pub jitter: f64,
+    pub delay_variation: OnlineStats,
+    pub delay_variation_percentiles: Vec<(f64, f64)>,
+    // The metrics reported by the registered collectors, in the order they were registered in.
+    pub collected: Vec<(String, f64)>,
+    // How packets dropped fared, if they were retransmitted.
+    pub retransmissions: Option<Retransmissions>,
+    // How long the client was blocked for, under backpressure or a window.
+    pub blocking: Option<Blocking>,
+    // The round-trip times of the packets acknowledged, if they were.
+    pub round_trips: Option<RoundTrips>,
+    // How packets fared crossing the link to the receiver, if there's one.
+    pub delivery: Option<Delivery>,
+    // The packets the rate limiter admitted and rejected, if there's one.
+    pub admission: Option<Admission>,
+    // The times service was preempted, if it's preemptive.
+    pub preemptions: Option<Preemptions>,
+}
+
+impl Results {
+    // Results.max_wait returns the longest wait seen across all packets (see Breakdown.max_wait), in
+    // seconds.
+    pub fn max_wait(&self) -> f64 {
+        self.classes.iter().map(|b| b.max_wait).fold(0.0, f64::max)
+    }
+
+    // Results.starved returns the number of packets that waited longer than the wait threshold.
+    pub fn starved(&self) -> u32 {
+        self.classes.iter().map(|b| b.starved).sum()
+    }
+
+    // Results.interdeparture_scv returns the squared coefficient of variation of the interdeparture
+    // times, 1 for a Poisson departure process and 0 for a regular one. It's that of the arrivals
+    // of a queue fed by this one.
+    pub fn interdeparture_scv(&self) -> f64 {
+        analysis::coefficient_of_variation(&self.interdeparture).powi(2)
+    }
+
+    // Results.occupancy_distribution returns the time-average distribution of the number of packets
+    // in the system, P(N = k).
+    pub fn occupancy_distribution(&self) -> Vec<f64> {
+        let total: u64 = self.occupancy.iter().sum();
+        self.occupancy.iter().map(|&t| t as f64 / total as f64).collect()
+    }
+
+    // Results.loss_probability returns the percentage of generated packets that were dropped.
+    pub fn loss_probability(&self) -> f64 {
+        f64::from(self.packets_dropped) / f64::from(self.packets_generated) * 100.0
+    }
+}
+
+// Retransmissions summarizes the retransmission of packets dropped over a run (see Retransmission).
+// Packets given up on are those counted as dropped in the results; the packets of the results
+// otherwise are the packets generated, however many times they were sent.
+#[derive(Clone, Copy, Debug)]
+pub struct Retransmissions {
+    // The number of retransmissions made, of packets given up on after as many as allowed, and of
+    // those still waiting out the timeout when the run ended.
+    pub retransmitted: u32,
+    pub abandoned: u32,
+    pub pending: usize,
+    // The sojourn times of the packets delivered after being retransmitted, counted from when they
+    // were first sent, in seconds.
+    pub recovered: OnlineStats,
+    // The rate packets were sent at, retransmissions included, and that distinct packets were
+    // delivered at, in packets/s.
+    pub throughput: f64,
+    pub goodput: f64,
+}
+
+// Blocking summarizes the time the client spent blocked over a run, on a full buffer under
+// backpressure (see Config.backpressure) or on acknowledgments with a window (see AckPath). Packets generated while it's blocked only arrive once
+// there's room for them, their sojourn times counted from then on.
+#[derive(Clone, Copy, Debug)]
+pub struct Blocking {
+    // The time the client spent blocked, in seconds, and as a proportion of the run.
+    pub time: f64,
+    pub proportion: f64,
+    // The time the packets that found the buffer full waited to get in, in seconds, and the number
+    // of packets still waiting when the run ended.
+    pub delay: OnlineStats,
+    pub pending: usize,
+}
+
+// RoundTrips summarizes the acknowledgment of packets delivered over a run (see AckPath). A round
+// trip is from when a packet was generated (first sent, if retransmitted) to when its ACK got back.
+#[derive(Clone, Debug)]
+pub struct RoundTrips {
+    // The round-trip times, in seconds, and the configured percentiles of them.
+    pub rtt: OnlineStats,
+    pub rtt_percentiles: Vec<(f64, f64)>,
+    // The number of ACKs on their way back when the run ended, and of packets sent and yet to be
+    // acknowledged (or given up on) then, those ACKs included.
+    pub in_flight: usize,
+    pub unacked: u32,
+}
+
+// Delivery summarizes how packets fared crossing the link to the receiver over a run (see
+// LinkPath), as the receiver sees them.
+#[derive(Clone, Copy, Debug)]
+pub struct Delivery {
+    // The distinct packets received, those lost on the link (as opposed to dropped by the server),
+    // the extra copies received of those duplicated, and the packets received after one generated
+    // later than them.
+    pub delivered: u32,
+    pub lost: u32,
+    pub duplicates: u32,
+    pub out_of_order: u32,
+    // How far behind the latest packet received thus far those out of order were, in packets
+    // generated in between, and the furthest.
+    pub depth: OnlineStats,
+    pub max_depth: u32,
+    // The time from generation to receipt, in seconds.
+    pub delay: OnlineStats,
+    // The copies of packets still crossing the link when the run ended.
+    pub in_flight: usize,
+}
+
+impl Delivery {
+    fn new() -> Delivery {
+        Delivery {
+            delivered: 0,
+            lost: 0,
+            duplicates: 0,
+            out_of_order: 0,
+            depth: OnlineStats::new(),
+            max_depth: 0,
+            delay: OnlineStats::new(),
+            in_flight: 0,
+        }
+    }
+}
+
+// Admission counts the packets the rate limiter ahead of the server admitted and rejected over a
+// run (see RateLimiting), retransmissions included.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Admission {
+    pub admitted: u32,
+    pub rejected: u32,
+}
+
+impl Admission {
+    // Admission.rejection_probability returns the percentage of packets rejected.
+    pub fn rejection_probability(&self) -> f64 {
+        f64::from(self.rejected) / f64::from((self.admitted + self.rejected).max(1)) * 100.0
+    }
+}
+
+// Preemptions counts the times service was preempted over a run, and the packets preempted that
+// were discarded (see Preemption).
+#[derive(Clone, Copy, Debug)]
+pub struct Preemptions {
+    pub count: u32,
+    pub discarded: u32,
+}
+
+// BusyPeriods summarizes the busy periods completed over the course of a run, their durations (in
+// seconds) and the number of packets served in each.
+#[derive(Clone, Debug)]
+pub struct BusyPeriods {
+    pub count: usize,
+    pub duration: OnlineStats,
+    pub duration_percentiles: Vec<(f64, f64)>,
+    pub packets: OnlineStats,
+    pub packets_percentiles: Vec<(f64, f64)>,
+}
+
+impl BusyPeriods {
+    fn new(periods: &[BusyPeriod], resolution: f64, percentiles: &[f64]) -> BusyPeriods {
+        let mut durations: Vec<f64> =
+            periods.iter().map(|p| f64::from(p.ticks) / resolution).collect();
+        let mut packets: Vec<f64> = periods.iter().map(|p| f64::from(p.packets)).collect();
+        let summary = |values: &mut Vec<f64>| {
+            let stats: OnlineStats = values.iter().cloned().collect();
+            values.sort_by(|a, b| a.total_cmp(b));
+            let percentiles = percentiles
+                .iter()
+                .map(|&p| (p, analysis::percentile(values, p)))
+                .collect();
+            (stats, percentiles)
+        };
+        let (duration, duration_percentiles) = summary(&mut durations);
+        let (packets, packets_percentiles) = summary(&mut packets);
+        BusyPeriods {
+            count: periods.len(),
+            duration,
+            duration_percentiles,
+            packets,
+            packets_percentiles,
+        }
+    }
+}
+
+// The relative error beyond which Little's Law is considered violated (see LittlesLaw).
+pub const LITTLE_TOLERANCE: f64 = 0.05;
+
+// LittlesLaw holds the quantities of Little's Law, L = λW, as measured independently: the
+// time-average number of packets in the system (queued or in service), the throughput in packets/s
+// and the average sojourn time in seconds. For a long enough run of a stable system the two sides
+// agree, a discrepancy points at an accounting bug (or a run too short to have settled).
+#[derive(Clone, Copy, Debug)]
+pub struct LittlesLaw {
+    pub l: f64,
+    pub lambda: f64,
+    pub w: f64,
+}
+
+impl LittlesLaw {
+    // LittlesLaw.relative_error returns |L - λW| / L.
+    pub fn relative_error(&self) -> f64 {
+        (self.l - self.lambda * self.w).abs() / self.l
+    }
+
+    // LittlesLaw.holds returns whether the relative error is within LITTLE_TOLERANCE.
+    pub fn holds(&self) -> bool {
+        self.relative_error() <= LITTLE_TOLERANCE
+    }
+}
+
+// Warmup describes the detected end of the initial transient, along with the data it was derived
+// from: the average queue length over each observation interval and its Welch moving average. The
+// average sojourn time of the packets departing in each interval (NaN if none did) is kept
+// alongside, for plotting.
+#[derive(Clone, Debug)]
+pub struct Warmup {
+    pub method: WarmupDetection,
+    // The truncation point and the length of an observation interval, in seconds.
+    pub truncation: f64,
+    pub interval: f64,
+    pub series: Vec<f64>,
+    pub moving_average: Vec<f64>,
+    pub sojourn_series: Vec<f64>,
+}
+
+impl Warmup {
+    fn detect(method: WarmupDetection, series: &[f64], sojourns: &[f64], interval: f64) -> Warmup {
+        let moving_average = analysis::moving_average(series, WELCH_WINDOW);
+        let truncation = match method {
+            WarmupDetection::Mser5 => analysis::mser(series, MSER_BATCH_SIZE),
+            WarmupDetection::Welch => analysis::welch_truncation(&moving_average, WELCH_TOLERANCE),
+        };
+        Warmup {
+            method,
+            truncation: truncation as f64 * interval,
+            interval,
+            series: series.to_vec(),
+            moving_average,
+            sojourn_series: sojourns.to_vec(),
+        }
+    }
+
+    // Warmup::averaged detects the end of the warm-up period from the series of several
+    // replications averaged together first, as Welch's procedure has it, or returns None if there
+    // are none. The replications are expected to share an observation interval.
+    pub fn averaged(warmups: &[Warmup]) -> Option<Warmup> {
+        let first = warmups.first()?;
+        let series: Vec<&[f64]> = warmups.iter().map(|w| &w.series[..]).collect();
+        let sojourns: Vec<&[f64]> = warmups.iter().map(|w| &w.sojourn_series[..]).collect();
+        Some(Warmup::detect(
+            first.method,
+            &analysis::welch_average(&series),
+            &analysis::welch_average(&sojourns),
+            first.interval,
+        ))
+    }
+}
+
+// Stretch is a run of consecutive ticks over which the queue length and the number of packets in
+// the system held steady. The statistics sampled every tick (the queue length and occupancy) are
+// accounted for a stretch at a time, once either changes, rather than tick by tick: the same
+// statistics, for a fraction of the work at fine resolutions, where most ticks change nothing.
+#[derive(Clone, Copy, Debug, Default)]
+struct Stretch {
+    qlen: usize,
+    occupancy: usize,
+    ticks: u64,
+}
+
+impl Stretch {
+    // Stretch.settle accounts for the stretch in the given statistics.
+    fn settle(
+        &self,
+        qlen: &mut OnlineStats,
+        occupancy: &mut Vec<u64>,
+        qlen_batches: &mut BatchMeans,
+        qlen_series: Option<&mut BatchMeans>,
+    ) {
+        if self.ticks == 0 {
+            return;
+        }
+        qlen.merge(analysis::repeated(self.qlen as f64, self.ticks));
+        if occupancy.len() <= self.occupancy {
+            occupancy.resize(self.occupancy + 1, 0);
+        }
+        occupancy[self.occupancy] += self.ticks;
+        qlen_batches.add_repeated(self.qlen as f64, self.ticks);
+        if let Some(series) = qlen_series {
+            series.add_repeated(self.qlen as f64, self.ticks);
+        }
+    }
+}
+
+// Simulation wires a client to a server and moves both forward in lockstep, one time unit at a
+// time, collecting statistics along the way.
+pub struct Simulation {
+    config: Config,
+    client: Client<Box<dyn Generator>>,
+    classifier: Classifier,
+    tenancy: Classifier,
+    lengths: Box<dyn Generator>,
+    server: Server,
+    now: Ticks,
+    // The tick statistics are collected from, i.e. the end of the warm-up period once it's over,
+    // and the number of packets generated before then.
+    start: Ticks,
+    generated_before: u32,
+    sojourn: OnlineStats,
+    sojourn_quantiles: Quantiles,
+    wait: OnlineStats,
+    wait_quantiles: Quantiles,
+    service: OnlineStats,
+    service_quantiles: Quantiles,
+    // The tick the packet currently in service entered service at, and under preemptive service or
+    // with several servers, the tick each packet in the server that's been in service first entered
+    // it at, by id.
+    service_start: Ticks,
+    first_start: HashMap<u32, Ticks>,
+    // The packets entering and leaving service within a tick, reused from one tick to the next.
+    started: Vec<Packet>,
+    departed: Vec<Packet>,
+    qlen: OnlineStats,
+    occupancy: Vec<u64>,
+    // The ticks the queue length and occupancy statistics have yet to account for.
+    stretch: Stretch,
+    sojourn_batches: BatchMeans,
+    wait_batches: BatchMeans,
+    qlen_batches: BatchMeans,
+    loss_batches: BatchMeans,
+    utilization_batches: BatchMeans,
+    qlen_series: BatchMeans,
+    // The average sojourn time over each observation interval closed, and the sojourn times of the
+    // packets departing within the current one, if detecting the end of the warm-up period.
+    sojourn_series: Vec<f64>,
+    sojourn_interval: OnlineStats,
+    samples: Vec<Sample>,
+    windows: Vec<Window>,
+    classes: Vec<Breakdown>,
+    // Only kept when packets are spread across several flows.
+    flows: HashMap<u32, Breakdown>,
+    tenants: Vec<Breakdown>,
+    colors: Vec<Breakdown>,
+    // The series of waiting and interdeparture times (in seconds), only kept if the autocorrelation
+    // function is to be computed.
+    waits: Vec<f64>,
+    interdepartures: Vec<f64>,
+    // The tick and sojourn time of the last departure.
+    last_departure: Option<(Ticks, f64)>,
+    interdeparture: OnlineStats,
+    interdeparture_quantiles: Quantiles,
+    poisson_test: PoissonTest,
+    jitter: f64,
+    delay_variation: OnlineStats,
+    delay_variation_quantiles: Quantiles,
+    // Pending changes, ordered by the tick they're to be applied at, latest first.
+    pending: Vec<(Ticks, Change)>,
+    paused: bool,
+    // Events that happened but have yet to be consumed, only recorded once
+    // Simulation.next_event is first called.
+    events: Option<VecDeque<Event>>,
+    collectors: Vec<Box<dyn StatsCollector>>,
+    // The layers the server's wrapped in, the first outermost, and the number of packets they've
+    // dropped before reaching the server.
+    layers: Vec<Box<dyn Layer>>,
+    layer_drops: u32,
+    // The tenants' quotas, if any, enforced outside of the layers, and the rate limiter ahead of
+    // them, if any, along with what it admitted.
+    quotas: Option<TenantQuotas>,
+    limiter: Option<RateLimiter>,
+    admission: Admission,
+    // The marker packets are colored by past the rate limiter, if any.
+    marker: Option<Marker>,
+    // Packets dropped that are to be retransmitted, in the order they're due, along with the tick
+    // they're due at and the number of times they've been retransmitted before; and the packets
+    // retransmitted that have yet to depart or be given up on, by id, with the number of times.
+    retransmits: VecDeque<(Ticks, u32, Packet)>,
+    in_flight: HashMap<u32, u32>,
+    retransmitted: u32,
+    abandoned: u32,
+    recovered: OnlineStats,
+    // The time the packets recovered spent awaiting retransmission, outside the system, in seconds.
+    awaited: f64,
+    // The packets the client's blocked on under backpressure, in the order they were generated,
+    // the time those let in waited, and the number of ticks the client had spent blocked by the
+    // end of the warm-up period.
+    blocked: VecDeque<Packet>,
+    blocking: OnlineStats,
+    blocked_before: u32,
+    // The reverse path ACKs take: the server they queue at, if any, and the link they cross; the
+    // number of packets sent and yet to be acknowledged or given up on; and the round-trip times of
+    // those acknowledged.
+    ack_server: Option<Server>,
+    ack_link: Link,
+    unacked: u32,
+    rtt: OnlineStats,
+    rtt_quantiles: Quantiles,
+    // The link to the receiver, if any; the packets it duplicated that have yet to arrive twice, by
+    // id, with whether they've arrived once; the latest packet received; and how they fared.
+    link: Option<Link>,
+    copies: HashMap<u32, bool>,
+    latest: Option<u32>,
+    delivery: Delivery,
+}
+
+impl Simulation {
+    pub fn new(config: Config) -> Simulation {
+        let server = ServerBuilder::new()
+            .resolution(config.resolution)
+            .pspeed(f64::from(config.pspeed))
+            .servers(config.servers)
+            .buffer_limit(config.qlimit)
+            .discipline(config.discipline.clone())
+            .preemption(config.preemption)
+            .build();
+        let mut sim = Simulation {
+            client: Client::new(config.arrivals(), config.resolution),
+            classifier: config.classifier(),
+            tenancy: config.tenancy(),
+            lengths: config.lengths(),
+            server,
+            now: Ticks::ZERO,
+            start: Ticks::ZERO,
+            generated_before: 0,
+            sojourn: OnlineStats::new(),
+            sojourn_quantiles: config.quantiles(),
+            wait: OnlineStats::new(),
+            wait_quantiles: config.quantiles(),
+            service: OnlineStats::new(),
+            service_quantiles: config.quantiles(),
+            service_start: Ticks::ZERO,
+            first_start: HashMap::new(),
+            started: vec![],
+            departed: vec![],
+            qlen: OnlineStats::new(),
+            occupancy: vec![],
+            stretch: Stretch::default(),
+            sojourn_batches: BatchMeans::new(),
+            wait_batches: BatchMeans::new(),
+            qlen_batches: BatchMeans::new(),
+            loss_batches: BatchMeans::new(),
+            utilization_batches: BatchMeans::new(),
+            qlen_series: BatchMeans::new(),
+            sojourn_series: vec![],
+            sojourn_interval: OnlineStats::new(),
+            samples: vec![],
+            windows: vec![],
+            classes: vec![Breakdown::new(); config.classes()],
+            flows: HashMap::new(),
+            tenants: vec![Breakdown::new(); config.tenant_count()],
+            colors: vec![Breakdown::new(); config.color_count()],
+            waits: vec![],
+            interdepartures: vec![],
+            last_departure: None,
+            interdeparture: OnlineStats::new(),
+            interdeparture_quantiles: config.quantiles(),
+            poisson_test: PoissonTest::new(),
+            jitter: 0.0,
+            delay_variation: OnlineStats::new(),
+            delay_variation_quantiles: config.quantiles(),
+            pending: vec![],
+            paused: false,
+            events: None,
+            collectors: vec![],
+            layers: vec![],
+            layer_drops: 0,
+            quotas: config.quotas(),
+            limiter: config.rate_limiter(),
+            admission: Admission::default(),
+            marker: config.marker(),
+            retransmits: VecDeque::new(),
+            in_flight: HashMap::new(),
+            retransmitted: 0,
+            abandoned: 0,
+            recovered: OnlineStats::new(),
+            awaited: 0.0,
+            blocked: VecDeque::new(),
+            blocking: OnlineStats::new(),
+            blocked_before: 0,
+            ack_server: config.ack.and_then(|a| a.pspeed).map(|pspeed| {
+                Server::new(config.resolution, f64::from(pspeed), None)
+            }),
+            ack_link: Link::new(config.ack_ticks().unwrap_or(Ticks::ZERO)),
+            unacked: 0,
+            rtt: OnlineStats::new(),
+            rtt_quantiles: config.quantiles(),
+            link: config.forward_link(),
+            copies: HashMap::new(),
+            latest: None,
+            delivery: Delivery::new(),
+            config: config.clone(),
+        };
+        for &(at, change) in &config.schedule {
+            sim.pend(at, change);
+        }
+        sim
+    }
+
+    // Simulation.tick moves the simulation forward by a single time unit, returning false if the
+    // configured duration has already elapsed or the simulation is paused. Changes scheduled for
+    // the current time are applied before anything else happens in the tick.
+    pub fn tick(&mut self) -> bool {
+        if self.paused || self.done() {
+            return false;
+        }
+        while self.pending.last().is_some_and(|&(at, _)| at <= self.now) {
+            let (_, change) = self.pending.pop().unwrap();
+            self.apply(change);
+            if self.paused {
+                return false;
+            }
+        }
+        if self.now > Ticks::ZERO && self.now == self.config.warmup_ticks() {
+            self.delete_warmup();
+        }
+
+        let now = self.now;
+        if self.config.sample_interval.is_some_and(|n| now.is_multiple_of(Ticks(n))) {
+            let sample = Sample {
+                time: now,
+                qlen: self.server.qlen(),
+                busy: !self.server.idle(),
+            };
+            self.sample(sample);
+        }
+        let n = self.server.qlen() + self.server.in_service().count();
+        self.hold(self.server.qlen(), n, 1);
+        while self.retransmits.front().is_some_and(|&(due, ..)| due <= now) {
+            let (_, retries, packet) = self.retransmits.pop_front().unwrap();
+            self.retransmitted += 1;
+            self.offer(packet, retries + 1);
+        }
+        // Packets the client's blocked on get in as there's room for them, as if arriving now; the
+        // client stays blocked for the rest of the tick if any are left.
+        while !self.blocked.is_empty() && !self.blocking() {
+            let mut packet = self.blocked.pop_front().unwrap();
+            self.blocking.add(now.since(packet.time_generated).seconds(self.config.resolution));
+            packet.time_generated = now;
+            self.record(|| Event::Arrival { time: now, packet: packet.clone() });
+            self.offer(packet, 0);
+        }
+        let first = self.client.packets_generated();
+        let generated = if self.blocked.is_empty() {
+            self.client.tick()
+        } else {
+            self.client.stall();
+            0
+        };
+        for id in first..first + generated {
+            let (class, flow) = self.classifier.classify();
+            let (tenant, _) = self.tenancy.classify();
+            let length = match self.config.trace {
+                Some(ref trace) => trace[id as usize].length,
+                None if self.config.service == Distribution::Deterministic => self.config.psize,
+                None => self.lengths.next_interval(1.0).round().max(1.0) as u32,
+            };
+            let packet = self.client.generate(Packet {
+                id,
+                time_generated: now,
+                length,
+                class,
+                flow,
+                tenant,
+                color: Color::Green,
+            });
+            if !self.blocked.is_empty() || self.blocking() {
+                self.blocked.push_back(packet);
+                continue;
+            }
+            self.record(|| Event::Arrival { time: now, packet: packet.clone() });
+            self.offer(packet, 0);
+        }
+        // The first packet departing may have entered service in an earlier tick; any others
+        // entered service in this one (with a single server; with several, the tick each entered
+        // service at is kept track of by id).
+        let carried = self.server.in_service().next().is_some();
+        let carried_start = self.service_start;
+        let (mut started, mut departed) = (take(&mut self.started), take(&mut self.departed));
+        let busy = self.server.statistics.process_count;
+        let (layers, server) = (&mut self.layers, &mut self.server);
+        match self.quotas {
+            Some(ref mut quotas) => {
+                quotas.tick(&mut departed, &mut |d| middleware::tick(layers, server, d))
+            }
+            None => middleware::tick(layers, server, &mut departed),
+        }
+        let busy = (self.server.statistics.process_count - busy) as f64;
+        self.utilization_batches.add(busy / self.config.servers as f64);
+        if !departed.is_empty() {
+            if let Some(w) = self.window() {
+                w.departures += departed.len() as u32;
+            }
+        }
+        started.extend(self.server.started().cloned());
+        for p in started.drain(..) {
+            self.service_start = now;
+            // A packet preempted only waited until it first entered service.
+            let resumed = self.first_start.contains_key(&p.id);
+            if (self.config.preemption.is_some() || self.config.servers > 1) && !resumed {
+                self.first_start.insert(p.id, now);
+            }
+            if !resumed {
+                let wait = now.since(p.time_generated).seconds(self.config.resolution);
+                self.wait.add(wait);
+                self.wait_quantiles.record(wait);
+                self.wait_batches.add(wait);
+                if self.config.acf_lags.is_some() {
+                    self.waits.push(wait);
+                }
+                let threshold = self.config.wait_threshold;
+                self.breakdown(&p, |b| {
+                    b.wait.add(wait);
+                    b.waited(wait, threshold);
+                });
+            }
+            self.record(|| Event::ServiceStart { time: now, packet: p });
+        }
+        for (i, p) in departed.drain(..).enumerate() {
+            // We record the time it took for the processed packet to get processed.
+            let sojourn = now.since(p.time_generated).seconds(self.config.resolution);
+            self.sojourn.add(sojourn);
+            self.sojourn_quantiles.record(sojourn);
+            self.sojourn_batches.add(sojourn);
+            if self.config.warmup_detection.is_some() {
+                self.sojourn_interval.add(sojourn);
+            }
+            self.breakdown(&p, |b| {
+                b.packets_processed += 1;
+                b.sojourn.add(sojourn);
+            });
+            if let Some(retries) = self.in_flight.remove(&p.id) {
+                let timeout = self.config.retransmission_ticks().unwrap_or(Ticks::ZERO);
+                self.recovered.add(sojourn);
+                self.awaited += f64::from(retries) * timeout.seconds(self.config.resolution);
+            }
+            let start = match self.first_start.remove(&p.id) {
+                Some(start) => start,
+                None if i == 0 && carried => carried_start,
+                None => now,
+            };
+            let service = now.since(start).seconds(self.config.resolution);
+            self.service.add(service);
+            self.service_quantiles.record(service);
+            if let Some((last, last_sojourn)) = self.last_departure {
+                let interdeparture = now.since(last).seconds(self.config.resolution);
+                self.interdeparture.add(interdeparture);
+                self.interdeparture_quantiles.record(interdeparture);
+                if self.config.poisson_test {
+                    self.poisson_test.add(now.since(last).0);
+                }
+                if self.config.acf_lags.is_some() {
+                    self.interdepartures.push(interdeparture);
+                }
+                let variation = (sojourn - last_sojourn).abs();
+                self.delay_variation.add(variation);
+                self.delay_variation_quantiles.record(variation);
+                self.jitter += (variation - self.jitter) / 16.0;
+            }
+            self.last_departure = Some((now, sojourn));
+            match self.link {
+                Some(ref mut link) => match link.send(now, p.clone()) {
+                    0 => {
+                        // Nor will a packet lost ever be acknowledged.
+                        self.delivery.lost += 1;
+                        self.unacked -= 1;
+                    }
+                    1 => {}
+                    _ => {
+                        self.copies.insert(p.id, false);
+                    }
+                },
+                None => self.acknowledge(&p),
+            }
+            self.record(|| Event::Departure { time: now, packet: p });
+        }
+        self.started = started;
+        self.departed = departed;
+        let discarded: Vec<Packet> = self.server.discarded().cloned().collect();
+        for p in discarded {
+            self.discard(p);
+        }
+        while let Some(p) = self.link.as_mut().and_then(|l| l.receive(now)) {
+            self.receive(p);
+        }
+        if let Some(ref mut server) = self.ack_server {
+            for ack in server.tick() {
+                self.ack_link.send(now, ack);
+            }
+        }
+        while let Some(ack) = self.ack_link.receive(now) {
+            let rtt = now.since(ack.time_generated).seconds(self.config.resolution);
+            self.rtt.add(rtt);
+            self.rtt_quantiles.record(rtt);
+            self.unacked -= 1;
+        }
+        self.now += Ticks(1);
+
+        if let Some(n) = self.config.batch_ticks() {
+            if self.now.since(self.start).is_multiple_of(n) {
+                self.settle();
+                self.sojourn_batches.close();
+                self.wait_batches.close();
+                self.qlen_batches.close();
+                self.loss_batches.close();
+                self.utilization_batches.close();
+            }
+        }
+        if self.now.is_multiple_of(self.config.observation_ticks()) {
+            self.settle();
+            self.qlen_series.close();
+            if self.config.warmup_detection.is_some() {
+                let i = take(&mut self.sojourn_interval);
+                self.sojourn_series.push(if i.len() > 0 { i.mean() } else { f64::NAN });
+            }
+        }
+        true
+    }
+
+    // discard accounts for a packet discarded on being preempted, dropped after all: it's never
+    // retransmitted, nor acknowledged.
+    fn discard(&mut self, packet: Packet) {
+        let now = self.now;
+        self.first_start.remove(&packet.id);
+        self.unacked -= 1;
+        if self.config.retransmission.is_some() {
+            self.in_flight.remove(&packet.id);
+            self.abandoned += 1;
+        }
+        if let Some(w) = self.window() {
+            w.drops += 1;
+        }
+        self.breakdown(&packet, |b| b.packets_dropped += 1);
+        let reason = DropReason::Preempted;
+        self.record(|| Event::Drop { time: now, packet, reason });
+    }
+
+    // offer offers the packet to the server, through the layers, as it's generated or retransmitted
+    // (having been retransmitted the given number of times). Dropped, it's retransmitted once the
+    // timeout is up if allowed, and only counted as dropped if not.
+    fn offer(&mut self, mut packet: Packet, retries: u32) {
+        let now = self.now;
+        self.unacked += u32::from(retries == 0);
+        let dropped = self.server.packets_dropped();
+        let over_quota = self.quotas.as_ref().map_or(0, |q| q.dropped.iter().sum::<u32>());
+        let admitted = match self.limiter {
+            Some(ref mut limiter) => limiter.admit(now),
+            None => true,
+        };
+        let rejected = !admitted;
+        if admitted {
+            self.admission.admitted += 1;
+        } else {
+            self.admission.rejected += 1;
+        }
+        // Packets admitted are colored, and those of a color over its limit dropped; the ones
+        // rejected are left green.
+        if let (true, Some(marker)) = (admitted, self.marker.as_mut()) {
+            packet.color = marker.mark(now, packet.length);
+        }
+        let limit = self.config.marking.and_then(|m| m.limit(packet.color));
+        let colored_out = admitted && limit.is_some_and(|n| self.server.qlen() >= n);
+        let admitted = admitted && !colored_out;
+        let (layers, server) = (&mut self.layers, &mut self.server);
+        let enqueued = admitted &&
+            match self.quotas {
+                Some(ref mut quotas) => {
+                    quotas.enqueue(packet.clone(), &mut |p| middleware::enqueue(layers, server, p))
+                }
+                None => middleware::enqueue(layers, server, packet.clone()),
+            };
+        if !enqueued && self.server.packets_dropped() == dropped {
+            self.layer_drops += 1;
+        }
+        let reason = if rejected {
+            DropReason::RateLimited
+        } else if colored_out {
+            DropReason::Color
+        } else if self.server.packets_dropped() > dropped {
+            DropReason::BufferFull
+        } else if self.quotas.as_ref().map_or(0, |q| q.dropped.iter().sum::<u32>()) > over_quota {
+            DropReason::Quota
+        } else {
+            DropReason::Layer
+        };
+        let retransmit = match self.config.retransmission {
+            Some(r) if !enqueued && retries < r.retries => self.config.retransmission_ticks(),
+            _ => None,
+        };
+        let lost = !enqueued && retransmit.is_none();
+        if lost && self.config.retransmission.is_some() {
+            self.abandoned += 1;
+        }
+        // Nor will a packet given up on ever be acknowledged.
+        self.unacked -= u32::from(lost);
+        // A packet's fate is only settled once it's queued or given up on.
+        if enqueued || lost {
+            self.loss_batches.add(if enqueued { 0.0 } else { 100.0 });
+        }
+        if let Some(w) = self.window() {
+            w.arrivals += u32::from(retries == 0);
+            w.drops += u32::from(lost);
+        }
+        self.breakdown(&packet, |b| {
+            b.packets_generated += u32::from(retries == 0);
+            b.packets_dropped += u32::from(lost);
+        });
+        if let Some(timeout) = retransmit {
+            self.in_flight.insert(packet.id, retries + 1);
+            self.retransmits.push_back((now + timeout, retries, packet.clone()));
+        } else if lost && retries > 0 {
+            self.in_flight.remove(&packet.id);
+        }
+        // Packets are only dropped once given up on, not on every attempt that'll be retried.
+        if enqueued {
+            self.record(|| Event::Enqueue { time: now, packet });
+        } else if lost {
+            self.record(|| Event::Drop { time: now, packet, reason });
+        }
+    }
+
+    // receive takes delivery of a packet off the link to the receiver, only the first copy of a
+    // packet duplicated counting (and being acknowledged).
+    fn receive(&mut self, p: Packet) {
+        match self.copies.get(&p.id) {
+            Some(&true) => {
+                self.copies.remove(&p.id);
+                self.delivery.duplicates += 1;
+                return;
+            }
+            Some(&false) => {
+                self.copies.insert(p.id, true);
+            }
+            None => {}
+        }
+        let d = &mut self.delivery;
+        d.delivered += 1;
+        d.delay.add(self.now.since(p.time_generated).seconds(self.config.resolution));
+        match self.latest {
+            Some(latest) if p.id < latest => {
+                d.out_of_order += 1;
+                d.depth.add(f64::from(latest - p.id));
+                d.max_depth = d.max_depth.max(latest - p.id);
+            }
+            _ => self.latest = Some(p.id),
+        }
+        self.acknowledge(&p);
+    }
+
+    // acknowledge sends the packet's ACK back to the client over the reverse path, if packets are
+    // acknowledged.
+    fn acknowledge(&mut self, p: &Packet) {
+        if let Some(a) = self.config.ack {
+            let ack = Packet { length: a.length, ..p.clone() };
+            match self.ack_server {
+                Some(ref mut server) => {
+                    server.enqueue(ack);
+                }
+                None => {
+                    self.ack_link.send(self.now, ack);
+                }
+            }
+        }
+    }
+
+    // blocking returns whether the client's to be blocked: under backpressure while the buffer's
+    // full, and with a window while as many packets are unacknowledged.
+    fn blocking(&self) -> bool {
+        self.config.backpressure && self.server.full() ||
+            self.config.window().is_some_and(|w| self.unacked >= w)
+    }
+
+    // Simulation.fast_forward skips over the upcoming ticks in which nothing happens, the server
+    // being idle while the client has yet to generate the next packet. Statistics are accounted
+    // for as if the ticks had been simulated one by one, and we never skip past a scheduled
+    // change, the end of the warm-up period or of the simulation, or the last tick of a batch or
+    // observation interval (which are simulated regularly so as to close them out). No events are
+    // generated for the skipped ticks. Nor do we skip anything with the server wrapped in layers,
+    // which may be holding on to packets of their own or acting on every tick.
+    fn fast_forward(&mut self) {
+        if self.paused || self.done() || !self.server.idle() || !self.layers.is_empty() ||
+           !self.blocked.is_empty() || self.ack_server.as_ref().is_some_and(|s| !s.idle()) {
+            return;
+        }
+        let mut n = ::std::cmp::min(
+            self.client.idle_ticks(),
+            self.config.ticks().saturating_sub(self.now),
+        );
+        if let Some(&(at, _)) = self.pending.last() {
+            n = n.min(at.saturating_sub(self.now));
+        }
+        if let Some(&(due, ..)) = self.retransmits.front() {
+            n = n.min(due.saturating_sub(self.now));
+        }
+        let links = self.link.iter().chain(Some(&self.ack_link));
+        if let Some(at) = links.filter_map(|l| l.next_arrival()).min() {
+            n = n.min(at.saturating_sub(self.now));
+        }
+        let warmup = self.config.warmup_ticks();
+        if warmup >= self.now {
+            n = n.min(warmup - self.now);
+        }
+        if let Some(b) = self.config.batch_ticks() {
+            n = n.min(b - self.now.since(self.start) % b - Ticks(1));
+        }
+        let o = self.config.observation_ticks();
+        n = n.min(o - self.now % o - Ticks(1));
+        if n == Ticks::ZERO {
+            return;
+        }
+
+        if let Some(interval) = self.config.sample_interval {
+            let first = self.now.0.div_ceil(interval) * interval;
+            for time in (first..(self.now + n).0).step_by(interval as usize) {
+                self.sample(Sample {
+                    time: Ticks(time),
+                    qlen: 0,
+                    busy: false,
+                });
+            }
+        }
+        self.client.skip(n);
+        self.server.skip_idle(n);
+        if let Some(ref mut quotas) = self.quotas {
+            quotas.skip(n.0);
+        }
+        self.hold(0, 0, u64::from(n));
+        self.utilization_batches.add_repeated(0.0, u64::from(n));
+        self.now += n;
+    }
+
+    // delete_warmup discards the statistics collected over the warm-up period, collecting them
+    // afresh from the current tick. The time series (the samples, the windows and the queue length
+    // series warm-up detection works off of) span the whole run regardless, and the registered
+    // collectors see every event.
+    fn delete_warmup(&mut self) {
+        logging::log(&Record {
+            level: Level::Info,
+            component: "simulation",
+            time: Some(self.now),
+            packet: None,
+            message: format_args!("warm-up period over, collecting statistics afresh"),
+        });
+        // The queue length series spans the whole run, the warm-up period included.
+        self.settle();
+        self.start = self.now;
+        self.generated_before = self.client.packets_generated();
+        self.server.reset_statistics();
+        self.layer_drops = 0;
+        self.retransmitted = 0;
+        self.abandoned = 0;
+        self.recovered = OnlineStats::new();
+        self.awaited = 0.0;
+        self.blocking = OnlineStats::new();
+        self.blocked_before = self.client.statistics.ticks_blocked;
+        self.rtt = OnlineStats::new();
+        self.rtt_quantiles = self.config.quantiles();
+        self.delivery = Delivery::new();
+        self.admission = Admission::default();
+        self.sojourn = OnlineStats::new();
+        self.sojourn_quantiles = self.config.quantiles();
+        self.wait = OnlineStats::new();
+        self.wait_quantiles = self.config.quantiles();
+        self.service = OnlineStats::new();
+        self.service_quantiles = self.config.quantiles();
+        self.qlen = OnlineStats::new();
+        self.occupancy.clear();
+        self.sojourn_batches = BatchMeans::new();
+        self.wait_batches = BatchMeans::new();
+        self.qlen_batches = BatchMeans::new();
+        self.loss_batches = BatchMeans::new();
+        self.utilization_batches = BatchMeans::new();
+        self.classes = vec![Breakdown::new(); self.config.classes()];
+        self.flows.clear();
+        self.tenants = vec![Breakdown::new(); self.config.tenant_count()];
+        self.colors = vec![Breakdown::new(); self.config.color_count()];
+        self.waits.clear();
+        self.interdepartures.clear();
+        self.interdeparture = OnlineStats::new();
+        self.interdeparture_quantiles = self.config.quantiles();
+        self.poisson_test = PoissonTest::new();
+        self.delay_variation = OnlineStats::new();
+        self.delay_variation_quantiles = self.config.quantiles();
+    }
+
+    // Simulation.run_until moves the simulation forward until the predicate, checked after every
+    // tick, holds. It returns false if the simulation ran to completion first.
+    pub fn run_until<F: FnMut(&Simulation) -> bool>(&mut self, mut predicate: F) -> bool {
+        while self.tick() {
+            if predicate(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    // all_flows returns the statistics of every flow, in order, including those that saw no
+    // packets (if packets are spread across several flows).
+    fn all_flows(&self) -> Vec<(u32, Breakdown)> {
+        if self.config.flows <= 1 {
+            return vec![];
+        }
+        (0..self.config.flows)
+            .map(|f| (f, self.flows.get(&f).copied().unwrap_or_else(Breakdown::new)))
+            .collect()
+    }
+
+    // pending returns the breakdown with the packets still queued that it includes accounted for as
+    // having waited as long as they have so far, bar those preempted (whose wait is over).
+    fn pending<F: Fn(&Packet) -> bool>(&self, mut b: Breakdown, includes: F) -> Breakdown {
+        for p in self.server.queue().filter(|p| includes(p)) {
+            if !self.first_start.contains_key(&p.id) {
+                let wait = self.now.since(p.time_generated).seconds(self.config.resolution);
+                b.waited(wait, self.config.wait_threshold);
+            }
+        }
+        b
+    }
+
+    // fairness returns Jain's fairness index over the throughputs of the given classes or flows.
+    // Their packets are all processed over the same time, so the counts stand in for the rates.
+    fn fairness<'a, I: Iterator<Item = &'a Breakdown>>(breakdowns: I) -> f64 {
+        let throughputs: Vec<f64> = breakdowns.map(|b| f64::from(b.packets_processed)).collect();
+        analysis::jain_fairness(&throughputs)
+    }
+
+    // class and tenant return the class and tenant the packet's accounted for under. Hooks are free
+    // to change either (see Simulation.client_mut), so packets of a class or tenant past the last
+    // configured are accounted for under the last.
+    fn class(&self, packet: &Packet) -> usize {
+        (packet.class as usize).min(self.classes.len() - 1)
+    }
+
+    fn tenant(&self, packet: &Packet) -> usize {
+        (packet.tenant as usize).min(self.tenants.len() - 1)
+    }
+
+    // breakdown applies the update to the statistics kept for the packet's class, flow, tenant and
+    // color.
+    fn breakdown<F: Fn(&mut Breakdown)>(&mut self, packet: &Packet, update: F) {
+        let (class, tenant) = (self.class(packet), self.tenant(packet));
+        update(&mut self.classes[class]);
+        update(&mut self.tenants[tenant]);
+        if let Some(b) = self.colors.get_mut(packet.color as usize) {
+            update(b);
+        }
+        if self.config.flows > 1 {
+            update(self.flows.entry(packet.flow).or_insert_with(Breakdown::new));
+        }
+    }
+
+    // window returns the window the current tick falls in, if counting over windows, filling in
+    // any skipped over.
+    fn window(&mut self) -> Option<&mut Window> {
+        let length = self.config.window?;
+        Simulation::fill_windows(&mut self.windows, length, self.now.0 / length + 1);
+        self.windows.last_mut()
+    }
+
+    fn fill_windows(windows: &mut Vec<Window>, length: u32, n: u32) {
+        while (windows.len() as u32) < n {
+            let start = windows.len() as u32 * length;
+            windows.push(Window {
+                start: Ticks(start),
+                arrivals: 0,
+                departures: 0,
+                drops: 0,
+            });
+        }
+    }
+
+    // record passes the event on to the registered collectors, and buffers it if events are being
+    // consumed. The event is only constructed if needed.
+    fn record<F: FnOnce() -> Event>(&mut self, event: F) {
+        let logged = logging::enabled("client", Level::Trace) ||
+            logging::enabled("server", Level::Debug);
+        if self.events.is_none() && self.collectors.is_empty() && !logged {
+            return;
+        }
+        let event = event();
+        if logged {
+            log(&event, self.server.qlen());
+        }
+        for c in &mut self.collectors {
+            c.on_event(&event);
+        }
+        if let Some(ref mut events) = self.events {
+            events.push_back(event);
+        }
+    }
+
+    // hold accounts for the given number of ticks spent with the given number of packets queued,
+    // and in the system overall, extending the current stretch if they're unchanged.
+    fn hold(&mut self, qlen: usize, occupancy: usize, ticks: u64) {
+        if self.stretch.qlen != qlen || self.stretch.occupancy != occupancy {
+            self.settle();
+            self.stretch.qlen = qlen;
+            self.stretch.occupancy = occupancy;
+        }
+        self.stretch.ticks += ticks;
+    }
+
+    // settle accounts for the current stretch, starting afresh.
+    fn settle(&mut self) {
+        let series = if self.config.warmup_detection.is_some() {
+            Some(&mut self.qlen_series)
+        } else {
+            None
+        };
+        self.stretch.settle(&mut self.qlen, &mut self.occupancy, &mut self.qlen_batches, series);
+        self.stretch.ticks = 0;
+    }
+
+    fn sample(&mut self, sample: Sample) {
+        for c in &mut self.collectors {
+            c.on_sample(&sample);
+        }
+        self.samples.push(sample);
+    }
+
+    // Simulation.add_collector registers a collector, to be notified of every event (and sample)
+    // from then on.
+    pub fn add_collector(&mut self, collector: Box<dyn StatsCollector>) {
+        self.collectors.push(collector);
+    }
+
+    // Simulation.add_layer wraps the server in another layer (see middleware::Layer), within those
+    // added before it.
+    pub fn add_layer(&mut self, layer: Box<dyn Layer>) {
+        self.layers.push(layer);
+    }
+
+    // Simulation.next_event returns the next event, moving the simulation forward as needed. It
+    // returns None once the simulation completes (or is paused) with no events left to consume.
+    pub fn next_event(&mut self) -> Option<Event> {
+        if self.events.is_none() {
+            self.events = Some(VecDeque::new());
+        }
+        loop {
+            if let Some(event) = self.events.as_mut().and_then(|e| e.pop_front()) {
+                return Some(event);
+            }
+            if !self.tick() {
+                return None;
+            }
+        }
+    }
+
+    // Simulation.events returns an iterator over the events of the simulation (see
+    // Simulation.next_event).
+    pub fn events(&mut self) -> Events<'_> {
+        Events { sim: self }
+    }
+
+    // Simulation.advance runs the simulation until it completes or is paused, returning whether it
+    // was paused.
+    pub fn advance(&mut self) -> bool {
+        loop {
+            self.fast_forward();
+            if !self.tick() {
+                break;
+            }
+        }
+        self.paused
+    }
+
+    // Simulation.pause pauses the simulation; Simulation.tick is a no-op until it's resumed.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    // Simulation.schedule schedules a change to be applied once the simulation reaches the given
+    // time (in seconds), provided it's valid (see Change.validate). Changes scheduled in the past
+    // are applied at the next tick, and changes scheduled for the same time are applied in the
+    // order they were scheduled in.
+    pub fn schedule(&mut self, at: f64, change: Change) -> Result<(), QsimError> {
+        if !at.is_finite() {
+            return Err(QsimError::invalid("change time", at, "it must be finite"));
+        }
+        change.validate()?;
+        self.pend(at, change);
+        Ok(())
+    }
+
+    fn pend(&mut self, at: f64, change: Change) {
+        let at = Ticks::from_seconds(at, self.config.resolution);
+        let i = self.pending.iter().position(|&(t, _)| t <= at).unwrap_or(self.pending.len());
+        self.pending.insert(i, (at, change));
+    }
+
+    // apply applies the change, unless it's invalid: changes are checked on being scheduled, bar
+    // those of a configuration that wasn't validated, which are skipped.
+    fn apply(&mut self, change: Change) {
+        if let Err(err) = change.validate() {
+            logging::log(&Record {
+                level: Level::Warn,
+                component: "simulation",
+                time: Some(self.now),
+                packet: None,
+                message: format_args!("skipping {:?}: {}", change, err),
+            });
+            return;
+        }
+        logging::log(&Record {
+            level: Level::Info,
+            component: "simulation",
+            time: Some(self.now),
+            packet: None,
+            message: format_args!("applying {:?}", change),
+        });
+        match change {
+            Change::Rate(rate) => self.set_rate(rate).expect("validated change"),
+            Change::Pspeed(pspeed) => self.set_pspeed(pspeed).expect("validated change"),
+            Change::Qlimit(qlimit) => self.set_qlimit(qlimit),
+            Change::Pause => self.pause(),
+        }
+    }
+
+    // Simulation.now returns the current simulated time, in ticks.
+    pub fn now(&self) -> Ticks {
+        self.now
+    }
+
+    // Simulation.done returns whether the configured duration has elapsed, or as many packets as
+    // configured have departed since the warm-up period.
+    pub fn done(&self) -> bool {
+        self.now >= self.config.ticks() ||
+            self.config.packets.is_some_and(|n| self.server.packets_processed() >= n)
+    }
+
+    // Simulation.progress returns the proportion of the run completed, going by the time elapsed or
+    // the packets departed, whichever is further along.
+    pub fn progress(&self) -> f64 {
+        let time = f64::from(self.now) / f64::from(self.config.ticks().max(Ticks(1)));
+        let packets = match self.config.packets {
+            Some(n) => f64::from(self.server.packets_processed()) / f64::from(n.max(1)),
+            None => 0.0,
+        };
+        time.max(packets).min(1.0)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+
+    // Simulation.client_mut and Simulation.server_mut return the simulation's client and server,
+    // for registering hooks with (see Client.on_generate and Server.on_enqueue). Packets fed to
+    // the server directly go unaccounted for in the results, and those a hook moves to a class or
+    // tenant past the last configured are accounted for under the last.
+    pub fn client_mut(&mut self) -> &mut Client<Box<dyn Generator>> {
+        &mut self.client
+    }
+
+    pub fn server_mut(&mut self) -> &mut Server {
+        &mut self.server
+    }
+
+    // Simulation.packets_generated returns the number of packets generated thus far, without the
+    // cost of putting together the full results.
+    pub fn packets_generated(&self) -> u32 {
+        self.client.packets_generated()
+    }
+
+    // Simulation.set_rate changes the arrival rate (packets/s) from the next arrival onwards,
+    // provided it's positive.
+    pub fn set_rate(&mut self, rate: u32) -> Result<(), QsimError> {
+        Change::Rate(rate).validate()?;
+        self.config.rate = rate;
+        self.client.set_rate(f64::from(rate));
+        Ok(())
+    }
+
+    // Simulation.set_pspeed changes the server's processing speed (bits/s), provided it's
+    // positive.
+    pub fn set_pspeed(&mut self, pspeed: u32) -> Result<(), QsimError> {
+        Change::Pspeed(pspeed).validate()?;
+        self.config.pspeed = pspeed;
+        self.server.set_pspeed(f64::from(pspeed));
+        Ok(())
+    }
+
+    // Simulation.set_qlimit changes the server's buffer limit.
+    pub fn set_qlimit(&mut self, qlimit: Option<usize>) {
+        self.config.qlimit = qlimit;
+        self.server.set_buffer_limit(qlimit);
+    }
+
+    // Simulation.snapshot returns a copy of the simulation as it stands, so that a run can be
+    // branched off into several futures from the exact same state: the queue, the packet in
+    // service, the statistics collected thus far, the changes scheduled and the state of the random
+    // number generators all carry over, so that a snapshot left alone runs just as the original
+    // would have. The collectors, layers and hooks registered aren't carried over, it's up to the
+    // caller to register them anew.
+    pub fn snapshot(&self) -> Simulation {
+        Simulation {
+            config: self.config.clone(),
+            client: self.client.clone(),
+            classifier: self.classifier.clone(),
+            tenancy: self.tenancy.clone(),
+            lengths: self.lengths.clone(),
+            server: self.server.clone(),
+            now: self.now,
+            start: self.start,
+            generated_before: self.generated_before,
+            sojourn: self.sojourn,
+            sojourn_quantiles: self.sojourn_quantiles.clone(),
+            wait: self.wait,
+            wait_quantiles: self.wait_quantiles.clone(),
+            service: self.service,
+            service_quantiles: self.service_quantiles.clone(),
+            service_start: self.service_start,
+            first_start: self.first_start.clone(),
+            started: vec![],
+            departed: vec![],
+            qlen: self.qlen,
+            occupancy: self.occupancy.clone(),
+            stretch: self.stretch,
+            sojourn_batches: self.sojourn_batches.clone(),
+            wait_batches: self.wait_batches.clone(),
+            qlen_batches: self.qlen_batches.clone(),
+            loss_batches: self.loss_batches.clone(),
+            utilization_batches: self.utilization_batches.clone(),
+            qlen_series: self.qlen_series.clone(),
+            sojourn_series: self.sojourn_series.clone(),
+            sojourn_interval: self.sojourn_interval,
+            samples: self.samples.clone(),
+            windows: self.windows.clone(),
+            classes: self.classes.clone(),
+            flows: self.flows.clone(),
+            tenants: self.tenants.clone(),
+            colors: self.colors.clone(),
+            waits: self.waits.clone(),
+            interdepartures: self.interdepartures.clone(),
+            last_departure: self.last_departure,
+            interdeparture: self.interdeparture,
+            interdeparture_quantiles: self.interdeparture_quantiles.clone(),
+            poisson_test: self.poisson_test.clone(),
+            jitter: self.jitter,
+            delay_variation: self.delay_variation,
+            delay_variation_quantiles: self.delay_variation_quantiles.clone(),
+            pending: self.pending.clone(),
+            paused: self.paused,
+            events: self.events.clone(),
+            collectors: vec![],
+            layers: vec![],
+            layer_drops: self.layer_drops,
+            quotas: self.quotas.clone(),
+            limiter: self.limiter.clone(),
+            admission: self.admission,
+            marker: self.marker.clone(),
+            retransmits: self.retransmits.clone(),
+            in_flight: self.in_flight.clone(),
+            retransmitted: self.retransmitted,
+            abandoned: self.abandoned,
+            recovered: self.recovered,
+            awaited: self.awaited,
+            blocked: self.blocked.clone(),
+            blocking: self.blocking,
+            blocked_before: self.blocked_before,
+            ack_server: self.ack_server.clone(),
+            ack_link: self.ack_link.clone(),
+            unacked: self.unacked,
+            rtt: self.rtt,
+            rtt_quantiles: self.rtt_quantiles.clone(),
+            link: self.link.clone(),
+            copies: self.copies.clone(),
+            latest: self.latest,
+            delivery: self.delivery,
+        }
+    }
+
+    // Simulation.run runs the simulation to completion and returns the collected results. Unlike
+    // when ticking through the simulation manually, idle periods are fast-forwarded (see
+    // Simulation.fast_forward). Should the simulation be paused, it's resumed.
+    pub fn run(mut self) -> Results {
+        while self.advance() {
+            self.resume();
+        }
+        self.results()
+    }
+
+    fn percentiles(&self, quantiles: &Quantiles) -> Vec<(f64, f64)> {
+        self.config
+            .percentiles
+            .iter()
+            .map(|&p| (p, quantiles.percentile(p)))
+            .collect()
+    }
+
+    // acf returns the autocorrelation function of the series, up to the lag asked for or the last
+    // the series is long enough for, whichever's lower.
+    fn acf(&self, series: &[f64]) -> Vec<f64> {
+        let lags = self.config.acf_lags.unwrap_or(0).min(series.len().saturating_sub(1));
+        (1..lags + 1).map(|lag| analysis::autocorrelation(series, lag)).collect()
+    }
+
+    // Simulation.results returns the statistics collected thus far.
+    pub fn results(&self) -> Results {
+        let (mut qlen, mut occupancy) = (self.qlen, self.occupancy.clone());
+        let mut qlen_batches = self.qlen_batches.clone();
+        self.stretch.settle(&mut qlen, &mut occupancy, &mut qlen_batches, None);
+        let generated = self.client.packets_generated() - self.generated_before;
+        let leftover = self.server.qlen();
+        let unbounded = self.config.qlimit.is_none() &&
+            leftover as f64 >= UNBOUNDED_SHARE * f64::from(generated) &&
+            leftover as f64 > 1.5 * qlen.mean();
+        Results {
+            sojourn: self.sojourn,
+            sojourn_histogram: self.sojourn_quantiles.histogram().cloned(),
+            sojourn_percentiles: self.percentiles(&self.sojourn_quantiles),
+            wait: self.wait,
+            wait_histogram: self.wait_quantiles.histogram().cloned(),
+            wait_percentiles: self.percentiles(&self.wait_quantiles),
+            service: self.service,
+            service_histogram: self.service_quantiles.histogram().cloned(),
+            service_percentiles: self.percentiles(&self.service_quantiles),
+            qlen,
+            occupancy,
+            packets_generated: generated,
+            packets_processed: self.server.packets_processed(),
+            packets_dropped: match self.config.retransmission {
+                Some(_) => self.abandoned,
+                None => self.server.packets_dropped() + self.layer_drops,
+            },
+            elapsed: self.now.since(self.start).seconds(self.config.resolution),
+            idle_proportion: self.server.idle_proportion(),
+            utilization: self.server.utilization(),
+            busy_periods: BusyPeriods::new(
+                &self.server.statistics.busy_periods,
+                self.config.resolution,
+                &self.config.percentiles,
+            ),
+            leftover,
+            unbounded,
+            sojourn_batches: self.sojourn_batches.summary(CONFIDENCE),
+            wait_batches: self.wait_batches.summary(CONFIDENCE),
+            qlen_batches: qlen_batches.summary(CONFIDENCE),
+            loss_batches: self.loss_batches.summary(CONFIDENCE),
+            utilization_batches: self.utilization_batches.summary(CONFIDENCE),
+            warmup: self.config.warmup_detection.map(|method| {
+                let interval = f64::from(self.config.observation_ticks()) / self.config.resolution;
+                Warmup::detect(method, self.qlen_series.means(), &self.sojourn_series, interval)
+            }),
+            samples: self.samples.clone(),
+            windows: match self.config.window {
+                Some(length) => {
+                    let mut windows = self.windows.clone();
+                    Simulation::fill_windows(&mut windows, length, self.now.0.div_ceil(length));
+                    windows
+                }
+                None => vec![],
+            },
+            classes: self
+                .classes
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| self.pending(b, |p| self.class(p) == i))
+                .collect(),
+            tenants: self
+                .tenants
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| self.pending(b, |p| self.tenant(p) == i))
+                .collect(),
+            wait_threshold: self.config.wait_threshold,
+            colors: self
+                .colors
+                .iter()
+                .enumerate()
+                .map(|(i, &b)| self.pending(b, |p| p.color as usize == i))
+                .collect(),
+            top_flows: {
+                let mut flows: Vec<(u32, Breakdown)> = self
+                    .flows
+                    .iter()
+                    .map(|(&f, &b)| (f, self.pending(b, |p| p.flow == f)))
+                    .collect();
+                flows.sort_by(|a, b| {
+                    b.1.packets_generated.cmp(&a.1.packets_generated).then(a.0.cmp(&b.0))
+                });
+                flows.truncate(self.config.top_flows);
+                flows
+            },
+            bottom_flows: {
+                let mut flows: Vec<(u32, Breakdown)> = self
+                    .all_flows()
+                    .into_iter()
+                    .map(|(f, b)| (f, self.pending(b, |p| p.flow == f)))
+                    .collect();
+                flows.sort_by(|a, b| {
+                    a.1.packets_processed.cmp(&b.1.packets_processed).then(a.0.cmp(&b.0))
+                });
+                flows.truncate(self.config.top_flows);
+                flows
+            },
+            class_fairness: if self.classes.len() > 1 {
+                Some(Simulation::fairness(self.classes.iter()))
+            } else {
+                None
+            },
+            flow_fairness: if self.config.flows > 1 {
+                Some(Simulation::fairness(self.all_flows().iter().map(|f| &f.1)))
+            } else {
+                None
+            },
+            wait_acf: self.acf(&self.waits),
+            interdeparture_acf: self.acf(&self.interdepartures),
+            little: LittlesLaw {
+                l: qlen.mean() + self.server.utilization() * self.config.servers as f64,
+                lambda: f64::from(self.server.packets_processed()) * self.config.resolution /
+                    f64::from(self.now.since(self.start)),
+                // The time spent awaiting retransmission is spent outside the system.
+                w: self.sojourn.mean() - self.awaited / self.sojourn.len().max(1) as f64,
+            },
+            interdeparture: self.interdeparture,
+            interdeparture_histogram: self.interdeparture_quantiles.histogram().cloned(),
+            interdeparture_percentiles: self.percentiles(&self.interdeparture_quantiles),
+            poisson_test: if self.config.poisson_test {
+                Some(self.poisson_test.clone())
+            } else {
+                None
+            },
+            jitter: self.jitter,
+            delay_variation: self.delay_variation,
+            delay_variation_percentiles: self.percentiles(&self.delay_variation_quantiles),
+            collected: self.collectors.iter().flat_map(|c| c.metrics()).collect(),
+            retransmissions: self.config.retransmission.map(|_| {
+                let elapsed = self.now.since(self.start).seconds(self.config.resolution);
+                let sent = generated + self.retransmitted;
+                Retransmissions {
+                    retransmitted: self.retransmitted,
+                    abandoned: self.abandoned,
+                    pending: self.retransmits.len(),
+                    recovered: self.recovered,
+                    throughput: f64::from(sent) / elapsed,
+                    goodput: f64::from(self.server.packets_processed()) / elapsed,
+                }
+            }),
+            blocking: if self.config.backpressure || self.config.window().is_some() {
+                let ticks = self.client.statistics.ticks_blocked - self.blocked_before;
+                let time = Ticks(ticks).seconds(self.config.resolution);
+                let elapsed = self.now.since(self.start).seconds(self.config.resolution);
+                Some(Blocking {
+                    time,
+                    proportion: time / elapsed,
+                    delay: self.blocking,
+                    pending: self.blocked.len(),
+                })
+            } else {
+                None
+            },
+            round_trips: self.config.ack.map(|_| {
+                let queued =
+                    self.ack_server.as_ref().map_or(0, |s| s.qlen() + s.in_service().count());
+                RoundTrips {
+                    rtt: self.rtt,
+                    rtt_percentiles: self.percentiles(&self.rtt_quantiles),
+                    in_flight: queued + self.ack_link.in_flight(),
+                    unacked: self.unacked,
+                }
+            }),
+            delivery: self.link.as_ref().map(|link| Delivery {
+                in_flight: link.in_flight(),
+                ..self.delivery
+            }),
+            admission: self.limiter.as_ref().map(|_| self.admission),
+            preemptions: self.config.preemption.map(|_| Preemptions {
+                count: self.server.statistics.preemptions,
+                discarded: self.server.statistics.packets_discarded,
+            }),
+        }
+    }
+}
+
+// Events is the iterator returned by Simulation.events.
+pub struct Events<'a> {
+    sim: &'a mut Simulation,
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.sim.next_event()
+    }
+}
+
+// Comparison holds, for each metric, the statistics of the paired differences (b - a) between two
+// configurations run under common random numbers.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub replications: u32,
+    pub sojourn: OnlineStats,
+    pub wait: OnlineStats,
+    pub qlen: OnlineStats,
+    pub loss_probability: OnlineStats,
+    pub idle_proportion: OnlineStats,
+}
+
+impl Comparison {
+    // Comparison.metrics returns the statistics of the differences in each metric, by name.
+    pub fn metrics(&self) -> Vec<(&'static str, &OnlineStats)> {
+        vec![
+            ("sojourn", &self.sojourn),
+            ("wait", &self.wait),
+            ("qlen", &self.qlen),
+            ("loss_probability", &self.loss_probability),
+            ("idle_proportion", &self.idle_proportion),
+        ]
+    }
+}
+
+// compare runs both configurations for the given number of replications. Replication i of either
+// configuration is seeded identically, so both see the same arrival stream and the variance of
+// the differences is reduced compared to running them independently.
+pub fn compare(a: &Config, b: &Config, replications: u32, seed: u64) -> Comparison {
+    let mut comparison = Comparison {
+        replications,
+        sojourn: OnlineStats::new(),
+        wait: OnlineStats::new(),
+        qlen: OnlineStats::new(),
+        loss_probability: OnlineStats::new(),
+        idle_proportion: OnlineStats::new(),
+    };
+    for i in 0..replications {
+        let seed = rng::derive(seed, u64::from(i));
+        let ra = Simulation::new(Config { seed: Some(seed), ..a.clone() }).run();
+        let rb = Simulation::new(Config { seed: Some(seed), ..b.clone() }).run();
+
+        comparison.sojourn.add(rb.sojourn.mean() - ra.sojourn.mean());
+        comparison.wait.add(rb.wait.mean() - ra.wait.mean());
+        comparison.qlen.add(rb.qlen.mean() - ra.qlen.mean());
+        comparison.loss_probability.add(rb.loss_probability() - ra.loss_probability());
+        comparison.idle_proportion.add(rb.idle_proportion - ra.idle_proportion);
+    }
+    comparison
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json;
+    use simulators::Visit;
+
+    fn config() -> Config {
+        Config {
+            rate: 900,
+            psize: 1,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            ..Config::default()
+        }
+    }
+
+    // drops runs the configuration, returning its results along with the reasons of the drop
+    // events, in order.
+    fn drops(c: &Config) -> (Results, Vec<DropReason>) {
+        let mut sim = Simulation::new(c.clone());
+        let reasons = sim
+            .events()
+            .filter_map(|e| match e {
+                Event::Drop { reason, .. } => Some(reason),
+                _ => None,
+            })
+            .collect();
+        (sim.results(), reasons)
+    }
+
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        let c = Config { seed: Some(1), ..config() };
+        let a = Simulation::new(c.clone()).run();
+        let b = Simulation::new(c).run();
+        assert_eq!(a.packets_generated, b.packets_generated);
+        assert_eq!(a.sojourn.mean(), b.sojourn.mean());
+    }
+
+    #[test]
+    fn golden_runs() {
+        // Canonical seeded configurations, and the packet counts and summary statistics they came
+        // out to when recorded: packets generated, processed and dropped, then the mean sojourn and
+        // waiting times and queue length, and the utilization. A change in any of them means the
+        // simulation no longer does what it did; if that's intended, they're to be recorded anew.
+        let golden = |rate: u32, service: Distribution, qlimit: Option<usize>| Config {
+            rate,
+            service,
+            qlimit,
+            psize: 100,
+            pspeed: 100_000,
+            duration: 10,
+            seed: Some(1),
+            ..config()
+        };
+        let runs = [
+            (
+                "M/M/1 at ρ = 0.5",
+                golden(500, Distribution::Exponential, None),
+                [5124, 5121, 0],
+                [0.0019171646162858774, 0.0009479500195236235, 0.48577, 0.52418],
+            ),
+            (
+                "M/M/1 at ρ = 0.8",
+                golden(800, Distribution::Exponential, None),
+                [7990, 7988, 0],
+                [0.004798347521281922, 0.00381597196144698, 3.04858, 0.80618],
+            ),
+            (
+                "M/M/1 at ρ = 0.95",
+                golden(950, Distribution::Exponential, None),
+                [9531, 9509, 0],
+                [0.017390556315069913, 0.016403722397476336, 15.61795, 0.95235],
+            ),
+            (
+                "M/D/1 at ρ = 0.9",
+                golden(900, Distribution::Deterministic, None),
+                [9042, 9028, 0],
+                [0.006193409392999545, 0.005294130025473471, 4.78895, 0.90284],
+            ),
+            (
+                "M/M/1/5 at ρ = 0.9",
+                golden(900, Distribution::Exponential, Some(4)),
+                [9042, 7953, 1087],
+                [0.0027191122846724498, 0.0017466054815187357, 1.3893, 0.7989],
+            ),
+        ];
+        for &(name, ref c, counts, stats) in &runs {
+            let r = Simulation::new(c.clone()).run();
+            let got = [r.packets_generated, r.packets_processed, r.packets_dropped];
+            assert_eq!(got, counts, "{}", name);
+            let got = [r.sojourn.mean(), r.wait.mean(), r.qlen.mean(), r.utilization];
+            for (g, s) in got.iter().zip(&stats) {
+                // Allowing for summation order and the like, but nothing more.
+                assert!(((g - s) / s).abs() < 1e-9, "{}: {:?} vs {:?}", name, got, stats);
+            }
+        }
+    }
+
+    #[test]
+    fn stability() {
+        let c = Config { seed: Some(1), ..config() };
+        assert!((c.offered_load() - 0.9).abs() < 1e-9);
+        assert!(!c.unstable());
+        assert!(!Simulation::new(c.clone()).run().unbounded);
+
+        // Overloaded, the queue grows for as long as the run lasts, unless the buffer's limited.
+        let c = Config { rate: 1200, ..c };
+        assert!((c.offered_load() - 1.2).abs() < 1e-9);
+        assert!(c.unstable());
+        assert!(Simulation::new(c.clone()).run().unbounded);
+        let c = Config { qlimit: Some(10), ..c };
+        assert!(!c.unstable());
+        assert!(!Simulation::new(c).run().unbounded);
+    }
+
+    #[test]
+    fn retransmission() {
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(2),
+            ..config()
+        };
+        let lossy = Simulation::new(c.clone()).run();
+        assert!(lossy.packets_dropped > 0);
+        assert!(lossy.retransmissions.is_none());
+
+        // Retransmitted up to three times a millisecond apart, fewer packets are lost for good, and
+        // those recovered took at least as long as the timeout.
+        let retransmission = Retransmission {
+            timeout: 0.001,
+            retries: 3,
+        };
+        let c = Config {
+            retransmission: Some(retransmission),
+            ..c
+        };
+        let r = Simulation::new(c.clone()).run();
+        let t = r.retransmissions.unwrap();
+        assert_eq!(r.packets_generated, lossy.packets_generated);
+        assert_eq!(r.packets_dropped, t.abandoned);
+        assert!(r.packets_dropped < lossy.packets_dropped);
+        assert_eq!(r.classes[0].packets_dropped, r.packets_dropped);
+        assert!(t.retransmitted > lossy.packets_dropped);
+        assert!(t.recovered.len() > 0 && t.recovered.mean() >= 0.001);
+        assert!(t.throughput > t.goodput);
+        // Every packet is delivered, given up on, or still in the system or awaiting retransmission.
+        let accounted = r.packets_processed + r.packets_dropped + (r.leftover + t.pending) as u32;
+        assert!(r.packets_generated - accounted <= 1);
+
+        // Never retransmitted, every packet dropped is given up on straight away.
+        let c = Config {
+            retransmission: Some(Retransmission { retries: 0, ..retransmission }),
+            ..c
+        };
+        let r = Simulation::new(c.clone()).run();
+        assert_eq!(r.packets_dropped, lossy.packets_dropped);
+        assert_eq!(r.retransmissions.unwrap().retransmitted, 0);
+        assert_eq!(r.sojourn.mean(), lossy.sojourn.mean());
+
+        let c = Config {
+            retransmission: Some(Retransmission { timeout: 0.0, ..retransmission }),
+            ..c
+        };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid retransmission timeout 0: it must be positive");
+    }
+
+    #[test]
+    fn distribution_parameters() {
+        // The generators can't be built off of parameters out of range, however they're given.
+        let c = Config { arrival: Distribution::LogNormal { sigma: -1.0 }, ..config() };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid arrival sigma -1: it must be finite, and can't be negative");
+        let c = Config { service: Distribution::Pareto { shape: f64::NAN }, ..config() };
+        assert!(c.validate().unwrap_err().to_string().starts_with("invalid service Pareto shape"));
+        let c = Config { service: Distribution::LogNormal { sigma: 0.0 }, ..config() };
+        assert!(c.validate().is_ok());
+    }
+
+    #[test]
+    fn class_weights() {
+        // Packets are spread across classes in proportion to their weights, which have to make
+        // for proportions.
+        for weights in &[vec![-1.0, 2.0], vec![0.0, 0.0], vec![f64::INFINITY, 1.0]] {
+            let c = Config { class_weights: weights.clone(), ..config() };
+            assert!(c.validate().is_err(), "{:?}", weights);
+        }
+        let c = Config { class_weights: vec![0.0, 1.0], ..config() };
+        assert!(c.validate().is_ok());
+    }
+
+    #[test]
+    fn percentiles() {
+        for &p in &[0.0, 200.0, f64::NAN] {
+            let c = Config { percentiles: vec![50.0, p], ..config() };
+            assert!(c.validate().unwrap_err().to_string().starts_with("invalid percentile"));
+        }
+        let c = Config { percentiles: vec![100.0], ..config() };
+        assert!(c.validate().is_ok());
+        let histogram = Bucketing::Exponential { min: 0.0, factor: 1.0, count: 0 };
+        assert!(Config { histogram, ..config() }.validate().is_err());
+    }
+
+    #[test]
+    fn series_intervals() {
+        let c = Config { sample_interval: Some(0), ..config() };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid sample interval 0: samples must be taken at least a tick apart");
+        let c = Config { window: Some(0), ..config() };
+        assert!(c.validate().is_err());
+        let doc = r#"{"sample_interval": 0}"#;
+        assert!(json::configure(doc, 1).unwrap_err().starts_with("invalid sample interval 0"));
+    }
+
+    #[test]
+    fn backpressure() {
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(2),
+            ..config()
+        };
+        let lossy = Simulation::new(c.clone()).run();
+        assert!(lossy.packets_dropped > 0);
+        assert!(lossy.blocking.is_none());
+
+        // Blocked instead, the client loses nothing but falls behind, generating fewer packets.
+        let c = Config { backpressure: true, ..c };
+        let mut sim = Simulation::new(c.clone());
+        while sim.tick() {
+            assert!(sim.server().qlen() <= 2);
+        }
+        let r = sim.results();
+        let b = r.blocking.unwrap();
+        assert_eq!(r.packets_dropped, 0);
+        assert!(r.packets_generated < lossy.packets_generated);
+        assert!(b.delay.len() > 0 && b.delay.mean() > 0.0);
+        assert!(b.time > 0.0 && b.proportion < 1.0);
+        assert_eq!(b.time, f64::from(sim.client.statistics.ticks_blocked) / c.resolution);
+        // Every packet is delivered, or still in the system or waiting to get in.
+        let accounted = r.packets_processed + (r.leftover + b.pending) as u32;
+        assert!(r.packets_generated - accounted <= 1);
+        assert!(r.little.holds());
+
+        let c = Config { qlimit: None, ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "backpressure needs a buffer limit for the source to block at");
+        let c = Config { qlimit: Some(0), ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid qlimit 0: the source would block forever under backpressure");
+    }
+
+    #[test]
+    fn acknowledgments() {
+        let c = Config { seed: Some(1), ..config() };
+        let open = Simulation::new(c.clone()).run();
+        assert!(open.round_trips.is_none());
+
+        // Acknowledged over a 2ms path, the forward path's none the wiser, and every round trip's
+        // a sojourn 2ms longer.
+        let ack = AckPath {
+            delay: 0.002,
+            length: 1,
+            pspeed: None,
+            window: None,
+        };
+        let c = Config { ack: Some(ack), ..c };
+        let r = Simulation::new(c.clone()).run();
+        let t = r.round_trips.unwrap();
+        assert_eq!(r.packets_generated, open.packets_generated);
+        assert_eq!(r.sojourn.mean(), open.sojourn.mean());
+        assert!(r.blocking.is_none());
+        assert_eq!(t.rtt.len() + t.in_flight, r.packets_processed as usize);
+        assert!((t.rtt.mean() - r.sojourn.mean() - 0.002).abs() < 1e-4);
+
+        // Queued up for a server of their own, ACKs take longer to get back.
+        let queued = AckPath { pspeed: Some(1000), ..ack };
+        let q = Simulation::new(Config { ack: Some(queued), ..c.clone() }).run();
+        assert!(q.round_trips.unwrap().rtt.mean() > t.rtt.mean());
+
+        // With a window of two, the client never has more than two packets unacknowledged, and
+        // falls behind waiting on ACKs.
+        let windowed = AckPath { window: Some(2), ..ack };
+        let mut sim = Simulation::new(Config { ack: Some(windowed), ..c.clone() });
+        while sim.tick() {
+            assert!(sim.unacked <= 2);
+        }
+        let w = sim.results();
+        assert!(w.packets_generated < r.packets_generated);
+        assert!(w.blocking.unwrap().time > 0.0);
+        assert_eq!(w.packets_dropped, 0);
+
+        let c = Config { ack: Some(AckPath { window: Some(0), ..ack }), ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid window 0: the client could never send a packet");
+    }
+
+    #[test]
+    fn reordering() {
+        let c = Config { seed: Some(1), ..config() };
+        let direct = Simulation::new(c.clone()).run();
+        assert!(direct.delivery.is_none());
+
+        // Over an unimpaired link, packets are received in the order they're processed (FIFO, so
+        // generated), a millisecond later.
+        let link = LinkPath {
+            delay: 0.001,
+            loss: None,
+            reorder: 0.0,
+            reorder_delay: 0.0,
+            duplicate: 0.0,
+        };
+        let r = Simulation::new(Config { link: Some(link), ..c.clone() }).run();
+        let d = r.delivery.unwrap();
+        assert_eq!(r.sojourn.mean(), direct.sojourn.mean());
+        assert_eq!(d.delivered as usize + d.in_flight, r.packets_processed as usize);
+        assert_eq!((d.out_of_order, d.duplicates, d.max_depth), (0, 0, 0));
+        assert!((d.delay.mean() - r.sojourn.mean() - 0.001).abs() < 1e-4);
+
+        // Held back and duplicated at random, some packets are overtaken, and some received twice
+        // but only acknowledged once.
+        let impaired = LinkPath {
+            reorder: 0.2,
+            reorder_delay: 0.01,
+            duplicate: 0.05,
+            ..link
+        };
+        let ack = AckPath {
+            delay: 0.0,
+            length: 1,
+            pspeed: None,
+            window: None,
+        };
+        let c = Config { link: Some(impaired), ack: Some(ack), ..c };
+        let r = Simulation::new(c.clone()).run();
+        let (d, t) = (r.delivery.unwrap(), r.round_trips.unwrap());
+        assert_eq!(r.sojourn.mean(), direct.sojourn.mean());
+        assert!(d.out_of_order > 0 && d.max_depth > 0 && d.depth.mean() >= 1.0);
+        assert!(d.duplicates > 0);
+        assert_eq!(t.rtt.len(), d.delivered as usize);
+        assert!(d.delay.mean() > r.sojourn.mean() + 0.001);
+
+        let c = Config { link: Some(LinkPath { duplicate: 1.5, ..impaired }), ..c };
+        let err = c.validate().unwrap_err().to_string();
+        let reason = "it must be a probability, from 0 to 1";
+        assert_eq!(err, format!("invalid duplication probability 1.5: {}", reason));
+    }
+
+    #[test]
+    fn link_loss() {
+        // Packets lost on the link are counted apart from those the server drops, and never
+        // acknowledged; the client's window isn't held up by them for good either.
+        let link = LinkPath {
+            delay: 0.001,
+            loss: Some(GilbertElliott {
+                p: 0.05,
+                r: 0.25,
+                good_loss: 0.01,
+                bad_loss: 0.5,
+            }),
+            reorder: 0.0,
+            reorder_delay: 0.0,
+            duplicate: 0.0,
+        };
+        let ack = AckPath {
+            delay: 0.001,
+            length: 1,
+            pspeed: None,
+            window: Some(8),
+        };
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(4),
+            link: Some(link),
+            ack: Some(ack),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        let (d, t) = (r.delivery.unwrap(), r.round_trips.unwrap());
+        assert!(d.lost > 0);
+        assert_eq!(d.delivered + d.lost + d.in_flight as u32, r.packets_processed);
+        assert_eq!(t.rtt.len(), d.delivered as usize - t.in_flight);
+        let rate = f64::from(d.lost) / f64::from(r.packets_processed);
+        assert!((rate - link.loss.unwrap().loss_rate()).abs() < 0.05);
+        assert!(t.unacked <= 8);
+    }
+
+    #[test]
+    fn tenant_quotas() {
+        // A heavy tenant overloading the server takes the light one down with it, both losing
+        // packets alike, unless it's held to a quota.
+        let tenants = vec![
+            Tenant { weight: 4.0, share: None, rate: None },
+            Tenant { weight: 1.0, share: None, rate: None },
+        ];
+        let c = Config {
+            rate: 1500,
+            duration: 4,
+            qlimit: Some(10),
+            seed: Some(1),
+            tenants: tenants.clone(),
+            ..config()
+        };
+        let shared = Simulation::new(c.clone()).run();
+        let (heavy, light) = (&shared.tenants[0], &shared.tenants[1]);
+        assert_eq!(heavy.packets_generated + light.packets_generated, shared.packets_generated);
+        assert!(light.loss_probability() > 20.0);
+        assert!((heavy.loss_probability() - light.loss_probability()).abs() < 5.0);
+
+        // Limited to half the buffer, the heavy tenant leaves room for the light one's packets.
+        let mut partitioned = tenants.clone();
+        partitioned[0].share = Some(0.5);
+        let r = Simulation::new(Config { tenants: partitioned.clone(), ..c.clone() }).run();
+        assert_eq!(r.packets_generated, shared.packets_generated);
+        assert!(r.tenants[1].loss_probability() < light.loss_probability() / 2.0);
+        let (r, reasons) = drops(&Config { tenants: partitioned, ..c.clone() });
+        assert_eq!(reasons.len() as u32, r.packets_dropped);
+        assert!(reasons.contains(&DropReason::Quota) && reasons.contains(&DropReason::BufferFull));
+
+        // Policed to 600 packets/s, it can't overload the server at all.
+        let mut policed = tenants;
+        policed[0].rate = Some(600.0);
+        let r = Simulation::new(Config { tenants: policed.clone(), ..c.clone() }).run();
+        let throughput = f64::from(r.tenants[0].packets_processed) / r.elapsed;
+        assert!((throughput - 600.0).abs() < 60.0);
+        assert!(r.tenants[1].loss_probability() < 5.0);
+        assert!(r.tenants[1].wait.mean() < light.wait.mean() / 2.0);
+
+        policed[1].share = Some(0.5);
+        let c = Config { qlimit: None, tenants: policed, ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "a tenant's share of the buffer needs a buffer limit");
+
+        // As with classes, tenants' weights have to make for proportions.
+        for &weight in &[0.0, -1.0, f64::NAN] {
+            let c = Config { tenants: vec![Tenant { weight, share: None, rate: None }], ..config() };
+            assert!(c.validate().unwrap_err().to_string().contains("tenant weight"));
+        }
+    }
+
+    #[test]
+    fn rate_limiting() {
+        // A gateway admitting 800 packets/s ahead of a server overloaded at 1500 rejects what it
+        // can't take instead of letting the buffer overflow, whatever the algorithm.
+        let c = Config {
+            rate: 1500,
+            duration: 4,
+            qlimit: Some(10),
+            seed: Some(1),
+            ..config()
+        };
+        let overloaded = Simulation::new(c.clone()).run();
+        assert!(overloaded.admission.is_none());
+        for &algorithm in &[Limiting::FixedWindow, Limiting::SlidingWindow, Limiting::TokenBucket] {
+            let limiting = RateLimiting { algorithm, limit: 800.0, burst: 8 };
+            let (r, reasons) = drops(&Config { rate_limiting: Some(limiting), ..c.clone() });
+            let a = r.admission.unwrap();
+            assert_eq!(a.admitted + a.rejected, r.packets_generated);
+            let limited = reasons.iter().filter(|&&r| r == DropReason::RateLimited).count();
+            assert_eq!(limited as u32, a.rejected);
+            assert!(f64::from(a.admitted) / r.elapsed <= 800.0 + 8.0 / r.elapsed);
+            // Packets dropped once admitted were the server's.
+            assert!(r.packets_dropped - a.rejected < overloaded.packets_dropped / 10);
+            assert!(r.wait.mean() < overloaded.wait.mean() / 2.0);
+        }
+
+        let limiting = RateLimiting {
+            algorithm: Limiting::TokenBucket,
+            limit: 800.0,
+            burst: 0,
+        };
+        let c = Config { rate_limiting: Some(limiting), ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid rate limit burst 0: not a single packet would get through");
+    }
+
+    #[test]
+    fn marking() {
+        // Overloading a server at 1200 packets/s (of a bit each), a marker committing to 600 bits/s
+        // and peaking at 900 colors about half the packets green, a quarter yellow and a quarter
+        // red. Red ones are dropped once 2 packets are queued, and yellow ones once 4 are, so the
+        // green ones hardly ever are.
+        let marking = Marking {
+            committed: 600.0,
+            committed_burst: 4,
+            peak: 900.0,
+            peak_burst: 8,
+            yellow_limit: Some(4),
+            red_limit: Some(2),
+        };
+        let c = Config {
+            rate: 1200,
+            duration: 4,
+            qlimit: Some(20),
+            seed: Some(1),
+            marking: Some(marking),
+            ..config()
+        };
+        let (r, reasons) = drops(&c);
+        let (green, yellow, red) = (&r.colors[0], &r.colors[1], &r.colors[2]);
+        let share = |b: &Breakdown| f64::from(b.packets_generated) / f64::from(r.packets_generated);
+        assert!((share(green) - 0.5).abs() < 0.05, "{}", share(green));
+        assert!((share(yellow) - 0.25).abs() < 0.05, "{}", share(yellow));
+        assert!(red.loss_probability() > yellow.loss_probability());
+        assert!(yellow.loss_probability() > 5.0 * green.loss_probability());
+        assert!(green.loss_probability() < 1.0, "{}", green.loss_probability());
+        let dropped = green.packets_dropped + yellow.packets_dropped + red.packets_dropped;
+        assert_eq!(dropped, r.packets_dropped);
+        // With as long a buffer, packets are only ever dropped over their color's limit.
+        assert_eq!(reasons.len() as u32, r.packets_dropped);
+        assert!(reasons.iter().all(|&r| r == DropReason::Color));
+        assert!(Simulation::new(Config { marking: None, ..c.clone() }).run().colors.is_empty());
+
+        let c = Config { marking: Some(Marking { peak: 500.0, ..marking }), ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "invalid peak rate 500: it can't be below the committed rate");
+    }
+
+    #[test]
+    fn starvation() {
+        // Under strict priority, the higher class taking up 90% of the server leaves the lower one
+        // a third of what it needs. Its packets starve, many never served at all; those still
+        // queued count as much as those that got through.
+        let c = Config {
+            rate: 1200,
+            class_weights: vec![3.0, 1.0],
+            discipline: Discipline::Priority(vec![0]),
+            qlimit: None,
+            duration: 4,
+            seed: Some(1),
+            wait_threshold: Some(0.1),
+            ..config()
+        };
+        let r = Simulation::new(c.clone()).run();
+        let (high, low) = (&r.classes[0], &r.classes[1]);
+        assert!(high.max_wait < 0.1 && high.starved == 0);
+        assert!(low.max_wait > 2.0, "{}", low.max_wait);
+        assert!(low.starved > low.packets_processed);
+        assert!(low.starved <= low.packets_generated);
+        assert_eq!((r.max_wait(), r.starved()), (low.max_wait, low.starved));
+        assert!(low.packets_processed < low.packets_generated / 2);
+
+        // Without a threshold the longest waits are still kept, but nothing counts as starved.
+        let r = Simulation::new(Config { wait_threshold: None, ..c.clone() }).run();
+        assert_eq!((r.classes[1].max_wait, r.starved()), (low.max_wait, 0));
+        let err = Config { wait_threshold: Some(-1.0), ..c }.validate().unwrap_err();
+        assert_eq!(err.to_string(), "invalid wait threshold -1: it can't be negative");
+    }
+
+    #[test]
+    fn preemption() {
+        // Preempting packets of the lower priority class speeds up those of the higher one, at the
+        // lower one's expense, by how much depending on what becomes of the packets preempted.
+        let c = Config {
+            class_weights: vec![1.0, 1.0],
+            discipline: Discipline::Priority(vec![0]),
+            duration: 4,
+            seed: Some(1),
+            ..config()
+        };
+        let run = |preemption| Simulation::new(Config { preemption, ..c.clone() }).run();
+        let (none, resumed) = (run(None), run(Some(Preemption::Resume)));
+        assert!(none.preemptions.is_none());
+        let p = resumed.preemptions.unwrap();
+        assert!(p.count > 0 && p.discarded == 0 && resumed.packets_dropped == 0);
+        assert!(resumed.classes[0].sojourn.mean() < none.classes[0].sojourn.mean());
+        assert!(resumed.classes[1].sojourn.mean() > none.classes[1].sojourn.mean());
+        // Packets preempted wait only until they first enter service, and are in service from then
+        // on, interruptions included.
+        let (wait, service) = (resumed.wait.mean(), resumed.service.mean());
+        assert!((wait + service - resumed.sojourn.mean()).abs() < resumed.sojourn.mean() * 0.01);
+
+        // Starting over wastes the work done, holding up the lower class further.
+        let restarted = run(Some(Preemption::Restart));
+        assert!(restarted.classes[1].sojourn.mean() > resumed.classes[1].sojourn.mean());
+
+        // Packets discarded are dropped, only ever from the lower class.
+        let preemption = Some(Preemption::Discard);
+        let (discarded, reasons) = drops(&Config { preemption, ..c.clone() });
+        assert_eq!(reasons.len() as u32, discarded.packets_dropped);
+        assert!(reasons.iter().all(|&r| r == DropReason::Preempted));
+        let p = discarded.preemptions.unwrap();
+        assert!(p.discarded > 0 && p.discarded == p.count);
+        assert_eq!(discarded.packets_dropped, p.discarded);
+        assert_eq!(discarded.classes[1].packets_dropped, p.discarded);
+        assert_eq!(discarded.classes[0].packets_dropped, 0);
+
+        let c = Config { discipline: Discipline::Fifo, preemption: Some(Preemption::Resume), ..c };
+        let err = c.validate().unwrap_err().to_string();
+        assert_eq!(err, "preemption needs a LIFO or priority discipline");
+    }
+
+    #[test]
+    fn polling() {
+        // With zero switchover times and every packet taking as long, polling is as work-conserving
+        // as FIFO, serving the same packets at the same times, only in a different order: the mean
+        // wait is the same however the queues are visited (the conservation law).
+        let c = Config {
+            class_weights: vec![1.0, 1.0, 2.0],
+            duration: 4,
+            seed: Some(1),
+            ..config()
+        };
+        let run = |discipline| Simulation::new(Config { discipline, ..c.clone() }).run();
+        let fifo = run(Discipline::Fifo);
+        let (gated, limited) = (Visit::Gated, Visit::Limited(1));
+        for visits in [vec![], vec![gated; 3], vec![limited; 3], vec![gated, limited]] {
+            let r = run(Discipline::Polling(visits));
+            assert!((r.wait.mean() - fifo.wait.mean()).abs() < fifo.wait.mean() * 0.01);
+        }
+        // The queue served exhaustively is favoured over those only served a packet at a time.
+        let r = run(Discipline::Polling(vec![Visit::Exhaustive, limited, limited]));
+        assert!(r.classes[0].wait.mean() < r.classes[1].wait.mean());
+        assert!(r.classes[0].wait.mean() < r.classes[2].wait.mean());
+    }
+
+    #[test]
+    fn common_random_numbers() {
+        // With identical arrival streams, the only difference between the two configurations is
+        // the buffer limit; the infinite buffer never drops anything.
+        let a = Config { qlimit: Some(2), ..config() };
+        let b = config();
+        let c = compare(&a, &b, 3, 1);
+        assert_eq!(c.replications, 3);
+        assert!(c.loss_probability.mean() < 0.0);
+        assert!(c.qlen.mean() > 0.0);
+        assert!(c.wait.mean() > 0.0);
+        assert!(analysis::significant(&c.loss_probability, CONFIDENCE));
+        // Identical configurations differ in nothing.
+        let c = compare(&b, &b, 3, 1);
+        assert!(c.metrics().iter().all(|&(_, d)| d.mean() == 0.0 && d.variance() == 0.0));
+        assert!(!analysis::significant(&c.sojourn, CONFIDENCE));
+    }
+
+    #[test]
+    fn batch_means() {
+        let c = Config {
+            seed: Some(1),
+            batching: Some(Batching::Count(10)),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        let qlen = r.qlen_batches.unwrap();
+        assert_eq!(qlen.batches, 10);
+        assert!((qlen.mean - r.qlen.mean()).abs() < 1e-9);
+        assert!(r.sojourn_batches.is_some());
+        assert!(r.wait_batches.is_some());
+        let utilization = r.utilization_batches.unwrap();
+        assert!((utilization.mean - r.utilization).abs() < 1e-9);
+        assert!(Simulation::new(config()).run().qlen_batches.is_none());
+
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(2),
+            batching: Some(Batching::Count(10)),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        assert!((r.loss_batches.unwrap().mean - r.loss_probability()).abs() < 0.5);
+    }
+
+    #[test]
+    fn sojourn_percentiles() {
+        let r = Simulation::new(Config { seed: Some(1), ..config() }).run();
+        assert_eq!(r.sojourn_histogram.unwrap().count(), u64::from(r.packets_processed));
+        let percentiles: Vec<f64> = r.sojourn_percentiles.iter().map(|&(p, _)| p).collect();
+        assert_eq!(percentiles, DEFAULT_PERCENTILES.to_vec());
+        for w in r.sojourn_percentiles.windows(2) {
+            assert!(w[0].1 <= w[1].1);
+        }
+        for (w, s) in r.wait_percentiles.iter().zip(&r.sojourn_percentiles) {
+            assert!(w.1 <= s.1);
+        }
+    }
+
+    #[test]
+    fn p2_percentiles() {
+        let r = Simulation::new(Config { seed: Some(1), ..config() }).run();
+        let c = Config {
+            seed: Some(1),
+            estimator: Estimator::P2,
+            ..config()
+        };
+        let s = Simulation::new(c).run();
+        assert!(s.sojourn_histogram.is_none());
+        for (h, p) in r.sojourn_percentiles.iter().zip(&s.sojourn_percentiles).take(3) {
+            assert!(((h.1 - p.1) / h.1).abs() < 0.1, "{:?} vs {:?}", h, p);
+        }
+    }
+
+    #[test]
+    fn sojourn_split() {
+        // Packets take ten ticks to process, departing nine ticks (0.9ms) after entering service in
+        // the tenth, and the last one may still be in service.
+        let r = Simulation::new(Config { seed: Some(1), ..config() }).run();
+        assert_eq!(r.service.len(), r.sojourn.len());
+        assert!(r.wait.len() - r.service.len() <= 1);
+        assert!((r.service.mean() - 0.0009).abs() < 1e-9);
+        assert!(r.service.stddev() < 1e-9);
+        assert!((r.sojourn.mean() - r.wait.mean() - r.service.mean()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn hdr_recording() {
+        let c = Config {
+            seed: Some(1),
+            histogram: Bucketing::parse("hdr:3").unwrap(),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        let (sojourn, wait) = (r.sojourn_histogram.unwrap(), r.wait_histogram.unwrap());
+        assert_eq!(sojourn.overflow(), 0);
+        assert_eq!(wait.overflow(), 0);
+        // The smallest sojourn time is the service time, recorded exactly: ten ticks in service,
+        // departing nine ticks (0.9ms) after entering it.
+        assert_eq!(sojourn.percentile(0.0), 9e-4);
+    }
+
+    #[test]
+    fn littles_law() {
+        for &qlimit in &[None, Some(5)] {
+            let c = Config {
+                seed: Some(1),
+                duration: 10,
+                qlimit,
+                ..config()
+            };
+            let little = Simulation::new(c).run().little;
+            assert!(little.holds(), "{:?}: {}", little, little.relative_error());
+        }
+    }
+
+    #[test]
+    fn occupancy() {
+        let c = Config { seed: Some(1), ..config() };
+        let ticks = u64::from(c.ticks());
+        let r = Simulation::new(c).run();
+        assert_eq!(r.occupancy.iter().sum::<u64>(), ticks);
+        let p = r.occupancy_distribution();
+        let mean: f64 = p.iter().enumerate().map(|(k, p)| k as f64 * p).sum();
+        // Packets are counted at the start of each tick, before the head of the queue enters
+        // service at an idle server, so the packet in service is counted a little less often than
+        // the server is busy.
+        let in_service = mean - r.qlen.mean();
+        assert!(in_service > 0.8 * r.utilization && in_service <= r.utilization);
+        assert!(p[0] >= r.idle_proportion / 100.0);
+    }
+
+    #[test]
+    fn stretches() {
+        // The queue length statistics, accounted for a stretch at a time, come out as if they were
+        // accounted for tick by tick: here, as sampled every tick.
+        let c = Config {
+            seed: Some(1),
+            sample_interval: Some(1),
+            batching: Some(Batching::Count(10)),
+            warmup: 0.2,
+            ..config()
+        };
+        let r = Simulation::new(c.clone()).run();
+        let warmup = c.warmup_ticks();
+        let samples = r.samples.iter().filter(|s| s.time >= warmup);
+        let qlen: OnlineStats = samples.map(|s| s.qlen).collect();
+        assert_eq!(r.qlen.len(), qlen.len());
+        assert!((r.qlen.mean() - qlen.mean()).abs() < 1e-9);
+        assert!((r.qlen.stddev() - qlen.stddev()).abs() < 1e-9);
+        let batches = r.qlen_batches.unwrap();
+        assert!((batches.mean - qlen.mean()).abs() < 1e-9);
+        assert_eq!(r.occupancy.iter().sum::<u64>(), u64::from(c.ticks() - warmup));
+    }
+
+    #[test]
+    fn busy_periods() {
+        // An M/D/1 queue at ρ = 0.8, busy periods serve 1 / (1 - ρ) = 5 packets on average and
+        // last 5 service times of 8 ticks each.
+        let c = Config {
+            rate: 1000,
+            pspeed: 1250,
+            duration: 20,
+            seed: Some(1),
+            ..config()
+        };
+        let b = Simulation::new(c).run().busy_periods;
+        assert!((b.packets.mean() - 5.0).abs() < 0.25, "{:?}", b.packets);
+        assert!((b.duration.mean() - 0.004).abs() < 0.0002, "{:?}", b.duration);
+        assert_eq!(b.packets_percentiles[0], (50.0, 2.0));
+    }
+
+    #[test]
+    fn windows() {
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(2),
+            window: Some(1000),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        assert_eq!(r.windows.len(), 10);
+        assert!(r.windows.iter().enumerate().all(|(i, w)| w.start == Ticks(1000 * i as u32)));
+        let sum = |f: fn(&Window) -> u32| r.windows.iter().map(f).sum::<u32>();
+        assert_eq!(sum(|w| w.arrivals), r.packets_generated);
+        assert_eq!(sum(|w| w.departures), r.packets_processed);
+        assert_eq!(sum(|w| w.drops), r.packets_dropped);
+    }
+
+    #[test]
+    fn autocorrelation() {
+        // Waiting times of consecutive packets in a heavily loaded queue are strongly correlated,
+        // decaying with the lag, interdeparture times (the service time back to back, bar the odd
+        // idle period) only weakly so.
+        let c = Config {
+            seed: Some(1),
+            acf_lags: Some(5),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        assert_eq!(r.wait_acf.len(), 5);
+        assert_eq!(r.interdeparture_acf.len(), 5);
+        assert!(r.wait_acf[0] > 0.9);
+        assert!(r.wait_acf.windows(2).all(|w| w[0] >= w[1]));
+        assert!(r.interdeparture_acf.iter().all(|a| a.abs() < 0.3), "{:?}", r.interdeparture_acf);
+        assert!(Simulation::new(config()).run().wait_acf.is_empty());
+
+        // Lags are only computed as far as there are samples for.
+        let c = Config { rate: 10, seed: Some(1), acf_lags: Some(100_000_000), ..config() };
+        let r = Simulation::new(c).run();
+        assert!(!r.wait_acf.is_empty() && r.wait_acf.len() as u32 <= r.packets_processed);
+        assert!(r.interdeparture_acf.iter().all(|a| !a.is_nan()));
+    }
+
+    #[test]
+    fn jitter() {
+        let r = Simulation::new(Config { seed: Some(1), ..config() }).run();
+        assert_eq!(r.interdeparture.len(), r.sojourn.len() - 1);
+        assert_eq!(r.delay_variation.len(), r.sojourn.len() - 1);
+        // Without drops, the output rate matches the input.
+        assert!((1.0 / r.interdeparture.mean() - 900.0).abs() < 50.0);
+        assert!(r.interdeparture.stddev() > 0.0);
+        assert!(r.jitter > 0.0 && r.jitter < 5.0 * r.delay_variation.mean());
+        assert!(r.delay_variation_percentiles.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert!(r.interdeparture_percentiles.windows(2).all(|w| w[0].1 <= w[1].1));
+        assert!(r.poisson_test.is_none());
+    }
+
+    #[test]
+    fn burke() {
+        // The departures out of a stable M/M/1 queue are Poisson, those out of an M/D/1 one are
+        // more regular, and correlated.
+        let c = Config {
+            rate: 700,
+            psize: 12_000,
+            pspeed: 12_000_000,
+            service: Distribution::Exponential,
+            duration: 10,
+            resolution: 1e5,
+            seed: Some(1),
+            poisson_test: true,
+            ..Config::default()
+        };
+        let r = Simulation::new(c.clone()).run();
+        let t = r.poisson_test.as_ref().unwrap();
+        assert_eq!(t.len(), r.interdeparture.len());
+        assert!(t.holds(), "{} {}", t.statistic(), t.lag1());
+        assert!((r.interdeparture_scv() - 1.0).abs() < 0.1, "{}", r.interdeparture_scv());
+
+        let r = Simulation::new(Config { service: Distribution::Deterministic, ..c }).run();
+        let t = r.poisson_test.as_ref().unwrap();
+        assert!(!t.exponential() && !t.holds());
+        assert!(r.interdeparture_scv() < 0.7, "{}", r.interdeparture_scv());
+    }
+
+    #[test]
+    fn breakdown() {
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(10),
+            class_weights: vec![2.0, 1.0],
+            flows: 100,
+            top_flows: 5,
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        assert_eq!(r.classes.len(), 2);
+        let sum = |f: fn(&Breakdown) -> u32| r.classes.iter().map(f).sum::<u32>();
+        assert_eq!(sum(|b| b.packets_generated), r.packets_generated);
+        assert_eq!(sum(|b| b.packets_processed), r.packets_processed);
+        assert_eq!(sum(|b| b.packets_dropped), r.packets_dropped);
+        let share = f64::from(r.classes[0].packets_generated) / f64::from(r.packets_generated);
+        assert!((share - 2.0 / 3.0).abs() < 0.05);
+
+        assert_eq!(r.top_flows.len(), 5);
+        let generated: Vec<u32> = r.top_flows.iter().map(|f| f.1.packets_generated).collect();
+        assert!(generated.windows(2).all(|w| w[0] >= w[1]));
+        assert!(Simulation::new(config()).run().top_flows.is_empty());
+
+        assert_eq!(r.bottom_flows.len(), 5);
+        let processed: Vec<u32> = r.bottom_flows.iter().map(|f| f.1.packets_processed).collect();
+        assert!(processed.windows(2).all(|w| w[0] <= w[1]));
+        assert!(r.top_flows.iter().all(|f| f.1.packets_processed >= processed[4]));
+    }
+
+    #[test]
+    fn hooks() {
+        // Packets a hook moves to a class or tenant that isn't configured count as the last's.
+        let c = Config { seed: Some(1), class_weights: vec![1.0, 1.0], ..config() };
+        let mut sim = Simulation::new(c);
+        sim.client_mut().on_generate(|p| p.class = 7);
+        sim.server_mut().on_enqueue(|p| {
+            p.tenant = 9;
+            true
+        });
+        let r = sim.run();
+        assert_eq!(r.classes[1].packets_generated, r.packets_generated);
+        assert_eq!(r.classes[1].packets_processed, r.packets_processed);
+        assert_eq!(r.tenants[0].packets_processed, r.packets_processed);
+    }
+
+    #[test]
+    fn fairness() {
+        let c = Config {
+            seed: Some(1),
+            class_weights: vec![1.0, 1.0],
+            flows: 10,
+            ..config()
+        };
+        let r = Simulation::new(c.clone()).run();
+        // Evenly split traffic is served about evenly.
+        assert!(r.class_fairness.unwrap() > 0.99);
+        assert!(r.flow_fairness.unwrap() > 0.95);
+        // A 9:1 split is served in proportion, i.e. unfairly: (0.9 + 0.1)² / 2(0.81 + 0.01).
+        let c = Config { class_weights: vec![9.0, 1.0], ..c };
+        let fairness = Simulation::new(c).run().class_fairness.unwrap();
+        assert!((fairness - 1.0 / 1.64).abs() < 0.02, "{}", fairness);
+        let r = Simulation::new(config()).run();
+        assert_eq!((r.class_fairness, r.flow_fairness), (None, None));
+    }
+
+    #[test]
+    fn packet_count() {
+        let c = Config {
+            seed: Some(1),
+            duration: 100,
+            packets: Some(2000),
+            batching: Some(Batching::Count(10)),
+            ..config()
+        };
+        let r = Simulation::new(c.clone()).run();
+        assert_eq!(r.packets_processed, 2000);
+        // 2000 packets take about 2000/900 seconds to depart, over which the batches are spread.
+        assert!((r.elapsed - 2000.0 / 900.0).abs() < 0.2, "{}", r.elapsed);
+        assert!((9..=11).contains(&r.qlen_batches.unwrap().batches), "{:?}", r.qlen_batches);
+        // The duration still caps the run.
+        let r = Simulation::new(Config { duration: 1, ..c }).run();
+        assert!(r.packets_processed < 1000 && r.elapsed == 1.0);
+    }
+
+    #[test]
+    fn time_units() {
+        assert_eq!(TimeUnit::parse("us"), Some(TimeUnit::Microseconds));
+        assert_eq!(TimeUnit::parse("min"), None);
+        assert_eq!(TimeUnit::Milliseconds.per_second(), 1e3);
+        assert_eq!(TimeUnit::fitting(2.0), TimeUnit::Seconds);
+        assert_eq!(TimeUnit::fitting(1e-6), TimeUnit::Microseconds);
+        assert_eq!(TimeUnit::fitting(5e-7), TimeUnit::Nanoseconds);
+
+        // A millisecond resolution is coarser, but the rates hold all the same.
+        let c = Config { rate: 200, resolution: 1e3, duration: 10, seed: Some(1), ..config() };
+        let r = Simulation::new(c).run();
+        assert!((r.little.lambda - 200.0).abs() < 10.0, "{}", r.little.lambda);
+        assert!((r.utilization - 0.2).abs() < 0.02, "{}", r.utilization);
+    }
+
+    #[test]
+    fn disciplines() {
+        let c = Config {
+            seed: Some(1),
+            class_weights: vec![1.0, 1.0],
+            duration: 10,
+            ..config()
+        };
+        let wait = |discipline: Discipline| {
+            let r = Simulation::new(Config { discipline, ..c.clone() }).run();
+            (r.wait.mean(), r.classes[0].wait.mean(), r.classes[1].wait.mean())
+        };
+        let fifo = wait(Discipline::Fifo);
+        // Priority favours one class at the other's expense, and LIFO reorders packets but, being
+        // work-conserving, leaves the mean wait about as is.
+        let prio = wait(Discipline::Priority(vec![]));
+        assert!(prio.1 < fifo.1 && fifo.2 < prio.2, "{:?} {:?}", fifo, prio);
+        let prio = wait(Discipline::Priority(vec![1]));
+        assert!(prio.2 < fifo.2 && fifo.1 < prio.1, "{:?} {:?}", fifo, prio);
+        let lifo = wait(Discipline::Lifo);
+        assert!((lifo.0 - fifo.0).abs() < 0.1 * fifo.0, "{:?} {:?}", fifo, lifo);
+    }
+
+    #[test]
+    fn sampling() {
+        let c = Config {
+            seed: Some(1),
+            sample_interval: Some(100),
+            ..config()
+        };
+        let r = Simulation::new(c).run();
+        assert_eq!(r.samples.len(), 100);
+        assert!(r.samples.iter().enumerate().all(|(i, s)| s.time == Ticks(100 * i as u32)));
+        assert!(r.samples.iter().all(|s| s.busy || s.qlen == 0));
+        assert!(Simulation::new(config()).run().samples.is_empty());
+    }
+
+    #[test]
+    fn fast_forward() {
+        // Fast-forwarding through idle periods must not change any of the results.
+        let c = Config {
+            rate: 100,
+            seed: Some(1),
+            batching: Some(Batching::Count(10)),
+            warmup_detection: Some(WarmupDetection::Mser5),
+            warmup: 0.25,
+            schedule: vec![(0.5, Change::Rate(500))],
+            sample_interval: Some(7),
+            window: Some(100),
+            ..config()
+        };
+        let mut ticked = Simulation::new(c.clone());
+        while ticked.tick() {}
+        let (a, b) = (ticked.results(), Simulation::new(c).run());
+
+        assert_eq!(a.packets_generated, b.packets_generated);
+        assert_eq!(a.packets_processed, b.packets_processed);
+        assert_eq!(a.qlen.len(), b.qlen.len());
+        assert!((a.qlen.mean() - b.qlen.mean()).abs() < 1e-12);
+        assert!((a.qlen.stddev() - b.qlen.stddev()).abs() < 1e-12);
+        assert!((a.idle_proportion - b.idle_proportion).abs() < 1e-12);
+        assert!((a.sojourn.mean() - b.sojourn.mean()).abs() < 1e-12);
+        let (qa, qb) = (a.qlen_batches.unwrap(), b.qlen_batches.unwrap());
+        assert_eq!(qa.batches, qb.batches);
+        assert!((qa.mean - qb.mean).abs() < 1e-12);
+        let (ua, ub) = (a.utilization_batches.unwrap(), b.utilization_batches.unwrap());
+        assert!((ua.mean - ub.mean).abs() < 1e-12);
+        assert!((ua.half_width - ub.half_width).abs() < 1e-12);
+        let (wa, wb) = (a.warmup.unwrap(), b.warmup.unwrap());
+        assert_eq!(wa.series.len(), wb.series.len());
+        assert_eq!(wa.truncation, wb.truncation);
+        assert_eq!(a.samples, b.samples);
+        assert_eq!(a.windows, b.windows);
+    }
+
+    #[test]
+    fn chained_by_trace() {
+        // The departures of one run, replayed as the arrivals of the next.
+        let mut first = Simulation::new(Config { seed: Some(1), ..config() });
+        let departures: Vec<(Ticks, TraceEntry)> = first
+            .events()
+            .filter_map(|e| match e {
+                Event::Departure { time, packet } => Some((
+                    time,
+                    TraceEntry {
+                        time: time.seconds(1e4),
+                        length: packet.length,
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+        let trace = departures.iter().map(|&(_, e)| e).collect();
+        let mut second = Simulation::new(Config {
+            pspeed: 2000,
+            trace: Some(Arc::new(trace)),
+            ..config()
+        });
+        let arrivals: Vec<Ticks> = second
+            .events()
+            .filter_map(|e| match e {
+                Event::Arrival { time, .. } => Some(time),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(arrivals.len(), departures.len());
+        // A packet generated at tick t is accounted for during the tick ending at t, i.e. the one
+        // starting at t - 1, give or take rounding.
+        for (a, &(d, _)) in arrivals.iter().zip(&departures) {
+            assert!(*a == d || *a + Ticks(1) == d, "{} vs {}", a, d);
+        }
+        assert_eq!(second.results().packets_generated, first.results().packets_processed);
+    }
+
+    #[test]
+    fn event_stream() {
+        let c = Config {
+            seed: Some(1),
+            qlimit: Some(1),
+            ..config()
+        };
+        let mut sim = Simulation::new(c);
+        let (mut arrivals, mut enqueues, mut drops, mut starts, mut departures) = (0, 0, 0, 0, 0);
+        let (mut last, mut next_id) = (Ticks::ZERO, 0);
+        for event in sim.events() {
+            assert!(event.time() >= last);
+            last = event.time();
+            match event {
+                Event::Arrival { packet, .. } => {
+                    assert_eq!(packet.id, next_id);
+                    next_id += 1;
+                    arrivals += 1;
+                }
+                Event::Enqueue { .. } => enqueues += 1,
+                Event::Drop { .. } => drops += 1,
+                Event::ServiceStart { .. } => starts += 1,
+                Event::Departure { time, packet } => {
+                    assert!(time > packet.time_generated);
+                    departures += 1;
+                }
+            }
+        }
+
+        let r = sim.results();
+        assert_eq!(arrivals, r.packets_generated);
+        assert_eq!(drops, r.packets_dropped);
+        assert_eq!(enqueues + drops, arrivals);
+        assert_eq!(departures, r.packets_processed);
+        assert!(starts >= departures && starts <= departures + 1);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let mut sim = Simulation::new(Config { seed: Some(1), ..config() });
+        sim.schedule(0.5, Change::Pause).unwrap();
+        assert!(sim.advance());
+        assert_eq!(sim.now(), Ticks(5000));
+        assert!(!sim.tick());
+
+        sim.resume();
+        assert!(!sim.advance());
+        assert!(sim.done());
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut sim = Simulation::new(Config { seed: Some(1), ..config() });
+        sim.schedule(0.5, Change::Pause).unwrap();
+        assert!(sim.advance());
+        sim.resume();
+        let (mut same, mut faster) = (sim.snapshot(), sim.snapshot());
+        assert_eq!(same.now(), Ticks(5000));
+        assert_eq!(same.server().qlen(), sim.server().qlen());
+
+        // Left alone, a snapshot runs just as the original does, whereas one with a faster server
+        // from here on sees shorter sojourn times.
+        let r = sim.run();
+        assert!(!same.advance());
+        let s = same.results();
+        assert_eq!(s.packets_generated, r.packets_generated);
+        assert_eq!(s.packets_processed, r.packets_processed);
+        assert_eq!(s.sojourn.mean(), r.sojourn.mean());
+        faster.set_pspeed(2 * faster.config().pspeed).unwrap();
+        let f = faster.run();
+        assert_eq!(f.packets_generated, r.packets_generated);
+        assert!(f.sojourn.mean() < r.sojourn.mean());
+    }
+
+    #[test]
+    fn scheduled_changes() {
+        let c = Config {
+            seed: Some(1),
+            schedule: vec![
+                (0.25, Change::Pause),
+                (0.25, Change::Rate(2000)),
+                (0.5, Change::Qlimit(Some(10))),
+            ],
+            ..config()
+        };
+        let mut sim = Simulation::new(c);
+        assert!(sim.advance());
+        assert_eq!(sim.config().rate, 900);
+        sim.resume();
+        sim.run_until(|sim| sim.now() == Ticks(5000));
+        assert_eq!(sim.config().rate, 2000);
+        assert_eq!(sim.config().qlimit, None);
+
+        // With arrivals at twice the service rate, the limit is hit soon after it applies.
+        let r = sim.run();
+        assert!(r.packets_dropped > 0);
+        assert!(r.leftover <= 10);
+    }
+
+    #[test]
+    fn invalid_changes() {
+        // Stopping arrivals or the server outright is rejected, however the change comes in.
+        let mut sim = Simulation::new(Config { seed: Some(1), ..config() });
+        assert!(sim.set_rate(0).is_err());
+        assert!(sim.set_pspeed(0).is_err());
+        assert!(sim.schedule(0.5, Change::Rate(0)).is_err());
+        assert!(sim.schedule(f64::NAN, Change::Pause).is_err());
+        assert_eq!(sim.config().rate, 900);
+        for &(at, change) in &[(0.5, Change::Pspeed(0)), (f64::INFINITY, Change::Pause)] {
+            let c = Config { schedule: vec![(at, change)], ..config() };
+            assert!(c.validate().is_err());
+        }
+
+        // Left unvalidated, an invalid change is skipped rather than applied.
+        let c = Config { seed: Some(1), schedule: vec![(0.25, Change::Rate(0))], ..config() };
+        let mut sim = Simulation::new(c);
+        sim.run_until(|sim| sim.now() == Ticks(5000));
+        assert_eq!(sim.config().rate, 900);
+    }
+
+    #[test]
+    fn warmup_detection() {
+        for &method in &[WarmupDetection::Mser5, WarmupDetection::Welch] {
+            let c = Config {
+                seed: Some(1),
+                warmup_detection: Some(method),
+                ..config()
+            };
+            let w = Simulation::new(c).run().warmup.unwrap();
+            assert_eq!(w.series.len(), WARMUP_INTERVALS as usize);
+            assert_eq!(w.sojourn_series.len(), WARMUP_INTERVALS as usize);
+            assert!(w.sojourn_series[999] > 0.0);
+            assert_eq!(w.interval, 1e-3);
+            assert!(w.truncation >= 0.0 && w.truncation <= 1.0);
+        }
+
+        // Welch's procedure averages the series across replications before smoothing them.
+        let warmups: Vec<Warmup> = (1..5)
+            .map(|seed| {
+                let c = Config {
+                    seed: Some(seed),
+                    warmup_detection: Some(WarmupDetection::Welch),
+                    ..config()
+                };
+                Simulation::new(c).run().warmup.unwrap()
+            })
+            .collect();
+        let w = Warmup::averaged(&warmups).unwrap();
+        assert_eq!(w.series.len(), WARMUP_INTERVALS as usize);
+        let mean = |s: &[f64]| s.iter().sum::<f64>() / s.len() as f64;
+        let means: Vec<f64> = warmups.iter().map(|w| mean(&w.series)).collect();
+        assert!((mean(&w.series) - mean(&means)).abs() < 1e-9);
+        assert!(w.truncation >= 0.0 && w.truncation <= 1.0);
+        assert!(Warmup::averaged(&[]).is_none());
+    }
+
+    #[test]
+    fn warmup_deletion() {
+        let c = Config {
+            seed: Some(1),
+            warmup_detection: Some(WarmupDetection::Mser5),
+            sample_interval: Some(100),
+            ..config()
+        };
+        let full = Simulation::new(c.clone()).run();
+        let r = Simulation::new(Config { warmup: 0.4, ..c }).run();
+        // Only the last 0.6s are accounted for, bar the time series.
+        assert_eq!(r.elapsed, 0.6);
+        assert_eq!(r.qlen.len(), 6000);
+        assert_eq!(r.occupancy.iter().sum::<u64>(), 6000);
+        assert!(r.packets_generated < full.packets_generated);
+        assert!((f64::from(r.packets_generated) - 540.0).abs() < 60.0, "{}", r.packets_generated);
+        assert_eq!(r.classes[0].packets_generated, r.packets_generated);
+        assert!(r.packets_processed <= r.packets_generated + 1);
+        assert!((r.utilization - 0.9).abs() < 0.1, "{}", r.utilization);
+        assert!((r.little.lambda - 900.0).abs() < 100.0, "{}", r.little.lambda);
+        assert_eq!(r.samples, full.samples);
+        assert_eq!(r.warmup.unwrap().series, full.warmup.unwrap().series);
+    }
+
+    #[test]
+    fn builder() {
+        let sim = SimulationBuilder::new()
+            .arrivals(900, Distribution::Deterministic)
+            .packets(2, Distribution::Exponential)
+            .server(2000, Some(8), Discipline::Lifo)
+            .duration(2)
+            .resolution(1e4)
+            .seed(1)
+            .stop_after(100)
+            .at(1.0, Change::Rate(1000))
+            .percentiles(vec![50.0], Estimator::P2, Bucketing::default())
+            .build()
+            .unwrap();
+        let c = sim.config();
+        assert_eq!((c.rate, c.psize, c.pspeed, c.qlimit), (900, 2, 2000, Some(8)));
+        assert_eq!(c.model(), "D/M/1/9/∞/LIFO");
+        assert_eq!(c.schedule, vec![(1.0, Change::Rate(1000))]);
+        let r = sim.run();
+        assert_eq!(r.packets_processed, 100);
+        assert_eq!(r.sojourn_percentiles.len(), 1);
+
+        // Left unset, it's the defaults, and invalid configurations are turned away.
+        let c = SimulationBuilder::new().config().unwrap();
+        assert_eq!((c.rate, c.duration, c.seed), (DEFAULT_RATE, DEFAULT_DURATION, None));
+        let err = SimulationBuilder::new().duration(2).warmup(2.0).build().err().unwrap();
+        let reason = "it must be shorter than the duration";
+        assert_eq!(err.to_string(), format!("invalid warm-up period 2: {}", reason));
+        let err = SimulationBuilder::new().resolution(1e10).config().unwrap_err().to_string();
+        assert!(err.starts_with("invalid resolution"), "{}", err);
+        let err = SimulationBuilder::new().server(0, None, Discipline::Fifo).config().unwrap_err();
+        match err {
+            QsimError::InvalidParameter { ref parameter, .. } => assert_eq!(parameter, "pspeed"),
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+}
@@ -1,83 +1,203 @@
-use std::collections::VecDeque;
+extern crate rand;
+
+use self::rand::{Rng, XorShiftRng};
+use std::collections::{btree_map, BTreeMap, HashMap, VecDeque};
+use std::ops::Index;
 use generators::Generator;
+use ring::Ring;
+use rng;
+use simulation::{DEFAULT_PSPEED, DEFAULT_QLIMIT, DEFAULT_RESOLUTION};
+use time::Ticks;
 
-// Packet holds the value of the time unit that it was generated at, and its length.
-#[derive(Clone)]
+// Packet holds the tick that it was generated at, and its length, along with
+// the traffic class, flow and tenant it belongs to, and the color it was marked (green unless
+// packets are). Packets are numbered in the order they're generated.
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Packet {
-    pub time_generated: u32,
+    pub id: u32,
+    pub time_generated: Ticks,
     pub length: u32,
+    pub class: u32,
+    pub flow: u32,
+    pub tenant: u32,
+    pub color: Color,
+}
+
+// Color is what a marker colors packets arriving (see Marker): green if within the committed
+// rate, yellow if over it but within the peak rate, and red if over both.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Color {
+    #[default]
+    Green,
+    Yellow,
+    Red,
+}
+
+impl Color {
+    // Color.name returns the name of the color.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Color::Green => "green",
+            Color::Yellow => "yellow",
+            Color::Red => "red",
+        }
+    }
 }
 
+// Hook is a callback fired on a packet, free to change it; Inspect, one that only looks at it; and
+// Filter, one that also decides whether the packet goes on (see Server.on_enqueue).
+type Hook = Box<dyn FnMut(&mut Packet)>;
+type Inspect = Box<dyn FnMut(&Packet)>;
+type Filter = Box<dyn FnMut(&mut Packet) -> bool>;
+
 // ClientStatistics is the set of statistics we care about post-simulation as far as the client is
 // concerned.
+#[derive(Clone, Debug, PartialEq)]
 pub struct ClientStatistics {
     pub packets_generated: u32,
+    // The number of ticks spent blocked, generating nothing (see Client.stall).
+    pub ticks_blocked: u32,
 }
 
 impl ClientStatistics {
     fn new() -> ClientStatistics {
-        ClientStatistics { packets_generated: 0 }
+        ClientStatistics {
+            packets_generated: 0,
+            ticks_blocked: 0,
+        }
     }
 }
 
-// Client generates packets according as per the parametrized generators::Generator. We maintain a
-// ticker count to the next time a packet is to be generated, moving forward at ticks of the
+// Client generates packets according as per the parametrized generators::Generator. We maintain
+// the (fractional) time the next packet is to be generated at, moving forward at ticks of the
 // specified resolution. We also collect client statistics through this progression.
 pub struct Client<G: Generator> {
     resolution: f64,
-    ticker: u32,
+    // The number of ticks elapsed, and the time of the next packet generation in ticks since the
+    // start. Keeping the latter fractional means that we don't accumulate rounding errors, and
+    // that several packets may be generated within a single tick.
+    elapsed: Ticks,
+    next: f64,
     generator: G,
+    // Callbacks fired on every packet generated (see Client.on_generate).
+    generate_hooks: Vec<Hook>,
     pub statistics: ClientStatistics,
 }
 
+// A copy of a client carries on from where the original is at, generating the same packets, bar
+// the hooks registered with it which aren't carried over.
+impl<G: Generator + Clone> Clone for Client<G> {
+    fn clone(&self) -> Client<G> {
+        Client {
+            resolution: self.resolution,
+            elapsed: self.elapsed,
+            next: self.next,
+            generator: self.generator.clone(),
+            generate_hooks: vec![],
+            statistics: self.statistics.clone(),
+        }
+    }
+}
+
 impl<G: Generator> Client<G> {
-    // Client::new seeds the ticker using the provided generator.
-    pub fn new(generator: G, resolution: f64) -> Client<G> {
+    // Client::new seeds the time of the first packet generation using the provided generator.
+    pub fn new(mut generator: G, resolution: f64) -> Client<G> {
         Client {
-            ticker: generator.next_event(resolution),
-            generator: generator,
+            elapsed: Ticks::ZERO,
+            next: generator.next_interval(resolution),
+            generator,
+            generate_hooks: vec![],
             statistics: ClientStatistics::new(),
-            resolution: resolution,
+            resolution,
         }
     }
 
     // The caller is responsible for calling Client.tick() at fixed time intervals, moving the
-    // client simulator one time unit per call. We return a boolean indicating whether or not a
-    // packet is generated in the most recently completed time unit.
-    //
-    // We're careful to check if self.ticker == 0 before decrementing because the parametrized
-    // generator may very well return 0 (see top-level comment in src/generators.rs).
-    pub fn tick(&mut self) -> bool {
-        // TODO(irfansharif): Resolution mismatch; no possibility of generating multiple packets.
-        if self.ticker == 0 {
-            self.statistics.packets_generated += 1;
-            self.ticker = self.generator.next_event(self.resolution);
-            return true;
-        }
-
-        self.ticker -= 1;
-        if self.ticker == 0 {
-            self.statistics.packets_generated += 1;
-            self.ticker = self.generator.next_event(self.resolution);
-            true
-        } else {
-            false
+    // client simulator one time unit per call. We return the number of packets generated in the
+    // most recently completed time unit, i.e. those generated at times in (t - 1, t] for the t-th
+    // tick. Packets generated at time 0 are accounted for in the first tick.
+    pub fn tick(&mut self) -> u32 {
+        self.elapsed += Ticks(1);
+        let mut generated = 0;
+        while self.next <= f64::from(self.elapsed) {
+            generated += 1;
+            self.next += self.generator.next_interval(self.resolution);
+        }
+        self.statistics.packets_generated += generated;
+        generated
+    }
+
+    // Client.stall accounts for a tick spent blocked, as a flow-controlled source is while there's
+    // no room for what it's generated. The client's clock stands still, so generation picks up
+    // where it left off once unblocked rather than making up for the ticks lost.
+    pub fn stall(&mut self) {
+        self.statistics.ticks_blocked += 1;
+    }
+
+    // Client.generate passes a packet generated in the most recent tick through the hooks
+    // registered with Client.on_generate. The client only keeps count of the packets it generates,
+    // it's up to the caller to put them together.
+    pub fn generate(&mut self, mut packet: Packet) -> Packet {
+        for hook in &mut self.generate_hooks {
+            hook(&mut packet);
         }
+        packet
+    }
+
+    // Client.on_generate registers a callback fired on every packet generated, in the order
+    // registered, free to change the packet (its length, say) before it's sent on its way.
+    pub fn on_generate<F: FnMut(&mut Packet) + 'static>(&mut self, hook: F) {
+        self.generate_hooks.push(Box::new(hook));
+    }
+
+    // Client.idle_ticks returns the number of upcoming ticks guaranteed not to generate a packet.
+    pub fn idle_ticks(&self) -> Ticks {
+        Ticks(self.next.ceil() as u32).saturating_sub(self.elapsed + Ticks(1))
+    }
+
+    // Client.skip moves the client forward by the given number of ticks, which must not exceed
+    // Client.idle_ticks().
+    pub fn skip(&mut self, ticks: Ticks) {
+        debug_assert!(ticks <= self.idle_ticks());
+        self.elapsed += ticks;
     }
 
     // Client.packets_generated returns the number of packets generated by the client thus far.
     pub fn packets_generated(&self) -> u32 {
         self.statistics.packets_generated
     }
+
+    // Client.set_rate changes the rate of the underlying generator. The packet currently scheduled
+    // is unaffected, the new rate applies from the one after onwards.
+    pub fn set_rate(&mut self, rate: f64) {
+        self.generator.set_rate(rate);
+    }
+}
+
+// BusyPeriod is an uninterrupted stretch of time during which the server is busy, from the tick a
+// packet enters service at an idle server to the tick the server next runs out of packets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusyPeriod {
+    pub ticks: Ticks,
+    pub packets: u32,
 }
 
 // ServerStatistics is the set of statistics we care about post-simulation as far as the server is
 // concerned.
+#[derive(Clone, Debug, PartialEq)]
 pub struct ServerStatistics {
     pub packets_processed: u32,
+    // The packets dropped, those discarded once preempted included.
     pub packets_dropped: u32,
-    pub idle_count: u32,
-    pub process_count: u32,
+    // The ticks spent idle and busy, summed across servers (so that with several, as many count
+    // for every tick).
+    pub idle_count: u64,
+    pub process_count: u64,
+    // The number of times service was preempted, and the packets preempted that were discarded.
+    pub preemptions: u32,
+    pub packets_discarded: u32,
+    // Busy periods completed thus far, in the order they occurred.
+    pub busy_periods: Vec<BusyPeriod>,
 }
 
 impl ServerStatistics {
@@ -87,100 +207,772 @@ impl ServerStatistics {
             packets_dropped: 0,
             idle_count: 0,
             process_count: 0,
+            preemptions: 0,
+            packets_discarded: 0,
+            busy_periods: vec![],
+        }
+    }
+}
+
+// Discipline is the order in which queued packets are served. Service is never preempted, unless
+// the server's given a preemption policy (see Preemption).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Discipline {
+    Fifo,
+    Lifo,
+    // Strict priority between traffic classes, FIFO within each. The classes are listed from the
+    // highest priority down; those left out (all of them, if empty) rank below the ones listed,
+    // in order of their class.
+    Priority(Vec<u32>),
+    // A polling system: each traffic class has a queue of its own, FIFO, and the server visits
+    // them in turn (0, 1, ... and back to 0), serving as many packets at each visit as the queue's
+    // setting allows before moving on to the next, the switch taking no time. The settings are
+    // listed by class, those left out (all of them, if empty) being exhaustive.
+    Polling(Vec<Visit>),
+}
+
+// Visit is how much of its queue the server serves on visiting it in a polling system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Visit {
+    // Until the queue's empty, packets arriving during the visit included.
+    Exhaustive,
+    // Only the packets queued as the visit begins, those arriving during it left for the next.
+    Gated,
+    // Up to the given number of packets (at least one), until the queue's empty.
+    Limited(u32),
+}
+
+impl Visit {
+    // Visit::parse returns the setting named: exhaustive, gated or the limit itself.
+    pub fn parse(s: &str) -> Option<Visit> {
+        match s {
+            "exhaustive" => Some(Visit::Exhaustive),
+            "gated" => Some(Visit::Gated),
+            k => k.parse().ok().filter(|&k| k > 0).map(Visit::Limited),
+        }
+    }
+
+    // Visit.name returns the name the setting goes by (see Visit::parse).
+    pub fn name(&self) -> String {
+        match *self {
+            Visit::Exhaustive => "exhaustive".to_string(),
+            Visit::Gated => "gated".to_string(),
+            Visit::Limited(k) => k.to_string(),
+        }
+    }
+}
+
+impl Discipline {
+    // Discipline.kendall returns the symbol for the discipline in Kendall's notation.
+    pub fn kendall(&self) -> &'static str {
+        match *self {
+            Discipline::Fifo => "FIFO",
+            Discipline::Lifo => "LIFO",
+            Discipline::Priority(_) => "PRIO",
+            Discipline::Polling(_) => "POLL",
+        }
+    }
+
+    // Discipline::rank returns the rank of the packet's class, lower ranks being served first.
+    fn rank(order: &[u32], packet: &Packet) -> (usize, u32) {
+        match order.iter().position(|&c| c == packet.class) {
+            Some(i) => (i, 0),
+            None => (order.len(), packet.class),
+        }
+    }
+}
+
+// Preemption is what becomes of a packet whose service is interrupted by one taking precedence over
+// it: under LIFO, any packet arriving; under priority, one of a higher priority class. Preemption
+// has no bearing on FIFO service.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preemption {
+    // The packet's queued again, its service picking up where it left off.
+    Resume,
+    // The packet's queued again, its service starting over, the work done on it lost.
+    Restart,
+    // The packet's dropped.
+    Discard,
+}
+
+impl Preemption {
+    // Preemption::parse returns the policy named, if known.
+    pub fn parse(s: &str) -> Option<Preemption> {
+        match s {
+            "resume" => Some(Preemption::Resume),
+            "restart" => Some(Preemption::Restart),
+            "discard" => Some(Preemption::Discard),
+            _ => None,
+        }
+    }
+
+    // Preemption.name returns the name the policy goes by (see Preemption::parse).
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Preemption::Resume => "resume",
+            Preemption::Restart => "restart",
+            Preemption::Discard => "discard",
+        }
+    }
+}
+
+// ServerHooks are the callbacks registered with a server, fired in the order registered (see
+// Server.on_enqueue and friends).
+#[derive(Default)]
+struct ServerHooks {
+    enqueue: Vec<Filter>,
+    drop: Vec<Inspect>,
+    service_start: Vec<Hook>,
+    departure: Vec<Inspect>,
+}
+
+// QueueBackend is what a server's queue is backed by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueueBackend {
+    // A fixed-capacity ring buffer if the buffer's limited, a growable one otherwise.
+    Auto,
+    // A growable ring buffer (VecDeque), allocating as the queue grows.
+    Growable,
+    // A ring buffer as large as the buffer limit, allocated up front so that nothing is allocated
+    // while queueing (see ring::Ring). Unlimited buffers fall back to a growable one, as do the
+    // queues of each class under priority.
+    Fixed,
+}
+
+// Queue holds the packets queued at a server, in the order they arrived in, or under priority by
+// class (see Classes).
+#[derive(Clone, Debug)]
+enum Queue {
+    Growable(VecDeque<Packet>),
+    Fixed(Ring<Packet>),
+    Classes(Classes),
+}
+
+impl Queue {
+    // Queue::new returns an empty queue with the given backend, for a buffer with the given limit
+    // served as per the discipline.
+    fn new(backend: QueueBackend, limit: Option<usize>, discipline: &Discipline) -> Queue {
+        match (backend, limit, discipline) {
+            (_, _, Discipline::Priority(order)) => Queue::Classes(Classes::new(order)),
+            (QueueBackend::Auto, Some(limit), _) | (QueueBackend::Fixed, Some(limit), _) => {
+                Queue::Fixed(Ring::with_capacity(limit))
+            }
+            _ => Queue::Growable(VecDeque::new()),
+        }
+    }
+
+    // Queue.rebuild moves the packets queued into a new queue with the given backend and limit,
+    // large enough to hold them all should the limit be lower than the number queued, and served
+    // as per the discipline. They're moved in the order they're queued in, class by class under
+    // priority.
+    fn rebuild(&mut self, backend: QueueBackend, limit: Option<usize>, discipline: &Discipline) {
+        let mut queue = Queue::new(backend, limit.map(|l| l.max(self.len())), discipline);
+        while let Some(p) = self.pop_front() {
+            queue.push_back(p);
+        }
+        *self = queue;
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Queue::Growable(ref q) => q.len(),
+            Queue::Fixed(ref q) => q.len(),
+            Queue::Classes(ref q) => q.len,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Queue.push_back queues the packet, which a fixed-capacity queue must have room for.
+    fn push_back(&mut self, packet: Packet) {
+        match *self {
+            Queue::Growable(ref mut q) => q.push_back(packet),
+            Queue::Fixed(ref mut q) => q.push_back(packet).expect("the queue is full"),
+            Queue::Classes(ref mut q) => q.push(packet, VecDeque::push_back),
+        }
+    }
+
+    // Queue.push_front queues the packet ahead of the others, which a fixed-capacity queue must
+    // have room for.
+    fn push_front(&mut self, packet: Packet) {
+        match *self {
+            Queue::Growable(ref mut q) => q.push_front(packet),
+            Queue::Fixed(ref mut q) => q.push_front(packet).expect("the queue is full"),
+            Queue::Classes(ref mut q) => q.push(packet, VecDeque::push_front),
+        }
+    }
+
+    fn pop_front(&mut self) -> Option<Packet> {
+        match *self {
+            Queue::Growable(ref mut q) => q.pop_front(),
+            Queue::Fixed(ref mut q) => q.pop_front(),
+            Queue::Classes(ref mut q) => q.pop(|mut queues| queues.find_map(VecDeque::pop_front)),
+        }
+    }
+
+    fn pop_back(&mut self) -> Option<Packet> {
+        match *self {
+            Queue::Growable(ref mut q) => q.pop_back(),
+            Queue::Fixed(ref mut q) => q.pop_back(),
+            Queue::Classes(ref mut q) => q.pop(|queues| queues.rev().find_map(VecDeque::pop_back)),
+        }
+    }
+
+    fn remove(&mut self, i: usize) -> Option<Packet> {
+        match *self {
+            Queue::Growable(ref mut q) => q.remove(i),
+            Queue::Fixed(ref mut q) => q.remove(i),
+            Queue::Classes(ref mut q) => q.remove(i),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &Packet> + '_> {
+        match *self {
+            Queue::Growable(ref q) => Box::new(q.iter()),
+            Queue::Fixed(ref q) => Box::new(q.iter()),
+            Queue::Classes(ref q) => Box::new(q.queues.values().flatten()),
+        }
+    }
+}
+
+// Classes holds the packets queued under priority, a FIFO per traffic class kept in order of the
+// classes' rank (see Discipline::rank), the packet next up for service being at the head of the
+// first class with any queued. Finding it takes as long as there are classes, not packets. A
+// class' queue is kept on emptying, along with what it allocated.
+#[derive(Clone, Debug)]
+struct Classes {
+    order: Vec<u32>,
+    queues: BTreeMap<(usize, u32), VecDeque<Packet>>,
+    len: usize,
+}
+
+impl Classes {
+    fn new(order: &[u32]) -> Classes {
+        Classes {
+            order: order.to_vec(),
+            queues: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    // Classes.push queues the packet in its class' queue, at the back or front.
+    fn push(&mut self, packet: Packet, push: fn(&mut VecDeque<Packet>, Packet)) {
+        let rank = Discipline::rank(&self.order, &packet);
+        push(self.queues.entry(rank).or_default(), packet);
+        self.len += 1;
+    }
+
+    // Classes.pop takes the packet the given function pops off the classes' queues, in order.
+    fn pop<F>(&mut self, pop: F) -> Option<Packet>
+    where
+        F: FnOnce(btree_map::ValuesMut<(usize, u32), VecDeque<Packet>>) -> Option<Packet>,
+    {
+        let packet = pop(self.queues.values_mut())?;
+        self.len -= 1;
+        Some(packet)
+    }
+
+    // Classes.remove removes the i-th packet queued, counting class by class.
+    fn remove(&mut self, mut i: usize) -> Option<Packet> {
+        for queue in self.queues.values_mut() {
+            if i < queue.len() {
+                self.len -= 1;
+                return queue.remove(i);
+            }
+            i -= queue.len();
         }
+        None
     }
 }
 
-// Server stores packets in a queue and processes them.
+impl Index<usize> for Queue {
+    type Output = Packet;
+
+    fn index(&self, i: usize) -> &Packet {
+        let packet = match *self {
+            Queue::Growable(ref q) => q.get(i),
+            Queue::Fixed(ref q) => q.get(i),
+            Queue::Classes(ref q) => q.queues.values().flatten().nth(i),
+        };
+        packet.expect("index out of bounds")
+    }
+}
+
+// Serving is a packet in service, along with the work left on it, in bits scaled up by the
+// resolution, a tick's worth of work being the speed in bits/s. The work's whole for whole speeds
+// and resolutions, counting down exactly rather than accumulating fractions of a bit that may fall
+// short of the packet's length (a tenth of a bit added ten times is less than a bit).
+#[derive(Clone, Debug)]
+struct Serving {
+    packet: Packet,
+    work: f64,
+    // Whether the packet entered service in the most recent tick.
+    started: bool,
+}
+
+// Server stores packets in a queue and processes them, with one or more servers (channels) taking
+// packets off of the same queue, each at the processing speed.
 pub struct Server {
-    queue: VecDeque<Packet>,
+    // Queued packets, in the order they arrived in.
+    queue: Queue,
+    backend: QueueBackend,
     buffer_limit: Option<usize>,
+    discipline: Discipline,
+    preemption: Option<Preemption>,
     resolution: f64,
     pub statistics: ServerStatistics,
     // Processing variables
     pspeed: f64,
-    currently_processing: Option<Packet>,
-    bits_processed: f64,
+    // The packet in service at each server, if any.
+    serving: Vec<Option<Serving>>,
+    // Copies of the packets that both entered and left service within the most recent tick.
+    instant: Vec<Packet>,
+    // The queue (class) being visited in a polling system, and the number of packets that may yet
+    // be served in the visit.
+    visit: Option<(u32, u32)>,
+    // Whether a packet arrived since the one in service entered service, the work left on the
+    // packets preempted that are to resume, by id, and those discarded in the most recent tick.
+    arrived: bool,
+    remaining: HashMap<u32, f64>,
+    discarded: Vec<Packet>,
+    // The busy period in progress, if any.
+    busy: Option<BusyPeriod>,
+    hooks: ServerHooks,
 }
 
-impl Server {
-    // Server::new returns a server with the specified buffer limit, if any.
-    pub fn new(resolution: f64, pspeed: f64, buffer_limit: Option<usize>) -> Server {
+// ServerBuilder puts together a server, one setting at a time, anything left unset taking on the
+// same default as on the command line: a server processing 10,000 bits/s, with an unlimited FIFO
+// buffer, ticking a million times a second.
+//
+//   let server = ServerBuilder::new().pspeed(1e6).buffer_limit(Some(64)).build();
+#[derive(Clone, Debug)]
+pub struct ServerBuilder {
+    resolution: f64,
+    pspeed: f64,
+    servers: usize,
+    buffer_limit: Option<usize>,
+    discipline: Discipline,
+    preemption: Option<Preemption>,
+    backend: QueueBackend,
+}
+
+impl ServerBuilder {
+    pub fn new() -> ServerBuilder {
+        ServerBuilder {
+            resolution: DEFAULT_RESOLUTION,
+            pspeed: f64::from(DEFAULT_PSPEED),
+            servers: 1,
+            buffer_limit: DEFAULT_QLIMIT,
+            discipline: Discipline::Fifo,
+            preemption: None,
+            backend: QueueBackend::Auto,
+        }
+    }
+
+    // ServerBuilder.resolution sets the number of ticks per second.
+    pub fn resolution(mut self, resolution: f64) -> ServerBuilder {
+        self.resolution = resolution;
+        self
+    }
+
+    // ServerBuilder.pspeed sets the processing speed, in bits/s.
+    pub fn pspeed(mut self, pspeed: f64) -> ServerBuilder {
+        self.pspeed = pspeed;
+        self
+    }
+
+    // ServerBuilder.servers sets the number of servers taking packets off of the queue, each at the
+    // processing speed. Preemption and polling only make sense of a single one.
+    pub fn servers(mut self, servers: usize) -> ServerBuilder {
+        self.servers = servers;
+        self
+    }
+
+    // ServerBuilder.buffer_limit sets the number of packets that can be queued, if limited, beyond
+    // those in service.
+    pub fn buffer_limit(mut self, buffer_limit: Option<usize>) -> ServerBuilder {
+        self.buffer_limit = buffer_limit;
+        self
+    }
+
+    // ServerBuilder.discipline sets the order in which queued packets are served.
+    pub fn discipline(mut self, discipline: Discipline) -> ServerBuilder {
+        self.discipline = discipline;
+        self
+    }
+
+    // ServerBuilder.preemption sets what becomes of packets preempted, if service is preemptive.
+    pub fn preemption(mut self, preemption: Option<Preemption>) -> ServerBuilder {
+        self.preemption = preemption;
+        self
+    }
+
+    // ServerBuilder.queue_backend sets what the queue is backed by, which only makes a difference
+    // to performance.
+    pub fn queue_backend(mut self, backend: QueueBackend) -> ServerBuilder {
+        self.backend = backend;
+        self
+    }
+
+    pub fn build(self) -> Server {
+        assert!(self.servers >= 1, "there must be at least a server");
         Server {
-            queue: VecDeque::new(),
-            buffer_limit: buffer_limit,
-            resolution: resolution,
+            queue: Queue::new(self.backend, self.buffer_limit, &self.discipline),
+            backend: self.backend,
+            buffer_limit: self.buffer_limit,
+            discipline: self.discipline,
+            preemption: self.preemption,
+            resolution: self.resolution,
             statistics: ServerStatistics::new(),
-            pspeed: pspeed,
-            currently_processing: None,
-            bits_processed: 0.0,
+            pspeed: self.pspeed,
+            serving: vec![None; self.servers],
+            instant: vec![],
+            visit: None,
+            arrived: false,
+            remaining: HashMap::new(),
+            discarded: vec![],
+            busy: None,
+            hooks: ServerHooks::default(),
+        }
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+}
+
+// A copy of a server carries on from where the original is at, its queue, packet in service and
+// statistics included, bar the hooks registered with it which aren't carried over.
+impl Clone for Server {
+    fn clone(&self) -> Server {
+        Server {
+            queue: self.queue.clone(),
+            backend: self.backend,
+            buffer_limit: self.buffer_limit,
+            discipline: self.discipline.clone(),
+            preemption: self.preemption,
+            resolution: self.resolution,
+            statistics: self.statistics.clone(),
+            pspeed: self.pspeed,
+            serving: self.serving.clone(),
+            instant: self.instant.clone(),
+            visit: self.visit,
+            arrived: self.arrived,
+            remaining: self.remaining.clone(),
+            discarded: self.discarded.clone(),
+            busy: self.busy,
+            hooks: ServerHooks::default(),
         }
     }
+}
+
+impl Server {
+    // Server::new returns a FIFO server with the specified buffer limit, if any. See ServerBuilder
+    // for the other settings.
+    pub fn new(resolution: f64, pspeed: f64, buffer_limit: Option<usize>) -> Server {
+        ServerBuilder::new()
+            .resolution(resolution)
+            .pspeed(pspeed)
+            .buffer_limit(buffer_limit)
+            .build()
+    }
 
     // Server.enqueue enqueues a packet for delivery. If the packet is to be dropped (due to the
-    // internal queue being full it is recorded in the server's internal statistics.
-    pub fn enqueue(&mut self, packet: Packet) {
-        match self.buffer_limit {
-            Some(limit) => {
-                if self.queue.len() < limit {
-                    self.queue.push_back(packet);
-                } else {
-                    self.statistics.packets_dropped += 1
-                }
+    // internal queue being full, or a hook rejecting it) it is recorded in the server's internal
+    // statistics. We return whether or not the packet was enqueued.
+    pub fn enqueue(&mut self, mut packet: Packet) -> bool {
+        let accepted = self.hooks.enqueue.iter_mut().all(|hook| hook(&mut packet));
+        let enqueued = accepted && !self.full();
+        if enqueued {
+            self.queue.push_back(packet);
+            self.arrived = true;
+        } else {
+            self.statistics.packets_dropped += 1;
+            for hook in &mut self.hooks.drop {
+                hook(&packet);
             }
-            // Infinite queue, limit == None.
-            None => {
-                self.queue.push_back(packet);
+        }
+        enqueued
+    }
+
+    // Server.on_enqueue registers a callback fired on every packet arriving at the server, before
+    // it's queued. It's free to change the packet, and returning false drops it, as if the buffer
+    // were full: a way of injecting faults such as random loss.
+    pub fn on_enqueue<F: FnMut(&mut Packet) -> bool + 'static>(&mut self, hook: F) {
+        self.hooks.enqueue.push(Box::new(hook));
+    }
+
+    // Server.on_drop registers a callback fired on every packet dropped.
+    pub fn on_drop<F: FnMut(&Packet) + 'static>(&mut self, hook: F) {
+        self.hooks.drop.push(Box::new(hook));
+    }
+
+    // Server.on_service_start registers a callback fired on every packet entering service, free to
+    // change it (lengthening it slows its service down, say).
+    pub fn on_service_start<F: FnMut(&mut Packet) + 'static>(&mut self, hook: F) {
+        self.hooks.service_start.push(Box::new(hook));
+    }
+
+    // Server.on_departure registers a callback fired on every packet processed, as it departs.
+    pub fn on_departure<F: FnMut(&Packet) + 'static>(&mut self, hook: F) {
+        self.hooks.departure.push(Box::new(hook));
+    }
+
+    // Server.tick works through a tick's worth of work on the packets queued, returning those that
+    // departed within it in the order they did. A tick's worth of work is usually a fraction of a
+    // packet, but where it's more, work left over once a packet's done carries over to the next one
+    // in line, so a fast enough server can process several packets in a single tick.
+    pub fn tick(&mut self) -> Vec<Packet> {
+        let mut departed = vec![];
+        self.tick_into(&mut departed);
+        departed
+    }
+
+    // Server.tick_into is Server.tick, appending the packets departed to the given buffer instead,
+    // which the caller's free to reuse from one tick to the next so as not to allocate.
+    pub fn tick_into(&mut self, departed: &mut Vec<Packet>) {
+        let (busy, first) = (self.statistics.process_count, departed.len());
+        self.process(departed);
+        if self.statistics.process_count > busy {
+            let period = self.busy.get_or_insert(BusyPeriod {
+                ticks: Ticks::ZERO,
+                packets: 0,
+            });
+            period.ticks += Ticks(1);
+            period.packets += (departed.len() - first) as u32;
+        }
+        if self.idle() {
+            if let Some(period) = self.busy.take() {
+                self.statistics.busy_periods.push(period);
+            }
+        }
+        for p in &departed[first..] {
+            for hook in &mut self.hooks.departure {
+                hook(p);
             }
         }
     }
 
-    // Server.tick checks to see if a packet is currently being processed, and if so,
-    // increments Server.bits_processed, and if the resulting sum is equal to the bits
-    // in the packet, then it returns the packet and resets the state of Server.
-    pub fn tick(&mut self) -> Option<Packet> {
-        match self.currently_processing.clone() {
-            Some(p) => {
-                self.bits_processed += self.pspeed / self.resolution;
-                if (self.bits_processed as u32) < p.length {
-                    self.statistics.process_count += 1;
-                    return None;
+    // process has every server work on its packet in service for as much of the tick as it takes,
+    // first putting the next one in line into service if there's none, and so on until the tick's
+    // worth of work is used up or there's nothing left to work on, the servers taking turns in
+    // order. Packets are only ever moved, out of the queue into service and out of service on
+    // completion, never copied: bar those entering and leaving service within the same tick, which
+    // are kept a copy of for Server.started.
+    fn process(&mut self, departed: &mut Vec<Packet>) {
+        for s in self.serving.iter_mut().flatten() {
+            s.started = false;
+        }
+        self.instant.clear();
+        self.discarded.clear();
+        if let Some(policy) = self.preemption {
+            self.preempt(policy);
+        }
+        for server in 0..self.serving.len() {
+            let (mut budget, mut worked) = (self.pspeed, false);
+            loop {
+                if self.serving[server].is_none() {
+                    match self.dequeue() {
+                        Some(p) => self.start(server, p),
+                        None => break,
+                    }
+                }
+                worked = true;
+                let s = self.serving[server].as_mut().expect("no packet in service");
+                if s.work > budget {
+                    s.work -= budget;
+                    break;
                 }
-                self.currently_processing = None;
-                self.bits_processed = 0.0;
+                budget -= s.work;
+                let s = self.serving[server].take().expect("no packet in service");
                 self.statistics.packets_processed += 1;
+                if s.started {
+                    self.instant.push(s.packet.clone());
+                }
+                departed.push(s.packet);
+                if budget <= 0.0 {
+                    break;
+                }
+            }
+            if worked {
                 self.statistics.process_count += 1;
-                Some(p)
+            } else {
+                self.statistics.idle_count += 1;
             }
-            None => {
-                match self.queue.pop_front() {
-                    Some(p) => {
-                        self.currently_processing = Some(p.clone());
-                        self.bits_processed += self.pspeed / self.resolution;
-                        if (self.bits_processed as u32) < p.length {
-                            return None;
-                        }
-                        self.currently_processing = None;
-                        self.bits_processed = 0.0;
-                        self.statistics.packets_processed += 1;
-                        self.statistics.process_count += 1;
-                        Some(p)
-                    }
-                    None => {
-                        self.statistics.idle_count += 1;
-                        None
+        }
+    }
+
+    // start puts the packet into service at the given server, with the work left on it if it's
+    // resuming.
+    fn start(&mut self, server: usize, mut packet: Packet) {
+        for hook in &mut self.hooks.service_start {
+            hook(&mut packet);
+        }
+        let work = match self.remaining.remove(&packet.id) {
+            Some(work) => work,
+            None => f64::from(packet.length) * self.resolution,
+        };
+        self.serving[server] = Some(Serving { packet, work, started: true });
+        self.arrived = false;
+    }
+
+    // preempt interrupts the packet in service should a packet that arrived since take precedence
+    // over it, as per the discipline, putting that one into service instead and dealing with the
+    // one preempted as per the policy. Packets queued again go back to the head of their class (or
+    // under LIFO, behind the one preempting them), as the longest waiting.
+    fn preempt(&mut self, policy: Preemption) {
+        let current = match self.serving[0] {
+            Some(ref s) if self.arrived && !self.queue.is_empty() => &s.packet,
+            _ => return,
+        };
+        let next = match self.discipline {
+            Discipline::Fifo | Discipline::Polling(_) => return,
+            Discipline::Lifo => self.queue.len() - 1,
+            // The packet next up for service heads the queue, ahead of the others of its class.
+            Discipline::Priority(ref order) => {
+                if Discipline::rank(order, &self.queue[0]) >= Discipline::rank(order, current) {
+                    return;
+                }
+                0
+            }
+        };
+        let next = self.queue.remove(next).expect("index out of bounds");
+        let serving = self.serving[0].take().expect("no packet in service");
+        let (preempted, work) = (serving.packet, serving.work);
+        self.statistics.preemptions += 1;
+        match policy {
+            Preemption::Discard => {
+                self.statistics.packets_dropped += 1;
+                self.statistics.packets_discarded += 1;
+                for hook in &mut self.hooks.drop {
+                    hook(&preempted);
+                }
+                self.discarded.push(preempted);
+            }
+            _ => {
+                if policy == Preemption::Resume {
+                    self.remaining.insert(preempted.id, work);
+                }
+                match self.discipline {
+                    Discipline::Lifo => self.queue.push_back(preempted),
+                    _ => self.queue.push_front(preempted),
+                }
+            }
+        }
+        self.start(0, next);
+    }
+
+    // dequeue removes the next packet to be served from the queue, as per the discipline.
+    fn dequeue(&mut self) -> Option<Packet> {
+        match self.discipline {
+            Discipline::Fifo => self.queue.pop_front(),
+            Discipline::Lifo => self.queue.pop_back(),
+            // The queue's kept by class (see Classes), the packet next up for service at its head.
+            Discipline::Priority(_) => self.queue.pop_front(),
+            Discipline::Polling(ref visits) => {
+                // Visiting the queues in turn, there's bound to be one with a packet to serve
+                // within a cycle.
+                let queues = self.queue.iter().map(|p| p.class + 1).max()?.max(visits.len() as u32);
+                let queue = &self.queue;
+                let allowance = |class: u32| match visits.get(class as usize) {
+                    None | Some(&Visit::Exhaustive) => u32::MAX,
+                    Some(&Visit::Gated) => queue.iter().filter(|p| p.class == class).count() as u32,
+                    Some(&Visit::Limited(k)) => k,
+                };
+                let (mut class, mut left) = self.visit.unwrap_or_else(|| (0, allowance(0)));
+                loop {
+                    let next = (0..queue.len()).find(|&i| queue[i].class == class);
+                    if let Some(i) = next.filter(|_| left > 0) {
+                        self.visit = Some((class, left - 1));
+                        return self.queue.remove(i);
                     }
+                    class = (class + 1) % queues;
+                    left = allowance(class);
                 }
             }
         }
     }
 
+    // Server.queue returns an iterator over the packets waiting to be processed, in the order they
+    // arrived in (which isn't necessarily the order they'll be served in), class by class under
+    // priority.
+    pub fn queue(&self) -> impl Iterator<Item = &Packet> {
+        self.queue.iter()
+    }
+
+    // Server.idle returns whether the server has nothing to process, neither in service nor queued.
+    pub fn idle(&self) -> bool {
+        self.serving.iter().all(Option::is_none) && self.queue.is_empty()
+    }
+
+    // Server.skip_idle accounts for the given number of ticks spent idle, as if Server.tick was
+    // called that many times on an idle server.
+    pub fn skip_idle(&mut self, ticks: Ticks) {
+        debug_assert!(self.idle());
+        self.instant.clear();
+        self.discarded.clear();
+        self.statistics.idle_count += u64::from(ticks.0) * self.serving.len() as u64;
+    }
+
+    // Server.started returns the packets that entered service during the most recent tick, in the
+    // order they did.
+    pub fn started(&self) -> impl Iterator<Item = &Packet> {
+        let current = self.serving.iter().flatten().filter(|s| s.started).map(|s| &s.packet);
+        self.instant.iter().chain(current)
+    }
+
+    // Server.discarded returns the packets discarded on being preempted during the most recent
+    // tick (see Preemption::Discard).
+    pub fn discarded(&self) -> impl Iterator<Item = &Packet> {
+        self.discarded.iter()
+    }
+
+    // Server.in_service returns the packets currently being processed, by server.
+    pub fn in_service(&self) -> impl Iterator<Item = &Packet> {
+        self.serving.iter().flatten().map(|s| &s.packet)
+    }
+
+    // Server.servers returns the number of servers taking packets off of the queue.
+    pub fn servers(&self) -> usize {
+        self.serving.len()
+    }
+
+    // Server.set_pspeed changes the processing speed, including that of the packets currently being
+    // processed.
+    pub fn set_pspeed(&mut self, pspeed: f64) {
+        self.pspeed = pspeed;
+    }
+
+    // Server.set_discipline changes the order in which packets still queued are served.
+    pub fn set_discipline(&mut self, discipline: Discipline) {
+        self.queue.rebuild(self.backend, self.buffer_limit, &discipline);
+        self.discipline = discipline;
+        self.visit = None;
+    }
+
+    // Server.set_buffer_limit changes the buffer limit. Packets already queued beyond a lowered
+    // limit are kept, only subsequent arrivals are dropped.
+    pub fn set_buffer_limit(&mut self, buffer_limit: Option<usize>) {
+        self.buffer_limit = buffer_limit;
+        self.queue.rebuild(self.backend, buffer_limit, &self.discipline);
+    }
+
     // Server.qlen returns the number of packets in the server's internal buffer, waiting to be
     // processed.
     pub fn qlen(&self) -> usize {
         self.queue.len()
     }
 
+    // Server.full returns whether the buffer's at its limit, any packet arriving dropped. An
+    // infinite buffer never is.
+    pub fn full(&self) -> bool {
+        self.buffer_limit.is_some_and(|limit| self.queue.len() >= limit)
+    }
+
     // Server.packets_processed returns the number of packets processed by the server thus far.
     pub fn packets_processed(&self) -> u32 {
         self.statistics.packets_processed
@@ -192,36 +984,412 @@ impl Server {
         self.statistics.packets_dropped
     }
 
-    // Server.idle_proportion returns the proportion of time the server is left idle thus far.
+    // Server.utilization returns the proportion of time the server is busy processing packets thus
+    // far (ρ), from the number of ticks spent busy; with several servers, that of each on average.
+    pub fn utilization(&self) -> f64 {
+        self.statistics.process_count as f64 /
+            (self.statistics.idle_count + self.statistics.process_count) as f64
+    }
+
+    // Server.idle_proportion returns the proportion of time the server is left idle thus far (each
+    // on average, with several).
     pub fn idle_proportion(&self) -> f64 {
-        f64::from(self.statistics.idle_count) /
-            f64::from(self.statistics.idle_count + self.statistics.process_count) * 100.0
+        self.statistics.idle_count as f64 /
+            (self.statistics.idle_count + self.statistics.process_count) as f64 * 100.0
+    }
+
+    // Server.reset_statistics discards the statistics collected thus far, as at the end of a
+    // warm-up period. The busy period in progress, if any, is only counted from here on.
+    pub fn reset_statistics(&mut self) {
+        self.statistics = ServerStatistics::new();
+        self.busy = None;
+    }
+}
+
+// GilbertElliott is a bursty loss channel: a two-state Markov chain, moving from the good state to
+// the bad one with probability p and back with probability r as every packet's sent, the packet
+// then lost with the probability of the state it's in. On average a packet's lost with probability
+// (r * good_loss + p * bad_loss) / (p + r), in bursts lasting 1/r packets in the bad state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GilbertElliott {
+    pub p: f64,
+    pub r: f64,
+    pub good_loss: f64,
+    pub bad_loss: f64,
+}
+
+impl GilbertElliott {
+    // GilbertElliott.loss_rate returns the long-run probability of a packet being lost.
+    pub fn loss_rate(&self) -> f64 {
+        if self.p + self.r == 0.0 {
+            return self.good_loss;
+        }
+        (self.r * self.good_loss + self.p * self.bad_loss) / (self.p + self.r)
+    }
+}
+
+// Impairments are the faults a link injects: packets are lost as per the loss channel, if any; with
+// the reorder probability a packet's held back up to the given number of ticks longer (uniformly),
+// letting those sent after it overtake it; and with the duplication probability it's sent twice,
+// each copy held back independently.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Impairments {
+    pub loss: Option<GilbertElliott>,
+    pub reorder: f64,
+    pub reorder_delay: Ticks,
+    pub duplicate: f64,
+}
+
+// Link carries packets from one end to the other after a fixed propagation delay, however many are
+// in flight, and in the order they're sent unless impaired (see Impairments).
+#[derive(Clone)]
+pub struct Link {
+    delay: Ticks,
+    impairments: Impairments,
+    rng: XorShiftRng,
+    // The packets in flight, in the order they arrive in, along with the tick each arrives at.
+    in_flight: VecDeque<(Ticks, Packet)>,
+    // Whether the loss channel's in the bad state, and the number of packets lost, held back and
+    // duplicated thus far.
+    bad: bool,
+    lost: u32,
+    held_back: u32,
+    duplicated: u32,
+}
+
+impl Link {
+    // Link::new returns a link that's only ever delayed, never impaired.
+    pub fn new(delay: Ticks) -> Link {
+        Link::impaired(delay, Impairments::default(), rng::unseeded())
+    }
+
+    // Link::impaired returns a link injecting the given faults, drawing from the provided RNG.
+    pub fn impaired(delay: Ticks, impairments: Impairments, rng: XorShiftRng) -> Link {
+        Link {
+            delay,
+            impairments,
+            rng,
+            in_flight: VecDeque::new(),
+            bad: false,
+            lost: 0,
+            held_back: 0,
+            duplicated: 0,
+        }
+    }
+
+    // Link.send puts the packet on the link at the given tick, arriving once the delay's up, and
+    // returns the number of copies of it sent, none if it's lost.
+    pub fn send(&mut self, now: Ticks, packet: Packet) -> u32 {
+        if let Some(channel) = self.impairments.loss {
+            let flip = if self.bad { channel.r } else { channel.p };
+            if self.rng.gen::<f64>() < flip {
+                self.bad = !self.bad;
+            }
+            let loss = if self.bad { channel.bad_loss } else { channel.good_loss };
+            if self.rng.gen::<f64>() < loss {
+                self.lost += 1;
+                return 0;
+            }
+        }
+        let duplicate = self.impairments.duplicate > 0.0 &&
+            self.rng.gen::<f64>() < self.impairments.duplicate;
+        if duplicate {
+            self.duplicated += 1;
+            self.put(now, packet.clone());
+        }
+        self.put(now, packet);
+        1 + u32::from(duplicate)
+    }
+
+    // put puts a single copy of the packet in flight, holding it back if it's to be reordered.
+    // Packets arriving at the same tick arrive in the order they were put in flight.
+    fn put(&mut self, now: Ticks, packet: Packet) {
+        let mut at = now + self.delay;
+        let Impairments { reorder, reorder_delay, .. } = self.impairments;
+        if reorder > 0.0 && self.rng.gen::<f64>() < reorder {
+            self.held_back += 1;
+            at += Ticks(self.rng.gen_range(1, reorder_delay.0.max(1) + 1));
+        }
+        let i = self.in_flight.iter().rposition(|&(t, _)| t <= at).map_or(0, |i| i + 1);
+        self.in_flight.insert(i, (at, packet));
+    }
+
+    // Link.receive takes the next packet off the link, if it's arrived by the given tick.
+    pub fn receive(&mut self, now: Ticks) -> Option<Packet> {
+        match self.in_flight.front() {
+            Some(&(at, _)) if at <= now => self.in_flight.pop_front().map(|(_, p)| p),
+            _ => None,
+        }
+    }
+
+    // Link.next_arrival returns the tick the next packet in flight arrives at, if there's any.
+    pub fn next_arrival(&self) -> Option<Ticks> {
+        self.in_flight.front().map(|&(at, _)| at)
+    }
+
+    // Link.in_flight returns the number of packets in flight.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    // Link.lost returns the number of packets lost thus far.
+    pub fn lost(&self) -> u32 {
+        self.lost
+    }
+
+    // Link.held_back returns the number of packets held back to be reordered thus far.
+    pub fn held_back(&self) -> u32 {
+        self.held_back
+    }
+
+    // Link.duplicated returns the number of packets duplicated thus far.
+    pub fn duplicated(&self) -> u32 {
+        self.duplicated
     }
 }
 
+// Limiting is the algorithm a rate limiter admits packets by, each letting through packets at a
+// given rate on average, and at most a burst of them at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limiting {
+    // At most a burst's worth of packets admitted in each fixed window, as long as it takes the rate
+    // to make up a burst; up to twice the burst can get through across the boundary of two.
+    FixedWindow,
+    // At most a burst's worth of packets admitted in any window (as above) ending now, keeping a
+    // log of the packets admitted within it.
+    SlidingWindow,
+    // A packet admitted per token spent, tokens accruing at the rate up to a burst's worth.
+    TokenBucket,
+}
+
+impl Limiting {
+    // Limiting::parse returns the algorithm named, if known.
+    pub fn parse(s: &str) -> Option<Limiting> {
+        match s {
+            "fixed-window" => Some(Limiting::FixedWindow),
+            "sliding-window" => Some(Limiting::SlidingWindow),
+            "token-bucket" => Some(Limiting::TokenBucket),
+            _ => None,
+        }
+    }
+
+    // Limiting.name returns the name the algorithm goes by (see Limiting::parse).
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Limiting::FixedWindow => "fixed-window",
+            Limiting::SlidingWindow => "sliding-window",
+            Limiting::TokenBucket => "token-bucket",
+        }
+    }
+}
+
+// RateLimiter sits ahead of a server, as an API gateway would, rejecting the packets arriving
+// faster than it allows (see Limiting) before they reach the server. It keeps no clock of its own,
+// catching up with the time given whenever a packet arrives.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    algorithm: Limiting,
+    // The rate packets are admitted at (per tick), and the window it takes to make up a burst.
+    rate: f64,
+    burst: u32,
+    window: f64,
+    // The window the count is of (fixed windows), the ticks packets were admitted at within the
+    // window (sliding windows), and the tokens in the bucket as of the tick last caught up with.
+    current: u64,
+    count: u32,
+    log: VecDeque<Ticks>,
+    tokens: f64,
+    last: Ticks,
+    admitted: u32,
+    rejected: u32,
+}
+
+impl RateLimiter {
+    // RateLimiter::new returns a rate limiter admitting packets at the given rate (per tick), in
+    // bursts of at most the given number of them, the first burst admitted straight away.
+    pub fn new(algorithm: Limiting, rate: f64, burst: u32) -> RateLimiter {
+        RateLimiter {
+            algorithm,
+            rate,
+            burst,
+            window: f64::from(burst) / rate,
+            current: 0,
+            count: 0,
+            log: VecDeque::new(),
+            tokens: f64::from(burst),
+            last: Ticks::ZERO,
+            admitted: 0,
+            rejected: 0,
+        }
+    }
+
+    // RateLimiter.admit returns whether the packet arriving at the given tick gets through.
+    pub fn admit(&mut self, now: Ticks) -> bool {
+        let admit = match self.algorithm {
+            Limiting::FixedWindow => {
+                let current = (f64::from(now.0) / self.window) as u64;
+                if current != self.current {
+                    self.current = current;
+                    self.count = 0;
+                }
+                let admit = self.count < self.burst;
+                self.count += u32::from(admit);
+                admit
+            }
+            Limiting::SlidingWindow => {
+                let window = self.window;
+                while self.log.front().is_some_and(|&t| f64::from(now.since(t).0) >= window) {
+                    self.log.pop_front();
+                }
+                let admit = self.log.len() < self.burst as usize;
+                if admit {
+                    self.log.push_back(now);
+                }
+                admit
+            }
+            Limiting::TokenBucket => {
+                let elapsed = f64::from(now.since(self.last).0);
+                self.tokens = (self.tokens + self.rate * elapsed).min(f64::from(self.burst));
+                self.last = now;
+                let admit = self.tokens >= 1.0;
+                if admit {
+                    self.tokens -= 1.0;
+                }
+                admit
+            }
+        };
+        if admit {
+            self.admitted += 1;
+        } else {
+            self.rejected += 1;
+        }
+        admit
+    }
+
+    // RateLimiter.admitted returns the number of packets admitted thus far.
+    pub fn admitted(&self) -> u32 {
+        self.admitted
+    }
+
+    // RateLimiter.rejected returns the number of packets rejected thus far.
+    pub fn rejected(&self) -> u32 {
+        self.rejected
+    }
+}
+
+// Marker is a two-rate three-color marker (trTCM, RFC 2698) metering packets ahead of a server,
+// color-blind: with a bucket of committed tokens and another of peak ones (in bits), each filling
+// up at its rate (per tick) up to its burst, a packet's red if there aren't as many peak tokens as
+// it has bits, yellow if there are but not as many committed ones, and green otherwise. Yellow
+// packets spend peak tokens, green ones both. Like RateLimiter, it catches up with the time given
+// whenever a packet arrives.
+#[derive(Clone, Debug)]
+pub struct Marker {
+    committed: f64,
+    committed_burst: f64,
+    peak: f64,
+    peak_burst: f64,
+    // The tokens in either bucket as of the tick last caught up with.
+    committed_tokens: f64,
+    peak_tokens: f64,
+    last: Ticks,
+}
+
+impl Marker {
+    // Marker::new returns a marker with the given committed and peak rates (in bits per tick) and
+    // bursts (in bits), its buckets full.
+    pub fn new(committed: f64, committed_burst: f64, peak: f64, peak_burst: f64) -> Marker {
+        Marker {
+            committed,
+            committed_burst,
+            peak,
+            peak_burst,
+            committed_tokens: committed_burst,
+            peak_tokens: peak_burst,
+            last: Ticks::ZERO,
+        }
+    }
+
+    // Marker.mark returns the color of the packet of the given length arriving at the given tick.
+    pub fn mark(&mut self, now: Ticks, length: u32) -> Color {
+        let elapsed = f64::from(now.since(self.last).0);
+        self.committed_tokens =
+            (self.committed_tokens + self.committed * elapsed).min(self.committed_burst);
+        self.peak_tokens = (self.peak_tokens + self.peak * elapsed).min(self.peak_burst);
+        self.last = now;
+        let length = f64::from(length);
+        if self.peak_tokens < length {
+            return Color::Red;
+        }
+        self.peak_tokens -= length;
+        if self.committed_tokens < length {
+            return Color::Yellow;
+        }
+        self.committed_tokens -= length;
+        Color::Green
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use super::super::generators::Deterministic;
+    use rng;
+
+    extern crate rand;
+    use self::rand::Rng;
 
     #[test]
     fn client_packet_generation() {
         let mut c = Client::new(Deterministic::new(0.5), 1.0);
-        assert!(!c.tick());
-        assert!(c.tick());
+        assert_eq!(c.tick(), 0);
+        assert_eq!(c.tick(), 1);
+    }
+
+    #[test]
+    fn client_multiple_packet_generation() {
+        // 2.5 packets per tick, alternating between two and three packets per tick.
+        let mut c = Client::new(Deterministic::new(2.5), 1.0);
+        let generated: Vec<u32> = (0..4).map(|_| c.tick()).collect();
+        assert_eq!(generated, vec![2, 3, 2, 3]);
+        assert_eq!(c.packets_generated(), 10);
+    }
+
+    #[test]
+    fn client_skip() {
+        let mut c = Client::new(Deterministic::new(0.25), 1.0);
+        assert_eq!(c.idle_ticks(), Ticks(3));
+        c.skip(Ticks(3));
+        assert_eq!(c.idle_ticks(), Ticks::ZERO);
+        assert_eq!(c.tick(), 1);
+        assert_eq!(c.idle_ticks(), Ticks(3));
+    }
+
+    #[test]
+    fn client_stall() {
+        // Stalled, the client picks up where it left off: the packet due at the third tick is
+        // generated the third tick it's not stalled.
+        let mut c = Client::new(Deterministic::new(1.0 / 3.0), 1.0);
+        assert_eq!(c.tick(), 0);
+        c.stall();
+        c.stall();
+        assert_eq!(c.tick(), 0);
+        assert_eq!(c.tick(), 1);
+        assert_eq!(c.statistics.ticks_blocked, 2);
     }
 
     #[test]
     fn server_packet_delivery() {
         let mut s = Server::new(1.0, 0.5, None);
         s.enqueue(Packet {
-            time_generated: 0,
+            time_generated: Ticks::ZERO,
             length: 1,
+            ..Packet::default()
         });
         s.enqueue(Packet {
-            time_generated: 0,
+            time_generated: Ticks::ZERO,
             length: 1,
+            ..Packet::default()
         });
         s.tick();
         assert_eq!(s.statistics.packets_processed, 0);
@@ -236,16 +1404,43 @@ mod tests {
         assert_eq!(s.statistics.packets_processed, 2);
     }
 
+    #[test]
+    fn server_multiple_servers() {
+        // Two servers take two packets at once, the third waiting for either to free up.
+        let mut s = ServerBuilder::new().resolution(1.0).pspeed(0.5).servers(2).build();
+        for _ in 0..3 {
+            s.enqueue(Packet {
+                length: 1,
+                ..Packet::default()
+            });
+        }
+        s.tick();
+        assert_eq!((s.in_service().count(), s.qlen()), (2, 1));
+        s.tick();
+        assert_eq!(s.statistics.packets_processed, 2);
+        s.tick();
+        assert_eq!((s.in_service().count(), s.qlen()), (1, 0));
+        s.tick();
+        assert_eq!(s.statistics.packets_processed, 3);
+        // Half the servers sat idle for the last two ticks.
+        assert_eq!((s.statistics.process_count, s.statistics.idle_count), (6, 2));
+        assert!((s.utilization() - 0.75).abs() < 1e-12);
+    }
+
     #[test]
     fn server_packet_dropped() {
         let mut s = Server::new(1.0, 1.0, Some(1));
+        assert!(!s.full());
         s.enqueue(Packet {
-            time_generated: 0,
+            time_generated: Ticks::ZERO,
             length: 1,
+            ..Packet::default()
         });
+        assert!(s.full());
         s.enqueue(Packet {
-            time_generated: 0,
+            time_generated: Ticks::ZERO,
             length: 1,
+            ..Packet::default()
         });
 
         s.tick();
@@ -253,6 +1448,308 @@ mod tests {
         assert_eq!(s.statistics.packets_dropped, 1);
     }
 
+    #[test]
+    fn server_hooks() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // Odd packets are lost, and even ones take twice as long to process.
+        let mut s = Server::new(1.0, 1.0, None);
+        let seen = Rc::new(RefCell::new(vec![]));
+        s.on_enqueue(|p| p.id % 2 == 0);
+        s.on_service_start(|p| p.length *= 2);
+        let drops = seen.clone();
+        s.on_drop(move |p| drops.borrow_mut().push(("drop", p.id)));
+        let departures = seen.clone();
+        s.on_departure(move |p| departures.borrow_mut().push(("departure", p.length)));
+        for id in 0..4 {
+            s.enqueue(Packet {
+                id,
+                length: 1,
+                ..Packet::default()
+            });
+        }
+        let departed: Vec<_> = (0..4).flat_map(|_| s.tick()).collect();
+        assert_eq!(departed.iter().map(|p| p.id).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(s.packets_dropped(), 2);
+        assert_eq!(
+            *seen.borrow(),
+            vec![("drop", 1), ("drop", 3), ("departure", 2), ("departure", 2)]
+        );
+
+        let mut c = Client::new(Deterministic::new(1.0), 1.0);
+        c.on_generate(|p| p.class = 7);
+        assert_eq!(c.generate(Packet::default()).class, 7);
+    }
+
+    #[test]
+    fn server_service_start() {
+        let mut s = Server::new(1.0, 0.5, None);
+        assert!(s.enqueue(Packet {
+            time_generated: Ticks::ZERO,
+            length: 1,
+            ..Packet::default()
+        }));
+        s.tick();
+        assert_eq!(s.started().map(|p| p.length).collect::<Vec<_>>(), vec![1]);
+        s.tick();
+        assert_eq!(s.started().count(), 0);
+    }
+
+    #[test]
+    fn server_disciplines() {
+        let served = |discipline: Discipline| {
+            let mut s = Server::new(1.0, 1.0, None);
+            s.set_discipline(discipline);
+            for (id, &class) in [2, 0, 1, 0, 2].iter().enumerate() {
+                s.enqueue(Packet {
+                    id: id as u32,
+                    length: 1,
+                    class,
+                    ..Packet::default()
+                });
+            }
+            (0..5).flat_map(|_| s.tick()).map(|p| p.id).collect::<Vec<u32>>()
+        };
+        assert_eq!(served(Discipline::Fifo), vec![0, 1, 2, 3, 4]);
+        assert_eq!(served(Discipline::Lifo), vec![4, 3, 2, 1, 0]);
+        assert_eq!(served(Discipline::Priority(vec![])), vec![1, 3, 2, 0, 4]);
+        assert_eq!(served(Discipline::Priority(vec![2])), vec![0, 4, 1, 3, 2]);
+
+        // Packets already queued are served as per the discipline switched to, and under priority
+        // are kept class by class.
+        let mut s = Server::new(1.0, 1.0, None);
+        for (id, &class) in [2, 0, 1, 0, 2].iter().enumerate() {
+            s.enqueue(Packet {
+                id: id as u32,
+                length: 1,
+                class,
+                ..Packet::default()
+            });
+        }
+        s.set_discipline(Discipline::Priority(vec![2]));
+        assert_eq!(s.queue().map(|p| p.id).collect::<Vec<_>>(), vec![0, 4, 1, 3, 2]);
+        assert_eq!(s.tick()[0].id, 0);
+        s.set_discipline(Discipline::Lifo);
+        assert_eq!((0..4).flat_map(|_| s.tick()).map(|p| p.id).collect::<Vec<_>>(), [2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn server_queue_backends() {
+        // Whatever backs the queue, the same packets are dropped and served in the same order.
+        let served = |backend: QueueBackend, discipline: Discipline| {
+            let mut s = ServerBuilder::new()
+                .resolution(1.0)
+                .pspeed(1.0)
+                .buffer_limit(Some(2))
+                .discipline(discipline)
+                .queue_backend(backend)
+                .build();
+            let mut served = vec![];
+            for (id, &class) in [2, 0, 1, 0, 2, 1, 0].iter().enumerate() {
+                s.enqueue(Packet {
+                    id: id as u32,
+                    length: 3,
+                    class,
+                    ..Packet::default()
+                });
+                if id == 4 {
+                    // Lowering the limit keeps what's queued, and raising it makes room.
+                    s.set_buffer_limit(Some(1));
+                    assert_eq!(s.qlen(), 2);
+                    s.set_buffer_limit(Some(4));
+                }
+                served.extend(s.tick().iter().map(|p| p.id));
+            }
+            served.extend((0..20).flat_map(|_| s.tick()).map(|p| p.id));
+            (served, s.packets_dropped())
+        };
+        for discipline in &[Discipline::Fifo, Discipline::Lifo, Discipline::Priority(vec![])] {
+            let growable = served(QueueBackend::Growable, discipline.clone());
+            assert_eq!(served(QueueBackend::Fixed, discipline.clone()), growable);
+            assert_eq!(served(QueueBackend::Auto, discipline.clone()), growable);
+        }
+        assert_eq!(served(QueueBackend::Fixed, Discipline::Fifo), (vec![0, 1, 2, 4, 5, 6], 1));
+    }
+
+    #[test]
+    fn server_service_accounting() {
+        // The number of ticks a packet spends in service, through the one it departs in, giving up
+        // after a few more than it should take.
+        let ticks = |resolution: f64, pspeed: f64, length: u32| {
+            let mut s = Server::new(resolution, pspeed, None);
+            s.enqueue(Packet {
+                length,
+                ..Packet::default()
+            });
+            let limit = (f64::from(length) * resolution / pspeed) as u32 + 2;
+            (1..=limit).find(|_| !s.tick().is_empty())
+        };
+        // Speeds that aren't a whole number of bits a tick, whose fractions don't add up exactly
+        // in binary (ten tenths of a bit come to less than a bit).
+        assert_eq!(ticks(1e4, 1000.0, 1), Some(10));
+        assert_eq!(ticks(1e6, 1e4, 1), Some(100));
+        assert_eq!(ticks(1e5, 3e4, 1), Some(4));
+        assert_eq!(ticks(1e6, 7.0, 3), Some(428_572));
+        assert_eq!(ticks(10.0, 3.0, 10), Some(34));
+        assert_eq!(ticks(1e6, 1e6, 1), Some(1));
+        assert_eq!(ticks(1.0, 2.5, 5), Some(2));
+
+        // Changing the speed mid-service applies to the work that's left.
+        let mut s = Server::new(10.0, 1.0, None);
+        s.enqueue(Packet {
+            length: 1,
+            ..Packet::default()
+        });
+        assert!((0..4).all(|_| s.tick().is_empty()));
+        s.set_pspeed(3.0);
+        assert!(s.tick().is_empty());
+        assert_eq!(s.tick().len(), 1);
+    }
+
+    #[test]
+    fn server_multiple_departures() {
+        // Five bits a tick gets through two 2-bit packets in the first tick, the bit left over
+        // going towards the third, which departs in the second tick along with the other two.
+        let mut s = Server::new(1.0, 5.0, None);
+        for id in 0..5 {
+            s.enqueue(Packet {
+                id,
+                length: 2,
+                ..Packet::default()
+            });
+        }
+        let ids = |departed: Vec<Packet>| departed.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids(s.tick()), vec![0, 1]);
+        assert_eq!(s.started().map(|p| p.id).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(ids(s.tick()), vec![2, 3, 4]);
+        assert_eq!(s.started().map(|p| p.id).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(s.tick(), vec![]);
+        assert_eq!(s.statistics.packets_processed, 5);
+        assert_eq!(s.statistics.process_count, 2);
+        assert_eq!(
+            s.statistics.busy_periods,
+            vec![BusyPeriod {
+                ticks: Ticks(2),
+                packets: 5,
+            }]
+        );
+    }
+
+    #[test]
+    fn server_invariants() {
+        // Random sequences of arrivals, ticks and speed changes at servers of every kind, checking
+        // after each step that no packet is lost track of and the buffer limit is kept to, and once
+        // drained, that packets were served in the order the discipline calls for.
+        let disciplines = [
+            Discipline::Fifo,
+            Discipline::Lifo,
+            Discipline::Priority(vec![1]),
+            Discipline::Polling(vec![Visit::Gated, Visit::Limited(1)]),
+        ];
+        let backends = [QueueBackend::Auto, QueueBackend::Growable, QueueBackend::Fixed];
+        for seed in 0..200 {
+            let mut r = rng::substream(seed, rng::ARRIVALS);
+            let limit = if r.gen() { None } else { Some(r.gen_range(1, 6)) };
+            let discipline = disciplines[r.gen_range(0, disciplines.len())].clone();
+            let mut s = ServerBuilder::new()
+                .resolution(1.0)
+                .pspeed(r.gen_range(0.2, 5.0))
+                .buffer_limit(limit)
+                .discipline(discipline.clone())
+                .queue_backend(backends[r.gen_range(0, backends.len())])
+                .build();
+            let (mut generated, mut departed) = (0, vec![]);
+            for _ in 0..r.gen_range(0, 200) {
+                match r.gen_range(0, 10) {
+                    0..=3 => {
+                        for _ in 0..r.gen_range(1, 4) {
+                            s.enqueue(Packet {
+                                id: generated,
+                                length: r.gen_range(1, 5),
+                                class: r.gen_range(0, 3),
+                                ..Packet::default()
+                            });
+                            generated += 1;
+                        }
+                    }
+                    4 => s.set_pspeed(r.gen_range(0.2, 5.0)),
+                    _ => departed.extend(s.tick()),
+                }
+                let in_service = s.in_service().count() as u32;
+                let accounted = s.packets_processed() + s.packets_dropped() + s.qlen() as u32;
+                assert_eq!(accounted + in_service, generated, "seed {}", seed);
+                assert!(limit.is_none_or(|l| s.qlen() <= l), "seed {}", seed);
+                assert_eq!(departed.len() as u32, s.packets_processed(), "seed {}", seed);
+            }
+            while !s.idle() {
+                departed.extend(s.tick());
+            }
+            assert_eq!(s.packets_processed() + s.packets_dropped(), generated, "seed {}", seed);
+            let served = |class: Option<u32>| {
+                departed
+                    .iter()
+                    .filter(|p| class.is_none_or(|c| p.class == c))
+                    .map(|p| p.id)
+                    .collect::<Vec<_>>()
+            };
+            match discipline {
+                Discipline::Fifo => assert!(served(None).windows(2).all(|w| w[0] < w[1])),
+                // Within a class, packets are served first come, first served.
+                Discipline::Priority(_) | Discipline::Polling(_) => {
+                    for class in 0..3 {
+                        assert!(served(Some(class)).windows(2).all(|w| w[0] < w[1]));
+                    }
+                }
+                Discipline::Lifo => {}
+            }
+        }
+    }
+
+    #[test]
+    fn server_utilization() {
+        // Two ticks busy with a packet, followed by two idle ones.
+        let mut s = Server::new(1.0, 0.5, None);
+        s.enqueue(Packet {
+            time_generated: Ticks::ZERO,
+            length: 1,
+            ..Packet::default()
+        });
+        for _ in 0..4 {
+            s.tick();
+        }
+        assert_eq!(s.statistics.process_count, 2);
+        assert_eq!(s.utilization(), 0.5);
+        assert_eq!(s.idle_proportion(), 50.0);
+    }
+
+    #[test]
+    fn server_busy_periods() {
+        let mut s = Server::new(1.0, 0.5, None);
+        let packet = Packet {
+            time_generated: Ticks::ZERO,
+            length: 1,
+            ..Packet::default()
+        };
+        s.enqueue(packet.clone());
+        s.enqueue(packet.clone());
+        for _ in 0..5 {
+            s.tick();
+        }
+        s.enqueue(packet);
+        s.tick();
+        assert_eq!(
+            s.statistics.busy_periods,
+            vec![BusyPeriod {
+                ticks: Ticks(4),
+                packets: 2,
+            }]
+        );
+        s.tick();
+        assert_eq!(s.statistics.busy_periods.len(), 2);
+        assert_eq!(s.statistics.busy_periods[1].ticks, Ticks(2));
+    }
+
     #[test]
     fn server_idle_count() {
         let mut s = Server::new(1.0, 1.0, Some(1));
@@ -264,11 +1761,248 @@ mod tests {
         assert_eq!(s.statistics.idle_count, 2);
 
         s.enqueue(Packet {
-            time_generated: 0,
+            time_generated: Ticks::ZERO,
             length: 1,
+            ..Packet::default()
         });
         s.tick();
         assert_eq!(s.statistics.idle_count, 2);
         assert_eq!(s.statistics.packets_processed, 1);
     }
+
+    #[test]
+    fn link() {
+        let mut l = Link::new(Ticks(2));
+        for (t, id) in [(0, 0), (0, 1), (1, 2)] {
+            l.send(Ticks(t), Packet { id, ..Packet::default() });
+        }
+        assert_eq!(l.in_flight(), 3);
+        assert_eq!(l.next_arrival(), Some(Ticks(2)));
+        assert!(l.receive(Ticks(1)).is_none());
+        let arrived = |l: &mut Link, t| {
+            let mut ids = vec![];
+            while let Some(p) = l.receive(Ticks(t)) {
+                ids.push(p.id);
+            }
+            ids
+        };
+        assert_eq!(arrived(&mut l, 2), vec![0, 1]);
+        assert_eq!(arrived(&mut l, 3), vec![2]);
+        assert_eq!(l.next_arrival(), None);
+
+        // Without a delay, packets arrive the tick they're sent.
+        let mut l = Link::new(Ticks::ZERO);
+        l.send(Ticks(5), Packet::default());
+        assert!(l.receive(Ticks(5)).is_some());
+    }
+
+    #[test]
+    fn link_impairments() {
+        // Always duplicated, every packet's sent twice.
+        let always = Impairments { duplicate: 1.0, ..Impairments::default() };
+        let mut l = Link::impaired(Ticks(1), always, rng::substream(1, rng::LINK));
+        assert_eq!(l.send(Ticks::ZERO, Packet { id: 7, ..Packet::default() }), 2);
+        assert_eq!(l.duplicated(), 1);
+        assert_eq!(l.receive(Ticks(1)).map(|p| p.id), Some(7));
+        assert_eq!(l.receive(Ticks(1)).map(|p| p.id), Some(7));
+
+        // Always held back, a packet's overtaken by one sent after it, but arrives within the
+        // reordering delay all the same.
+        let impairments = Impairments {
+            reorder: 1.0,
+            reorder_delay: Ticks(10),
+            ..Impairments::default()
+        };
+        let mut l = Link::impaired(Ticks(1), impairments, rng::substream(1, rng::LINK));
+        l.send(Ticks::ZERO, Packet { id: 0, ..Packet::default() });
+        l.impairments.reorder = 0.0;
+        l.send(Ticks::ZERO, Packet { id: 1, ..Packet::default() });
+        assert_eq!(l.held_back(), 1);
+        assert_eq!(l.next_arrival(), Some(Ticks(1)));
+        let mut ids = vec![];
+        for t in 0..12 {
+            while let Some(p) = l.receive(Ticks(t)) {
+                ids.push(p.id);
+            }
+        }
+        assert_eq!(ids, vec![1, 0]);
+        assert_eq!(l.in_flight(), 0);
+    }
+
+    #[test]
+    fn link_loss() {
+        // Lossless in the good state and lossy in the bad one, losses come in bursts: a packet
+        // lost is far likelier to be followed by another than the average.
+        let channel = GilbertElliott {
+            p: 0.01,
+            r: 0.1,
+            good_loss: 0.0,
+            bad_loss: 1.0,
+        };
+        assert!((channel.loss_rate() - 1.0 / 11.0).abs() < 1e-12);
+        let loss = Impairments { loss: Some(channel), ..Impairments::default() };
+        let mut l = Link::impaired(Ticks::ZERO, loss, rng::substream(1, rng::LINK));
+        let n = 100_000;
+        let sent: Vec<u32> = (0..n).map(|_| l.send(Ticks::ZERO, Packet::default())).collect();
+        assert_eq!(sent.iter().filter(|&&c| c == 0).count(), l.lost() as usize);
+        assert_eq!(l.in_flight() + l.lost() as usize, n);
+        let rate = f64::from(l.lost()) / n as f64;
+        assert!((rate - channel.loss_rate()).abs() < 0.01);
+        let consecutive = sent.windows(2).filter(|w| w[0] == 0 && w[1] == 0).count();
+        assert!(consecutive as f64 / f64::from(l.lost()) > 0.8);
+    }
+
+    #[test]
+    fn rate_limiter() {
+        // Admitting 0.1 packets per tick in bursts of 2, windows are 20 ticks long. A burst at the
+        // end of one fixed window can be followed by another at the start of the next, which the
+        // sliding window and token bucket both hold back.
+        let admitted = |algorithm| {
+            let mut l = RateLimiter::new(algorithm, 0.1, 2);
+            let admitted: Vec<u32> = (18..22).filter(|&t| l.admit(Ticks(t))).collect();
+            assert_eq!(l.admitted() + l.rejected(), 4);
+            admitted
+        };
+        assert_eq!(admitted(Limiting::FixedWindow), vec![18, 19, 20, 21]);
+        assert_eq!(admitted(Limiting::SlidingWindow), vec![18, 19]);
+        assert_eq!(admitted(Limiting::TokenBucket), vec![18, 19]);
+
+        // Over the long run, all of them admit packets at the given rate.
+        for &algorithm in &[Limiting::FixedWindow, Limiting::SlidingWindow, Limiting::TokenBucket] {
+            let mut l = RateLimiter::new(algorithm, 0.1, 2);
+            let admitted = (0..1000).filter(|&t| l.admit(Ticks(t))).count();
+            assert!((100..=102).contains(&admitted), "{:?}: {}", algorithm, admitted);
+            assert_eq!(Limiting::parse(algorithm.name()), Some(algorithm));
+        }
+    }
+
+    #[test]
+    fn marker() {
+        // Committing to 1 bit per tick in bursts of 2, and peaking at 2 in bursts of 4, a burst of
+        // 4-bit packets is green for 2 bits, yellow for the next 2, and red after.
+        let mut m = Marker::new(1.0, 2.0, 2.0, 4.0);
+        let colors: Vec<Color> = (0..4).map(|_| m.mark(Ticks(0), 1)).collect();
+        assert_eq!(colors, vec![Color::Green, Color::Green, Color::Yellow, Color::Yellow]);
+        assert_eq!(m.mark(Ticks(0), 1), Color::Red);
+        // A packet longer than the committed burst is never green.
+        assert_eq!(m.mark(Ticks(10), 3), Color::Yellow);
+        assert_eq!(m.mark(Ticks(20), 5), Color::Red);
+
+        // Over the long run, the green traffic makes up the committed rate, and the yellow the
+        // rest of the peak rate.
+        let mut m = Marker::new(0.5, 2.0, 1.5, 4.0);
+        let mut bits = [0; 3];
+        for t in 0..1000 {
+            bits[m.mark(Ticks(t), 2) as usize] += 2;
+        }
+        assert!((498..=502).contains(&bits[Color::Green as usize]), "{:?}", bits);
+        assert!((998..=1002).contains(&bits[Color::Yellow as usize]), "{:?}", bits);
+        assert_eq!(bits.iter().sum::<u32>(), 2000);
+    }
+
+    #[test]
+    fn server_polling() {
+        // Packets 0 and 1 of class 0 and 2 of class 1 are queued as the server visits class 0's
+        // queue, and 3 of class 0 arrives during the visit.
+        let order = |visits| {
+            let mut s = ServerBuilder::new()
+                .resolution(1.0)
+                .pspeed(1.0)
+                .discipline(Discipline::Polling(visits))
+                .build();
+            let packet = |id, class| Packet { id, class, length: 1, ..Packet::default() };
+            for &(id, class) in &[(0, 0), (1, 0), (2, 1)] {
+                s.enqueue(packet(id, class));
+            }
+            let mut departed = s.tick();
+            s.enqueue(packet(3, 0));
+            while !s.idle() {
+                departed.extend(s.tick());
+            }
+            departed.iter().map(|p| p.id).collect::<Vec<_>>()
+        };
+        assert_eq!(order(vec![]), vec![0, 1, 3, 2]);
+        assert_eq!(order(vec![Visit::Gated]), vec![0, 1, 2, 3]);
+        assert_eq!(order(vec![Visit::Limited(1)]), vec![0, 2, 1, 3]);
+        assert_eq!(Visit::parse("2"), Some(Visit::Limited(2)));
+        assert_eq!(Visit::parse("0"), None);
+    }
+
+    #[test]
+    fn server_preemption() {
+        // Packets take two ticks each; the second arrives halfway through the first's service and
+        // preempts it (LIFO), departing first. What becomes of the first depends on the policy.
+        let departures = |discipline, policy| {
+            let mut s = ServerBuilder::new()
+                .resolution(1.0)
+                .pspeed(1.0)
+                .discipline(discipline)
+                .preemption(Some(policy))
+                .build();
+            let packet = |id, class| Packet { id, class, length: 2, ..Packet::default() };
+            let mut departed: Vec<Vec<u32>> = vec![];
+            s.enqueue(packet(0, 1));
+            departed.push(s.tick().iter().map(|p| p.id).collect());
+            s.enqueue(packet(1, 0));
+            for _ in 0..4 {
+                departed.push(s.tick().iter().map(|p| p.id).collect());
+                if s.discarded().next().is_some() {
+                    assert_eq!(s.discarded().map(|p| p.id).collect::<Vec<_>>(), vec![0]);
+                }
+            }
+            assert_eq!(s.statistics.preemptions, 1);
+            (departed, s.statistics.packets_dropped)
+        };
+        let resumed = vec![vec![], vec![], vec![1], vec![0], vec![]];
+        assert_eq!(departures(Discipline::Lifo, Preemption::Resume), (resumed.clone(), 0));
+        let restarted = vec![vec![], vec![], vec![1], vec![], vec![0]];
+        assert_eq!(departures(Discipline::Lifo, Preemption::Restart), (restarted, 0));
+        let discarded = vec![vec![], vec![], vec![1], vec![], vec![]];
+        assert_eq!(departures(Discipline::Lifo, Preemption::Discard), (discarded, 1));
+        // Under priority, only a packet of a higher priority class preempts.
+        assert_eq!(departures(Discipline::Priority(vec![0, 1]), Preemption::Resume), (resumed, 0));
+        let mut s = ServerBuilder::new()
+            .resolution(1.0)
+            .pspeed(1.0)
+            .discipline(Discipline::Priority(vec![0, 1]))
+            .preemption(Some(Preemption::Resume))
+            .build();
+        s.enqueue(Packet { id: 0, length: 2, ..Packet::default() });
+        s.tick();
+        s.enqueue(Packet { id: 1, class: 1, length: 2, ..Packet::default() });
+        assert_eq!(s.tick().iter().map(|p| p.id).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(s.statistics.preemptions, 0);
+    }
+
+    #[test]
+    fn server_builder() {
+        let mut s = ServerBuilder::new()
+            .resolution(1.0)
+            .pspeed(1.0)
+            .buffer_limit(Some(2))
+            .discipline(Discipline::Lifo)
+            .build();
+        for id in 0..4 {
+            let accepted = s.enqueue(Packet {
+                id,
+                length: 1,
+                ..Packet::default()
+            });
+            assert_eq!(accepted, id < 2);
+        }
+        assert_eq!(s.tick().iter().map(|p| p.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(s.tick().iter().map(|p| p.id).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(s.statistics.packets_dropped, 2);
+
+        // Unset, it's the command line's defaults: 10,000 bits/s at a million ticks per second.
+        let mut s = ServerBuilder::default().build();
+        s.enqueue(Packet {
+            length: 1,
+            ..Packet::default()
+        });
+        for _ in 0..99 {
+            assert_eq!(s.tick(), vec![]);
+        }
+        assert_eq!(s.tick().len(), 1);
+    }
 }
@@ -0,0 +1,176 @@
+use simulation::Results;
+
+// Metric is a statistic of a run that an objective can be set on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    // The given percentile of the sojourn time, in seconds.
+    Sojourn(f64),
+    // The mean sojourn time, in seconds.
+    MeanSojourn,
+    // The proportion of generated packets that were dropped.
+    Loss,
+    // The proportion of time the server was busy.
+    Utilization,
+    // The rate at which packets were processed, in packets/s.
+    Throughput,
+}
+
+// Objective is a bound on a metric, e.g. p99<=50ms.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Objective {
+    pub metric: Metric,
+    // Whether the metric is bounded from above (<=) rather than from below (>=).
+    pub upper: bool,
+    pub threshold: f64,
+    // The objective as given, for reporting.
+    pub spec: String,
+}
+
+impl Objective {
+    // Objective::parse parses a single objective of the form METRIC<=VALUE or METRIC>=VALUE. The
+    // metric is one of pNN (a sojourn time percentile), mean (the mean sojourn time), loss,
+    // utilization or throughput. Times may be suffixed with s, ms, us or ns (seconds if not),
+    // proportions with % (a fraction if not), and throughputs are in packets/s.
+    pub fn parse(spec: &str) -> Result<Objective, String> {
+        let invalid = |msg: &str| format!("invalid objective {}: {}", spec, msg);
+        let (metric, upper, value) = match (spec.find("<="), spec.find(">=")) {
+            (Some(i), None) => (&spec[..i], true, &spec[i + 2..]),
+            (None, Some(i)) => (&spec[..i], false, &spec[i + 2..]),
+            _ => return Err(invalid("expected METRIC<=VALUE or METRIC>=VALUE")),
+        };
+        let metric = match metric.trim() {
+            "mean" => Metric::MeanSojourn,
+            "loss" => Metric::Loss,
+            "utilization" => Metric::Utilization,
+            "throughput" => Metric::Throughput,
+            m if m.starts_with('p') => match m[1..].parse::<f64>() {
+                Ok(p) if p > 0.0 && p <= 100.0 => Metric::Sojourn(p),
+                _ => return Err(invalid("percentiles range from above p0 up to p100")),
+            },
+            m => return Err(invalid(&format!("unknown metric {}", m))),
+        };
+        let value = value.trim();
+        let (number, scale) = match metric {
+            Metric::Sojourn(_) | Metric::MeanSojourn => {
+                match [("ns", 1e-9), ("us", 1e-6), ("µs", 1e-6), ("ms", 1e-3), ("s", 1.0)]
+                    .iter()
+                    .find(|&&(suffix, _)| value.ends_with(suffix))
+                {
+                    Some(&(suffix, scale)) => (&value[..value.len() - suffix.len()], scale),
+                    None => (value, 1.0),
+                }
+            }
+            Metric::Loss | Metric::Utilization if value.ends_with('%') => {
+                (&value[..value.len() - 1], 0.01)
+            }
+            _ => (value, 1.0),
+        };
+        match number.trim().parse::<f64>() {
+            Ok(n) if n >= 0.0 => Ok(Objective {
+                metric,
+                upper,
+                threshold: n * scale,
+                spec: spec.to_string(),
+            }),
+            _ => Err(invalid(&format!("invalid value {}", value))),
+        }
+    }
+
+    // Objective.value returns the value of the metric over the given run. Sojourn time percentiles
+    // are only available if they were configured (see Config.percentiles), and are NaN otherwise.
+    pub fn value(&self, results: &Results) -> f64 {
+        match self.metric {
+            Metric::Sojourn(p) => results
+                .sojourn_percentiles
+                .iter()
+                .find(|&&(q, _)| q == p)
+                .map_or(f64::NAN, |&(_, value)| value),
+            Metric::MeanSojourn => results.sojourn.mean(),
+            Metric::Loss => results.loss_probability() / 100.0,
+            Metric::Utilization => results.utilization,
+            Metric::Throughput => results.little.lambda,
+        }
+    }
+
+    // Objective.met reports whether the value is within bounds. NaNs (e.g. the sojourn time of a
+    // run that processed no packets) never are.
+    pub fn met(&self, value: f64) -> bool {
+        if self.upper {
+            value <= self.threshold
+        } else {
+            value >= self.threshold
+        }
+    }
+}
+
+// parse parses a comma-separated list of objectives.
+pub fn parse(s: &str) -> Result<Vec<Objective>, String> {
+    s.split(',').map(Objective::parse).collect()
+}
+
+// Evaluation is the value an objective was evaluated to over a run, and whether it was met.
+#[derive(Clone, Debug)]
+pub struct Evaluation {
+    pub objective: Objective,
+    pub value: f64,
+    pub met: bool,
+}
+
+// evaluate evaluates each of the objectives over the given run.
+pub fn evaluate(objectives: &[Objective], results: &Results) -> Vec<Evaluation> {
+    objectives
+        .iter()
+        .map(|o| {
+            let value = o.value(results);
+            Evaluation { objective: o.clone(), value, met: o.met(value) }
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::{Config, Simulation};
+
+    #[test]
+    fn parsing() {
+        let o = Objective::parse("p99<=50ms").unwrap();
+        assert_eq!((o.metric, o.upper, o.threshold), (Metric::Sojourn(99.0), true, 0.05));
+        let o = Objective::parse("loss<=0.1%").unwrap();
+        assert_eq!((o.metric, o.threshold), (Metric::Loss, 0.001));
+        let o = Objective::parse("throughput>=900").unwrap();
+        assert_eq!((o.metric, o.upper, o.threshold), (Metric::Throughput, false, 900.0));
+        assert_eq!(Objective::parse("mean<=250us").unwrap().threshold, 250e-6);
+        assert_eq!(Objective::parse("p99.9<=2").unwrap().metric, Metric::Sojourn(99.9));
+        assert_eq!(parse("p50<=1ms, utilization<=0.8").unwrap().len(), 2);
+
+        let err = |s: &str| Objective::parse(s).unwrap_err();
+        assert!(err("p99=5ms").ends_with("expected METRIC<=VALUE or METRIC>=VALUE"));
+        assert!(err("p101<=5ms").ends_with("percentiles range from above p0 up to p100"));
+        assert!(err("p0<=5ms").ends_with("percentiles range from above p0 up to p100"));
+        assert_eq!(err("jitter<=1"), "invalid objective jitter<=1: unknown metric jitter");
+        assert_eq!(err("loss<=x%"), "invalid objective loss<=x%: invalid value x%");
+    }
+
+    #[test]
+    fn evaluation() {
+        let r = Simulation::new(Config {
+            rate: 900,
+            psize: 1,
+            pspeed: 1000,
+            duration: 1,
+            qlimit: Some(5),
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        })
+        .run();
+        let objectives = parse("p99<=1s,p99<=1us,p42<=1s,loss<=100%,throughput>=1000").unwrap();
+        let met: Vec<bool> = evaluate(&objectives, &r).iter().map(|e| e.met).collect();
+        // p42 isn't among the configured percentiles, and the server can't keep up with more
+        // than 1000 packets/s.
+        assert_eq!(met, vec![true, false, false, true, false]);
+        assert!(evaluate(&objectives, &r)[2].value.is_nan());
+    }
+}
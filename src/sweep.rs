@@ -0,0 +1,308 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use rng;
+use simulation::{Config, Results, Simulation};
+
+// Parameter is a configuration parameter that can be swept over.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parameter {
+    Rate,
+    Psize,
+    Pspeed,
+    Qlimit,
+    Duration,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 5] = [
+        Parameter::Rate,
+        Parameter::Psize,
+        Parameter::Pspeed,
+        Parameter::Qlimit,
+        Parameter::Duration,
+    ];
+
+    pub fn parse(s: &str) -> Option<Parameter> {
+        match s {
+            "rate" => Some(Parameter::Rate),
+            "psize" => Some(Parameter::Psize),
+            "pspeed" => Some(Parameter::Pspeed),
+            "qlimit" => Some(Parameter::Qlimit),
+            "duration" => Some(Parameter::Duration),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Parameter::Rate => "rate",
+            Parameter::Psize => "psize",
+            Parameter::Pspeed => "pspeed",
+            Parameter::Qlimit => "qlimit",
+            Parameter::Duration => "duration",
+        }
+    }
+
+    // Parameter.apply sets the parameter to the given value, rounded to the nearest integer.
+    pub fn apply(&self, config: &mut Config, value: f64) {
+        let v = value.round() as u32;
+        match *self {
+            Parameter::Rate => config.rate = v,
+            Parameter::Psize => config.psize = v,
+            Parameter::Pspeed => config.pspeed = v,
+            Parameter::Qlimit => config.qlimit = Some(v as usize),
+            Parameter::Duration => config.duration = v,
+        }
+    }
+
+    // Parameter.value returns the parameter's value in the configuration, an unlimited buffer being
+    // infinite.
+    pub fn value(&self, config: &Config) -> f64 {
+        match *self {
+            Parameter::Rate => f64::from(config.rate),
+            Parameter::Psize => f64::from(config.psize),
+            Parameter::Pspeed => f64::from(config.pspeed),
+            Parameter::Qlimit => config.qlimit.map_or(f64::INFINITY, |q| q as f64),
+            Parameter::Duration => f64::from(config.duration),
+        }
+    }
+}
+
+// Range is the set of values a parameter is swept over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Range {
+    pub parameter: Parameter,
+    pub values: Vec<f64>,
+}
+
+impl Range {
+    // Range::parse parses ranges of the form PARAM=FROM:TO:STEP (both ends inclusive) or
+    // PARAM=V1,V2,...
+    pub fn parse(s: &str) -> Result<Range, String> {
+        let invalid = |msg: &str| format!("invalid range {}: {}", s, msg);
+        let mut parts = s.splitn(2, '=');
+        let name = parts.next().unwrap().trim();
+        let parameter = Parameter::parse(name)
+            .ok_or_else(|| invalid(&format!("unknown parameter {}", name)))?;
+        let spec = parts.next().ok_or_else(|| invalid("expected PARAM=FROM:TO:STEP"))?;
+        let nums = |sep: char| -> Result<Vec<f64>, String> {
+            spec.split(sep)
+                .map(|x| {
+                    let x = x.trim();
+                    x.parse::<f64>().map_err(|_| invalid(&format!("invalid value {}", x)))
+                })
+                .collect()
+        };
+        let values = if spec.contains(':') {
+            match nums(':')?[..] {
+                [from, to, step] if step > 0.0 && from <= to => {
+                    let n = ((to - from) / step + 1e-9).floor() as usize + 1;
+                    (0..n).map(|i| from + i as f64 * step).collect()
+                }
+                [_, _, _] => return Err(invalid("expected FROM <= TO and STEP > 0")),
+                _ => return Err(invalid("expected PARAM=FROM:TO:STEP")),
+            }
+        } else {
+            nums(',')?
+        };
+        Ok(Range { parameter, values })
+    }
+}
+
+// Point is a single combination of parameter values, along with the metrics observed in each
+// replication run at it.
+#[derive(Clone, Debug)]
+pub struct Point {
+    pub values: Vec<f64>,
+    pub metrics: Vec<OnlineStats>,
+}
+
+// Sweep is the outcome of running every combination of parameter values.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    pub parameters: Vec<Parameter>,
+    pub metrics: Vec<String>,
+    pub replications: u32,
+    pub points: Vec<Point>,
+}
+
+// metrics returns the names of the metrics observed at each point: the mean sojourn and waiting
+// times (seconds), queue length, loss probability (%), utilization and throughput (packets/s),
+// followed by the configured sojourn time percentiles.
+pub fn metrics(config: &Config) -> Vec<String> {
+    let mut names: Vec<String> = ["sojourn", "wait", "qlen", "loss", "utilization", "throughput"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    names.extend(config.percentiles.iter().map(|p| format!("sojourn_p{}", p)));
+    names
+}
+
+// observe returns the metrics (see metrics) of a single run.
+pub fn observe(r: &Results) -> Vec<f64> {
+    let mut values = vec![
+        r.sojourn.mean(),
+        r.wait.mean(),
+        r.qlen.mean(),
+        r.loss_probability(),
+        r.utilization,
+        r.little.lambda,
+    ];
+    values.extend(r.sojourn_percentiles.iter().map(|&(_, v)| v));
+    values
+}
+
+// sweep runs the base configuration at every combination of the ranges' values (the first range
+// varying slowest), for the given number of replications each. Replication i is run with the same
+// seed at every point, so that differences between neighbouring points aren't drowned out by
+// noise (see simulation::compare).
+pub fn sweep(base: &Config, ranges: &[Range], replications: u32, seed: u64) -> Sweep {
+    let mut combinations: Vec<Vec<f64>> = vec![vec![]];
+    for range in ranges {
+        combinations = combinations
+            .iter()
+            .flat_map(|c| {
+                range.values.iter().map(move |&v| {
+                    let mut c = c.clone();
+                    c.push(v);
+                    c
+                })
+            })
+            .collect();
+    }
+    let points = combinations
+        .into_iter()
+        .map(|values| {
+            let mut config = base.clone();
+            for (range, &v) in ranges.iter().zip(&values) {
+                range.parameter.apply(&mut config, v);
+            }
+            let metrics = replicate(&config, replications, seed, observe);
+            Point { values, metrics }
+        })
+        .collect();
+    Sweep {
+        parameters: ranges.iter().map(|r| r.parameter).collect(),
+        metrics: metrics(base),
+        replications,
+        points,
+    }
+}
+
+// single returns the sweep made up of the configuration alone, every parameter included, with the
+// metrics observed across the given number of replications of it.
+pub fn single(config: &Config, replications: u32, metrics: Vec<OnlineStats>) -> Sweep {
+    Sweep {
+        parameters: Parameter::ALL.to_vec(),
+        metrics: self::metrics(config),
+        replications,
+        points: vec![Point {
+            values: Parameter::ALL.iter().map(|p| p.value(config)).collect(),
+            metrics,
+        }],
+    }
+}
+
+// replicate runs the configuration for the given number of independent replications, replication
+// i being seeded with rng::derive(seed, i), and returns the statistics of the values observed of
+// each (e.g. by observe) across them.
+pub fn replicate<F>(config: &Config, replications: u32, seed: u64, observe: F) -> Vec<OnlineStats>
+where
+    F: Fn(&Results) -> Vec<f64>,
+{
+    let samples = samples(config, replications, seed, observe);
+    samples.iter().map(|s| OnlineStats::from_slice(s)).collect()
+}
+
+// samples runs the replications as replicate does, but returns the values observed of each in
+// every replication, in order.
+pub fn samples<F>(config: &Config, replications: u32, seed: u64, mut observe: F) -> Vec<Vec<f64>>
+where
+    F: FnMut(&Results) -> Vec<f64>,
+{
+    let mut samples: Vec<Vec<f64>> = vec![];
+    for i in 0..replications {
+        let seed = rng::derive(seed, u64::from(i));
+        let values = observe(&Simulation::new(Config { seed: Some(seed), ..config.clone() }).run());
+        samples.resize(values.len(), vec![]);
+        for (s, v) in samples.iter_mut().zip(values) {
+            s.push(v);
+        }
+    }
+    samples
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let r = Range::parse("rate=1000:2000:250").unwrap();
+        assert_eq!(r.parameter, Parameter::Rate);
+        assert_eq!(r.values, vec![1000.0, 1250.0, 1500.0, 1750.0, 2000.0]);
+        assert_eq!(Range::parse("qlimit=8, 64").unwrap().values, vec![8.0, 64.0]);
+        assert_eq!(Range::parse("pspeed=0.1:0.3:0.1").unwrap().values.len(), 3);
+
+        let err = |s: &str| Range::parse(s).unwrap_err();
+        assert_eq!(err("seed=1,2"), "invalid range seed=1,2: unknown parameter seed");
+        assert_eq!(err("rate"), "invalid range rate: expected PARAM=FROM:TO:STEP");
+        assert!(err("rate=2:1:1").ends_with("expected FROM <= TO and STEP > 0"));
+        assert_eq!(err("rate=1,x"), "invalid range rate=1,x: invalid value x");
+    }
+
+    #[test]
+    fn latency_vs_load() {
+        let base = Config {
+            psize: 1,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            ..Config::default()
+        };
+        let ranges = vec![
+            Range::parse("rate=300:900:300").unwrap(),
+            Range::parse("qlimit=5,50").unwrap(),
+        ];
+        let s = sweep(&base, &ranges, 2, 1);
+        assert_eq!(s.parameters, vec![Parameter::Rate, Parameter::Qlimit]);
+        assert_eq!(s.metrics.len(), 6 + base.percentiles.len());
+        let values: Vec<Vec<f64>> = s.points.iter().map(|p| p.values.clone()).collect();
+        assert_eq!(values[..3], [vec![300.0, 5.0], vec![300.0, 50.0], vec![600.0, 5.0]]);
+        assert_eq!(values.len(), 6);
+        assert!(s.points.iter().all(|p| p.metrics.iter().all(|m| m.len() == 2)));
+        // The sojourn time grows with the load, as does the loss with the smaller buffer.
+        let sojourn: Vec<f64> = s.points.iter().map(|p| p.metrics[0].mean()).collect();
+        assert!(sojourn[0] < sojourn[2] && sojourn[2] < sojourn[4]);
+        assert!(s.points[4].metrics[3].mean() > s.points[5].metrics[3].mean());
+    }
+
+    #[test]
+    fn replications() {
+        let config = Config {
+            rate: 600,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            warmup: 0.2,
+            ..Config::default()
+        };
+        let metrics = replicate(&config, 4, 1, observe);
+        assert_eq!(metrics.len(), 6 + config.percentiles.len());
+        assert!(metrics.iter().all(|m| m.len() == 4));
+        // The replications differ from one another, but are reproducible as a whole.
+        assert!(metrics[0].stddev() > 0.0);
+        assert_eq!(replicate(&config, 4, 1, observe)[0].mean(), metrics[0].mean());
+        assert!((metrics[4].mean() - 0.6).abs() < 0.1, "{}", metrics[4].mean());
+        // Replication by replication, the first seeded with rng::derive(seed, 0).
+        let values = samples(&config, 4, 1, observe);
+        assert_eq!(values[0].iter().sum::<f64>() / 4.0, metrics[0].mean());
+        let first = Simulation::new(Config { seed: Some(rng::derive(1, 0)), ..config.clone() });
+        assert_eq!(values[4][0], observe(&first.run())[4]);
+        let s = single(&config, 4, metrics);
+        assert_eq!(s.parameters, Parameter::ALL);
+        assert_eq!(s.points[0].values, vec![600.0, 1.0, 1000.0, f64::INFINITY, 1.0]);
+    }
+}
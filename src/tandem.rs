@@ -0,0 +1,367 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use collectors::StatsCollector;
+use error::QsimError;
+use simulation::{Config, Results, Simulation};
+use simulators::{Packet, Server, ServerBuilder};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::panic;
+use std::rc::Rc;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+use time::Ticks;
+
+// The number of messages a stage can have in flight to the next before it blocks, waiting for the
+// next to catch up.
+const CHANNEL: usize = 1 << 10;
+
+// Execution is how the stages of a tandem network are moved forward: serially, every stage a tick
+// at a time on a single thread, or pipelined, each stage on a thread of its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Execution {
+    Serial,
+    // Stages pass the packets departing them on to the next over a bounded channel, timestamped
+    // with the tick they departed at, and promise every so many ticks (the window) that nothing
+    // else departed them before then. A stage only works through a tick once the one before has
+    // promised as much, so it never sees a packet arrive out of order, and runs at most a window
+    // (and a channel's worth of packets) behind it.
+    Pipelined { window: u32 },
+}
+
+impl Execution {
+    // Execution.name returns the name the execution goes by.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Execution::Serial => "serial",
+            Execution::Pipelined { .. } => "pipelined",
+        }
+    }
+}
+
+// Tandem describes a tandem network: packets are processed by the configured run's server first,
+// then by further stages in turn, each a FIFO server of the given processing speed (bits/s) with
+// as much buffer as the first. Packets get from one stage to the next in no time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tandem {
+    pub pspeeds: Vec<u32>,
+    pub execution: Execution,
+}
+
+// Stage is how a stage past the first fared, from the warm-up period on: the packets it processed
+// and dropped, how busy it was, the time packets spent at it (in seconds), and the packets still
+// held at it once the run was over.
+#[derive(Clone, Debug)]
+pub struct Stage {
+    pub pspeed: u32,
+    pub packets_processed: u32,
+    pub packets_dropped: u32,
+    pub utilization: f64,
+    pub sojourn: OnlineStats,
+    pub leftover: usize,
+}
+
+// Network is what running a tandem network yields: the results of the run through the first
+// stage, how the stages after it fared, and the time packets took to get through all of them (in
+// seconds).
+#[derive(Clone, Debug)]
+pub struct Network {
+    pub first: Results,
+    pub stages: Vec<Stage>,
+    pub sojourn: OnlineStats,
+}
+
+// Message is what a stage passes on to the next: a packet departing it, along with the tick it
+// did, or a promise that every packet departing before the given tick has been passed on.
+enum Message {
+    Packet(Ticks, Packet),
+    Until(Ticks),
+}
+
+// Departures collects the packets departing the first stage, along with the tick they did.
+#[derive(Default)]
+struct Departures(Vec<(Ticks, Packet)>);
+
+impl StatsCollector for Departures {
+    fn on_depart(&mut self, time: Ticks, packet: &Packet) {
+        self.0.push((time, packet.clone()));
+    }
+}
+
+// Hop is a stage past the first as it's run, keeping track of the tick packets reached it at.
+struct Hop {
+    server: Server,
+    pspeed: u32,
+    resolution: f64,
+    warmup: Ticks,
+    arrived: HashMap<u32, Ticks>,
+    sojourn: OnlineStats,
+    // The time packets departing the stage took to get through the network up to it.
+    through: OnlineStats,
+}
+
+impl Hop {
+    fn new(config: &Config, pspeed: u32) -> Hop {
+        Hop {
+            server: ServerBuilder::new()
+                .resolution(config.resolution)
+                .pspeed(f64::from(pspeed))
+                .buffer_limit(config.qlimit)
+                .build(),
+            pspeed,
+            resolution: config.resolution,
+            warmup: config.warmup_ticks(),
+            arrived: HashMap::new(),
+            sojourn: OnlineStats::new(),
+            through: OnlineStats::new(),
+        }
+    }
+
+    // Hop.tick works through the tick at the given time, the given packets reaching the stage at
+    // the start of it, and returns those departing within it. As with the first stage, statistics
+    // are only collected from the warm-up period on.
+    fn tick(&mut self, now: Ticks, arrivals: Vec<Packet>) -> Vec<Packet> {
+        if now > Ticks::ZERO && now == self.warmup {
+            self.server.reset_statistics();
+            self.sojourn = OnlineStats::new();
+            self.through = OnlineStats::new();
+        }
+        for p in arrivals {
+            let id = p.id;
+            if self.server.enqueue(p) {
+                self.arrived.insert(id, now);
+            }
+        }
+        let departed = self.server.tick();
+        for p in &departed {
+            if let Some(arrived) = self.arrived.remove(&p.id) {
+                self.sojourn.add(now.since(arrived).seconds(self.resolution));
+            }
+            self.through.add(now.since(p.time_generated).seconds(self.resolution));
+        }
+        departed
+    }
+
+    // Hop.stage returns how the stage fared, along with the time packets took to get through the
+    // network up to it.
+    fn stage(&self) -> (Stage, OnlineStats) {
+        let stage = Stage {
+            pspeed: self.pspeed,
+            packets_processed: self.server.packets_processed(),
+            packets_dropped: self.server.packets_dropped(),
+            utilization: self.server.utilization(),
+            sojourn: self.sojourn,
+            leftover: self.server.qlen() + self.server.in_service().count(),
+        };
+        (stage, self.through)
+    }
+}
+
+impl Tandem {
+    fn validate(&self) -> Result<(), QsimError> {
+        if self.pspeeds.is_empty() {
+            let reason = "a tandem network needs at least a stage past the first";
+            return Err(QsimError::Config(reason.to_string()));
+        }
+        if self.pspeeds.contains(&0) {
+            let reason = "every stage must process at least a bit per second";
+            return Err(QsimError::invalid("stage pspeed", 0, reason));
+        }
+        if self.execution == (Execution::Pipelined { window: 0 }) {
+            return Err(QsimError::invalid("window", 0, "stages must sync at least a tick apart"));
+        }
+        Ok(())
+    }
+
+    // Tandem.run runs the configured run through the first stage, and the packets departing it
+    // through the stages after. However they're executed, the stages see the same packets at the
+    // same ticks, and so fare the same.
+    pub fn run(&self, config: &Config) -> Result<Network, QsimError> {
+        config.validate()?;
+        self.validate()?;
+        let (first, stages) = match self.execution {
+            Execution::Serial => self.serial(config),
+            Execution::Pipelined { window } => self.pipelined(config, Ticks(window)),
+        };
+        let sojourn = stages.last().expect("no stages").1;
+        Ok(Network { first, stages: stages.into_iter().map(|s| s.0).collect(), sojourn })
+    }
+
+    fn serial(&self, config: &Config) -> (Results, Vec<(Stage, OnlineStats)>) {
+        let (mut sim, departures) = first(config);
+        let mut hops: Vec<Hop> = self.pspeeds.iter().map(|&p| Hop::new(config, p)).collect();
+        while step(&mut sim) {
+            let now = sim.now() - Ticks(1);
+            let mut packets = departures.borrow_mut().0.drain(..).map(|(_, p)| p).collect();
+            for hop in &mut hops {
+                packets = hop.tick(now, packets);
+            }
+        }
+        (sim.results(), hops.iter().map(Hop::stage).collect())
+    }
+
+    fn pipelined(&self, config: &Config, window: Ticks) -> (Results, Vec<(Stage, OnlineStats)>) {
+        // Every stage but the last passes packets on to the next over a channel of its own.
+        let (mut senders, receivers): (Vec<_>, Vec<_>) =
+            self.pspeeds.iter().map(|_| sync_channel(CHANNEL)).unzip();
+        let tx = senders.remove(0);
+        let downstream = senders.into_iter().map(Some).chain(Some(None));
+        thread::scope(|s| {
+            let first = s.spawn(move || {
+                let (mut sim, departures) = first(config);
+                while step(&mut sim) {
+                    for (t, p) in departures.borrow_mut().0.drain(..) {
+                        send(&tx, Message::Packet(t, p));
+                    }
+                    if sim.now().is_multiple_of(window) {
+                        send(&tx, Message::Until(sim.now()));
+                    }
+                }
+                send(&tx, Message::Until(sim.now()));
+                sim.results()
+            });
+            let hops: Vec<_> = self
+                .pspeeds
+                .iter()
+                .zip(receivers)
+                .zip(downstream)
+                .map(|((&pspeed, rx), tx)| {
+                    s.spawn(move || {
+                        let mut hop = Hop::new(config, pspeed);
+                        relay(&mut hop, rx, tx, window);
+                        hop.stage()
+                    })
+                })
+                .collect();
+            (join(first), hops.into_iter().map(join).collect())
+        })
+    }
+}
+
+// first returns the simulation of the configured run, the first stage, along with the departures
+// it collects.
+fn first(config: &Config) -> (Simulation, Rc<RefCell<Departures>>) {
+    let mut sim = Simulation::new(config.clone());
+    let departures = Rc::new(RefCell::new(Departures::default()));
+    sim.add_collector(Box::new(departures.clone()));
+    (sim, departures)
+}
+
+// step moves the first stage forward a tick, resuming it should a scheduled change pause it. It
+// returns false once the run's over.
+fn step(sim: &mut Simulation) -> bool {
+    while !sim.tick() {
+        if sim.done() {
+            return false;
+        }
+        sim.resume();
+    }
+    true
+}
+
+// join waits for the stage's thread to finish, propagating its panic if it panicked.
+fn join<T>(handle: thread::ScopedJoinHandle<'_, T>) -> T {
+    handle.join().unwrap_or_else(|e| panic::resume_unwind(e))
+}
+
+// send sends the message on to the next stage. Should it be gone, it panicked, and the panic's
+// propagated once the stages are joined.
+fn send(tx: &SyncSender<Message>, message: Message) {
+    let _ = tx.send(message);
+}
+
+// relay runs a stage past the first, pipelined (see Execution::Pipelined), until the stage before
+// is done with and every packet it passed on has been worked through.
+fn relay(hop: &mut Hop, rx: Receiver<Message>, tx: Option<SyncSender<Message>>, window: Ticks) {
+    let (mut now, mut horizon) = (Ticks::ZERO, Ticks::ZERO);
+    let mut pending: VecDeque<(Ticks, Packet)> = VecDeque::new();
+    loop {
+        while horizon <= now {
+            match rx.recv() {
+                Ok(Message::Packet(t, p)) => pending.push_back((t, p)),
+                Ok(Message::Until(t)) => horizon = t,
+                Err(_) => {
+                    if let Some(ref tx) = tx {
+                        send(tx, Message::Until(now));
+                    }
+                    return;
+                }
+            }
+        }
+        let arrived = pending.iter().take_while(|&&(t, _)| t <= now).count();
+        let arrivals = pending.drain(..arrived).map(|(_, p)| p).collect();
+        let departed = hop.tick(now, arrivals);
+        now += Ticks(1);
+        if let Some(ref tx) = tx {
+            for p in departed {
+                send(tx, Message::Packet(now - Ticks(1), p));
+            }
+            if now.is_multiple_of(window) {
+                send(tx, Message::Until(now));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Change;
+
+    fn config() -> Config {
+        Config {
+            rate: 8000,
+            duration: 2,
+            resolution: 1e5,
+            seed: Some(1),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn pipelining() {
+        let tandem = |execution| Tandem { pspeeds: vec![12_000, 9000], execution };
+        let serial = tandem(Execution::Serial).run(&config()).unwrap();
+        // Pipelined, however far apart the stages sync, each stage fares as it does run serially.
+        for &window in &[1, 7, 1000, 1_000_000] {
+            let pipelined = tandem(Execution::Pipelined { window }).run(&config()).unwrap();
+            assert_eq!(pipelined.first.packets_processed, serial.first.packets_processed);
+            for (p, s) in pipelined.stages.iter().zip(&serial.stages) {
+                assert_eq!(p.packets_processed, s.packets_processed);
+                assert_eq!(p.sojourn.mean(), s.sojourn.mean());
+                assert_eq!(p.leftover, s.leftover);
+            }
+            assert_eq!(pipelined.sojourn.mean(), serial.sojourn.mean());
+        }
+
+        // Packets get through every stage, the slowest of which is the busiest, and take longer to
+        // than they do getting through the first.
+        let (fast, slow) = (&serial.stages[0], &serial.stages[1]);
+        let processed = serial.first.packets_processed;
+        assert!(fast.packets_processed <= processed && fast.packets_processed + 5 >= processed);
+        assert!(slow.utilization > fast.utilization, "{}", slow.utilization);
+        assert!((slow.utilization - 8000.0 / 9000.0).abs() < 0.05, "{}", slow.utilization);
+        let stages: f64 = serial.stages.iter().map(|s| s.sojourn.mean()).sum();
+        let through = serial.first.sojourn.mean() + stages;
+        assert!((serial.sojourn.mean() - through).abs() < 1e-3 * through);
+    }
+
+    #[test]
+    fn pauses() {
+        // A scheduled pause doesn't hold up the stages, pipelined or not.
+        let c = Config { schedule: vec![(0.5, Change::Pause)], ..config() };
+        for &execution in &[Execution::Serial, Execution::Pipelined { window: 100 }] {
+            let n = Tandem { pspeeds: vec![12_000], execution }.run(&c).unwrap();
+            assert!(n.stages[0].packets_processed > 15_000);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let run = |pspeeds: Vec<u32>, execution| Tandem { pspeeds, execution }.run(&config());
+        assert!(run(vec![], Execution::Serial).is_err());
+        assert!(run(vec![1000, 0], Execution::Serial).is_err());
+        assert!(run(vec![1000], Execution::Pipelined { window: 0 }).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::ops::{Add, AddAssign, Rem, Sub};
+
+// Ticks is a point in, or a stretch of, simulated time, counted in ticks of the simulation's
+// resolution (see Config.resolution). A run spans at most 2^32 ticks, and simulated time never
+// goes backwards, so arithmetic that overflows or underflows is a bug: the operators panic rather
+// than silently wrap around (as u32 arithmetic does in release builds), and the checked and
+// saturating variants are there for when it's expected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ticks(pub u32);
+
+impl Ticks {
+    pub const ZERO: Ticks = Ticks(0);
+    pub const MAX: Ticks = Ticks(u32::MAX);
+
+    // Ticks::from_seconds returns the number of ticks in the given number of seconds at the given
+    // resolution (ticks per second), rounded to the nearest and clamped to the ticks a run can
+    // span.
+    pub fn from_seconds(seconds: f64, resolution: f64) -> Ticks {
+        // Float to integer casts saturate, with NaN going to zero.
+        Ticks((seconds * resolution).round() as u32)
+    }
+
+    // Ticks.seconds returns the time in seconds at the given resolution.
+    pub fn seconds(self, resolution: f64) -> f64 {
+        f64::from(self.0) / resolution
+    }
+
+    // Ticks.since returns the time elapsed since the given earlier time.
+    pub fn since(self, earlier: Ticks) -> Ticks {
+        self - earlier
+    }
+
+    pub fn checked_add(self, other: Ticks) -> Option<Ticks> {
+        self.0.checked_add(other.0).map(Ticks)
+    }
+
+    pub fn checked_sub(self, other: Ticks) -> Option<Ticks> {
+        self.0.checked_sub(other.0).map(Ticks)
+    }
+
+    pub fn saturating_add(self, other: Ticks) -> Ticks {
+        Ticks(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Ticks) -> Ticks {
+        Ticks(self.0.saturating_sub(other.0))
+    }
+
+    // Ticks.is_multiple_of returns whether the time falls on a multiple of the given interval,
+    // which, as with u32::is_multiple_of, only zero is of zero.
+    pub fn is_multiple_of(self, interval: Ticks) -> bool {
+        self.0.is_multiple_of(interval.0)
+    }
+}
+
+impl Add for Ticks {
+    type Output = Ticks;
+
+    fn add(self, other: Ticks) -> Ticks {
+        self.checked_add(other).expect("simulated time overflowed")
+    }
+}
+
+impl AddAssign for Ticks {
+    fn add_assign(&mut self, other: Ticks) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Ticks {
+    type Output = Ticks;
+
+    fn sub(self, other: Ticks) -> Ticks {
+        self.checked_sub(other).expect("simulated time went backwards")
+    }
+}
+
+impl Rem for Ticks {
+    type Output = Ticks;
+
+    fn rem(self, other: Ticks) -> Ticks {
+        Ticks(self.0 % other.0)
+    }
+}
+
+impl From<u32> for Ticks {
+    fn from(ticks: u32) -> Ticks {
+        Ticks(ticks)
+    }
+}
+
+impl From<Ticks> for u64 {
+    fn from(ticks: Ticks) -> u64 {
+        u64::from(ticks.0)
+    }
+}
+
+impl From<Ticks> for f64 {
+    fn from(ticks: Ticks) -> f64 {
+        f64::from(ticks.0)
+    }
+}
+
+impl fmt::Display for Ticks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(Ticks(3) + Ticks(4), Ticks(7));
+        assert_eq!(Ticks(7).since(Ticks(3)), Ticks(4));
+        assert_eq!(Ticks(7) % Ticks(3), Ticks(1));
+        assert_eq!(Ticks::MAX.checked_add(Ticks(1)), None);
+        assert_eq!(Ticks(1).checked_sub(Ticks(2)), None);
+        assert_eq!(Ticks::MAX.saturating_add(Ticks(1)), Ticks::MAX);
+        assert_eq!(Ticks(1).saturating_sub(Ticks(2)), Ticks::ZERO);
+        assert!(Ticks(10).is_multiple_of(Ticks(5)) && !Ticks(10).is_multiple_of(Ticks(4)));
+    }
+
+    #[test]
+    #[should_panic(expected = "simulated time went backwards")]
+    fn backwards() {
+        let _ = Ticks(1).since(Ticks(2));
+    }
+
+    #[test]
+    fn seconds() {
+        assert_eq!(Ticks::from_seconds(1.5, 1e3), Ticks(1500));
+        assert_eq!(Ticks::from_seconds(1e-4, 1e3), Ticks::ZERO);
+        assert_eq!(Ticks::from_seconds(1e10, 1e6), Ticks::MAX);
+        assert_eq!(Ticks(2500).seconds(1e3), 2.5);
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// Value is a TOML value, just enough of one to read configuration files without pulling in a
+// parser: strings, integers, floats, booleans and arrays thereof. Dates and inline tables aren't
+// supported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+// Values are displayed the way they're given on the command line, arrays as comma-separated
+// lists.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::String(ref s) => f.write_str(s),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Array(ref values) => {
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// parse parses a TOML document into its key/value pairs, in the order given. Keys within tables
+// are qualified by the table's name, e.g. "b.c" for c in [b].
+pub fn parse(s: &str) -> Result<Vec<(String, Value)>, String> {
+    let mut p = Parser { chars: s.chars().peekable(), line: 1 };
+    let mut table = String::new();
+    let mut entries: Vec<(String, Value)> = vec![];
+    loop {
+        p.skip_whitespace(true);
+        match p.chars.peek() {
+            None => return Ok(entries),
+            Some(&'[') => {
+                p.chars.next();
+                table = p.key()?;
+                p.expect(']')?;
+            }
+            Some(_) => {
+                let mut key = p.key()?;
+                if !table.is_empty() {
+                    key = format!("{}.{}", table, key);
+                }
+                p.expect('=')?;
+                let value = p.value()?;
+                if entries.iter().any(|e| e.0 == key) {
+                    return Err(p.error(&format!("duplicate key {}", key)));
+                }
+                entries.push((key, value));
+            }
+        }
+        p.skip_whitespace(false);
+        match p.chars.next() {
+            None | Some('\n') => p.line += 1,
+            Some(c) => return Err(p.error(&format!("unexpected {:?}", c))),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> String {
+        format!("line {}: {}", self.line, msg)
+    }
+
+    // skip_whitespace skips spaces and comments, and newlines too if asked to.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => self.line += 1,
+                '#' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace(false);
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected {:?}, found {:?}", expected, c))),
+            None => Err(self.error(&format!("expected {:?}", expected))),
+        }
+    }
+
+    // key parses a possibly dotted key, the parts of which are either bare or quoted.
+    fn key(&mut self) -> Result<String, String> {
+        let mut parts = vec![];
+        loop {
+            self.skip_whitespace(false);
+            let part = match self.chars.peek() {
+                Some(&'"') | Some(&'\'') => self.string()?,
+                _ => {
+                    let mut part = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                            break;
+                        }
+                        part.push(c);
+                        self.chars.next();
+                    }
+                    if part.is_empty() {
+                        return Err(self.error("expected a key"));
+                    }
+                    part
+                }
+            };
+            parts.push(part);
+            self.skip_whitespace(false);
+            if self.chars.peek() != Some(&'.') {
+                return Ok(parts.join("."));
+            }
+            self.chars.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace(false);
+        match self.chars.peek() {
+            Some(&'"') | Some(&'\'') => Ok(Value::String(self.string()?)),
+            Some(&'[') => {
+                self.chars.next();
+                let mut values = vec![];
+                loop {
+                    self.skip_whitespace(true);
+                    if self.chars.peek() == Some(&']') {
+                        self.chars.next();
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_whitespace(true);
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !(c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+                        break;
+                    }
+                    token.push(c);
+                    self.chars.next();
+                }
+                let number = token.replace('_', "");
+                match token.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ if number.parse::<i64>().is_ok() => {
+                        Ok(Value::Integer(number.parse().unwrap()))
+                    }
+                    _ if !token.is_empty() && number.parse::<f64>().is_ok() => {
+                        Ok(Value::Float(number.parse().unwrap()))
+                    }
+                    _ => Err(self.error(&format!("invalid value {:?}", token))),
+                }
+            }
+        }
+    }
+
+    // string parses a single-line basic ("...", with escapes) or literal ('...') string.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.chars.next().unwrap();
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some(c) if c == quote => return Ok(s),
+                Some('\\') if quote == '"' => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn document() {
+        let doc = "# A comment.\n\
+                   rate = 1_000 # packets/s\n\
+                   qlimit = 64\n\
+                   \n\
+                   percentiles = [50, 99.9,\n  99.99,  # trailing\n]\n\
+                   validate = true\n\
+                   [output]\n\
+                   format = \"json\"\n\
+                   'literal' = 'C:\\path'\n\
+                   \"a.b\".c = -1.5e3\n";
+        assert_eq!(
+            parse(doc).unwrap(),
+            vec![
+                ("rate".to_string(), Value::Integer(1000)),
+                ("qlimit".to_string(), Value::Integer(64)),
+                (
+                    "percentiles".to_string(),
+                    Value::Array(vec![
+                        Value::Integer(50),
+                        Value::Float(99.9),
+                        Value::Float(99.99),
+                    ]),
+                ),
+                ("validate".to_string(), Value::Boolean(true)),
+                ("output.format".to_string(), Value::String("json".to_string())),
+                ("output.literal".to_string(), Value::String("C:\\path".to_string())),
+                ("output.a.b.c".to_string(), Value::Float(-1500.0)),
+            ]
+        );
+        let v = Value::Array(vec![Value::Integer(2), Value::Float(0.5), Value::String("x".into())]);
+        assert_eq!(v.to_string(), "2,0.5,x");
+    }
+
+    #[test]
+    fn invalid() {
+        let err = |s: &str| parse(s).unwrap_err();
+        assert_eq!(err("rate = 1\nrate = 2\n"), "line 2: duplicate key rate");
+        assert_eq!(err("\n\nrate 1\n"), "line 3: expected '=', found '1'");
+        assert_eq!(err("rate = fast\n"), "line 1: invalid value \"fast\"");
+        assert_eq!(err("name = \"x\n"), "line 1: unterminated string");
+        assert_eq!(err("a = 1 b = 2\n"), "line 1: unexpected 'b'");
+        assert_eq!(err("a = [1 2]\n"), "line 1: expected ',' or ']'");
+    }
+}
@@ -0,0 +1,150 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use std::io::{self, BufRead, Write};
+
+// TraceEntry is a single packet of a trace: the time it arrives at (in seconds since the start of
+// the run) and its length (in bits).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub time: f64,
+    pub length: u32,
+}
+
+// read parses a trace in CSV form, one "time,length" entry per line in non-decreasing order of
+// time, optionally preceded by a header. Blank lines are skipped.
+pub fn read<R: BufRead>(r: R) -> io::Result<Vec<TraceEntry>> {
+    let invalid = |n: usize, msg: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", n, msg))
+    };
+    let mut entries: Vec<TraceEntry> = vec![];
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line == HEADER) {
+            continue;
+        }
+        let mut fields = line.split(',');
+        let (time, length) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(t), Some(l), None) => (t.trim().parse::<f64>(), l.trim().parse::<u32>()),
+            _ => return Err(invalid(i + 1, "expected time,length")),
+        };
+        let entry = match (time, length) {
+            (Ok(time), Ok(length)) if time >= 0.0 => TraceEntry { time, length },
+            _ => return Err(invalid(i + 1, "invalid time or length")),
+        };
+        if entries.last().is_some_and(|last| last.time > entry.time) {
+            return Err(invalid(i + 1, "time goes backwards"));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+const HEADER: &str = "time,length";
+
+// TraceWriter writes a trace in the form read by trace::read, e.g. the departure process of one
+// run, to be replayed as the arrival process of another.
+pub struct TraceWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    // TraceWriter::new writes out the header.
+    pub fn new(mut w: W) -> io::Result<TraceWriter<W>> {
+        writeln!(w, "{}", HEADER)?;
+        Ok(TraceWriter { w })
+    }
+
+    pub fn write(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        writeln!(self.w, "{},{}", entry.time, entry.length)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+// Summary describes a trace: the time between its first and last packets, and the distributions of
+// its interarrival times (seconds) and packet lengths (bits).
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub packets: usize,
+    pub span: f64,
+    pub interarrival: OnlineStats,
+    pub length: OnlineStats,
+}
+
+impl Summary {
+    // Summary.rate returns the average arrival rate (packets/s), NaN for fewer than two packets.
+    pub fn rate(&self) -> f64 {
+        self.interarrival.len() as f64 / self.span
+    }
+
+    // Summary.bitrate returns the average offered load (bits/s).
+    pub fn bitrate(&self) -> f64 {
+        self.rate() * self.length.mean()
+    }
+}
+
+// summarize summarizes the given trace.
+pub fn summarize(entries: &[TraceEntry]) -> Summary {
+    let mut interarrival = OnlineStats::new();
+    for pair in entries.windows(2) {
+        interarrival.add(pair[1].time - pair[0].time);
+    }
+    Summary {
+        packets: entries.len(),
+        span: entries.last().map_or(0.0, |last| last.time - entries[0].time),
+        interarrival,
+        length: entries.iter().map(|e| e.length).collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let entries = vec![
+            TraceEntry { time: 0.0, length: 8 },
+            TraceEntry { time: 0.25, length: 1500 },
+            TraceEntry { time: 0.25, length: 1 },
+        ];
+        let mut buf = vec![];
+        {
+            let mut w = TraceWriter::new(&mut buf).unwrap();
+            for e in &entries {
+                w.write(e).unwrap();
+            }
+        }
+        assert_eq!(
+            String::from_utf8(buf.clone()).unwrap(),
+            "time,length\n0,8\n0.25,1500\n0.25,1\n"
+        );
+        assert_eq!(read(&buf[..]).unwrap(), entries);
+        assert_eq!(read(&b"0.5, 10\n\n1,20\n"[..]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn invalid() {
+        let err = |s: &str| read(s.as_bytes()).unwrap_err().to_string();
+        assert_eq!(err("time,length\n1,2,3\n"), "line 2: expected time,length");
+        assert_eq!(err("1,x\n"), "line 1: invalid time or length");
+        assert_eq!(err("-1,2\n"), "line 1: invalid time or length");
+        assert_eq!(err("2,1\n1,1\n"), "line 2: time goes backwards");
+    }
+
+    #[test]
+    fn summary() {
+        let entries = read(&b"0,100\n0.5,300\n1,100\n2,300\n"[..]).unwrap();
+        let s = summarize(&entries);
+        assert_eq!((s.packets, s.span), (4, 2.0));
+        assert!((s.rate() - 1.5).abs() < 1e-9);
+        assert_eq!(s.length.mean(), 200.0);
+        assert!((s.bitrate() - 300.0).abs() < 1e-9);
+        assert!(summarize(&[]).rate().is_nan());
+    }
+}
@@ -0,0 +1,104 @@
+extern crate stats;
+
+use self::stats::OnlineStats;
+use error::QsimError;
+use simulation::Config;
+use sweep;
+use time::Ticks;
+
+// The number of points in time the system is observed at by default, evenly spread over the run.
+pub const DEFAULT_POINTS: u32 = 100;
+
+// Point is the state of the system at a point in simulated time (in seconds) across replications:
+// the number of packets queued, and whether the server was busy (1) or not (0), in each.
+#[derive(Clone, Debug)]
+pub struct Point {
+    pub time: f64,
+    pub qlen: OnlineStats,
+    pub busy: OnlineStats,
+}
+
+impl Point {
+    // Point.busy_probability returns the share of replications the server was busy at the time in,
+    // an estimate of P(busy at t).
+    pub fn busy_probability(&self) -> f64 {
+        self.busy.mean()
+    }
+
+    // Point.occupancy returns the average number of packets in the system at the time, queued or in
+    // service, an estimate of E[N(t)].
+    pub fn occupancy(&self) -> f64 {
+        self.qlen.mean() + self.busy.mean()
+    }
+}
+
+// run runs the configuration for the given number of independent replications (see
+// sweep::samples), sampling the server state every interval ticks, and aggregates the samples
+// taken at the same time across them: the ensemble averages over replications, rather than the
+// time averages over a run, track the system as it evolves, e.g. while it fills up from empty or
+// settles after a change in load scheduled into the run. The statistics otherwise collected,
+// warm-up deletion included, don't bear on them.
+pub fn run(
+    config: &Config,
+    interval: u32,
+    replications: u32,
+    seed: u64,
+) -> Result<Vec<Point>, QsimError> {
+    config.validate()?;
+    if interval == 0 {
+        return Err(QsimError::invalid("interval", 0, "samples have to be at least a tick apart"));
+    }
+    if replications < 2 {
+        let reason = "averages across replications need at least two";
+        return Err(QsimError::invalid("replications", replications, reason));
+    }
+    let c = Config { sample_interval: Some(interval), ..config.clone() };
+    // The queue length and server state of every sample, one after the other.
+    let samples = sweep::samples(&c, replications, seed, |r| {
+        let busy = |b: bool| if b { 1.0 } else { 0.0 };
+        r.samples.iter().flat_map(|s| vec![s.qlen as f64, busy(s.busy)]).collect()
+    });
+    Ok(samples
+        .chunks(2)
+        .enumerate()
+        .map(|(i, s)| Point {
+            time: Ticks(i as u32 * interval).seconds(config.resolution),
+            qlen: OnlineStats::from_slice(&s[0]),
+            busy: OnlineStats::from_slice(&s[1]),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simulation::Change;
+
+    #[test]
+    fn load_change() {
+        // Starting out empty and lightly loaded, the queue builds up once the load is raised
+        // halfway through, toward the steady state of the heavier load.
+        let config = Config {
+            rate: 300,
+            psize: 1,
+            pspeed: 1000,
+            duration: 2,
+            resolution: 1e4,
+            schedule: vec![(1.0, Change::Rate(950))],
+            ..Config::default()
+        };
+        let points = run(&config, 1000, 20, 1).unwrap();
+        assert_eq!(points.len(), 20);
+        assert_eq!(points[10].time, 1.0);
+        assert!(points.iter().all(|p| p.qlen.len() == 20));
+        assert_eq!(points[0].occupancy(), 0.0);
+        let (light, heavy) = (&points[5..10], &points[15..]);
+        let busy = |ps: &[Point]| ps.iter().map(|p| p.busy_probability()).sum::<f64>() / 5.0;
+        assert!((busy(light) - 0.3).abs() < 0.15, "{}", busy(light));
+        assert!((busy(heavy) - 0.95).abs() < 0.1, "{}", busy(heavy));
+        assert!(points[19].occupancy() > points[11].occupancy());
+
+        assert!(run(&config, 0, 20, 1).is_err());
+        assert!(run(&config, 1000, 1, 1).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use analysis;
+use json::Json;
+
+// Test is the t-test the replications of two runs are compared with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Test {
+    // The paired t-test, on the differences between replications run off of the same seeds (see
+    // sweep::replicate), which cancels out much of the noise common to both.
+    Paired,
+    // Welch's t-test, for independent runs, the variances of which may differ.
+    Welch,
+}
+
+impl Test {
+    pub fn parse(s: &str) -> Result<Test, String> {
+        match s {
+            "paired" => Ok(Test::Paired),
+            "welch" | "unpaired" => Ok(Test::Welch),
+            _ => Err(format!("unknown test {}, expected paired or welch", s)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Test::Paired => "paired",
+            Test::Welch => "Welch's",
+        }
+    }
+}
+
+// Replications holds the values of the metrics (see sweep::metrics) observed in each replication of
+// a run, as read back from its results (see json::replicated).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replications {
+    // The seed the replications were derived from, if it was given.
+    pub seed: Option<f64>,
+    pub metrics: Vec<(String, Vec<f64>)>,
+}
+
+impl Replications {
+    pub fn from_json(doc: &Json) -> Result<Replications, String> {
+        let fields = match doc.get("metrics") {
+            Some(Json::Object(fields)) => fields,
+            _ => return Err("no replications, expected the results of --replications".to_string()),
+        };
+        let mut metrics = vec![];
+        for (name, m) in fields {
+            let values = match m.get("values") {
+                Some(Json::Array(values)) => values.iter().map(Json::as_f64).collect(),
+                _ => None,
+            };
+            match values {
+                Some(values) => metrics.push((name.clone(), values)),
+                None => return Err(format!("no values of {} by replication", name)),
+            }
+        }
+        let seed = doc.get("config").and_then(|c| c.get("seed")).and_then(Json::as_f64);
+        Ok(Replications {
+            seed: seed.filter(|s| s.is_finite()),
+            metrics,
+        })
+    }
+
+    // Replications.len returns the number of replications.
+    pub fn len(&self) -> usize {
+        self.metrics.first().map_or(0, |m| m.1.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Replications.paired_with returns whether the replications were run off of the same seeds as
+    // the other's, and so can be paired up.
+    pub fn paired_with(&self, other: &Replications) -> bool {
+        self.seed.is_some() && self.seed == other.seed && self.len() == other.len()
+    }
+}
+
+// Outcome is the result of testing the difference in a metric between two runs.
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub metric: String,
+    // The difference in the metric's mean, the second run's less the first's.
+    pub difference: f64,
+    pub t: f64,
+    pub df: f64,
+    // The two-sided p-value, the probability of seeing at least as large a difference if there
+    // were none at all.
+    pub p: f64,
+    // Cohen's d, the difference in standard deviations: of the differences for a paired test, or
+    // the average of the two runs' otherwise.
+    pub effect: f64,
+}
+
+impl Outcome {
+    fn new(metric: &str, difference: f64, stddev: f64, stderr: f64, df: f64) -> Outcome {
+        let t = difference / stderr;
+        // The values needn't vary at all (e.g. no loss either way), in which case any difference
+        // is a certain one.
+        let p = if stderr > 0.0 {
+            2.0 * (1.0 - analysis::t_cdf(t.abs(), df))
+        } else if difference == 0.0 {
+            1.0
+        } else {
+            0.0
+        };
+        Outcome {
+            metric: metric.to_string(),
+            difference,
+            t,
+            df,
+            p,
+            effect: if difference == 0.0 { 0.0 } else { difference / stddev },
+        }
+    }
+}
+
+// mean_variance returns the mean and sample variance of the values.
+fn mean_variance(xs: &[f64]) -> (f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let variance = xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, variance)
+}
+
+// paired tests the mean of the differences between paired observations, b less a, against zero.
+pub fn paired(metric: &str, a: &[f64], b: &[f64]) -> Outcome {
+    let differences: Vec<f64> = a.iter().zip(b).map(|(a, b)| b - a).collect();
+    let n = differences.len() as f64;
+    let (mean, variance) = mean_variance(&differences);
+    Outcome::new(metric, mean, variance.sqrt(), (variance / n).sqrt(), n - 1.0)
+}
+
+// welch tests the difference between the means of independent observations, b's less a's, with
+// the degrees of freedom given by the Welch-Satterthwaite equation.
+pub fn welch(metric: &str, a: &[f64], b: &[f64]) -> Outcome {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let ((ma, va), (mb, vb)) = (mean_variance(a), mean_variance(b));
+    let (sa, sb) = (va / na, vb / nb);
+    let df = (sa + sb).powi(2) / (sa.powi(2) / (na - 1.0) + sb.powi(2) / (nb - 1.0));
+    Outcome::new(metric, mb - ma, ((va + vb) / 2.0).sqrt(), (sa + sb).sqrt(), df)
+}
+
+// ttest tests the difference between the two runs in each of the metrics they have in common, in
+// the order of the first's.
+pub fn ttest(a: &Replications, b: &Replications, test: Test) -> Vec<Outcome> {
+    a.metrics
+        .iter()
+        .filter_map(|(name, a)| {
+            let b = &b.metrics.iter().find(|m| &m.0 == name)?.1;
+            Some(match test {
+                Test::Paired => paired(name, a, b),
+                Test::Welch => welch(name, a, b),
+            })
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use json;
+    use simulation::Config;
+    use sweep;
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() < tolerance, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn t_tests() {
+        let a = [5.1, 4.9, 5.6, 5.8, 6.0, 5.3];
+        let b = [5.9, 5.2, 6.1, 6.6, 6.1, 5.9];
+        let o = paired("x", &a, &b);
+        assert_close(o.difference, 0.516_667, 1e-6);
+        assert_close(o.t, 4.541_180, 1e-6);
+        assert_eq!(o.df, 5.0);
+        assert_close(o.p, 0.006_162, 1e-6);
+        assert_close(o.effect, 1.853_929, 1e-6);
+        let o = welch("x", &a, &b);
+        assert_close(o.t, 2.037_887, 1e-6);
+        assert_close(o.df, 9.948_801, 1e-6);
+        assert_close(o.p, 0.069_035, 1e-6);
+        assert_close(o.effect, 1.176_574, 1e-6);
+
+        let o = paired("loss", &[0.0, 0.0], &[0.0, 0.0]);
+        assert_eq!((o.p, o.effect), (1.0, 0.0));
+        let o = welch("loss", &[0.0, 0.0], &[1.0, 1.0]);
+        assert_eq!(o.p, 0.0);
+    }
+
+    #[test]
+    fn replications() {
+        let config = |rate: u32| Config {
+            rate,
+            pspeed: 1000,
+            duration: 1,
+            resolution: 1e4,
+            seed: Some(1),
+            ..Config::default()
+        };
+        let run = |config: &Config| {
+            let names = sweep::metrics(config);
+            let samples = sweep::samples(config, 5, 1, sweep::observe);
+            let doc = json::replicated(config, 5, &names, &samples).to_string();
+            Replications::from_json(&Json::parse(&doc).unwrap()).unwrap()
+        };
+        let (a, b) = (run(&config(500)), run(&config(700)));
+        assert_eq!(a.len(), 5);
+        assert!(a.paired_with(&b));
+        let outcomes = ttest(&a, &b, Test::Paired);
+        assert_eq!(outcomes.len(), a.metrics.len());
+        // Under common random numbers, the higher load is clearly slower.
+        assert_eq!(outcomes[0].metric, "sojourn");
+        assert!(outcomes[0].difference > 0.0 && outcomes[0].p < 0.01, "{:?}", outcomes[0]);
+
+        let unseeded = Replications { seed: None, ..b.clone() };
+        assert!(!a.paired_with(&unseeded));
+        let err = Replications::from_json(&Json::parse(r#"{"results":{}}"#).unwrap());
+        assert!(err.unwrap_err().starts_with("no replications"));
+    }
+}